@@ -0,0 +1,628 @@
+//! `koko book`: render a whole book, one chapter per TTS instance at a time.
+//!
+//! Chapters are rendered to 16-bit PCM files in a work directory next to the
+//! output, a few sentences at a time. After every piece the chapter's
+//! progress file records how many pieces and bytes are safely on disk, so an
+//! interrupted run picks up where it stopped as long as the book and voice
+//! settings are unchanged. The finished parts are then encoded into one
+//! tagged file with chapter markers, or one file per chapter.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use kokoros::error::KokorosError;
+use kokoros::tts::book::{Book, Chapter};
+use kokoros::tts::document::DocumentOptions;
+use kokoros::tts::frontend::TextFrontend;
+use kokoros::tts::koko::TTSKoko;
+use kokoros::tts::segment;
+use kokoros::utils::chapters::{AudioMetadata, ChapterMark};
+use kokoros::utils::dsp::Effects;
+use kokoros::utils::mp3::Mp3Stream;
+
+/// Longest piece of text rendered, and checkpointed, at once
+const MAX_PIECE_CHARS: usize = 1500;
+/// Silence after a paragraph, in seconds
+const PARAGRAPH_PAUSE: f32 = 0.5;
+/// Silence at the end of a chapter, in seconds
+const CHAPTER_PAUSE: f32 = 1.5;
+/// Samples read from a part file per encoder block
+const BLOCK_SAMPLES: usize = 1 << 18;
+
+pub struct BookJob<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Throw away the parts of an earlier run
+    pub restart: bool,
+    pub model_path: &'a str,
+    pub lan: &'a str,
+    pub style: &'a str,
+    pub speed: f32,
+    pub initial_silence: Option<usize>,
+    pub effects: Effects,
+    pub frontend: TextFrontend,
+    pub document_options: DocumentOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputKind {
+    Mp3,
+    M4b,
+    /// A directory with one MP3 per chapter
+    Chapters,
+}
+
+impl OutputKind {
+    fn of(path: &Path) -> Result<Self, KokorosError> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("mp3") => Ok(Self::Mp3),
+            Some("m4b" | "m4a") => Ok(Self::M4b),
+            None => Ok(Self::Chapters),
+            Some(other) => Err(KokorosError::Document(format!(
+                "unsupported book output .{}: use .mp3, .m4b or a directory",
+                other
+            ))),
+        }
+    }
+}
+
+pub fn render(instances: &[TTSKoko], job: &BookJob) -> Result<(), KokorosError> {
+    let kind = OutputKind::of(job.output)?;
+    let mut book = Book::open(job.input, &job.document_options)?;
+    if job.title.is_some() {
+        book.title = job.title.clone();
+    }
+    if job.author.is_some() {
+        book.author = job.author.clone();
+    }
+    for chapter in &mut book.chapters {
+        chapter.text = job.frontend.apply(&chapter.text, job.lan);
+    }
+    let sample_rate = instances[0].sample_rate();
+
+    let work_dir = match kind {
+        OutputKind::Chapters => job.output.join(".parts"),
+        _ => PathBuf::from(format!("{}.parts", job.output.display())),
+    };
+    let fingerprint = format!("{:016x}", fingerprint(&book, job, sample_rate));
+    let manifest = work_dir.join("fingerprint");
+    if work_dir.exists() {
+        let previous = fs::read_to_string(&manifest).unwrap_or_default();
+        if job.restart || previous.trim() != fingerprint {
+            if !job.restart {
+                eprintln!("The book or voice settings changed since the last run, starting over");
+            }
+            fs::remove_dir_all(&work_dir)?;
+        } else {
+            eprintln!("Resuming from {}", work_dir.display());
+        }
+    }
+    fs::create_dir_all(&work_dir)?;
+    fs::write(&manifest, &fingerprint)?;
+
+    eprintln!(
+        "Rendering {} chapters of {} with {} instances",
+        book.chapters.len(),
+        book.title.as_deref().unwrap_or("the book"),
+        instances.len()
+    );
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let failure = Mutex::new(None);
+    std::thread::scope(|scope| {
+        for (instance, tts) in instances.iter().enumerate() {
+            let (book, work_dir, next, cancelled, failure) =
+                (&book, &work_dir, &next, &cancelled, &failure);
+            scope.spawn(move || {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(chapter) = book.chapters.get(index) else {
+                        break;
+                    };
+                    let part = Part::new(work_dir, index);
+                    match render_chapter(tts, chapter, &part, instance, job, sample_rate, cancelled)
+                    {
+                        Ok(true) => {
+                            eprintln!("[{}/{}] {}", index + 1, book.chapters.len(), chapter.title)
+                        }
+                        Ok(false) => break,
+                        Err(e) => {
+                            cancelled.store(true, Ordering::Relaxed);
+                            failure
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .get_or_insert(e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = failure.into_inner().unwrap_or_else(PoisonError::into_inner) {
+        eprintln!(
+            "Stopped; run the same command again to resume from {}",
+            work_dir.display()
+        );
+        return Err(e);
+    }
+
+    let parts: Vec<Part> = (0..book.chapters.len())
+        .map(|index| Part::new(&work_dir, index))
+        .collect();
+    let mut marks = Vec::new();
+    let mut start_ms = 0u32;
+    for (chapter, part) in book.chapters.iter().zip(&parts) {
+        let samples = fs::metadata(&part.pcm)?.len() / 2;
+        let end_ms = start_ms + (samples * 1000 / sample_rate as u64) as u32;
+        marks.push(ChapterMark {
+            title: chapter.title.clone(),
+            start_ms,
+            end_ms,
+        });
+        start_ms = end_ms;
+    }
+
+    let title = book.title.as_deref();
+    let author = book.author.as_deref();
+    match kind {
+        OutputKind::Mp3 => {
+            let metadata = AudioMetadata {
+                title,
+                artist: author,
+                album: title,
+                chapters: &marks,
+            };
+            write_mp3(job.output, &metadata, &parts, sample_rate)?;
+        }
+        OutputKind::M4b => {
+            let metadata = AudioMetadata {
+                title,
+                artist: author,
+                album: title,
+                chapters: &marks,
+            };
+            write_m4b(job.output, &metadata, &parts, sample_rate, &work_dir)?;
+        }
+        OutputKind::Chapters => {
+            for (index, (chapter, part)) in book.chapters.iter().zip(&parts).enumerate() {
+                let metadata = AudioMetadata {
+                    title: Some(&chapter.title),
+                    artist: author,
+                    album: title,
+                    chapters: &[],
+                };
+                let path = job.output.join(format!(
+                    "{:03} {}.mp3",
+                    index + 1,
+                    file_name(&chapter.title)
+                ));
+                write_mp3(&path, &metadata, std::slice::from_ref(part), sample_rate)?;
+            }
+        }
+    }
+
+    fs::remove_dir_all(&work_dir)?;
+    let total = marks.last().map_or(0, |mark| mark.end_ms) / 1000;
+    eprintln!(
+        "Book saved to {} ({}h{:02}m{:02}s)",
+        job.output.display(),
+        total / 3600,
+        total / 60 % 60,
+        total % 60
+    );
+    Ok(())
+}
+
+/// The rendered audio of one chapter and how far it got
+struct Part {
+    pcm: PathBuf,
+    progress: PathBuf,
+}
+
+impl Part {
+    fn new(work_dir: &Path, index: usize) -> Self {
+        Self {
+            pcm: work_dir.join(format!("{:03}.pcm", index)),
+            progress: work_dir.join(format!("{:03}.progress", index)),
+        }
+    }
+
+    /// Pieces and bytes written, as recorded after the last finished piece
+    fn read_progress(&self) -> (usize, u64) {
+        let progress = fs::read_to_string(&self.progress).unwrap_or_default();
+        let mut fields = progress.split_whitespace().map(|f| f.parse().ok());
+        match (fields.next().flatten(), fields.next().flatten()) {
+            (Some(pieces), Some(bytes)) => (pieces as usize, bytes),
+            _ => (0, 0),
+        }
+    }
+
+    fn write_progress(&self, pieces: usize, bytes: u64) -> std::io::Result<()> {
+        let tmp = self.progress.with_extension("progress.tmp");
+        fs::write(&tmp, format!("{} {}\n", pieces, bytes))?;
+        fs::rename(tmp, &self.progress)
+    }
+}
+
+fn render_chapter(
+    tts: &TTSKoko,
+    chapter: &Chapter,
+    part: &Part,
+    instance: usize,
+    job: &BookJob,
+    sample_rate: u32,
+    cancelled: &AtomicBool,
+) -> Result<bool, KokorosError> {
+    let instance_id = format!("{:02x}", instance);
+    write_pieces(
+        part,
+        &pieces(&chapter.text),
+        sample_rate,
+        cancelled,
+        |i, text| {
+            let audio = tts.tts_raw_audio(
+                text,
+                job.lan,
+                job.style,
+                job.speed,
+                job.initial_silence,
+                None,
+                Some(&instance_id),
+                Some(i),
+            )?;
+            Ok(job.effects.apply(&audio, sample_rate))
+        },
+    )
+}
+
+/// Append the audio of the pieces not yet on disk to a part, checkpointing
+/// after each one. Returns whether the part is complete, which it is not
+/// when cancelled first.
+fn write_pieces(
+    part: &Part,
+    pieces: &[(String, bool)],
+    sample_rate: u32,
+    cancelled: &AtomicBool,
+    mut synthesize: impl FnMut(usize, &str) -> Result<Vec<f32>, KokorosError>,
+) -> Result<bool, KokorosError> {
+    let (done, mut bytes) = part.read_progress();
+    if done >= pieces.len() {
+        return Ok(true);
+    }
+
+    // Anything past the last checkpoint is from a piece that did not finish
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&part.pcm)?;
+    file.set_len(bytes)?;
+    file.seek(SeekFrom::End(0))?;
+
+    for (i, (text, paragraph_end)) in pieces.iter().enumerate().skip(done) {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let audio = synthesize(i, text)?;
+        let pause = if i + 1 == pieces.len() {
+            CHAPTER_PAUSE
+        } else if *paragraph_end {
+            PARAGRAPH_PAUSE
+        } else {
+            0.0
+        };
+        let silence = (pause * sample_rate as f32) as usize;
+
+        let mut data = Vec::with_capacity((audio.len() + silence) * 2);
+        for sample in audio {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            data.extend(sample.to_le_bytes());
+        }
+        data.resize(data.len() + silence * 2, 0);
+        file.write_all(&data)?;
+        file.sync_data()?;
+        bytes += data.len() as u64;
+        part.write_progress(i + 1, bytes)?;
+    }
+    Ok(true)
+}
+
+/// Split chapter text into pieces of whole sentences, never across
+/// paragraphs, each flagged with whether it ends a paragraph
+fn pieces(text: &str) -> Vec<(String, bool)> {
+    let mut pieces = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut piece = String::new();
+        for sentence in segment::split_sentences(paragraph) {
+            if !piece.is_empty() && piece.len() + sentence.len() > MAX_PIECE_CHARS {
+                pieces.push((std::mem::take(&mut piece), false));
+            }
+            if !piece.is_empty() {
+                piece.push(' ');
+            }
+            piece.push_str(sentence);
+        }
+        if !piece.is_empty() {
+            pieces.push((piece, true));
+        }
+    }
+    pieces
+}
+
+/// FNV-1a over everything that changes the rendered audio
+fn fingerprint(book: &Book, job: &BookJob, sample_rate: u32) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes.iter().chain([&0u8]) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(job.model_path.as_bytes());
+    feed(job.lan.as_bytes());
+    feed(job.style.as_bytes());
+    feed(&job.speed.to_le_bytes());
+    feed(format!("{:?}", job.initial_silence).as_bytes());
+    feed(format!("{:?}", job.effects).as_bytes());
+    feed(&sample_rate.to_le_bytes());
+    for chapter in &book.chapters {
+        feed(chapter.title.as_bytes());
+        feed(chapter.text.as_bytes());
+    }
+    hash
+}
+
+/// Feed the part files to `sink` in blocks of samples
+fn read_parts(
+    parts: &[Part],
+    mut sink: impl FnMut(&[i16]) -> Result<(), KokorosError>,
+) -> Result<(), KokorosError> {
+    let mut bytes = vec![0u8; BLOCK_SAMPLES * 2];
+    for part in parts {
+        let mut reader = BufReader::new(File::open(&part.pcm)?);
+        loop {
+            let mut len = 0;
+            while len < bytes.len() {
+                match reader.read(&mut bytes[len..])? {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            if len == 0 {
+                break;
+            }
+            let samples: Vec<i16> = bytes[..len]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            sink(&samples)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_mp3(
+    path: &Path,
+    metadata: &AudioMetadata,
+    parts: &[Part],
+    sample_rate: u32,
+) -> Result<(), KokorosError> {
+    let encoding = |e: std::io::Error| KokorosError::Encoding(e.to_string());
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&metadata.id3v2())?;
+    let mut encoder = Mp3Stream::new(sample_rate).map_err(encoding)?;
+    read_parts(parts, |samples| {
+        out.write_all(&encoder.encode(samples).map_err(encoding)?)?;
+        Ok(())
+    })?;
+    out.write_all(&encoder.finish().map_err(encoding)?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// M4B needs an AAC encoder and an MP4 muxer, so it is left to ffmpeg
+fn write_m4b(
+    path: &Path,
+    metadata: &AudioMetadata,
+    parts: &[Part],
+    sample_rate: u32,
+    work_dir: &Path,
+) -> Result<(), KokorosError> {
+    let chapters = work_dir.join("chapters.txt");
+    fs::write(&chapters, metadata.ffmetadata())?;
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "s16le", "-ac", "1", "-ar"])
+        .arg(sample_rate.to_string())
+        .args(["-i", "-", "-i"])
+        .arg(&chapters)
+        .args([
+            "-map",
+            "0:a",
+            "-map_metadata",
+            "1",
+            "-map_chapters",
+            "1",
+            "-c:a",
+            "aac",
+            "-b:a",
+            "64k",
+            "-f",
+            "mp4",
+        ])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            KokorosError::Encoding(format!(
+                "M4B output needs ffmpeg on the PATH ({}); write an .mp3 instead",
+                e
+            ))
+        })?;
+
+    let mut stdin = ffmpeg.stdin.take().expect("ffmpeg stdin is piped");
+    let fed = read_parts(parts, |samples| {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        stdin.write_all(&bytes)?;
+        Ok(())
+    });
+    drop(stdin);
+    let status = ffmpeg.wait()?;
+    fed?;
+    if !status.success() {
+        return Err(KokorosError::Encoding(format!(
+            "ffmpeg exited with {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// A chapter title usable as a file name
+fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(60)
+        .collect();
+    name.trim().trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pieces() {
+        let text = "One. Two.\n\nThree.";
+        assert_eq!(
+            pieces(text),
+            [
+                ("One. Two.".to_string(), true),
+                ("Three.".to_string(), true)
+            ]
+        );
+
+        // Long paragraphs break between sentences, the last piece ends it
+        let sentence = format!("Some{}.", " words".repeat(60));
+        let long = [sentence.as_str(); 8].join(" ");
+        let split = pieces(&long);
+        assert!(split.len() > 1);
+        assert!(
+            split
+                .iter()
+                .all(|(piece, _)| piece.len() <= MAX_PIECE_CHARS)
+        );
+        let ends: Vec<bool> = split.iter().map(|(_, end)| *end).collect();
+        assert_eq!(ends.last(), Some(&true));
+        assert!(ends[..ends.len() - 1].iter().all(|end| !end));
+        assert_eq!(
+            split
+                .iter()
+                .map(|(p, _)| p.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            long
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let book = Book {
+            title: Some("Title".to_string()),
+            author: None,
+            chapters: vec![Chapter {
+                title: "One".to_string(),
+                text: "Hello.".to_string(),
+            }],
+        };
+        let path = Path::new("book.txt");
+        let job = BookJob {
+            input: path,
+            output: path,
+            title: None,
+            author: None,
+            restart: false,
+            model_path: "model.onnx",
+            lan: "en-us",
+            style: "af_sky",
+            speed: 1.0,
+            initial_silence: None,
+            effects: Effects::default(),
+            frontend: TextFrontend::default(),
+            document_options: DocumentOptions::default(),
+        };
+        let print = fingerprint(&book, &job, 24000);
+        assert_eq!(print, fingerprint(&book, &job, 24000));
+
+        let faster = BookJob { speed: 1.2, ..job };
+        assert_ne!(print, fingerprint(&book, &faster, 24000));
+        let mut edited = book.clone();
+        edited.chapters[0].text.push_str(" Bye.");
+        assert_ne!(print, fingerprint(&edited, &faster, 24000));
+        assert_ne!(print, fingerprint(&book, &faster, 22050));
+    }
+
+    #[test]
+    fn test_resume() {
+        let dir = std::env::temp_dir().join(format!("kokoros-book-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let part = Part::new(&dir, 0);
+        let pieces: Vec<(String, bool)> = ["A.", "B.", "C."]
+            .iter()
+            .map(|text| (text.to_string(), false))
+            .collect();
+        let cancelled = AtomicBool::new(false);
+        // Four samples a piece, no pause but the chapter's at the end
+        let rate = 10;
+        let tone = |_: usize, _: &str| Ok(vec![0.5; 4]);
+
+        // The first piece finished, the second was cut off mid-write
+        write_pieces(&part, &pieces[..1], rate, &cancelled, tone).unwrap();
+        fs::write(&part.progress, "1 8\n").unwrap();
+        let mut pcm = fs::read(&part.pcm).unwrap();
+        pcm.truncate(8);
+        pcm.extend([1, 2, 3]);
+        fs::write(&part.pcm, &pcm).unwrap();
+
+        // A cancelled run leaves the part unfinished
+        let stop = AtomicBool::new(true);
+        assert!(!write_pieces(&part, &pieces, rate, &stop, tone).unwrap());
+
+        let mut rendered = Vec::new();
+        let finished = write_pieces(&part, &pieces, rate, &cancelled, |i, text| {
+            rendered.push((i, text.to_string()));
+            Ok(vec![0.5; 4])
+        })
+        .unwrap();
+        assert!(finished);
+        assert_eq!(rendered, [(1, "B.".to_string()), (2, "C.".to_string())]);
+        let silence = (CHAPTER_PAUSE * rate as f32) as u64;
+        let len = 3 * 4 * 2 + silence * 2;
+        assert_eq!(fs::metadata(&part.pcm).unwrap().len(), len);
+        assert_eq!(part.read_progress(), (3, len));
+
+        // A finished part is left alone
+        let finished = write_pieces(&part, &pieces, rate, &cancelled, |_, _| {
+            panic!("rendered again")
+        })
+        .unwrap();
+        assert!(finished);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
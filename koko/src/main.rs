@@ -1,15 +1,39 @@
+mod book;
+
 use clap::{Parser, Subcommand};
 use kokoros::{
-    tts::koko::{TTSKoko, TTSOpts},
-    utils::wav::{WavHeader, write_audio_chunk},
+    onn::{
+        ort_base::OrtBase, ort_koko::OrtKoko, providers::ExecutionProvider, signature::TensorNames,
+    },
+    tts::{
+        config::ModelConfig,
+        design::{Slider, VoiceDesign},
+        detect,
+        dialogue::{DialogueOptions, Script, SpeakerWord},
+        document::{BlockPolicy, DocumentOptions, InputFormat, SpokenDocument},
+        fit::{FitOptions, Remainder},
+        frontend::TextFrontend,
+        incremental::{IncrementalOptions, IncrementalTTS},
+        koko::{InitConfig, TTSKoko, TTSOpts, WordAlignment},
+        prosody,
+        semiotic::ReadAloudOptions,
+        subtitles,
+        voices::{
+            self, Blend, Gender, StyleTable, VoiceFilter, VoiceInfo, VoiceLibrary, VoiceStats,
+        },
+    },
+    utils::{
+        dsp::Effects,
+        wav::{WavHeader, write_audio_chunk},
+    },
 };
 use std::net::{IpAddr, SocketAddr};
 use std::{
     fs::{self},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
 };
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use tracing_subscriber::fmt::time::FormatTime;
 
 /// Custom Unix timestamp formatter for tracing logs
@@ -46,6 +70,14 @@ enum Mode {
             default_value = "tmp/output.wav"
         )]
         save_path: String,
+
+        /// Fit the speech into this many seconds by choosing its speed
+        #[arg(long = "target-duration", value_name = "SECONDS")]
+        target_duration: Option<f32>,
+
+        /// Read `*word*` as emphasis: slower, after a short beat
+        #[arg(long = "emphasis", conflicts_with = "target_duration")]
+        emphasis: bool,
     },
 
     /// Read from a file path and generate a speech file for each line
@@ -64,9 +96,102 @@ enum Mode {
         save_path_format: String,
     },
 
+    /// Render a plain text, markdown or EPUB book with chapter markers.
+    /// Interrupted runs resume where they stopped when run again
+    Book {
+        /// Book to read (.txt, .md or .epub)
+        input_path: String,
+
+        /// Where to write the book: an .mp3 with ID3 chapters, an .m4b
+        /// (needs ffmpeg), or a directory for one MP3 per chapter
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "OUTPUT_PATH",
+            default_value = "tmp/book.mp3"
+        )]
+        save_path: String,
+
+        /// Book title, instead of the one found in the book or its file name
+        #[arg(long = "title")]
+        title: Option<String>,
+
+        /// Book author, instead of the one found in the book
+        #[arg(long = "author")]
+        author: Option<String>,
+
+        /// Discard the progress of an earlier run and start over
+        #[arg(long = "restart", default_value_t = false)]
+        restart: bool,
+    },
+
+    /// Render a multi-speaker script into one recording. Lines are
+    /// `SPEAKER: text`, voices are declared with `@SPEAKER = VOICE speed=1.1
+    /// lan=en-gb pan=-0.5` lines or `--speaker`; JSON scripts are read too
+    Dialogue {
+        /// Script to read
+        input_path: String,
+
+        /// Path to output the WAV file to, stereo when a speaker is panned
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "OUTPUT_PATH",
+            default_value = "tmp/dialogue.wav"
+        )]
+        save_path: String,
+
+        /// Declare or override a speaker, e.g. `--speaker "HOST=af_sarah pan=-0.4"`
+        #[arg(long = "speaker", value_name = "NAME=VOICE [speed=S] [lan=L] [pan=P]")]
+        speakers: Vec<String>,
+
+        /// Silence between turns in seconds
+        #[arg(long = "gap", value_name = "SECONDS", default_value_t = 0.4)]
+        gap: f32,
+    },
+
+    /// Dub an SRT or WebVTT file: every cue is spoken within its slot and
+    /// placed at its start time
+    #[command(alias = "dub")]
+    Subtitles {
+        /// Subtitles to read (.srt or .vtt)
+        input_path: String,
+
+        /// Path to output the WAV file to
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "OUTPUT_PATH",
+            default_value = "tmp/subtitles.wav"
+        )]
+        save_path: String,
+    },
+
     /// Continuously read from stdin to generate speech, outputting to stdout, for each line
     #[command(aliases = ["stdio", "stdin", "-"], long_flag_aliases = ["stdio", "stdin"])]
-    Stream,
+    Stream {
+        /// Read stdin as running text, such as the tokens of a language
+        /// model, and speak each sentence as soon as it is complete and
+        /// each line as soon as it ends
+        #[arg(long = "incremental")]
+        incremental: bool,
+
+        /// With --incremental, speak the first clause once it has this many
+        /// words instead of waiting for the first sentence; 0 always waits
+        #[arg(long = "first-chunk-words", value_name = "WORDS", default_value_t = 4)]
+        first_chunk_words: usize,
+    },
+
+    /// List, inspect, compare, blend, design, import and export voices. Only a design
+    /// preview loads the model
+    Voices {
+        #[command(subcommand)]
+        action: VoicesAction,
+    },
+
+    /// Print the tensor signature of the ONNX model and exit
+    #[command(name = "model-info")]
+    ModelInfo,
 
     /// Start an OpenAI-compatible HTTP server
     #[command(name = "openai", alias = "oai", long_flag_aliases = ["oai", "openai"])]
@@ -78,16 +203,150 @@ enum Mode {
         /// Port to expose the HTTP server on
         #[arg(long, default_value_t = 3000)]
         port: u16,
+
+        /// JSON server configuration, e.g. voice aliases with default speed,
+        /// language and lexicon
+        #[arg(long = "config", value_name = "CONFIG_PATH")]
+        config: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum VoicesAction {
+    /// List voices with their language, gender and source
+    #[command(alias = "ls")]
+    List {
+        /// Only list voices made for this language, e.g. `en`, `en-gb` or `ja`
+        #[arg(long = "language", value_name = "LANGUAGE")]
+        language: Option<String>,
+    },
+
+    /// List the voices whose style vectors are nearest to a voice's
+    Similar {
+        /// A voice, or a blend such as `af_sarah:0.4,af_nicole:0.6`
+        voice: String,
+
+        /// Only voices of this language, e.g. `en-gb` or `en`, or accent,
+        /// e.g. `British`
+        #[arg(long = "language", value_name = "LANGUAGE")]
+        language: Option<String>,
+
+        /// Only `female` or `male` voices
+        #[arg(long = "gender", value_name = "GENDER")]
+        gender: Option<Gender>,
+
+        /// How many voices to list
+        #[arg(short = 'n', long = "limit", default_value_t = 5)]
+        limit: usize,
+    },
+
+    /// Show statistics of a voice's style vectors
+    Info {
+        /// A voice, or a blend such as `af_sarah:0.4,af_nicole:0.6`
+        voice: String,
+    },
+
+    /// Save a blend of voices under a new name, usable like any built-in voice
+    Blend {
+        /// Name of the new voice: lowercase letters, digits and _
+        name: String,
+
+        /// Voices and weights, e.g. `af_sarah:0.4,af_nicole:0.6`
+        blend: String,
+    },
+
+    /// Write voices to a `.npy` array, a raw `.bin` voice file or an `.npz` pack
+    Export {
+        /// Voices or blends to export; all voices when writing an `.npz` pack
+        voices: Vec<String>,
+
+        /// Output file; its extension picks the format
+        #[arg(short = 'o', long = "output", value_name = "OUTPUT_PATH")]
+        output: PathBuf,
+    },
+
+    /// Save voices from a `.npy` array, a raw `.bin` voice file, an `.npz`
+    /// pack or a directory of voice files to the user voices
+    Import {
+        /// File or directory to import
+        path: PathBuf,
+
+        /// Name of the imported voice; defaults to the file name. Only for a
+        /// single voice
+        #[arg(long = "name", value_name = "NAME")]
+        name: Option<String>,
+    },
+
+    /// Delete a saved voice
+    #[command(alias = "rm")]
+    Remove {
+        /// Name of the saved voice
+        name: String,
+    },
+
+    /// List the axes of the built-in voices that `design` moves along
+    Axes,
+
+    /// Make a new voice from the built-in voices: start from a base, add
+    /// seeded random variation and move along axes
+    Design {
+        /// Save the voice under this name; without it the voice is only
+        /// described and previewed
+        name: Option<String>,
+
+        /// Voice or blend to start from; defaults to the mean built-in voice
+        #[arg(long = "base", value_name = "VOICE")]
+        base: Option<String>,
+
+        /// Seed of the random variation; the same seed gives the same voice
+        #[arg(long = "seed", value_name = "SEED")]
+        seed: Option<u64>,
+
+        /// Size of the random variation, in standard deviations
+        #[arg(long = "variation", value_name = "AMOUNT", default_value_t = 1.0)]
+        variation: f32,
+
+        /// Move along an axis in standard deviations, e.g. `gender=-1.5` or
+        /// `pc2=0.8`; may be repeated
+        #[arg(long = "axis", value_name = "AXIS=VALUE")]
+        sliders: Vec<Slider>,
+
+        /// Speak this text with the new voice (loads the model)
+        #[arg(long = "preview", value_name = "TEXT")]
+        preview: Option<String>,
+
+        /// Where to write the preview
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "OUTPUT_PATH",
+            default_value = "tmp/design.wav"
+        )]
+        output: String,
     },
 }
 
+/// Voice name of an unsaved design while it is previewed
+const PREVIEW_VOICE: &str = "design_preview";
+
+/// A designed voice to speak once the model is loaded
+struct DesignPreview {
+    voice: String,
+    table: Option<StyleTable>,
+    text: String,
+    output: String,
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "kokoros")]
 #[command(version = "0.1")]
 #[command(author = "Lucas Jin")]
 struct Cli {
     /// A language identifier from
-    /// https://github.com/espeak-ng/espeak-ng/blob/master/docs/languages.md
+    /// https://github.com/espeak-ng/espeak-ng/blob/master/docs/languages.md.
+    /// `zh` uses the pinyin front end with Kokoro v1.1-zh models. `auto`
+    /// detects the language of every sentence, `auto+voice` also switches to
+    /// a voice of the detected language
     #[arg(
         short = 'l',
         long = "lan",
@@ -114,17 +373,22 @@ struct Cli {
     )]
     data_path: String,
 
-    /// Which single voice to use or voices to combine to serve as the style of speech
+    /// Which single voice to use or voices to combine to serve as the style of speech.
+    /// `af_sarah:0.4,af_nicole:0.6` blends 0.4*af_sarah + 0.6*af_nicole, with
+    /// weights normalized to sum to 1; the older `af_sarah.4+af_nicole.6` works too
     #[arg(
         short = 's',
         long = "style",
         value_name = "STYLE",
-        // if users use `af_sarah.4+af_nicole.6` as style name
-        // then we blend it, with 0.4*af_sarah + 0.6*af_nicole
-        default_value = "af_sarah.4+af_nicole.6"
+        default_value = "af_sarah:0.4,af_nicole:0.6"
     )]
     style: String,
 
+    /// Directory of saved voices, loaded next to the voices file. Defaults to
+    /// $KOKOROS_VOICES_DIR, else $XDG_DATA_HOME/kokoros/voices or ~/.local/share/kokoros/voices
+    #[arg(long = "user-voices", value_name = "DIR", global = true)]
+    user_voices: Option<PathBuf>,
+
     /// Rate of speech, as a coefficient of the default
     /// (i.e. 0.0 to 1.0 is slower than default,
     /// whereas 1.0 and beyond is faster than default)
@@ -136,6 +400,46 @@ struct Cli {
     )]
     speed: f32,
 
+    /// Shift the pitch of the speech by this many semitones (-12 to 12),
+    /// moving its formants along
+    #[arg(
+        long = "pitch",
+        value_name = "SEMITONES",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    pitch: f32,
+
+    /// Change the pace of the speech (0.5 to 2.0) without changing its
+    /// pitch or, unlike --speed, its prosody
+    #[arg(
+        long = "tempo",
+        value_name = "TEMPO",
+        default_value_t = 1.0,
+        global = true
+    )]
+    tempo: f32,
+
+    /// How close to a target duration, in seconds, counts as a fit
+    #[arg(
+        long = "duration-tolerance",
+        value_name = "SECONDS",
+        default_value_t = 0.1,
+        global = true
+    )]
+    duration_tolerance: f32,
+
+    /// What to do when speed alone doesn't reach a target duration: keep the
+    /// speech as is, pad it with silence, or time-stretch it
+    #[arg(
+        long = "fit",
+        value_name = "REMAINDER",
+        default_value_t = Remainder::Pad,
+        global = true
+    )]
+    fit: Remainder,
+
     /// Output audio in mono (as opposed to stereo)
     #[arg(long = "mono", default_value_t = false)]
     mono: bool,
@@ -152,6 +456,71 @@ struct Cli {
     #[arg(long = "instances", value_name = "INSTANCES", default_value_t = 2)]
     instances: usize,
 
+    /// Comma separated list of ONNX Runtime execution providers to try in order
+    /// (cpu, xnnpack, cuda, tensorrt, openvino, coreml, directml).
+    /// Providers that are unavailable fall back to the next one, and finally to the CPU
+    #[arg(
+        long = "provider",
+        value_name = "PROVIDERS",
+        value_delimiter = ',',
+        global = true
+    )]
+    providers: Vec<ExecutionProvider>,
+
+    /// Override the tensor name used for a role in custom model exports,
+    /// e.g. `--tensor-name tokens=input_ids`. Roles: tokens, style, speed, audio, durations
+    #[arg(long = "tensor-name", value_name = "ROLE=NAME", global = true)]
+    tensor_names: Vec<String>,
+
+    /// Path to the model's config.json (vocab, sample rate, hop length).
+    /// Defaults to a config.json next to the model file, or the Kokoro v1.0 values
+    #[arg(long = "model-config", value_name = "MODEL_CONFIG", global = true)]
+    model_config: Option<String>,
+
+    /// How URLs, e-mails, paths, hashtags, emoji and code are read, as
+    /// `CLASS=POLICY` pairs, e.g. `--read-aloud urls=spell,emoji=skip`.
+    /// Policies: keep, spell, short, skip; `all=keep` turns the rules off
+    #[arg(
+        long = "read-aloud",
+        value_name = "CLASS=POLICY",
+        value_delimiter = ',',
+        global = true
+    )]
+    read_aloud: Vec<String>,
+
+    /// Read units of measure in full, e.g. `10km` as `10 kilometers` (English only)
+    #[arg(long = "units", default_value_t = false, global = true)]
+    units: bool,
+
+    /// Markup of the input: text, markdown, html or subtitles. Markup is
+    /// stripped and the document structure read as pauses; subtitles are
+    /// read without their timing, which the `subtitles` command keeps
+    #[arg(
+        long = "input-format",
+        value_name = "FORMAT",
+        default_value_t = InputFormat::Text,
+        global = true
+    )]
+    input_format: InputFormat,
+
+    /// How code blocks in markdown or html input are read: read, summarize or skip
+    #[arg(
+        long = "code-blocks",
+        value_name = "POLICY",
+        default_value_t = BlockPolicy::Summarize,
+        global = true
+    )]
+    code_blocks: BlockPolicy,
+
+    /// How tables in markdown or html input are read: read, summarize or skip
+    #[arg(
+        long = "tables",
+        value_name = "POLICY",
+        default_value_t = BlockPolicy::Read,
+        global = true
+    )]
+    tables: BlockPolicy,
+
     #[command(subcommand)]
     mode: Mode,
 }
@@ -181,19 +550,60 @@ fn write_wav_file(
     use std::io::Write;
 
     let channels: u16 = if mono { 1 } else { 2 };
+    let mut f = File::create(path)?;
+    write_wav_header(
+        &mut f,
+        channels,
+        sample_rate,
+        samples.len() * channels as usize,
+    )?;
+
+    // write samples
+    if mono {
+        for &s in samples {
+            f.write_all(&s.to_le_bytes())?;
+        }
+    } else {
+        for &s in samples {
+            f.write_all(&s.to_le_bytes())?; // left
+            f.write_all(&s.to_le_bytes())?; // right (duplicate for simple stereo)
+        }
+    }
+
+    Ok(())
+}
+
+/// Write samples that are already interleaved for `channels`
+fn write_interleaved_wav(
+    path: &str,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> std::io::Result<()> {
+    let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+    write_wav_header(&mut f, channels, sample_rate, samples.len())?;
+    for &s in samples {
+        f.write_all(&s.to_le_bytes())?;
+    }
+    f.flush()
+}
+
+/// RIFF header for `total_samples` 32-bit float samples over all channels
+fn write_wav_header(
+    f: &mut impl Write,
+    channels: u16,
+    sample_rate: u32,
+    total_samples_to_write: usize,
+) -> std::io::Result<()> {
     let bits_per_sample: u16 = 32; // f32
     let bytes_per_sample: u32 = (bits_per_sample as u32) / 8;
     let block_align: u16 = channels * bits_per_sample / 8;
     let byte_rate: u32 = sample_rate * (block_align as u32);
 
     // Data size in bytes
-    let num_frames: usize = samples.len();
-    let total_samples_to_write: usize = if mono { num_frames } else { num_frames * 2 };
     let data_size: u32 = (total_samples_to_write as u32) * bytes_per_sample;
     let riff_chunk_size: u32 = 36 + data_size; // 4 + (8+16) + (8+data)
 
-    let mut f = File::create(path)?;
-
     // RIFF header
     f.write_all(b"RIFF")?;
     f.write_all(&riff_chunk_size.to_le_bytes())?;
@@ -212,35 +622,273 @@ fn write_wav_file(
     // data chunk
     f.write_all(b"data")?;
     f.write_all(&data_size.to_le_bytes())?;
+    Ok(())
+}
 
-    // write samples
-    if mono {
-        for &s in samples {
-            f.write_all(&s.to_le_bytes())?;
-        }
-    } else {
-        for &s in samples {
-            f.write_all(&s.to_le_bytes())?; // left
-            f.write_all(&s.to_le_bytes())?; // right (duplicate for simple stereo)
-        }
+/// Word timestamps of a dialogue, with the speaker of each word
+fn write_dialogue_tsv(path: &str, words: &[SpeakerWord]) -> std::io::Result<()> {
+    let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+    f.write_all(b"speaker\tword\tstart_sec\tend_sec\n")?;
+    for w in words {
+        writeln!(
+            f,
+            "{}\t{}\t{:.3}\t{:.3}",
+            w.speaker, w.word.word, w.word.start_sec, w.word.end_sec
+        )?;
     }
-
-    Ok(())
+    f.flush()
 }
 
-fn write_tsv(path: &str, alignments: &[(String, f32, f32)]) -> std::io::Result<()> {
+/// Write word timestamps; `with_source` adds the byte range of each word in
+/// the markdown or html source, empty where it is unknown
+fn write_tsv(path: &str, alignments: &[WordAlignment], with_source: bool) -> std::io::Result<()> {
     use std::fs::File;
     use std::io::Write;
     let mut f = File::create(path)?;
-    f.write_all(b"word\tstart_sec\tend_sec\n")?;
-    for (w, s, e) in alignments {
+    if with_source {
+        f.write_all(b"word\tstart_sec\tend_sec\tsource_start\tsource_end\n")?;
+    } else {
+        f.write_all(b"word\tstart_sec\tend_sec\n")?;
+    }
+    for w in alignments {
         // Use 3 decimal places by default
-        let line = format!("{}\t{:.3}\t{:.3}\n", w, s, e);
+        let mut line = format!("{}\t{:.3}\t{:.3}", w.word, w.start_sec, w.end_sec);
+        if with_source {
+            match &w.source {
+                Some(range) => line.push_str(&format!("\t{}\t{}", range.start, range.end)),
+                None => line.push_str("\t\t"),
+            }
+        }
+        line.push('\n');
         f.write_all(line.as_bytes())?;
     }
     Ok(())
 }
 
+/// Print the language and voice of every span when the language is detected
+fn report_languages(tts: &TTSKoko, text: &str, lan: &str, style: &str) {
+    if !detect::is_auto(lan) {
+        return;
+    }
+    for span in tts.detect_languages(text, lan, style) {
+        let preview: String = span.text.chars().take(40).collect();
+        eprintln!(
+            "Detected {} (voice {}): {}",
+            span.language, span.voice, preview
+        );
+    }
+}
+
+/// Run a voices action; a design to preview is returned for the caller to
+/// speak with the model
+fn run_voices(
+    library: &mut VoiceLibrary,
+    action: VoicesAction,
+) -> Result<Option<DesignPreview>, Box<dyn std::error::Error>> {
+    match action {
+        VoicesAction::List { language } => {
+            let filter = VoiceFilter {
+                language,
+                gender: None,
+            };
+            for VoiceInfo { meta, source } in library.list()? {
+                if !filter.matches(&meta) {
+                    continue;
+                }
+                println!(
+                    "{:<20} {:<8} {:<8} {:<12} {:<4} {}",
+                    meta.id,
+                    meta.language.as_deref().unwrap_or("-"),
+                    meta.gender
+                        .map_or("-".to_string(), |gender| gender.to_string()),
+                    meta.accent.as_deref().unwrap_or("-"),
+                    meta.quality.as_deref().unwrap_or("-"),
+                    source
+                );
+            }
+        }
+
+        VoicesAction::Similar {
+            voice,
+            language,
+            gender,
+            limit,
+        } => {
+            let filter = VoiceFilter { language, gender };
+            for neighbour in library.similar(&voice, &filter, limit)? {
+                let meta = &neighbour.meta;
+                println!(
+                    "{:<20} {:>8.4}   {:<8} {:<8} {}",
+                    meta.id,
+                    neighbour.distance,
+                    meta.language.as_deref().unwrap_or("-"),
+                    meta.gender
+                        .map_or("-".to_string(), |gender| gender.to_string()),
+                    meta.accent.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+
+        VoicesAction::Info { voice } => {
+            let stats = if Blend::is_blend(&voice) {
+                println!("Blend: {}", voice.parse::<Blend>()?);
+                VoiceStats::of(&library.mix(&voice)?)
+            } else {
+                let (VoiceInfo { meta, source }, stats) = library.info(&voice)?;
+                println!("Voice: {} ({})", meta.id, meta.name);
+                println!(
+                    "Language: {}",
+                    meta.language.as_deref().unwrap_or("unknown")
+                );
+                if let Some(gender) = meta.gender {
+                    println!("Gender: {}", gender);
+                }
+                if let Some(accent) = meta.accent {
+                    println!("Accent: {}", accent);
+                }
+                if let Some(quality) = meta.quality {
+                    println!("Quality: {}", quality);
+                }
+                if !meta.aliases.is_empty() {
+                    println!("Aliases: {}", meta.aliases.join(", "));
+                }
+                println!("Source: {}", source);
+                stats
+            };
+            println!("Rows: {} x 256", stats.rows);
+            println!(
+                "Values: mean {:.4}, std {:.4}, min {:.4}, max {:.4}",
+                stats.mean, stats.std, stats.min, stats.max
+            );
+            println!("Mean row norm: {:.4}", stats.norm);
+        }
+
+        VoicesAction::Blend { name, blend } => {
+            let table = library.mix(&blend)?;
+            let path = library.save(&name, table)?;
+            eprintln!("Voice '{}' saved to {}", name, path.display());
+        }
+
+        VoicesAction::Export { voices, output } => {
+            if output.extension().is_some_and(|ext| ext == "npz") {
+                let names = if voices.is_empty() {
+                    library
+                        .list()?
+                        .into_iter()
+                        .map(|voice| voice.meta.id)
+                        .collect()
+                } else {
+                    voices
+                };
+                let tables = names
+                    .iter()
+                    .map(|name| library.mix(name))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let pack: Vec<_> = names.iter().map(String::as_str).zip(&tables).collect();
+                voices::write_pack(&output, &pack)?;
+                eprintln!("{} voices exported to {}", pack.len(), output.display());
+            } else {
+                let [voice] = voices.as_slice() else {
+                    return Err(
+                        "export one voice per .npy or .bin file, or use an .npz pack".into(),
+                    );
+                };
+                voices::write_tensor(&output, &library.mix(voice)?)?;
+                eprintln!("Voice '{}' exported to {}", voice, output.display());
+            }
+        }
+
+        VoicesAction::Import { path, name } => {
+            let rows = library.rows();
+            let imported = if path.is_dir() || path.extension().is_some_and(|ext| ext == "npz") {
+                if name.is_some() {
+                    return Err("--name only applies when importing a single voice".into());
+                }
+                let mut pack: Vec<_> = voices::read_pack(&path, rows)?.into_iter().collect();
+                pack.sort_by(|a, b| a.0.cmp(&b.0));
+                pack
+            } else {
+                let name = match name {
+                    Some(name) => name,
+                    None => path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_lowercase())
+                        .ok_or("the file has no name, pass --name")?,
+                };
+                vec![(name, voices::read_tensor(&path, rows)?)]
+            };
+            for (name, table) in imported {
+                let saved = library.save(&name, table)?;
+                eprintln!("Voice '{}' saved to {}", name, saved.display());
+            }
+        }
+
+        VoicesAction::Remove { name } => {
+            let path = library.remove(&name)?;
+            eprintln!("Voice '{}' removed from {}", name, path.display());
+        }
+
+        VoicesAction::Axes => {
+            let space = library.style_space()?;
+            for axis in space.axes() {
+                println!(
+                    "{:<8} {:>6}   {} .. {}",
+                    axis.name,
+                    axis.explained
+                        .map_or("-".to_string(), |share| format!("{:.1}%", share * 100.0)),
+                    axis.extremes.0,
+                    axis.extremes.1
+                );
+            }
+        }
+
+        VoicesAction::Design {
+            name,
+            base,
+            seed,
+            variation,
+            sliders,
+            preview,
+            output,
+        } => {
+            let space = library.style_space()?;
+            let base = base.map(|base| library.mix(&base)).transpose()?;
+            let design = VoiceDesign {
+                seed,
+                variation,
+                sliders,
+            };
+            let table = space.design(&design, base.as_deref())?;
+            let positions: Vec<String> = space
+                .axes()
+                .iter()
+                .take(4)
+                .map(|axis| format!("{} {:+.2}", axis.name, axis.position(&space, &table)))
+                .collect();
+            eprintln!("Designed voice: {}", positions.join(", "));
+
+            let voice = match name {
+                Some(name) => {
+                    let path = library.save(&name, table.clone())?;
+                    eprintln!("Voice '{}' saved to {}", name, path.display());
+                    name
+                }
+                None => PREVIEW_VOICE.to_string(),
+            };
+            if let Some(text) = preview {
+                let table = (voice == PREVIEW_VOICE).then_some(table);
+                return Ok(Some(DesignPreview {
+                    voice,
+                    table,
+                    text,
+                    output,
+                }));
+            }
+        }
+    }
+    Ok(None)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing with Unix timestamp format and environment-based log level
     tracing_subscriber::fmt()
@@ -260,14 +908,124 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             data_path,
             style,
             speed,
+            pitch,
+            tempo,
+            duration_tolerance,
+            fit,
             initial_silence,
             mono,
             timestamps,
             instances,
+            providers,
+            tensor_names,
+            model_config,
+            user_voices,
+            read_aloud,
+            units,
+            input_format,
+            code_blocks,
+            tables,
             mode,
         } = Cli::parse();
+        let frontend = TextFrontend {
+            read_aloud: ReadAloudOptions::from_pairs(&read_aloud)?,
+            units,
+            ..TextFrontend::default()
+        };
+        let document_options = DocumentOptions {
+            code_blocks,
+            tables,
+        };
+        let with_source = input_format != InputFormat::Text;
+        let fit_options = FitOptions {
+            tolerance: duration_tolerance,
+            remainder: fit,
+            initial_silence,
+            timestamps,
+        };
+
+        let effects = Effects::new(pitch, tempo)?;
+
+        let init_config = InitConfig {
+            providers: if providers.is_empty() {
+                ExecutionProvider::default_order()
+            } else {
+                providers
+            },
+            tensor_names: TensorNames::from_pairs(&tensor_names)?,
+            model_config_path: model_config,
+            user_voices_path: user_voices.or_else(voices::default_user_dir),
+            ..InitConfig::default()
+        };
+
+        if let Mode::ModelInfo = mode {
+            let model = OrtKoko::new(
+                model_path.clone(),
+                &init_config.providers,
+                &init_config.tensor_names,
+            )?;
+            println!("Model: {}", model_path);
+            if let Some(provider) = model.provider() {
+                println!("Execution provider: {}", provider);
+            }
+            if let Some(signature) = model.signature() {
+                println!("{}", signature);
+            }
+            let config =
+                ModelConfig::load_for_model(&model_path, init_config.model_config_path.as_deref())?;
+            println!(
+                "Config: {} vocab symbols, {} Hz, hop {} ({} frames/s), {} tokens per chunk",
+                config.vocab.len(),
+                config.sample_rate,
+                config.hop_length,
+                config.frames_per_sec(),
+                config.max_tokens
+            );
+            return Ok(());
+        }
+
+        let mode = match mode {
+            Mode::Voices { action } => {
+                let config = ModelConfig::load_for_model(
+                    &model_path,
+                    init_config.model_config_path.as_deref(),
+                )?;
+                let mut library = VoiceLibrary::open(
+                    Path::new(&data_path),
+                    init_config.user_voices_path.clone(),
+                    config.style_table_len,
+                )?;
+                let Some(preview) = run_voices(&mut library, action)? else {
+                    return Ok(());
+                };
+                let mut tts =
+                    TTSKoko::from_config(&model_path, &data_path, init_config.clone()).await?;
+                if let Some(table) = preview.table {
+                    tts.add_voice(&preview.voice, table)?;
+                }
+                if let Some(dir) = Path::new(&preview.output).parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let text = frontend.apply(&preview.text, &lan);
+                let audio = tts.tts_raw_audio(
+                    &text,
+                    &lan,
+                    &preview.voice,
+                    speed,
+                    initial_silence,
+                    None,
+                    None,
+                    None,
+                )?;
+                let audio = effects.apply(&audio, tts.sample_rate());
+                write_wav_file(&preview.output, &audio, tts.sample_rate(), mono)?;
+                eprintln!("Preview saved to {}", preview.output);
+                return Ok(());
+            }
+            mode => mode,
+        };
 
-        let tts = TTSKoko::new(&model_path, &data_path).await;
+        let tts = TTSKoko::from_config(&model_path, &data_path, init_config.clone()).await?;
 
         match mode {
             Mode::File {
@@ -280,8 +1038,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     if stripped_line.is_empty() {
                         continue;
                     }
+                    let document =
+                        SpokenDocument::parse(stripped_line, input_format, &document_options);
+                    let stripped_line = &frontend.apply(&document.text, &lan);
 
                     let save_path = save_path_format.replace("{line}", &i.to_string());
+                    report_languages(&tts, stripped_line, &lan, &style);
                     if timestamps {
                         match tts.tts_timestamped_raw_audio(
                             stripped_line,
@@ -293,18 +1055,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             None,
                             None,
                         ) {
-                            Ok(Some((audio, words))) => {
+                            Ok(Some((audio, mut words))) => {
+                                let audio = effects.apply(&audio, tts.sample_rate());
+                                effects.retime(&mut words);
                                 // Write WAV
                                 // Note: current engine uses 24kHz
-                                write_wav_file(&save_path, &audio, 24_000, mono)?;
+                                write_wav_file(&save_path, &audio, tts.sample_rate(), mono)?;
 
                                 // Write TSV sidecar
                                 let tsv_path = derive_tsv_path_from_wav(&save_path);
-                                let rows: Vec<(String, f32, f32)> = words
-                                    .into_iter()
-                                    .map(|w| (w.word, w.start_sec, w.end_sec))
-                                    .collect();
-                                write_tsv(&tsv_path, &rows)?;
+                                document.locate(&mut words);
+                                write_tsv(&tsv_path, &words, with_source)?;
                                 eprintln!("Audio saved to {}", save_path);
                                 eprintln!("Timestamps saved to {}", tsv_path);
                             }
@@ -324,14 +1085,64 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             mono,
                             speed,
                             initial_silence,
+                            effects,
                         })?;
                     }
                 }
             }
 
-            Mode::Text { text, save_path } => {
+            Mode::Text {
+                text,
+                save_path,
+                target_duration,
+                emphasis,
+            } => {
                 let s = std::time::Instant::now();
-                if timestamps {
+                let document = SpokenDocument::parse(&text, input_format, &document_options);
+                let text = frontend.apply(&document.text, &lan);
+                report_languages(&tts, &text, &lan, &style);
+                if let Some(target) = target_duration {
+                    if !target.is_finite() || target <= 0.0 {
+                        return Err("--target-duration must be more than 0 seconds".into());
+                    }
+                    if tempo != 1.0 {
+                        return Err("--tempo can't be combined with --target-duration, \
+                                    which sets the pace itself"
+                            .into());
+                    }
+                    let mut fitted =
+                        tts.tts_fitted(&text, &lan, &style, speed, target, &fit_options)?;
+                    fitted.samples = effects.apply(&fitted.samples, tts.sample_rate());
+                    write_wav_file(&save_path, &fitted.samples, tts.sample_rate(), mono)?;
+                    eprintln!(
+                        "Fitted {:.2}s of speech into {:.2}s at speed {:.2}",
+                        fitted.duration_sec, target, fitted.speed
+                    );
+                    eprintln!("Audio saved to {}", save_path);
+                    if timestamps {
+                        let tsv_path = derive_tsv_path_from_wav(&save_path);
+                        document.locate(&mut fitted.words);
+                        write_tsv(&tsv_path, &fitted.words, with_source)?;
+                        eprintln!("Timestamps saved to {}", tsv_path);
+                    }
+                } else if emphasis {
+                    let mut segments = prosody::parse_emphasis(&document.text);
+                    for segment in &mut segments {
+                        segment.text = frontend.apply(&segment.text, &lan);
+                    }
+                    let (audio, mut words) =
+                        tts.tts_segments(&segments, &lan, &style, speed, initial_silence)?;
+                    let audio = effects.apply(&audio, tts.sample_rate());
+                    effects.retime(&mut words);
+                    write_wav_file(&save_path, &audio, tts.sample_rate(), mono)?;
+                    eprintln!("Audio saved to {}", save_path);
+                    if timestamps {
+                        let tsv_path = derive_tsv_path_from_wav(&save_path);
+                        document.locate(&mut words);
+                        write_tsv(&tsv_path, &words, with_source)?;
+                        eprintln!("Timestamps saved to {}", tsv_path);
+                    }
+                } else if timestamps {
                     match tts.tts_timestamped_raw_audio(
                         &text,
                         &lan,
@@ -342,14 +1153,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         None,
                         None,
                     ) {
-                        Ok(Some((audio, words))) => {
-                            write_wav_file(&save_path, &audio, 24_000, mono)?;
+                        Ok(Some((audio, mut words))) => {
+                            let audio = effects.apply(&audio, tts.sample_rate());
+                            effects.retime(&mut words);
+                            write_wav_file(&save_path, &audio, tts.sample_rate(), mono)?;
                             let tsv_path = derive_tsv_path_from_wav(&save_path);
-                            let rows: Vec<(String, f32, f32)> = words
-                                .into_iter()
-                                .map(|w| (w.word, w.start_sec, w.end_sec))
-                                .collect();
-                            write_tsv(&tsv_path, &rows)?;
+                            document.locate(&mut words);
+                            write_tsv(&tsv_path, &words, with_source)?;
                             eprintln!("Audio saved to {}", save_path);
                             eprintln!("Timestamps saved to {}", tsv_path);
                         }
@@ -369,6 +1179,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         mono,
                         speed,
                         initial_silence,
+                        effects,
                     })?;
                 }
                 println!("Time taken: {:?}", s.elapsed());
@@ -377,7 +1188,154 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("Words per second: {:.2}", words_per_second);
             }
 
-            Mode::OpenAI { ip, port } => {
+            Mode::Book {
+                input_path,
+                save_path,
+                title,
+                author,
+                restart,
+            } => {
+                // The voices are already loaded once; each further instance
+                // renders another chapter at the same time
+                let mut tts_instances = vec![tts];
+                for i in 1..instances.max(1) {
+                    tracing::info!(
+                        "Initializing TTS instance [{}] ({}/{})",
+                        format!("{:02x}", i),
+                        i + 1,
+                        instances
+                    );
+                    tts_instances.push(
+                        TTSKoko::from_config(&model_path, &data_path, init_config.clone()).await?,
+                    );
+                }
+                book::render(
+                    &tts_instances,
+                    &book::BookJob {
+                        input: Path::new(&input_path),
+                        output: Path::new(&save_path),
+                        title,
+                        author,
+                        restart,
+                        model_path: &model_path,
+                        lan: &lan,
+                        style: &style,
+                        speed,
+                        initial_silence,
+                        effects,
+                        frontend,
+                        document_options,
+                    },
+                )?;
+            }
+
+            Mode::Subtitles {
+                input_path,
+                save_path,
+            } => {
+                if tempo != 1.0 {
+                    return Err("--tempo can't be combined with subtitles, \
+                                whose cues set the pace"
+                        .into());
+                }
+                let mut cues = subtitles::parse(&fs::read_to_string(&input_path)?);
+                if cues.is_empty() {
+                    return Err(format!("no subtitle cues found in {}", input_path).into());
+                }
+                for cue in &mut cues {
+                    cue.text = frontend.apply(&cue.text, &lan);
+                }
+                let track = tts.tts_subtitles(&cues, &lan, &style, speed, &fit_options)?;
+                for cue in &track.cues {
+                    if cue.duration_sec > cue.slot_sec + duration_tolerance {
+                        eprintln!(
+                            "Cue {} runs {:.2}s over its {:.2}s slot even at speed {:.2}",
+                            cue.index + 1,
+                            cue.duration_sec - cue.slot_sec,
+                            cue.slot_sec,
+                            cue.speed
+                        );
+                    }
+                }
+                let samples = effects.apply(&track.samples, tts.sample_rate());
+                write_wav_file(&save_path, &samples, tts.sample_rate(), mono)?;
+                eprintln!("Audio saved to {}", save_path);
+                if timestamps {
+                    let tsv_path = derive_tsv_path_from_wav(&save_path);
+                    write_tsv(&tsv_path, &track.words, false)?;
+                    eprintln!("Timestamps saved to {}", tsv_path);
+                }
+            }
+
+            Mode::Dialogue {
+                input_path,
+                save_path,
+                speakers,
+                gap,
+            } => {
+                if !effects.is_neutral() {
+                    return Err("--pitch and --tempo don't apply to dialogues".into());
+                }
+                let mut declared = Script::default();
+                for speaker in &speakers {
+                    declared.declare(speaker)?;
+                }
+                let mut script = Script::parse_with(
+                    &fs::read_to_string(&input_path)?,
+                    !declared.speakers.is_empty(),
+                    |name| declared.speakers.contains_key(name) || tts.check_voice(name).is_ok(),
+                )?;
+                script.speakers.extend(declared.speakers);
+                for turn in &mut script.turns {
+                    let turn_lan = script
+                        .speakers
+                        .get(&turn.speaker)
+                        .and_then(|speaker| speaker.lan.as_deref())
+                        .unwrap_or(&lan);
+                    let document =
+                        SpokenDocument::parse(&turn.text, input_format, &document_options);
+                    turn.text = frontend.apply(&document.text, turn_lan);
+                }
+
+                let dialogue = tts.tts_dialogue(
+                    &script,
+                    &DialogueOptions {
+                        lan: lan.clone(),
+                        speed,
+                        gap,
+                        initial_silence,
+                        timestamps,
+                    },
+                )?;
+                write_interleaved_wav(
+                    &save_path,
+                    &dialogue.samples,
+                    tts.sample_rate(),
+                    dialogue.channels,
+                )?;
+                eprintln!(
+                    "Dialogue of {} turns saved to {}",
+                    script.turns.len(),
+                    save_path
+                );
+                if timestamps {
+                    let tsv_path = derive_tsv_path_from_wav(&save_path);
+                    write_dialogue_tsv(&tsv_path, &dialogue.words)?;
+                    eprintln!("Timestamps saved to {}", tsv_path);
+                }
+            }
+
+            Mode::ModelInfo | Mode::Voices { .. } => {
+                unreachable!("handled before the model is loaded")
+            }
+
+            Mode::OpenAI { ip, port, config } => {
+                let mut config = match config {
+                    Some(path) => kokoros_openai::ServerConfig::load(&path)?,
+                    None => kokoros_openai::ServerConfig::default(),
+                };
+                config.validate(&tts)?;
+
                 // Create multiple independent TTS instances for parallel processing
                 let mut tts_instances = Vec::new();
                 for i in 0..instances {
@@ -387,17 +1345,93 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         i + 1,
                         instances
                     );
-                    let instance = TTSKoko::new(&model_path, &data_path).await;
+                    let instance =
+                        TTSKoko::from_config(&model_path, &data_path, init_config.clone()).await?;
                     tts_instances.push(instance);
                 }
-                let app = kokoros_openai::create_server(tts_instances).await;
+                let app = kokoros_openai::create_server_with_config(tts_instances, config).await;
                 let addr = SocketAddr::from((ip, port));
                 let binding = tokio::net::TcpListener::bind(&addr).await?;
                 tracing::info!("Starting OpenAI-compatible HTTP server on {}", addr);
                 kokoros_openai::serve(binding, app.into_make_service()).await?;
             }
 
-            Mode::Stream => {
+            Mode::Stream {
+                incremental: true,
+                first_chunk_words,
+            } => {
+                let mut stdin = tokio::io::stdin();
+                let mut stdout = std::io::stdout();
+                eprintln!(
+                    "Entering incremental streaming mode. Sentences are spoken as they complete, and lines as they end. Use Ctrl+D to exit."
+                );
+                let header = WavHeader::new(1, tts.sample_rate(), 32);
+                header.write_header(&mut stdout)?;
+                stdout.flush()?;
+                // Chunks go through the effects in order, each continuing the last
+                let mut effects_stream = effects.stream(tts.sample_rate());
+
+                let mut synth = IncrementalTTS::new(
+                    &tts,
+                    &lan,
+                    &style,
+                    speed,
+                    IncrementalOptions {
+                        first_chunk_words: (first_chunk_words > 0).then_some(first_chunk_words),
+                        initial_silence,
+                        frontend: Some(frontend),
+                        ..IncrementalOptions::default()
+                    },
+                );
+                let mut buffer = [0u8; 4096];
+                let mut bytes = Vec::new();
+                loop {
+                    let read = stdin.read(&mut buffer).await?;
+                    if read == 0 {
+                        break;
+                    }
+                    bytes.extend_from_slice(&buffer[..read]);
+                    // A character may be split between two reads
+                    let valid = match std::str::from_utf8(&bytes) {
+                        Ok(text) => text.len(),
+                        Err(e) => e.valid_up_to(),
+                    };
+                    let text = String::from_utf8_lossy(&bytes[..valid]).into_owned();
+                    bytes.drain(..valid);
+                    // The end of a line is spoken at once, even without a full stop
+                    let mut results = Vec::new();
+                    let mut rest = text.as_str();
+                    while let Some(end) = rest.find('\n') {
+                        results.push(synth.push_text(&rest[..=end]).and_then(|mut chunks| {
+                            chunks.extend(synth.flush()?);
+                            Ok(chunks)
+                        }));
+                        rest = &rest[end + 1..];
+                    }
+                    results.push(synth.push_text(rest));
+                    for result in results {
+                        match result {
+                            Ok(chunks) => {
+                                for chunk in chunks {
+                                    let samples = effects_stream.process(&chunk.samples);
+                                    write_audio_chunk(&mut stdout, &samples)?;
+                                }
+                                stdout.flush()?;
+                            }
+                            Err(e) => eprintln!("Error processing text: {}", e),
+                        }
+                    }
+                }
+                bytes.clear();
+                for chunk in synth.finish()? {
+                    let samples = effects_stream.process(&chunk.samples);
+                    write_audio_chunk(&mut stdout, &samples)?;
+                }
+                write_audio_chunk(&mut stdout, &effects_stream.finish())?;
+                stdout.flush()?;
+            }
+
+            Mode::Stream { .. } => {
                 let stdin = tokio::io::stdin();
                 let reader = BufReader::new(stdin);
                 let mut lines = reader.lines();
@@ -410,15 +1444,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 );
 
                 // Write WAV header first
-                let header = WavHeader::new(1, 24000, 32);
+                let header = WavHeader::new(1, tts.sample_rate(), 32);
                 header.write_header(&mut stdout)?;
                 stdout.flush()?;
+                let mut effects_stream = effects.stream(tts.sample_rate());
 
                 while let Some(line) = lines.next_line().await? {
                     let stripped_line = line.trim();
                     if stripped_line.is_empty() {
                         continue;
                     }
+                    let document =
+                        SpokenDocument::parse(stripped_line, input_format, &document_options);
+                    let stripped_line = frontend.apply(&document.text, &lan);
 
                     // Process the line and get audio data
                     match tts.tts_raw_audio(
@@ -432,7 +1470,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         None,
                     ) {
                         Ok(raw_audio) => {
-                            // Write the raw audio samples directly
+                            // Each line is processed as soon as it is synthesized
+                            let raw_audio = effects_stream.process(&raw_audio);
                             write_audio_chunk(&mut stdout, &raw_audio)?;
                             stdout.flush()?;
                             eprintln!("Audio written to stdout. Ready for another line of text.");
@@ -440,6 +1479,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Err(e) => eprintln!("Error processing line: {}", e),
                     }
                 }
+                write_audio_chunk(&mut stdout, &effects_stream.finish())?;
+                stdout.flush()?;
             }
         }
 
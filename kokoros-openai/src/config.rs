@@ -0,0 +1,412 @@
+//! Server configuration, read from a JSON file given to `koko openai --config`.
+//!
+//! `voices` names voices for clients. An alias stands for a voice or a
+//! blend, with a default speed, language and lexicon. The default
+//! configuration has the OpenAI voice names as aliases; a file adds to them,
+//! replaces one by defining the same name, or removes one with `null`:
+//!
+//! ```json
+//! {
+//!   "voices": {
+//!     "alloy": { "voice": "af_sarah:0.4,af_nicole:0.6" },
+//!     "marin": null,
+//!     "support-agent": {
+//!       "voice": "bf_emma",
+//!       "speed": 1.1,
+//!       "lang_code": "en-gb",
+//!       "lexicon": { "Kokoros": "Kokoro-ess", "SQL": "sequel" }
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use kokoros::tts::{catalog::VoiceCatalog, koko::TTSKoko, voices::Blend};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+/// OpenAI voice names and the closest Kokoro voice, the aliases of the
+/// default configuration
+const OPENAI_VOICES: &[(&str, &str)] = &[
+    // Same name in both
+    ("alloy", "af_alloy"),
+    ("echo", "am_echo"),
+    ("nova", "af_nova"),
+    ("onyx", "am_onyx"),
+    // Female OpenAI voices to similar-sounding Kokoro voices
+    ("shimmer", "af_sky"),
+    ("fable", "af_bella"),
+    ("coral", "af_nicole"),
+    ("sage", "af_sarah"),
+    ("marin", "af_river"),
+    // Male OpenAI voices to similar-sounding Kokoro voices
+    ("ash", "am_adam"),
+    ("ballad", "am_michael"),
+    ("verse", "am_eric"),
+    ("cedar", "am_liam"),
+];
+
+/// Settings of the HTTP server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Aliases by name; names are matched in any case
+    #[serde(deserialize_with = "merge_aliases")]
+    pub voices: BTreeMap<String, VoiceAlias>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            voices: OPENAI_VOICES
+                .iter()
+                .map(|(name, voice)| (name.to_string(), VoiceAlias::new(voice)))
+                .collect(),
+        }
+    }
+}
+
+/// What a voice alias stands for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VoiceAlias {
+    /// A voice, a catalogue alias or a blend
+    pub voice: String,
+    /// Speed when the request sets none
+    #[serde(default)]
+    pub speed: Option<f32>,
+    /// Language when the request sets none
+    #[serde(default)]
+    pub lang_code: Option<String>,
+    #[serde(default)]
+    pub lexicon: Lexicon,
+}
+
+impl VoiceAlias {
+    /// An alias for `voice` without defaults of its own
+    pub fn new(voice: &str) -> Self {
+        Self {
+            voice: voice.to_string(),
+            speed: None,
+            lang_code: None,
+            lexicon: Lexicon::default(),
+        }
+    }
+}
+
+/// Words respelled before synthesis so they are read right, matched as
+/// whole words in any case. An entry that starts or ends with a symbol,
+/// such as `C++` or `.NET`, matches wherever that symbol is.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct Lexicon {
+    /// The spelling for each alternative of the pattern, in order
+    spellings: Vec<String>,
+    pattern: Option<Regex>,
+}
+
+impl TryFrom<BTreeMap<String, String>> for Lexicon {
+    type Error = String;
+
+    fn try_from(entries: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        if entries.is_empty() {
+            return Ok(Self::default());
+        }
+        if let Some(word) = entries.keys().find(|word| word.trim().is_empty()) {
+            return Err(format!("lexicon entry '{}' has no word", word));
+        }
+        let mut seen = HashSet::new();
+        if let Some(word) = entries
+            .keys()
+            .find(|word| !seen.insert(word.to_lowercase()))
+        {
+            return Err(format!("lexicon entry '{}' is defined twice", word));
+        }
+        // Longest first, so a phrase wins over a word it starts with
+        let mut entries: Vec<(String, String)> = entries.into_iter().collect();
+        entries.sort_by_key(|(word, _)| std::cmp::Reverse(word.len()));
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let alternatives: Vec<String> = entries
+            .iter()
+            .map(|(word, _)| {
+                // `\b` next to a symbol would need a letter on its other side
+                let start = if word.starts_with(is_word) { r"\b" } else { "" };
+                let end = if word.ends_with(is_word) { r"\b" } else { "" };
+                format!("({}{}{})", start, regex::escape(word), end)
+            })
+            .collect();
+        let pattern = Regex::new(&format!(r"(?i)(?:{})", alternatives.join("|")))
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            spellings: entries.into_iter().map(|(_, spelling)| spelling).collect(),
+            pattern: Some(pattern),
+        })
+    }
+}
+
+impl Lexicon {
+    pub fn apply(&self, text: &str) -> String {
+        match &self.pattern {
+            Some(pattern) => pattern
+                .replace_all(text, |caps: &Captures| {
+                    // Case folding can match text that lowercases to another
+                    // word, so the group that matched names the entry
+                    caps.iter()
+                        .skip(1)
+                        .position(|group| group.is_some())
+                        .map_or_else(|| caps[0].to_string(), |i| self.spellings[i].clone())
+                })
+                .into_owned(),
+            None => text.to_string(),
+        }
+    }
+}
+
+/// The default aliases with those of the file applied, by lowercase name
+fn merge_aliases<'de, D>(deserializer: D) -> Result<BTreeMap<String, VoiceAlias>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let aliases = BTreeMap::<String, Option<VoiceAlias>>::deserialize(deserializer)?;
+    let mut merged = ServerConfig::default().voices;
+    let mut seen = HashSet::new();
+    for (name, alias) in aliases {
+        let key = name.to_lowercase();
+        if !seen.insert(key.clone()) {
+            return Err(serde::de::Error::custom(format!(
+                "voice alias '{}' is defined twice",
+                name
+            )));
+        }
+        match alias {
+            Some(alias) => merged.insert(key, alias),
+            None => merged.remove(&key),
+        };
+    }
+    Ok(merged)
+}
+
+/// The voice a request asked for, with the defaults of its alias
+pub(crate) struct VoiceChoice<'a> {
+    pub voice: String,
+    pub speed: Option<f32>,
+    pub lang_code: Option<&'a str>,
+    pub lexicon: Option<&'a Lexicon>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Check that every alias names loaded voices, a usable speed and a
+    /// language the phonemizer reads. Default aliases whose voice is not
+    /// loaded, as with another voices file, are dropped.
+    pub fn validate(&mut self, tts: &TTSKoko) -> Result<(), String> {
+        let catalog = tts.voice_catalog();
+        self.voices.retain(|name, alias| {
+            !is_default(name, alias)
+                || tts
+                    .check_voice(&resolve_in_catalog(&alias.voice, catalog))
+                    .is_ok()
+        });
+        for (name, alias) in &self.voices {
+            if let Some(speed) = alias.speed
+                && !(0.25..=4.0).contains(&speed)
+            {
+                return Err(format!(
+                    "voice alias '{}': speed must be between 0.25 and 4.0, got {}",
+                    name, speed
+                ));
+            }
+            if let Some(lan) = &alias.lang_code {
+                tts.check_language(lan)
+                    .map_err(|e| format!("voice alias '{}': lang_code '{}': {}", name, lan, e))?;
+            }
+            let voice = self.choose(name, catalog).voice;
+            tts.check_voice(&voice)
+                .map_err(|e| format!("voice alias '{}': {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    fn alias(&self, name: &str) -> Option<&VoiceAlias> {
+        self.voices.get(&name.to_lowercase())
+    }
+
+    /// Resolve a requested voice or blend. Aliases defined here come first,
+    /// then the catalogue; the voices of an alias are looked up in the
+    /// catalogue only, so aliases don't chain.
+    pub(crate) fn choose<'a>(&'a self, requested: &str, catalog: &VoiceCatalog) -> VoiceChoice<'a> {
+        if let Some(alias) = self.alias(requested) {
+            return VoiceChoice {
+                voice: resolve_in_catalog(&alias.voice, catalog),
+                speed: alias.speed,
+                lang_code: alias.lang_code.as_deref(),
+                lexicon: Some(&alias.lexicon),
+            };
+        }
+        let voice = if Blend::is_blend(requested) {
+            self.flatten_blend(requested, catalog)
+        } else {
+            catalog.resolve(requested).to_string()
+        };
+        VoiceChoice {
+            voice,
+            speed: None,
+            lang_code: None,
+            lexicon: None,
+        }
+    }
+
+    /// A blend with every alias replaced by its voices, weighted. A blend
+    /// that does not parse is kept as sent and rejected by the voice check.
+    fn flatten_blend(&self, spec: &str, catalog: &VoiceCatalog) -> String {
+        let Ok(blend) = spec.parse::<Blend>() else {
+            return spec.to_string();
+        };
+        let mut parts = Vec::new();
+        for (name, weight) in blend.components() {
+            let voice = match self.alias(name) {
+                Some(alias) => resolve_in_catalog(&alias.voice, catalog),
+                None => catalog.resolve(name).to_string(),
+            };
+            match voice.parse::<Blend>() {
+                Ok(inner) if Blend::is_blend(&voice) => {
+                    for (inner_name, inner_weight) in inner.components() {
+                        parts.push(format!("{}:{}", inner_name, weight * inner_weight));
+                    }
+                }
+                _ => parts.push(format!("{}:{}", voice, weight)),
+            }
+        }
+        parts
+            .join(",")
+            .parse::<Blend>()
+            .map_or_else(|_| spec.to_string(), |blend| blend.to_string())
+    }
+
+    /// Alias names and the voices they stand for, for `/v1/audio/voices`
+    pub(crate) fn aliases<'a>(
+        &'a self,
+        catalog: &'a VoiceCatalog,
+    ) -> impl Iterator<Item = (&'a str, String)> + 'a {
+        self.voices
+            .iter()
+            .map(|(name, alias)| (name.as_str(), resolve_in_catalog(&alias.voice, catalog)))
+    }
+
+    /// Whether `name` is an alias defined here
+    pub(crate) fn defines(&self, name: &str) -> bool {
+        self.alias(name).is_some()
+    }
+}
+
+/// Whether an alias is one of the default configuration, untouched
+fn is_default(name: &str, alias: &VoiceAlias) -> bool {
+    OPENAI_VOICES.contains(&(name, alias.voice.as_str()))
+        && alias.speed.is_none()
+        && alias.lang_code.is_none()
+        && alias.lexicon.pattern.is_none()
+}
+
+fn resolve_in_catalog(spec: &str, catalog: &VoiceCatalog) -> String {
+    if !Blend::is_blend(spec) {
+        return catalog.resolve(spec).to_string();
+    }
+    match spec.parse::<Blend>() {
+        Ok(blend) => blend
+            .map_names(|name| catalog.resolve(name).to_string())
+            .to_string(),
+        Err(_) => spec.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_aliases() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{"voices": {
+                "Alloy": {"voice": "af_sarah:0.4,af_nicole:0.6"},
+                "support-agent": {"voice": "bf_emma", "speed": 1.1, "lexicon": {"SQL": "sequel"}}
+            }}"#,
+        )
+        .unwrap();
+        let catalog = VoiceCatalog::new(["af_sarah", "af_nicole", "af_alloy", "bf_emma"]);
+
+        assert_eq!(
+            config.choose("alloy", &catalog).voice,
+            "af_sarah:0.4,af_nicole:0.6"
+        );
+        let agent = config.choose("Support-Agent", &catalog);
+        assert_eq!((agent.voice.as_str(), agent.speed), ("bf_emma", Some(1.1)));
+        assert_eq!(
+            agent.lexicon.unwrap().apply("Ask about sql, not SQLite."),
+            "Ask about sequel, not SQLite."
+        );
+        // Aliases inside a blend are replaced by their weighted voices
+        assert_eq!(
+            config.choose("alloy:0.5,bf_emma:0.5", &catalog).voice,
+            "af_sarah:0.2,af_nicole:0.3,bf_emma:0.5"
+        );
+        assert_eq!(config.choose("af_sarah", &catalog).speed, None);
+        // Default aliases stay unless the file replaces or removes them
+        assert_eq!(config.choose("Nova", &catalog).voice, "af_nova");
+        assert_eq!(
+            ServerConfig::default().choose("alloy", &catalog).voice,
+            "af_alloy"
+        );
+        let trimmed: ServerConfig = serde_json::from_str(r#"{"voices": {"Nova": null}}"#).unwrap();
+        assert!(!trimmed.defines("nova"));
+        assert!(trimmed.defines("onyx"));
+        let none: ServerConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(none.voices.len(), ServerConfig::default().voices.len());
+
+        assert!(
+            serde_json::from_str::<ServerConfig>(
+                r#"{"voices": {"a": {"voice": "af_sky"}, "A": {"voice": "af_bella"}}}"#
+            )
+            .is_err()
+        );
+        assert!(serde_json::from_str::<ServerConfig>(r#"{"voice": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_lexicon_symbols() {
+        let lexicon = Lexicon::try_from(BTreeMap::from([
+            ("C++".to_string(), "C plus plus".to_string()),
+            ("Node.js".to_string(), "Node J S".to_string()),
+            (".NET".to_string(), "dot net".to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            lexicon.apply("C++, node.js and .NET, not Node.jsx."),
+            "C plus plus, Node J S and dot net, not Node.jsx."
+        );
+    }
+
+    #[test]
+    fn test_lexicon_case() {
+        let lexicon =
+            Lexicon::try_from(BTreeMap::from([("sql".to_string(), "sequel".to_string())])).unwrap();
+        assert_eq!(
+            lexicon.apply("SQL, Sql and \u{17f}ql"),
+            "sequel, sequel and sequel"
+        );
+
+        assert!(
+            Lexicon::try_from(BTreeMap::from([
+                ("SQL".to_string(), "S Q L".to_string()),
+                ("sql".to_string(), "sequel".to_string()),
+            ]))
+            .is_err()
+        );
+    }
+}
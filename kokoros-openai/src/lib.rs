@@ -5,263 +5,126 @@
 //!
 //! ## Implemented Features
 //! - `/v1/audio/speech` - Text-to-speech generation with streaming support
-//! - `/v1/audio/voices` - List available voices
+//! - `/v1/audio/dialogue` - Multi-speaker scripts rendered into one recording
+//! - `/v1/audio/voices` - List available voices: `voices` holds the ids and
+//!   aliases, `data` the catalogue entries with language, gender, accent,
+//!   display name, aliases and quality grade, and `aliases` the voice aliases
+//!   of the [`ServerConfig`]
+//! - `/v1/audio/voices/{voice}/similar` - The voices nearest to a voice,
+//!   alias or blend, filtered by `language` and `gender`, up to `limit`
 //! - `/v1/models` - List available models (static dummy list)
-//! - Multiple audio formats: MP3, WAV, PCM, OPUS, AAC, FLAC
+//! - `/v1/runtime` - Report the active ONNX Runtime execution provider
+//! - `/v1/model-info` - Report the tensor signature of the loaded model
+//! - Multiple audio formats: MP3, WAV, PCM, OPUS
 //! - Streaming audio generation for low-latency responses
+//! - OpenAI-shaped error bodies (`{"error": {"message", "type", "param", "code"}}`)
+//!
+//! ## Error Statuses
+//! - 400: malformed JSON, empty `input`, `speed` outside 0.25-4.0, a `voice`
+//!   blend that does not parse, invalid
+//!   `normalization_options`, `input_format`, `code_blocks`, `tables` or
+//!   `fit`, `pitch` outside -12 to 12 or `tempo` outside 0.5 to 2.0, a
+//!   `target_duration` under 0.2 seconds, subtitles without cues, a
+//!   dialogue `script` that does not parse or a speaker without a voice, or
+//!   a similar-voices `gender` other than `female` or `male`
+//! - 404: unknown `model` or `voice`
+//! - 413: request body over 1 MiB, or a chunk too long for the voice tables
+//! - 422: unsupported `response_format`, a panned dialogue in a format other than WAV or PCM, streaming a
+//!   `target_duration` or subtitles, a `target_duration` with subtitles, a
+//!   `tempo` with either, or a `lang_code`
+//!   the phonemizer cannot handle
+//! - 503: every synthesis slot is busy; retry later
+//! - 500: inference or encoding failed
+//!
+//! Once a streaming response has started the status is already sent, so
+//! failures in later chunks only end the stream early.
 //!
 //! ## OpenAI API Compatibility Limitations
 //! - `return_download_link`: Not implemented (files are streamed directly)
-//! - `lang_code`: Language code for phonemization (defaults to first letter of voice name)
+//! - `voice`: a voice, a voice saved with `koko voices blend`, or a blend such as
+//!   `af_sarah:0.4,af_nicole:0.6` (OpenAI names are mapped in blends too), or an
+//!   alias of the [`ServerConfig`], which may set a default `speed`, `lang_code`
+//!   and lexicon
+//! - `lang_code`: Language code for phonemization (defaults to the catalogue language of the voice);
+//!   `auto` detects it per sentence and `auto+voice` also switches voices, with the
+//!   spans reported as `language:voice` pairs in `X-Detected-Languages`
 //! - `volume_multiplier`: Not implemented (audio returned at original levels)
 //! - `download_format`: Not implemented (only response_format used)
-//! - `normalization_options`: Not implemented (basic text processing only)
-//! - Streaming only supports PCM format (other formats fall back to PCM)
-
-use std::error::Error;
+//! - `normalization_options`: the Kokoro-FastAPI switches (`normalize`, `unit_normalization`,
+//!   `url_normalization`, `email_normalization`, `phone_normalization`,
+//!   `optional_pluralization_normalization`, `replace_remaining_symbols`), plus a read-aloud
+//!   policy (`keep`, `spell`, `short`, `skip`) per class: `urls`, `emails`, `paths`,
+//!   `hashtags`, `emoji`, `code`. Units, plurals and symbols are English only
+//! - `input_format`: `markdown` and `html` input is read for its structure, with
+//!   `code_blocks` and `tables` set to `read`, `summarize` or `skip`; `srt` and
+//!   `vtt` subtitles are spoken cue by cue, each fitted to its slot
+//! - `target_duration`: seconds the speech should last, within `duration_tolerance`
+//!   (0.1 by default); `fit` says what to do with what speed can't reach: `keep`,
+//!   `pad` (default) or `stretch`. The speech length is sent in `X-Speech-Duration`
+//!   and the speed chosen in `X-Speech-Speed`
+//! - `pitch` and `tempo`: semitones and pace applied after synthesis, to each
+//!   chunk as it is synthesized when streaming
+//! - Streaming only supports PCM format (other formats fall back to PCM, with a
+//!   warning in the log and `Content-Type: audio/pcm`)
+
+mod config;
+
+pub use config::{Lexicon, ServerConfig, VoiceAlias};
+
+use std::collections::BTreeMap;
 use std::io;
 use std::sync::Arc;
 use std::time::Instant;
 
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
     body::Body,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{StatusCode, header},
     response::{IntoResponse, Response},
     routing::{get, post},
 };
+use base64::Engine;
 use futures::stream::StreamExt;
 use kokoros::{
-    tts::koko::{InitConfig as TTSKokoInitConfig, TTSKoko},
+    error::KokorosError,
+    onn::{providers::ExecutionProvider, signature::TensorSpec},
+    tts::{
+        catalog::{VoiceCatalog, VoiceMeta},
+        detect,
+        dialogue::{DialogueOptions, Script, Speaker, Turn},
+        document::{DocumentOptions, InputFormat, SpokenDocument},
+        fit::{self, FitOptions},
+        frontend::TextFrontend,
+        koko::TTSKoko,
+        segment,
+        semiotic::{ReadAloudOptions, ReadPolicy},
+        subtitles,
+        voices::{Gender, VoiceFilter},
+    },
+    utils::dsp::{Effects, EffectsStream},
     utils::mp3::pcm_to_mp3,
     utils::opus::pcm_to_opus_ogg,
     utils::wav::{WavHeader, write_audio_chunk},
 };
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
 use tower_http::cors::CorsLayer;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-/// Break words used for chunk splitting
-const BREAK_WORDS: &[&str] = &[
-    "and", "or", "but", "&", "because", "if", "since", "though", "although", "however", "which",
-];
-
-/// Split text into speech chunks for streaming
-///
-/// Prioritizes sentence boundaries over word count for natural speech breaks
-/// Then applies center-break word splitting for long chunks
-fn split_text_into_speech_chunks(text: &str, words_per_chunk: usize) -> Vec<String> {
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    let mut word_count = 0;
-
-    // First pass: split by punctuation
-    for word in text.split_whitespace() {
-        if !current_chunk.is_empty() {
-            current_chunk.push(' ');
-        }
-        // Check for numbered list patterns: 1. 2) 3: (4), 5(\s)[.\)\:]
-        let is_numbered_break = is_numbered_list_item(word);
-
-        if is_numbered_break && !current_chunk.is_empty() {
-            chunks.push(current_chunk.trim().to_string());
-            current_chunk.clear();
-            word_count = 0;
-        }
-        current_chunk.push_str(word);
-        word_count += 1;
-
-        // Check for unconditional breaks (always break regardless of word count)
-        let ends_with_unconditional = word.ends_with('.')
-            || word.ends_with('!')
-            || word.ends_with('?')
-            || word.ends_with(':')
-            || word.ends_with(';');
-
-        // Check for conditional breaks (commas - only break if enough words)
-        let ends_with_conditional = word.ends_with(',');
-
-        // Split conditions:
-        // 1. Unconditional punctuation - always break
-        // 2. Conditional punctuation + target word count reached
-        if ends_with_unconditional
-            || is_numbered_break
-            || (ends_with_conditional && word_count >= words_per_chunk)
-        {
-            chunks.push(current_chunk.trim().to_string());
-            current_chunk.clear();
-            word_count = 0;
-        }
-    }
-
-    if !current_chunk.trim().is_empty() {
-        chunks.push(current_chunk.trim().to_string());
-    }
-
-    // Second pass: apply center-break splitting for long chunks
-    // All chunks: ≥12 words
-    // First 2 chunks: punctuation priority, Others: break words only
-    let mut final_chunks = Vec::new();
-    for (index, chunk) in chunks.iter().enumerate() {
-        let threshold = 12;
-        let use_punctuation = index < 2; // First 2 chunks can use punctuation
-        let split_chunks = split_long_chunk_with_depth(chunk, threshold, use_punctuation, 0);
-        final_chunks.extend(split_chunks);
-    }
-
-    // Final processing: Move break words from end of chunks to beginning of next chunk
-    for i in 0..final_chunks.len() - 1 {
-        let current_chunk = &final_chunks[i];
-        let words: Vec<&str> = current_chunk.trim().split_whitespace().collect();
-
-        if let Some(last_word) = words.last() {
-            // Check if last word is a break word (case insensitive)
-            if BREAK_WORDS.contains(&last_word.to_lowercase().as_str()) && words.len() > 1 {
-                // Only move if it won't create an empty chunk (need more than 1 word)
-                let new_current = words[..words.len() - 1].join(" ");
-
-                // Add break word to beginning of next chunk
-                let next_chunk = &final_chunks[i + 1];
-                let new_next = format!("{} {}", last_word, next_chunk);
-
-                // Update the chunks
-                final_chunks[i] = new_current;
-                final_chunks[i + 1] = new_next;
-            }
-        }
-    }
-
-    // After all processing, there is no explicit filter to remove empty chunks.
-    // If any empty string slipped through (e.g., from .trim().to_string() on
-    // whitespace-only current_chunk, or from split_long_chunk), it would remain.
-    // Dont consider filtering out empty chunks here, to enable catching potential bugs
-    // in the chunking logic.
-    final_chunks
-}
+/// Largest accepted `/v1/audio/speech` request body
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
 
-/// Check if a word is a numbered list item: 1. 2) 3: (4), 5(\s)[.\)\:]
-fn is_numbered_list_item(word: &str) -> bool {
-    // Pattern matches: number followed by . ) or :
-    // Examples: "1.", "2)", "3:", "(4)", "(5),"
-    let numbered_regex = Regex::new(r"^\(?[0-9]+[.\)\:],?$").unwrap();
-    numbered_regex.is_match(word)
-}
+/// Speech requests admitted per TTS instance, running or waiting, before the
+/// server answers 503
+const MAX_PENDING_REQUESTS_PER_INSTANCE: usize = 4;
 
-fn split_long_chunk_with_depth(
-    chunk: &str,
-    threshold: usize,
-    use_punctuation: bool,
-    depth: usize,
-) -> Vec<String> {
-    // Prevent infinite recursion
-    if depth >= 3 {
-        return vec![chunk.to_string()];
-    }
-    let words: Vec<&str> = chunk.split_whitespace().collect();
-    let word_count = words.len();
-
-    // Only split if chunk meets the threshold
-    if word_count < threshold {
-        return vec![chunk.to_string()];
-    }
+/// Model ids accepted in requests and listed by `/v1/models`; all of them
+/// use the same Kokoro model
+const MODEL_IDS: &[&str] = &["tts-1", "tts-1-hd", "kokoro", "gpt-4o-mini-tts"];
 
-    let center = word_count / 2;
-
-    if use_punctuation {
-        // Priority 1: Search for commas closest to center
-        if let Some(pos) = find_closest_punctuation(&words, center, &[","]) {
-            if pos >= 3 && pos < words.len() {
-                let first_chunk = words[..pos].join(" ");
-                let second_chunk = words[pos..].join(" ");
-
-                // Recursively split both chunks if they're still too long
-                let mut result = Vec::new();
-                result.extend(split_long_chunk_with_depth(
-                    &first_chunk,
-                    threshold,
-                    use_punctuation,
-                    depth + 1,
-                ));
-                result.extend(split_long_chunk_with_depth(
-                    &second_chunk,
-                    threshold,
-                    use_punctuation,
-                    depth + 1,
-                ));
-                return result;
-            }
-        }
-    }
-
-    // Priority 2: Search for break words closest to center
-    if let Some(pos) = find_closest_break_word(&words, center, BREAK_WORDS) {
-        if pos >= 3 && pos < words.len() {
-            let first_chunk = words[..pos].join(" ");
-            let second_chunk = words[pos..].join(" ");
-
-            // Recursively split both chunks if they're still too long
-            let mut result = Vec::new();
-            result.extend(split_long_chunk_with_depth(
-                &first_chunk,
-                threshold,
-                use_punctuation,
-                depth + 1,
-            ));
-            result.extend(split_long_chunk_with_depth(
-                &second_chunk,
-                threshold,
-                use_punctuation,
-                depth + 1,
-            ));
-            return result;
-        }
-    }
-
-    // No suitable break point found, return original chunk
-    vec![chunk.to_string()]
-}
-
-/// Find closest punctuation to center
-fn find_closest_punctuation(words: &[&str], center: usize, punctuation: &[&str]) -> Option<usize> {
-    let mut closest_pos = None;
-    let mut min_distance = usize::MAX;
-
-    for (i, word) in words.iter().enumerate() {
-        if punctuation.iter().any(|p| word.ends_with(p)) {
-            let distance = if i < center { center - i } else { i - center };
-            if distance < min_distance {
-                min_distance = distance;
-                closest_pos = Some(i + 1); // Split after the punctuation
-            }
-        }
-    }
-
-    closest_pos
-}
-
-/// Find closest break word to center
-fn find_closest_break_word(words: &[&str], center: usize, break_words: &[&str]) -> Option<usize> {
-    let mut closest_pos = None;
-    let mut min_distance = usize::MAX;
-
-    for (i, word) in words.iter().enumerate() {
-        if break_words.contains(&word.to_lowercase().as_str()) {
-            let distance = if i < center { center - i } else { i - center };
-            if distance < min_distance {
-                min_distance = distance;
-                closest_pos = Some(i); // Break word becomes first word of second chunk
-            }
-        }
-    }
-
-    closest_pos
-}
-
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 enum AudioFormat {
     #[default]
@@ -273,57 +136,12 @@ enum AudioFormat {
     Pcm,
 }
 
-/// Map OpenAI voice names to Kokoro voice names for backwards compatibility
-///
-/// OpenAI voices are mapped to their closest Kokoro equivalents:
-/// - Direct matches: alloy → af_alloy, echo → am_echo, nova → af_nova, onyx → am_onyx
-/// - Mapped voices: Other OpenAI voices are mapped to similar-sounding Kokoro voices
-fn map_openai_voice_to_kokoro(voice: &str) -> &str {
-    match voice.to_lowercase().as_str() {
-        // Direct matches (same name exists in both)
-        "alloy" => "af_alloy",
-        "echo" => "am_echo",
-        "nova" => "af_nova",
-        "onyx" => "am_onyx",
-        // Female OpenAI voices → Female Kokoro voices
-        "shimmer" => "af_sky",
-        "fable" => "af_bella",
-        "coral" => "af_nicole",
-        "sage" => "af_sarah",
-        "marin" => "af_river",
-        // Male OpenAI voices → Male Kokoro voices
-        "ash" => "am_adam",
-        "ballad" => "am_michael",
-        "verse" => "am_eric",
-        "cedar" => "am_liam",
-        // If not an OpenAI voice, return as-is (assume it's already a Kokoro voice)
-        _ => voice,
-    }
-}
-
-fn get_language_code(lang_code: Option<&str>, voice: &str) -> String {
-    lang_code.map(|s| s.to_string()).unwrap_or_else(|| {
-        let prefix = voice
-            .chars()
-            .next()
-            .unwrap_or('a')
-            .to_lowercase()
-            .next()
-            .unwrap();
-        match prefix {
-            'a' => "en-us",
-            'b' => "en-gb",
-            'p' => "pt-br",
-            'j' => "ja",
-            'z' => "cmn",
-            'f' => "fr-fr",
-            'h' => "hi",
-            'i' => "it",
-            'e' => "es",
-            _ => "en-us",
-        }
+/// The language of `lang_code`, else the catalogue language of the voice
+fn get_language_code(lang_code: Option<&str>, voice: &str, catalog: &VoiceCatalog) -> String {
+    lang_code
+        .or_else(|| catalog.language(voice))
+        .unwrap_or("en-us")
         .to_string()
-    })
 }
 
 #[derive(Deserialize)]
@@ -335,13 +153,6 @@ impl Default for Voice {
     }
 }
 
-impl Voice {
-    /// Get the Kokoro voice name, mapping from OpenAI voice names if necessary
-    fn to_kokoro_voice(&self) -> String {
-        map_openai_voice_to_kokoro(&self.0).to_string()
-    }
-}
-
 #[derive(Deserialize)]
 struct Speed(f32);
 
@@ -353,8 +164,7 @@ impl Default for Speed {
 
 #[derive(Deserialize)]
 struct SpeechRequest {
-    // Only one Kokoro model exists
-    #[allow(dead_code)]
+    // Only one Kokoro model exists, `MODEL_IDS` are all aliases for it
     model: String,
 
     input: String,
@@ -362,11 +172,13 @@ struct SpeechRequest {
     #[serde(default)]
     voice: Voice,
 
+    /// MP3 when not streaming, PCM when streaming
     #[serde(default)]
-    response_format: AudioFormat,
+    response_format: Option<AudioFormat>,
 
+    /// 1.0 unless the voice is an alias with its own speed
     #[serde(default)]
-    speed: Speed,
+    speed: Option<Speed>,
 
     #[serde(default)]
     initial_silence: Option<usize>,
@@ -395,10 +207,198 @@ struct SpeechRequest {
     #[allow(dead_code)]
     download_format: Option<String>,
 
-    /// Text normalization options (not implemented)
+    /// Text front end switches, as in Kokoro-FastAPI
     #[serde(default)]
-    #[allow(dead_code)]
-    normalization_options: Option<serde_json::Value>,
+    normalization_options: NormalizationOptions,
+
+    /// Markup of `input`: `text` (default), `markdown` or `html`
+    #[serde(default)]
+    input_format: Option<String>,
+
+    /// How code blocks in markdown or HTML input are read: `read`,
+    /// `summarize` (default) or `skip`
+    #[serde(default)]
+    code_blocks: Option<String>,
+
+    /// How tables in markdown or HTML input are read: `read` (default),
+    /// `summarize` or `skip`
+    #[serde(default)]
+    tables: Option<String>,
+
+    /// Seconds the speech should last; the speed is chosen to fit
+    #[serde(default)]
+    target_duration: Option<f32>,
+
+    /// Seconds off the target that still count as a fit, 0.1 by default
+    #[serde(default)]
+    duration_tolerance: Option<f32>,
+
+    /// What to do when speed alone doesn't reach the target duration or a
+    /// subtitle slot: `keep`, `pad` (default) or `stretch`
+    #[serde(default)]
+    fit: Option<String>,
+
+    /// Pitch shift in semitones, -12 to 12, applied after synthesis
+    #[serde(default)]
+    pitch: Option<f32>,
+
+    /// Pace of the speech, 0.5 to 2.0, changed without changing its pitch
+    #[serde(default)]
+    tempo: Option<f32>,
+}
+
+/// Kokoro-FastAPI's `normalization_options`, plus a read-aloud policy
+/// (`keep`, `spell`, `short`, `skip`) per class that overrides the switches
+#[derive(Deserialize)]
+#[serde(default)]
+struct NormalizationOptions {
+    normalize: bool,
+    unit_normalization: bool,
+    url_normalization: bool,
+    email_normalization: bool,
+    optional_pluralization_normalization: bool,
+    phone_normalization: bool,
+    replace_remaining_symbols: bool,
+    urls: Option<String>,
+    emails: Option<String>,
+    paths: Option<String>,
+    hashtags: Option<String>,
+    emoji: Option<String>,
+    code: Option<String>,
+}
+
+impl Default for NormalizationOptions {
+    fn default() -> Self {
+        Self {
+            normalize: true,
+            unit_normalization: false,
+            url_normalization: true,
+            email_normalization: true,
+            optional_pluralization_normalization: true,
+            phone_normalization: true,
+            replace_remaining_symbols: true,
+            urls: None,
+            emails: None,
+            paths: None,
+            hashtags: None,
+            emoji: None,
+            code: None,
+        }
+    }
+}
+
+impl NormalizationOptions {
+    fn frontend(&self) -> Result<TextFrontend, SpeechError> {
+        if !self.normalize {
+            return Ok(TextFrontend::off());
+        }
+        let mut read_aloud = ReadAloudOptions::default();
+        if !self.url_normalization {
+            read_aloud.urls = ReadPolicy::Keep;
+        }
+        if !self.email_normalization {
+            read_aloud.emails = ReadPolicy::Keep;
+        }
+        let policies = [
+            ("urls", &self.urls),
+            ("emails", &self.emails),
+            ("paths", &self.paths),
+            ("hashtags", &self.hashtags),
+            ("emoji", &self.emoji),
+            ("code", &self.code),
+        ];
+        for (class, policy) in policies {
+            if let Some(policy) = policy {
+                let invalid = |message| SpeechError::invalid(message, "normalization_options");
+                read_aloud
+                    .set(class, policy.parse().map_err(invalid)?)
+                    .map_err(invalid)?;
+            }
+        }
+        Ok(TextFrontend {
+            read_aloud,
+            units: self.unit_normalization,
+            phone_numbers: self.phone_normalization,
+            optional_plurals: self.optional_pluralization_normalization,
+            replace_symbols: self.replace_remaining_symbols,
+            language_rules: true,
+        })
+    }
+}
+
+/// A voice of `/v1/audio/dialogue`. The language defaults to the one of
+/// the voice, as for `/v1/audio/speech`.
+#[derive(Deserialize)]
+struct DialogueSpeaker {
+    voice: Voice,
+    #[serde(default)]
+    speed: Option<f32>,
+    #[serde(default)]
+    lang_code: Option<String>,
+    /// Stereo position from -1.0 (left) to 1.0 (right)
+    #[serde(default)]
+    pan: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct DialogueTurn {
+    speaker: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct DialogueRequest {
+    model: String,
+
+    /// `SPEAKER: text` lines, as read by `koko dialogue`; or use `turns`
+    #[serde(default)]
+    script: Option<String>,
+
+    #[serde(default)]
+    turns: Vec<DialogueTurn>,
+
+    /// Voices by speaker name. Speakers named after a voice need no entry
+    #[serde(default)]
+    speakers: BTreeMap<String, DialogueSpeaker>,
+
+    /// MP3 for mono dialogues, WAV once a speaker is panned
+    #[serde(default)]
+    response_format: Option<AudioFormat>,
+
+    /// Speed of speakers without their own
+    #[serde(default)]
+    speed: Speed,
+
+    /// Silence between turns in seconds
+    #[serde(default)]
+    gap: Option<f32>,
+
+    /// Language of every speaker without their own `lang_code`
+    #[serde(default)]
+    lang_code: Option<String>,
+
+    /// Answer with JSON holding base64 audio and word timestamps per speaker
+    #[serde(default)]
+    timestamps: bool,
+
+    #[serde(default)]
+    normalization_options: NormalizationOptions,
+}
+
+#[derive(Serialize)]
+struct DialogueWord {
+    speaker: String,
+    word: String,
+    start_sec: f32,
+    end_sec: f32,
+}
+
+#[derive(Serialize)]
+struct CaptionedDialogue {
+    /// Base64 of the encoded audio
+    audio: String,
+    content_type: &'static str,
+    words: Vec<DialogueWord>,
 }
 
 /// Async TTS worker task
@@ -450,7 +450,111 @@ impl TTSWorkerPool {
 
 #[derive(Serialize)]
 struct VoicesResponse {
+    /// Voice ids followed by their aliases, the original flat list
     voices: Vec<String>,
+    data: Vec<VoiceEntry>,
+    /// Aliases of the server configuration
+    aliases: Vec<AliasEntry>,
+}
+
+/// A voice alias of the server configuration
+#[derive(Serialize)]
+struct AliasEntry {
+    name: String,
+    /// The voice or blend it stands for
+    voice: String,
+    speed: Option<f32>,
+    lang_code: Option<String>,
+}
+
+/// A voice of the catalogue
+#[derive(Serialize)]
+struct VoiceEntry {
+    id: String,
+    name: String,
+    /// Kokoro language code, e.g. `a` for American English
+    lang_code: Option<String>,
+    /// eSpeak language
+    language: Option<String>,
+    gender: Option<String>,
+    accent: Option<String>,
+    aliases: Vec<String>,
+    quality: Option<String>,
+}
+
+impl From<&VoiceMeta> for VoiceEntry {
+    fn from(meta: &VoiceMeta) -> Self {
+        Self {
+            id: meta.id.clone(),
+            name: meta.name.clone(),
+            lang_code: meta.lang_code.map(String::from),
+            language: meta.language.clone(),
+            gender: meta.gender.map(|gender| gender.to_string()),
+            accent: meta.accent.clone(),
+            aliases: meta.aliases.clone(),
+            quality: meta.quality.clone(),
+        }
+    }
+}
+
+/// Query of `/v1/audio/voices/{voice}/similar`
+#[derive(Deserialize)]
+struct SimilarQuery {
+    /// Language such as `en-gb` or `en`, or accent such as `British`
+    language: Option<String>,
+    gender: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SimilarResponse {
+    /// The voice or blend searched for, with aliases resolved
+    voice: String,
+    /// Nearest voices first
+    data: Vec<SimilarEntry>,
+}
+
+#[derive(Serialize)]
+struct SimilarEntry {
+    #[serde(flatten)]
+    voice: VoiceEntry,
+    /// Mean distance between the style vectors of the two voices
+    distance: f32,
+}
+
+#[derive(Serialize)]
+struct RuntimeResponse {
+    execution_provider: Option<String>,
+    compiled_providers: Vec<String>,
+    instances: usize,
+}
+
+#[derive(Serialize)]
+struct TensorInfo {
+    name: String,
+    dtype: String,
+    /// `-1` marks a dynamic axis
+    shape: Vec<i64>,
+}
+
+impl From<&TensorSpec> for TensorInfo {
+    fn from(spec: &TensorSpec) -> Self {
+        Self {
+            name: spec.name.clone(),
+            dtype: spec.dtype.to_string(),
+            shape: spec.shape.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ModelInfoResponse {
+    tokens: TensorInfo,
+    style: TensorInfo,
+    speed: TensorInfo,
+    audio: TensorInfo,
+    durations: Option<TensorInfo>,
+    timestamped: bool,
 }
 
 #[derive(Serialize)]
@@ -468,7 +572,19 @@ struct ModelsResponse {
 }
 
 pub async fn create_server(tts_instances: Vec<TTSKoko>) -> Router {
+    create_server_with_config(tts_instances, ServerConfig::default()).await
+}
+
+/// The server with the voice aliases of `config`; see [`ServerConfig::validate`]
+/// to check them against the loaded voices first
+pub async fn create_server_with_config(
+    tts_instances: Vec<TTSKoko>,
+    config: ServerConfig,
+) -> Router {
     info!("Starting TTS server with {} instances", tts_instances.len());
+    if !config.voices.is_empty() {
+        info!("{} voice aliases configured", config.voices.len());
+    }
 
     // Use first instance for compatibility with non-streaming endpoints
     let tts_single = tts_instances
@@ -476,12 +592,30 @@ pub async fn create_server(tts_instances: Vec<TTSKoko>) -> Router {
         .cloned()
         .expect("At least one TTS instance required");
 
+    match tts_single.execution_provider() {
+        Some(provider) => info!("TTS instances running on '{}' execution provider", provider),
+        None => info!("TTS execution provider unknown"),
+    }
+
+    let slots = SpeechSlots(Arc::new(Semaphore::new(
+        tts_instances.len() * MAX_PENDING_REQUESTS_PER_INSTANCE,
+    )));
+
     Router::new()
         .route("/", get(handle_home))
         .route("/v1/audio/speech", post(handle_tts))
+        .route("/v1/audio/dialogue", post(handle_dialogue))
         .route("/v1/audio/voices", get(handle_voices))
+        .route(
+            "/v1/audio/voices/{voice}/similar",
+            get(handle_similar_voices),
+        )
         .route("/v1/models", get(handle_models))
         .route("/v1/models/{model}", get(handle_model))
+        .route("/v1/runtime", get(handle_runtime))
+        .route("/v1/model-info", get(handle_model_info))
+        .layer(Extension(slots))
+        .layer(Extension(Arc::new(config)))
         .layer(axum::middleware::from_fn(request_id_middleware))
         .layer(CorsLayer::permissive())
         .with_state((tts_single, tts_instances))
@@ -491,40 +625,195 @@ pub use axum::serve;
 
 #[derive(Debug)]
 enum SpeechError {
-    // Deciding to modify this example in order to see errors
-    // (e.g. with tracing) is up to the developer
-    #[allow(dead_code)]
-    Koko(Box<dyn Error>),
+    /// Malformed body or a field with an invalid value (400)
+    InvalidRequest {
+        message: String,
+        param: Option<&'static str>,
+    },
+
+    /// Unknown model or voice (404)
+    NotFound {
+        message: String,
+        param: &'static str,
+        code: &'static str,
+    },
+
+    /// Request body over `MAX_REQUEST_BYTES` (413)
+    PayloadTooLarge,
+
+    /// Valid fields that are not supported, alone or together (422)
+    Unsupported {
+        message: String,
+        param: &'static str,
+    },
+
+    /// All synthesis slots are taken (503)
+    Overloaded,
+
+    Koko(KokorosError),
 
-    #[allow(dead_code)]
     Header(io::Error),
 
-    #[allow(dead_code)]
     Chunk(io::Error),
 
-    #[allow(dead_code)]
     Mp3Conversion(std::io::Error),
 
-    #[allow(dead_code)]
     OpusConversion(std::io::Error),
+
+    Response(axum::http::Error),
 }
 
 impl std::fmt::Display for SpeechError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SpeechError::Koko(e) => write!(f, "Koko TTS error: {}", e),
+            SpeechError::InvalidRequest { message, .. }
+            | SpeechError::NotFound { message, .. }
+            | SpeechError::Unsupported { message, .. } => write!(f, "{}", message),
+            SpeechError::PayloadTooLarge => write!(
+                f,
+                "Request body exceeds the limit of {} bytes",
+                MAX_REQUEST_BYTES
+            ),
+            SpeechError::Overloaded => {
+                write!(f, "The server is busy with other requests, retry later")
+            }
+            SpeechError::Koko(e) => write!(f, "{}", e),
             SpeechError::Header(e) => write!(f, "Header error: {}", e),
             SpeechError::Chunk(e) => write!(f, "Chunk error: {}", e),
             SpeechError::Mp3Conversion(e) => write!(f, "MP3 conversion error: {}", e),
             SpeechError::OpusConversion(e) => write!(f, "Opus conversion error: {}", e),
+            SpeechError::Response(e) => write!(f, "Response error: {}", e),
+        }
+    }
+}
+
+impl SpeechError {
+    fn invalid(message: impl Into<String>, param: &'static str) -> Self {
+        SpeechError::InvalidRequest {
+            message: message.into(),
+            param: Some(param),
+        }
+    }
+
+    fn unsupported(message: impl Into<String>, param: &'static str) -> Self {
+        SpeechError::Unsupported {
+            message: message.into(),
+            param,
+        }
+    }
+
+    fn model_not_found(model: &str) -> Self {
+        SpeechError::NotFound {
+            message: format!("The model '{}' does not exist", model),
+            param: "model",
+            code: "model_not_found",
+        }
+    }
+
+    /// Status, `param` and `code` of the OpenAI error body
+    fn parts(&self) -> (StatusCode, Option<&'static str>, Option<&'static str>) {
+        match self {
+            SpeechError::InvalidRequest { param, .. } => (StatusCode::BAD_REQUEST, *param, None),
+            SpeechError::NotFound { param, code, .. } => {
+                (StatusCode::NOT_FOUND, Some(param), Some(code))
+            }
+            SpeechError::PayloadTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                None,
+                Some("request_too_large"),
+            ),
+            SpeechError::Unsupported { param, .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, Some(param), None)
+            }
+            SpeechError::Overloaded => (StatusCode::SERVICE_UNAVAILABLE, None, Some("overloaded")),
+            SpeechError::Koko(KokorosError::UnknownVoice(_)) => (
+                StatusCode::NOT_FOUND,
+                Some("voice"),
+                Some("voice_not_found"),
+            ),
+            SpeechError::Koko(KokorosError::InvalidVoice(_)) => {
+                (StatusCode::BAD_REQUEST, Some("voice"), None)
+            }
+            SpeechError::Koko(KokorosError::InputTooLong { .. }) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Some("input"),
+                Some("input_too_long"),
+            ),
+            SpeechError::Koko(KokorosError::Document(_)) => {
+                (StatusCode::BAD_REQUEST, Some("input"), None)
+            }
+            SpeechError::Koko(KokorosError::InvalidInput(_)) => {
+                (StatusCode::BAD_REQUEST, None, None)
+            }
+            SpeechError::Koko(KokorosError::Phonemization(_)) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Some("lang_code"),
+                Some("unsupported_language"),
+            ),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, None, None),
         }
     }
 }
 
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: ErrorObject,
+}
+
+#[derive(Serialize)]
+struct ErrorObject {
+    message: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    param: Option<&'static str>,
+    code: Option<&'static str>,
+}
+
 impl IntoResponse for SpeechError {
     fn into_response(self) -> Response {
-        // None of these errors make sense to expose to the user of the API
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        let (status, param, code) = self.parts();
+
+        let (kind, message) = if status.is_server_error() {
+            error!("Speech request failed: {}", self);
+            // Internal failures are logged, not exposed to the API user
+            let message = match self {
+                SpeechError::Overloaded => self.to_string(),
+                _ => "The server failed to generate audio".to_string(),
+            };
+            ("server_error", message)
+        } else {
+            debug!("Speech request rejected ({}): {}", status, self);
+            ("invalid_request_error", self.to_string())
+        };
+
+        let body = Json(ErrorResponse {
+            error: ErrorObject {
+                message,
+                kind,
+                param,
+                code,
+            },
+        });
+
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            (status, [(header::RETRY_AFTER, "1")], body).into_response()
+        } else {
+            (status, body).into_response()
+        }
+    }
+}
+
+/// Admission control for `/v1/audio/speech`. A request holds its permit
+/// until its audio, streamed or not, has been produced.
+#[derive(Clone)]
+struct SpeechSlots(Arc<Semaphore>);
+
+impl SpeechSlots {
+    fn try_acquire(&self) -> Result<OwnedSemaphorePermit, SpeechError> {
+        self.0
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| SpeechError::Overloaded)
     }
 }
 
@@ -534,6 +823,32 @@ async fn handle_home() -> &'static str {
     "OK"
 }
 
+/// Read a JSON request body of at most `MAX_REQUEST_BYTES`
+async fn read_json<T: DeserializeOwned>(body: Body) -> Result<T, SpeechError> {
+    let bytes = axum::body::to_bytes(body, MAX_REQUEST_BYTES)
+        .await
+        .map_err(|e| {
+            let e = e.into_inner();
+            if e.is::<http_body_util::LengthLimitError>() {
+                SpeechError::PayloadTooLarge
+            } else {
+                error!("Error reading request body: {:?}", e);
+                SpeechError::InvalidRequest {
+                    message: format!("Could not read request body: {}", e),
+                    param: None,
+                }
+            }
+        })?;
+
+    serde_json::from_slice(&bytes).map_err(|e| {
+        debug!("JSON parsing error: {:?}", e);
+        SpeechError::InvalidRequest {
+            message: format!("Invalid JSON body: {}", e),
+            param: None,
+        }
+    })
+}
+
 async fn handle_tts(
     State((tts_single, tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
     request: axum::extract::Request,
@@ -547,78 +862,292 @@ async fn handle_tts(
     // OpenAI TTS always streams by default - client decides how to consume
     // Only send complete file when explicitly requested via stream: false
 
-    // Parse the JSON body
-    let bytes = axum::body::to_bytes(request.into_body(), usize::MAX)
-        .await
-        .map_err(|e| {
-            error!("Error reading request body: {:?}", e);
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
-        })?;
+    let slots = request.extensions().get::<SpeechSlots>().cloned();
+    let config = request
+        .extensions()
+        .get::<Arc<ServerConfig>>()
+        .cloned()
+        .unwrap_or_default();
 
-    let speech_request: SpeechRequest = serde_json::from_slice(&bytes).map_err(|e| {
-        error!("JSON parsing error: {:?}", e);
-        SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
-    })?;
+    let speech_request: SpeechRequest = read_json(request.into_body()).await?;
 
     let SpeechRequest {
+        model,
         input,
         voice,
         response_format,
-        speed: Speed(speed),
+        speed,
         initial_silence,
         stream,
         lang_code,
+        normalization_options,
+        input_format,
+        code_blocks,
+        tables,
+        target_duration,
+        duration_tolerance,
+        fit,
+        pitch,
+        tempo,
         ..
     } = speech_request;
 
-    // Map OpenAI voice names to Kokoro voice names
-    let voice = voice.to_kokoro_voice();
-    let language = get_language_code(lang_code.as_deref(), &voice);
-
     // OpenAI-compliant behavior: Stream by default, only send complete file if stream: false
     let should_stream = stream.unwrap_or(false); // Default to not streaming
 
+    if !MODEL_IDS.contains(&model.as_str()) {
+        return Err(SpeechError::model_not_found(&model));
+    }
+    if input.trim().is_empty() {
+        return Err(SpeechError::invalid("'input' must not be empty", "input"));
+    }
+    // Resolve aliases and OpenAI voice names, with the defaults of an alias
+    let catalog = tts_single.voice_catalog();
+    let choice = config.choose(&voice.0, catalog);
+    let speed = speed
+        .map(|Speed(speed)| speed)
+        .or(choice.speed)
+        .unwrap_or(1.0);
+    if !(0.25..=4.0).contains(&speed) {
+        return Err(SpeechError::invalid(
+            format!("'speed' must be between 0.25 and 4.0, got {}", speed),
+            "speed",
+        ));
+    }
+    let pitch = pitch.unwrap_or(0.0);
+    Effects::new(pitch, 1.0).map_err(|e| SpeechError::invalid(e, "pitch"))?;
+    let effects =
+        Effects::new(pitch, tempo.unwrap_or(1.0)).map_err(|e| SpeechError::invalid(e, "tempo"))?;
+    let frontend = normalization_options.frontend()?;
+    let input_format: InputFormat = match input_format {
+        Some(format) => format
+            .parse()
+            .map_err(|e| SpeechError::invalid(e, "input_format"))?,
+        None => InputFormat::Text,
+    };
+    let mut document_options = DocumentOptions::default();
+    if let Some(policy) = code_blocks {
+        document_options.code_blocks = policy
+            .parse()
+            .map_err(|e| SpeechError::invalid(e, "code_blocks"))?;
+    }
+    if let Some(policy) = tables {
+        document_options.tables = policy
+            .parse()
+            .map_err(|e| SpeechError::invalid(e, "tables"))?;
+    }
+    let subtitled = input_format == InputFormat::Subtitles;
+    if let Some(target) = target_duration {
+        if !target.is_finite() || target < fit::MIN_TARGET_SEC {
+            return Err(SpeechError::invalid(
+                format!(
+                    "'target_duration' must be at least {} seconds, got {}",
+                    fit::MIN_TARGET_SEC,
+                    target
+                ),
+                "target_duration",
+            ));
+        }
+        if subtitled {
+            return Err(SpeechError::unsupported(
+                "'target_duration' does not apply to subtitles, each cue fits its own slot",
+                "target_duration",
+            ));
+        }
+    }
+    if effects.tempo != 1.0 && (target_duration.is_some() || subtitled) {
+        return Err(SpeechError::unsupported(
+            "'tempo' can't be combined with a target duration or subtitles, which set the pace",
+            "tempo",
+        ));
+    }
+    if should_stream && (target_duration.is_some() || subtitled) {
+        return Err(SpeechError::unsupported(
+            "Fitting speech to a duration or to subtitle slots can't be streamed",
+            if subtitled {
+                "input_format"
+            } else {
+                "target_duration"
+            },
+        ));
+    }
+    let mut fit_options = FitOptions {
+        initial_silence,
+        ..FitOptions::default()
+    };
+    if let Some(tolerance) = duration_tolerance {
+        if tolerance.is_nan() || tolerance < 0.0 {
+            return Err(SpeechError::invalid(
+                format!("'duration_tolerance' must be 0 or more, got {}", tolerance),
+                "duration_tolerance",
+            ));
+        }
+        fit_options.tolerance = tolerance;
+    }
+    if let Some(remainder) = fit {
+        fit_options.remainder = remainder
+            .parse()
+            .map_err(|e| SpeechError::invalid(e, "fit"))?;
+    }
+    let response_format = match response_format {
+        Some(format @ (AudioFormat::Aac | AudioFormat::Flac)) => {
+            return Err(SpeechError::unsupported(
+                format!(
+                    "response_format '{:?}' is not supported, use mp3, opus, wav or pcm",
+                    format
+                )
+                .to_lowercase(),
+                "response_format",
+            ));
+        }
+        Some(format) if should_stream && format != AudioFormat::Pcm => {
+            warn!(
+                "Streaming only supports response_format 'pcm', sending pcm instead of {:?}",
+                format
+            );
+            AudioFormat::Pcm
+        }
+        Some(format) => format,
+        None if should_stream => AudioFormat::Pcm,
+        None => AudioFormat::Mp3,
+    };
+
+    let voice = choice.voice;
+    tts_single.check_voice(&voice).map_err(SpeechError::Koko)?;
+    let language = get_language_code(lang_code.as_deref().or(choice.lang_code), &voice, catalog);
+    let prepare = |text: &str| {
+        let text = match choice.lexicon {
+            Some(lexicon) => lexicon.apply(text),
+            None => text.to_string(),
+        };
+        frontend.apply(&text, &language)
+    };
+    let mut cues = if subtitled {
+        subtitles::parse(&input)
+    } else {
+        Vec::new()
+    };
+    for cue in &mut cues {
+        cue.text = prepare(&cue.text);
+    }
+    let document = SpokenDocument::parse(&input, input_format, &document_options);
+    let input = prepare(&document.text);
+    if input.trim().is_empty() {
+        return Err(SpeechError::invalid(
+            "'input' has no text to read once markup is removed",
+            "input",
+        ));
+    }
+
+    let permit = slots.map(|slots| slots.try_acquire()).transpose()?;
+
     let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
     debug!(
         "{} Streaming decision: stream_param={:?}, final_decision={}",
         colored_request_id, stream, should_stream
     );
 
+    let detected = detected_languages(&tts_single, &input, &language, &voice);
+
     if should_stream {
-        return handle_tts_streaming(
+        let response = handle_tts_streaming(
             tts_instances,
             input,
             voice,
             response_format,
             speed,
             initial_silence,
+            effects,
             language.clone(),
             request_id,
             request_start,
+            permit,
         )
-        .await;
+        .await?;
+        return Ok(with_detected_languages(response, detected));
     }
 
     // Non-streaming mode (existing implementation)
-    let raw_audio = tts_single
-        .tts_raw_audio(
-            &input,
-            &language,
-            &voice,
-            speed,
-            initial_silence,
-            Some(&request_id),
-            Some("00"),
-            None,
-        )
+    let (raw_audio, fitted) = if subtitled {
+        // Fitting synthesizes every cue several times, off the async runtime
+        let tts = tts_single.clone();
+        let (language, voice) = (language.clone(), voice.clone());
+        let track = tokio::task::spawn_blocking(move || {
+            tts.tts_subtitles(&cues, &language, &voice, speed, &fit_options)
+        })
+        .await
+        .map_err(|e| SpeechError::Koko(KokorosError::Inference(e.to_string())))?
         .map_err(SpeechError::Koko)?;
+        let speech: f32 = track.cues.iter().map(|cue| cue.duration_sec).sum();
+        (track.samples, Some((speech, None)))
+    } else if let Some(target) = target_duration {
+        let tts = tts_single.clone();
+        let (input, language, voice) = (input.clone(), language.clone(), voice.clone());
+        let fitted = tokio::task::spawn_blocking(move || {
+            tts.tts_fitted(&input, &language, &voice, speed, target, &fit_options)
+        })
+        .await
+        .map_err(|e| SpeechError::Koko(KokorosError::Inference(e.to_string())))?
+        .map_err(SpeechError::Koko)?;
+        (
+            fitted.samples,
+            Some((fitted.duration_sec, Some(fitted.speed))),
+        )
+    } else {
+        let raw_audio = tts_single
+            .tts_raw_audio(
+                &input,
+                &language,
+                &voice,
+                speed,
+                initial_silence,
+                Some(&request_id),
+                Some("00"),
+                None,
+            )
+            .map_err(SpeechError::Koko)?;
+        (raw_audio, None)
+    };
+
+    let sample_rate = tts_single.sample_rate();
+    let raw_audio = effects.apply(&raw_audio, sample_rate);
+
+    let (content_type, audio_data, format_name) =
+        encode_audio(raw_audio, sample_rate, 1, &response_format)?;
+
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+    info!(
+        "{} TTS non-streaming completed - {} bytes, {} format",
+        colored_request_id,
+        audio_data.len(),
+        format_name
+    );
 
-    let sample_rate = TTSKokoInitConfig::default().sample_rate;
+    let mut response = Response::builder().header(header::CONTENT_TYPE, content_type);
+    if let Some((duration, fitted_speed)) = fitted {
+        response = response.header("X-Speech-Duration", format!("{:.3}", duration));
+        if let Some(fitted_speed) = fitted_speed {
+            response = response.header("X-Speech-Speed", format!("{:.3}", fitted_speed));
+        }
+    }
+    let response = response
+        .body(audio_data.into())
+        .map_err(SpeechError::Response)?;
+    Ok(with_detected_languages(response, detected))
+}
 
-    let (content_type, audio_data, format_name) = match response_format {
+/// Encode a whole recording. MP3 and Opus are mono only, multi-channel
+/// audio is rejected before synthesis.
+fn encode_audio(
+    raw_audio: Vec<f32>,
+    sample_rate: u32,
+    channels: u16,
+    response_format: &AudioFormat,
+) -> Result<(&'static str, Vec<u8>, &'static str), SpeechError> {
+    Ok(match response_format {
         AudioFormat::Wav => {
             let mut wav_data = Vec::default();
-            let header = WavHeader::new(1, sample_rate, 32);
+            let header = WavHeader::new(channels, sample_rate, 32);
             header
                 .write_header(&mut wav_data)
                 .map_err(SpeechError::Header)?;
@@ -648,29 +1177,258 @@ async fn handle_tts(
             }
             ("audio/pcm", pcm_data, "PCM")
         }
-        // For now, unsupported formats fall back to MP3
-        _ => {
-            let mp3_data =
-                pcm_to_mp3(&raw_audio, sample_rate).map_err(|e| SpeechError::Mp3Conversion(e))?;
+        AudioFormat::Aac | AudioFormat::Flac => unreachable!("rejected before synthesis"),
+    })
+}
 
-            ("audio/mpeg", mp3_data, "MP3")
+/// `language:voice` of every span, comma separated, when `lang_code` asks
+/// for detection
+fn detected_languages(tts: &TTSKoko, input: &str, language: &str, voice: &str) -> Option<String> {
+    if !detect::is_auto(language) {
+        return None;
+    }
+    let spans: Vec<String> = tts
+        .detect_languages(input, language, voice)
+        .into_iter()
+        .map(|span| format!("{}:{}", span.language, span.voice))
+        .collect();
+    Some(spans.join(","))
+}
+
+/// Report detected languages in the `X-Detected-Languages` header
+fn with_detected_languages(mut response: Response, detected: Option<String>) -> Response {
+    if let Some(value) = detected.and_then(|d| header::HeaderValue::from_str(&d).ok()) {
+        response.headers_mut().insert("X-Detected-Languages", value);
+    }
+    response
+}
+
+/// Handle /v1/audio/dialogue
+///
+/// Renders every turn with its speaker's voice into one recording, with
+/// `gap` seconds between turns and panned speakers in stereo.
+async fn handle_dialogue(
+    State((tts_single, _tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
+    request: axum::extract::Request,
+) -> Result<Response, SpeechError> {
+    let (request_id, request_start) = request
+        .extensions()
+        .get::<(String, Instant)>()
+        .cloned()
+        .unwrap_or_else(|| ("unknown".to_string(), Instant::now()));
+    let slots = request.extensions().get::<SpeechSlots>().cloned();
+    let config = request
+        .extensions()
+        .get::<Arc<ServerConfig>>()
+        .cloned()
+        .unwrap_or_default();
+
+    let DialogueRequest {
+        model,
+        script,
+        turns,
+        speakers,
+        response_format,
+        speed: Speed(speed),
+        gap,
+        lang_code,
+        timestamps,
+        normalization_options,
+    } = read_json(request.into_body()).await?;
+
+    if !MODEL_IDS.contains(&model.as_str()) {
+        return Err(SpeechError::model_not_found(&model));
+    }
+    if !(0.25..=4.0).contains(&speed) {
+        return Err(SpeechError::invalid(
+            format!("'speed' must be between 0.25 and 4.0, got {}", speed),
+            "speed",
+        ));
+    }
+    let frontend = normalization_options.frontend()?;
+
+    // Speakers sent apart, voices and aliases start turns of a text script
+    let catalog = tts_single.voice_catalog();
+    let known = |name: &str| {
+        speakers.contains_key(name)
+            || tts_single
+                .check_voice(&config.choose(name, catalog).voice)
+                .is_ok()
+    };
+    let mut script = match script {
+        Some(script) => Script::from_text_with(&script, !speakers.is_empty(), known)
+            .map_err(|e| SpeechError::invalid(e, "script"))?,
+        None => Script::default(),
+    };
+    script.turns.extend(turns.into_iter().map(|turn| Turn {
+        speaker: turn.speaker,
+        text: turn.text,
+    }));
+    script.turns.retain(|turn| !turn.text.trim().is_empty());
+    if script.turns.is_empty() {
+        return Err(SpeechError::invalid(
+            "the dialogue needs a 'script' or 'turns' with text",
+            "turns",
+        ));
+    }
+
+    // Voices may be aliases, whose defaults fill what the speaker leaves out
+    let mut lexicons: BTreeMap<String, &Lexicon> = BTreeMap::new();
+    let mut cast = |name: &str, voice: &str, speed: Option<f32>, lan: Option<String>, pan| {
+        let choice = config.choose(voice, catalog);
+        if let Some(lexicon) = choice.lexicon {
+            lexicons.insert(name.to_string(), lexicon);
+        }
+        Speaker {
+            voice: choice.voice,
+            speed: speed.or(choice.speed),
+            lan: lan.or_else(|| choice.lang_code.map(str::to_string)),
+            pan,
+        }
+    };
+    for (name, speaker) in &mut script.speakers {
+        let lan = speaker.lan.take();
+        *speaker = cast(name, &speaker.voice, speaker.speed, lan, speaker.pan);
+    }
+    for (name, speaker) in speakers {
+        let speaker = cast(
+            &name,
+            &speaker.voice.0,
+            speaker.speed,
+            speaker.lang_code,
+            speaker.pan.unwrap_or(0.0),
+        );
+        if let Some(speed) = speaker.speed
+            && !(0.25..=4.0).contains(&speed)
+        {
+            return Err(SpeechError::invalid(
+                format!("speaker '{}': speed must be between 0.25 and 4.0", name),
+                "speakers",
+            ));
+        }
+        if !(-1.0..=1.0).contains(&speaker.pan) {
+            return Err(SpeechError::invalid(
+                format!("speaker '{}': pan must be between -1.0 and 1.0", name),
+                "speakers",
+            ));
+        }
+        script.speakers.insert(name, speaker);
+    }
+    // Speakers may be named after voices or their aliases
+    for turn in &script.turns {
+        if script.speakers.contains_key(&turn.speaker) {
+            continue;
+        }
+        let speaker = cast(&turn.speaker, &turn.speaker, None, None, 0.0);
+        if tts_single.check_voice(&speaker.voice).is_err() {
+            return Err(SpeechError::invalid(
+                format!("speaker '{}' has no voice in 'speakers'", turn.speaker),
+                "speakers",
+            ));
+        }
+        script.speakers.insert(turn.speaker.clone(), speaker);
+    }
+    for speaker in script.speakers.values_mut() {
+        tts_single
+            .check_voice(&speaker.voice)
+            .map_err(SpeechError::Koko)?;
+        if speaker.lan.is_none() {
+            speaker.lan = Some(get_language_code(
+                lang_code.as_deref(),
+                &speaker.voice,
+                catalog,
+            ));
+        }
+    }
+    for turn in &mut script.turns {
+        let lan = script.speakers[&turn.speaker]
+            .lan
+            .as_deref()
+            .unwrap_or("en-us");
+        if let Some(lexicon) = lexicons.get(&turn.speaker) {
+            turn.text = lexicon.apply(&turn.text);
+        }
+        turn.text = frontend.apply(&turn.text, lan);
+    }
+
+    let stereo = script.speakers.values().any(|speaker| speaker.pan != 0.0);
+    let response_format = match response_format {
+        Some(format @ (AudioFormat::Aac | AudioFormat::Flac)) => {
+            return Err(SpeechError::unsupported(
+                format!(
+                    "response_format '{:?}' is not supported, use mp3, opus, wav or pcm",
+                    format
+                )
+                .to_lowercase(),
+                "response_format",
+            ));
         }
+        Some(format @ (AudioFormat::Mp3 | AudioFormat::Opus)) if stereo => {
+            return Err(SpeechError::unsupported(
+                format!(
+                    "panned dialogues are stereo, which response_format '{:?}' does not support; use wav or pcm",
+                    format
+                )
+                .to_lowercase(),
+                "response_format",
+            ));
+        }
+        Some(format) => format,
+        None if stereo => AudioFormat::Wav,
+        None => AudioFormat::Mp3,
     };
 
+    let _permit = slots.map(|slots| slots.try_acquire()).transpose()?;
+    let options = DialogueOptions {
+        lan: get_language_code(lang_code.as_deref(), "a", catalog),
+        speed,
+        gap: gap.unwrap_or(DialogueOptions::default().gap),
+        initial_silence: None,
+        timestamps,
+    };
+    let tts = tts_single.clone();
+    let dialogue = tokio::task::spawn_blocking(move || tts.tts_dialogue(&script, &options))
+        .await
+        .map_err(|e| SpeechError::Koko(KokorosError::Inference(e.to_string())))?
+        .map_err(SpeechError::Koko)?;
+
+    let words = dialogue.words;
+    let (content_type, audio_data, format_name) = encode_audio(
+        dialogue.samples,
+        tts_single.sample_rate(),
+        dialogue.channels,
+        &response_format,
+    )?;
+
     let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
     info!(
-        "{} TTS non-streaming completed - {} bytes, {} format",
+        "{} TTS dialogue completed - {} bytes, {} format, {} channels",
         colored_request_id,
         audio_data.len(),
-        format_name
+        format_name,
+        dialogue.channels
     );
 
-    Ok(Response::builder()
+    if timestamps {
+        return Ok(Json(CaptionedDialogue {
+            audio: base64::engine::general_purpose::STANDARD.encode(audio_data),
+            content_type,
+            words: words
+                .into_iter()
+                .map(|w| DialogueWord {
+                    speaker: w.speaker,
+                    word: w.word.word,
+                    start_sec: w.word.start_sec,
+                    end_sec: w.word.end_sec,
+                })
+                .collect(),
+        })
+        .into_response());
+    }
+    Response::builder()
         .header(header::CONTENT_TYPE, content_type)
         .body(audio_data.into())
-        .map_err(|e| {
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::Other, e))
-        })?)
+        .map_err(SpeechError::Response)
 }
 
 /// Handle streaming TTS requests with true async processing
@@ -684,21 +1442,27 @@ async fn handle_tts_streaming(
     response_format: AudioFormat,
     speed: f32,
     initial_silence: Option<usize>,
+    effects: Effects,
     language: String,
     request_id: String,
     request_start: Instant,
+    permit: Option<OwnedSemaphorePermit>,
 ) -> Result<Response, SpeechError> {
-    // Streaming implementation: PCM format for optimal performance
-    let content_type = match response_format {
-        AudioFormat::Pcm => "audio/pcm",
-        _ => "audio/pcm", // Force PCM for optimal streaming performance
-    };
+    // Streaming implementation: PCM format for optimal performance, other
+    // formats fall back to it before we get here
+    debug_assert_eq!(response_format, AudioFormat::Pcm);
+    let content_type = "audio/pcm";
+
+    let sample_rate = tts_instances
+        .first()
+        .map(|tts| tts.sample_rate())
+        .unwrap_or(24000);
 
     // Create worker pool with vector of TTS instances for true parallelism
     let worker_pool = TTSWorkerPool::new(tts_instances);
 
     // Create speech chunks based on word count and punctuation
-    let mut chunks = split_text_into_speech_chunks(&input, 10);
+    let mut chunks = segment::speech_chunks(&input, 10);
 
     // Add empty chunk at end as completion signal to client
     chunks.push(String::new());
@@ -713,10 +1477,7 @@ async fn handle_tts_streaming(
     );
 
     if chunks.is_empty() {
-        return Err(SpeechError::Mp3Conversion(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "No text to process",
-        )));
+        return Err(SpeechError::invalid("No text to process", "input"));
     }
 
     // Create channels for sequential chunk processing
@@ -767,8 +1528,10 @@ async fn handle_tts_streaming(
         let mut chunk_counter = 0;
         let mut pending_chunks: BTreeMap<
             usize,
-            tokio::task::JoinHandle<Result<(usize, Vec<u8>), String>>,
+            tokio::task::JoinHandle<Result<(usize, Vec<f32>), String>>,
         > = BTreeMap::new();
+        // Effects run on the chunks in order, carrying their state across
+        let mut effects_stream = (!effects.is_neutral()).then(|| effects.stream(sample_rate));
         let mut next_to_send = 0;
         let mut chunks_processed = 0;
         let window_size = worker_pool_clone.instance_count(); // Allow chunks to process in parallel up to available TTS instances
@@ -781,7 +1544,6 @@ async fn handle_tts_streaming(
                     Ok(task) => {
                         let task_id = task.id;
                         let worker_pool_clone = worker_pool_clone.clone();
-                        let total_bytes_clone = total_bytes_clone.clone();
                         let request_id_clone = request_id.clone();
 
                         // Process chunk with dedicated TTS instance (alternates between instances)
@@ -803,38 +1565,23 @@ async fn handle_tts_streaming(
                             }
 
                             let result = tokio::task::spawn_blocking(move || {
-                                let audio_result = tts_instance.tts_raw_audio(
-                                    &chunk_text,
-                                    &language,
-                                    &voice,
-                                    speed,
-                                    initial_silence,
-                                    Some(&request_id_clone),
-                                    Some(&actual_instance_id),
-                                    Some(chunk_num),
-                                );
-
-                                audio_result
-                                    .map(|audio| audio)
+                                tts_instance
+                                    .tts_raw_audio(
+                                        &chunk_text,
+                                        &language,
+                                        &voice,
+                                        speed,
+                                        initial_silence,
+                                        Some(&request_id_clone),
+                                        Some(&actual_instance_id),
+                                        Some(chunk_num),
+                                    )
                                     .map_err(|e| format!("TTS processing error: {:?}", e))
                             })
                             .await;
 
-                            // Convert audio to PCM
                             match result {
-                                Ok(Ok(audio_samples)) => {
-                                    let mut pcm_data = Vec::with_capacity(audio_samples.len() * 2);
-                                    for sample in audio_samples {
-                                        let pcm_sample =
-                                            (sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
-                                        pcm_data.extend_from_slice(&pcm_sample.to_le_bytes());
-                                    }
-                                    total_bytes_clone.fetch_add(
-                                        pcm_data.len(),
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    );
-                                    Ok((task_id, pcm_data))
-                                }
+                                Ok(Ok(audio_samples)) => Ok((task_id, audio_samples)),
                                 Ok(Err(e)) => Err(e),
                                 Err(e) => Err(format!("Task execution error: {:?}", e)),
                             }
@@ -858,8 +1605,16 @@ async fn handle_tts_streaming(
             if let Some(handle) = pending_chunks.remove(&next_to_send) {
                 if handle.is_finished() {
                     match handle.await {
-                        Ok(Ok((task_id, pcm_data))) => {
-                            if let Err(_) = audio_tx_clone.send((task_id, pcm_data)) {
+                        Ok(Ok((task_id, samples))) => {
+                            let last = task_id + 1 == total_chunks_expected;
+                            let samples = stream_effects(&mut effects_stream, samples, last).await;
+                            if !send_pcm(
+                                &audio_tx_clone,
+                                &total_bytes_clone,
+                                task_id,
+                                &samples,
+                                last,
+                            ) {
                                 break;
                             }
                             next_to_send += 1;
@@ -911,9 +1666,9 @@ async fn handle_tts_streaming(
 
         for (chunk_id, handle) in pending_chunks {
             match handle.await {
-                Ok(Ok((task_id, pcm_data))) => {
+                Ok(Ok((task_id, samples))) => {
                     // Collect all successful chunks regardless of order
-                    remaining_chunks.push((chunk_id, task_id, pcm_data));
+                    remaining_chunks.push((chunk_id, task_id, samples));
                 }
                 Ok(Err(_e)) => {
                     // TTS processing error - still count as processed
@@ -931,11 +1686,13 @@ async fn handle_tts_streaming(
         remaining_chunks.sort_by_key(|(chunk_id, _, _)| *chunk_id);
 
         // Send all remaining chunks in order, preventing data loss
-        for (chunk_id, task_id, pcm_data) in remaining_chunks {
+        for (chunk_id, task_id, samples) in remaining_chunks {
             // Only send chunks that are in the expected sequence (>= next_to_send)
             // This prevents duplicate sends while ensuring no valid chunks are skipped
             if chunk_id >= next_to_send {
-                let _ = audio_tx_clone.send((task_id, pcm_data));
+                let last = task_id + 1 == total_chunks_expected;
+                let samples = stream_effects(&mut effects_stream, samples, last).await;
+                send_pcm(&audio_tx_clone, &total_bytes_clone, task_id, &samples, last);
                 chunks_processed += 1;
             }
         }
@@ -944,9 +1701,9 @@ async fn handle_tts_streaming(
 
         // Log completion
         let bytes_transferred = total_bytes.load(std::sync::atomic::Ordering::Relaxed);
-        // Calculate audio duration: 16-bit PCM (2 bytes per sample) at 24000 Hz
+        // Calculate audio duration: 16-bit PCM (2 bytes per sample) at the model sample rate
         let total_samples = bytes_transferred / 2;
-        let duration_seconds = total_samples as f64 / 24000.0;
+        let duration_seconds = total_samples as f64 / sample_rate as f64;
         let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
         info!(
             "{} TTS session completed - {} chunks, {} bytes, {:.1}s audio, PCM format",
@@ -955,6 +1712,9 @@ async fn handle_tts_streaming(
 
         // Send termination signal
         let _ = audio_tx.send((total_chunks, vec![])); // Empty data as termination signal
+
+        // Free the request's slot only once all of its chunks are synthesized
+        drop(permit);
     });
 
     // No ordering needed - sequential processing guarantees order
@@ -987,35 +1747,176 @@ async fn handle_tts_streaming(
         .header("Transfer-Encoding", "chunked") // Enable HTTP chunked transfer encoding
         .header("Access-Control-Allow-Origin", "*") // CORS for browser clients
         .body(body)
-        .map_err(|e| {
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::Other, e))
-        })?)
+        .map_err(SpeechError::Response)?)
+}
+
+/// The next chunk of a stream through its effects, off the async runtime
+async fn stream_effects(
+    effects: &mut Option<EffectsStream>,
+    samples: Vec<f32>,
+    last: bool,
+) -> Vec<f32> {
+    let Some(mut stream) = effects.take() else {
+        return samples;
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let mut out = stream.process(&samples);
+        if last {
+            out.extend(stream.finish());
+        }
+        (stream, out)
+    })
+    .await;
+    match result {
+        Ok((stream, out)) => {
+            *effects = Some(stream);
+            out
+        }
+        Err(e) => {
+            error!("Effects failed on a streamed chunk: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Send samples of a stream as 16-bit PCM, followed by the empty chunk
+/// that ends it after the `last` one. False once the client is gone.
+fn send_pcm(
+    audio_tx: &mpsc::UnboundedSender<(usize, Vec<u8>)>,
+    total_bytes: &std::sync::atomic::AtomicUsize,
+    task_id: usize,
+    samples: &[f32],
+    last: bool,
+) -> bool {
+    let mut pcm_data = Vec::with_capacity(samples.len() * 2);
+    for sample in samples {
+        let pcm_sample = (sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
+        pcm_data.extend_from_slice(&pcm_sample.to_le_bytes());
+    }
+    total_bytes.fetch_add(pcm_data.len(), std::sync::atomic::Ordering::Relaxed);
+    // An empty chunk ends the stream, so only the last one may be empty
+    if !pcm_data.is_empty() && audio_tx.send((task_id, pcm_data)).is_err() {
+        return false;
+    }
+    !last || audio_tx.send((task_id, Vec::new())).is_ok()
 }
 
 async fn handle_voices(
     State((tts_single, _tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
+    Extension(config): Extension<Arc<ServerConfig>>,
 ) -> Json<VoicesResponse> {
+    let catalog = tts_single.voice_catalog();
+    let configured: Vec<(&str, String)> = config.aliases(catalog).collect();
+    let mut data: Vec<VoiceEntry> = catalog.voices().map(VoiceEntry::from).collect();
+    for entry in &mut data {
+        // Configured aliases replace catalogue ones of the same name
+        entry.aliases.retain(|alias| !config.defines(alias));
+        entry.aliases.extend(
+            configured
+                .iter()
+                .filter(|(_, voice)| *voice == entry.id)
+                .map(|(name, _)| name.to_string()),
+        );
+    }
+
     let mut voices = tts_single.get_available_voices();
+    // Catalogue aliases, from `voices.json`, are accepted as voices too
+    voices.extend(
+        catalog
+            .aliases()
+            .filter(|(alias, _)| !config.defines(alias))
+            .map(|(alias, _)| alias.to_string()),
+    );
+    voices.extend(configured.iter().map(|(name, _)| name.to_string()));
+    Json(VoicesResponse {
+        voices,
+        data,
+        aliases: config
+            .voices
+            .iter()
+            .zip(&configured)
+            .map(|((name, alias), (_, voice))| AliasEntry {
+                name: name.clone(),
+                voice: voice.clone(),
+                speed: alias.speed,
+                lang_code: alias.lang_code.clone(),
+            })
+            .collect(),
+    })
+}
+
+/// Handle /v1/audio/voices/{voice}/similar endpoint
+///
+/// Lists the loaded voices nearest to a voice, alias or blend, optionally
+/// only those of a language or gender.
+async fn handle_similar_voices(
+    State((tts_single, _tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(voice): Path<String>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<Json<SimilarResponse>, SpeechError> {
+    let gender = query
+        .gender
+        .map(|gender| gender.parse::<Gender>())
+        .transpose()
+        .map_err(|e| SpeechError::invalid(e, "gender"))?;
+    let filter = VoiceFilter {
+        language: query.language,
+        gender,
+    };
+    let catalog = tts_single.voice_catalog();
+    let voice = config.choose(&voice, catalog).voice;
+    let neighbours = tts_single
+        .similar_voices(&voice, &filter, query.limit.unwrap_or(5))
+        .map_err(SpeechError::Koko)?;
+    Ok(Json(SimilarResponse {
+        voice,
+        data: neighbours
+            .iter()
+            .map(|neighbour| SimilarEntry {
+                voice: VoiceEntry::from(&neighbour.meta),
+                distance: neighbour.distance,
+            })
+            .collect(),
+    }))
+}
+
+/// Handle /v1/runtime endpoint
+///
+/// Reports the execution provider the ONNX sessions ended up using, after any
+/// fallback, along with the providers compiled into this build.
+async fn handle_runtime(
+    State((tts_single, tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
+) -> Json<RuntimeResponse> {
+    Json(RuntimeResponse {
+        execution_provider: tts_single.execution_provider().map(|p| p.to_string()),
+        compiled_providers: ExecutionProvider::compiled()
+            .into_iter()
+            .map(|p| p.to_string())
+            .collect(),
+        instances: tts_instances.len(),
+    })
+}
 
-    // Add OpenAI voice names for compatibility
-    let openai_voices = vec![
-        "alloy".to_string(),
-        "echo".to_string(),
-        "nova".to_string(),
-        "onyx".to_string(),
-        "shimmer".to_string(),
-        "fable".to_string(),
-        "coral".to_string(),
-        "sage".to_string(),
-        "marin".to_string(),
-        "ash".to_string(),
-        "ballad".to_string(),
-        "verse".to_string(),
-        "cedar".to_string(),
-    ];
-
-    voices.extend(openai_voices);
-    Json(VoicesResponse { voices })
+/// Handle /v1/model-info endpoint
+///
+/// Returns the tensor names, element types and shapes resolved when the model
+/// was loaded.
+async fn handle_model_info(
+    State((tts_single, _tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
+) -> Result<Json<ModelInfoResponse>, StatusCode> {
+    let signature = tts_single
+        .model_signature()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    Ok(Json(ModelInfoResponse {
+        tokens: (&signature.tokens).into(),
+        style: (&signature.style).into(),
+        speed: (&signature.speed).into(),
+        audio: (&signature.audio).into(),
+        durations: signature.durations.as_ref().map(TensorInfo::from),
+        timestamped: signature.is_timestamped(),
+    }))
 }
 
 /// Handle /v1/models endpoint
@@ -1023,69 +1924,27 @@ async fn handle_voices(
 /// Returns a static list of models for OpenAI API compatibility.
 /// Note: All models use the same underlying Kokoro TTS engine.
 async fn handle_models() -> Json<ModelsResponse> {
-    let models = vec![
-        ModelObject {
-            id: "tts-1".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        ModelObject {
-            id: "tts-1-hd".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        ModelObject {
-            id: "kokoro".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        ModelObject {
-            id: "gpt-4o-mini-tts".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-    ];
-
     Json(ModelsResponse {
         object: "list".to_string(),
-        data: models,
+        data: MODEL_IDS.iter().map(|id| model_object(id)).collect(),
     })
 }
 
-async fn handle_model(Path(model_id): Path<String>) -> Result<Json<ModelObject>, StatusCode> {
-    let model = match model_id.as_str() {
-        "tts-1" => ModelObject {
-            id: "tts-1".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        "tts-1-hd" => ModelObject {
-            id: "tts-1-hd".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        "kokoro" => ModelObject {
-            id: "kokoro".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        "gpt-4o-mini-tts" => ModelObject {
-            id: "tts-1-hd".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        _ => return Err(StatusCode::NOT_FOUND),
-    };
+async fn handle_model(Path(model_id): Path<String>) -> Result<Json<ModelObject>, SpeechError> {
+    if !MODEL_IDS.contains(&model_id.as_str()) {
+        return Err(SpeechError::model_not_found(&model_id));
+    }
 
-    Ok(Json(model))
+    Ok(Json(model_object(&model_id)))
+}
+
+fn model_object(id: &str) -> ModelObject {
+    ModelObject {
+        id: id.to_string(),
+        object: "model".to_string(),
+        created: 1686935002,
+        owned_by: "kokoro".to_string(),
+    }
 }
 
 fn get_colored_request_id_with_relative(request_id: &str, start_time: Instant) -> String {
@@ -1123,3 +1982,189 @@ async fn request_id_middleware(
 
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    /// Status and JSON body of an error response
+    async fn error_body(response: Response) -> (StatusCode, serde_json::Value) {
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_speech_error_parts() {
+        let io_error = || io::Error::other("broken pipe");
+        let http_error = axum::http::Response::builder()
+            .status(1000)
+            .body(())
+            .unwrap_err();
+        let cases = vec![
+            (
+                SpeechError::invalid("bad speed", "speed"),
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("speed"),
+                None,
+            ),
+            (
+                SpeechError::InvalidRequest {
+                    message: "Invalid JSON body".to_string(),
+                    param: None,
+                },
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                None,
+                None,
+            ),
+            (
+                SpeechError::model_not_found("tts-9"),
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                Some("model"),
+                Some("model_not_found"),
+            ),
+            (
+                SpeechError::PayloadTooLarge,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "invalid_request_error",
+                None,
+                Some("request_too_large"),
+            ),
+            (
+                SpeechError::unsupported("no flac", "response_format"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_request_error",
+                Some("response_format"),
+                None,
+            ),
+            (
+                SpeechError::Overloaded,
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_error",
+                None,
+                Some("overloaded"),
+            ),
+            (
+                SpeechError::Koko(KokorosError::UnknownVoice("zz_nobody".to_string())),
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                Some("voice"),
+                Some("voice_not_found"),
+            ),
+            (
+                SpeechError::Koko(KokorosError::InvalidVoice("af_sky:x".to_string())),
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("voice"),
+                None,
+            ),
+            (
+                SpeechError::Koko(KokorosError::InputTooLong {
+                    tokens: 600,
+                    max: 510,
+                }),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "invalid_request_error",
+                Some("input"),
+                Some("input_too_long"),
+            ),
+            (
+                SpeechError::Koko(KokorosError::Document("no chapters".to_string())),
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                Some("input"),
+                None,
+            ),
+            (
+                SpeechError::Koko(KokorosError::InvalidInput("speed 9".to_string())),
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                None,
+                None,
+            ),
+            (
+                SpeechError::Koko(KokorosError::Phonemization("xx".to_string())),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_request_error",
+                Some("lang_code"),
+                Some("unsupported_language"),
+            ),
+        ];
+        let server_errors = [
+            SpeechError::Koko(KokorosError::ModelLoad("missing".to_string())),
+            SpeechError::Koko(KokorosError::VoiceLoad("missing".to_string())),
+            SpeechError::Koko(KokorosError::Inference("session".to_string())),
+            SpeechError::Koko(KokorosError::Encoding("wav".to_string())),
+            SpeechError::Koko(KokorosError::Io(io_error())),
+            SpeechError::Header(io_error()),
+            SpeechError::Chunk(io_error()),
+            SpeechError::Mp3Conversion(io_error()),
+            SpeechError::OpusConversion(io_error()),
+            SpeechError::Response(http_error),
+        ];
+        let cases = cases.into_iter().chain(server_errors.into_iter().map(|e| {
+            (
+                e,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                None,
+                None,
+            )
+        }));
+
+        for (error, status, kind, param, code) in cases {
+            let name = format!("{:?}", error);
+            assert_eq!(error.parts(), (status, param, code), "{}", name);
+            let (found, body) = error_body(error.into_response()).await;
+            assert_eq!(found, status, "{}", name);
+            assert_eq!(body["error"]["type"], kind, "{}", name);
+            assert_eq!(body["error"]["param"].as_str(), param, "{}", name);
+            assert_eq!(body["error"]["code"].as_str(), code, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_hide_details() {
+        let error = SpeechError::Koko(KokorosError::Inference("CUDA out of memory".to_string()));
+        let (_, body) = error_body(error.into_response()).await;
+        assert_eq!(
+            body["error"]["message"],
+            "The server failed to generate audio"
+        );
+
+        let response = SpeechError::Overloaded.into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn test_handle_model() {
+        let Json(model) = handle_model(Path("tts-1".to_string())).await.unwrap();
+        assert_eq!(model.id, "tts-1");
+
+        let response = handle_model(Path("tts-9".to_string()))
+            .await
+            .into_response();
+        let (status, body) = error_body(response).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["message"], "The model 'tts-9' does not exist");
+        assert_eq!(body["error"]["code"], "model_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_read_json_errors() {
+        let error = read_json::<serde_json::Value>(Body::from("{\"input\":"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.parts(), (StatusCode::BAD_REQUEST, None, None));
+
+        let oversized = vec![b' '; MAX_REQUEST_BYTES + 1];
+        let error = read_json::<serde_json::Value>(Body::from(oversized))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SpeechError::PayloadTooLarge));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+use std::io;
+
+/// Errors returned by the kokoros library.
+///
+/// Every variant owns plain data so the error is `Send + Sync` and can be
+/// moved out of blocking tasks.
+#[derive(Debug)]
+pub enum KokorosError {
+    /// The ONNX model or its config could not be downloaded, read or initialised
+    ModelLoad(String),
+    /// The voices file could not be downloaded, read or parsed
+    VoiceLoad(String),
+    /// A requested voice is not part of the loaded voice pack
+    UnknownVoice(String),
+    /// A voice blend does not parse, or a voice cannot be saved under a name
+    InvalidVoice(String),
+    /// A chunk has more phoneme tokens than the voice style table covers
+    InputTooLong {
+        tokens: usize,
+        max: usize,
+    },
+    /// Text could not be converted to phonemes
+    Phonemization(String),
+    /// Running the ONNX session or reading its outputs failed
+    Inference(String),
+    /// Encoding audio (WAV, MP3, Opus) failed
+    Encoding(String),
+    /// A book or other input document could not be read
+    Document(String),
+    /// A setting of the request, such as a speed or a duration, is out of range
+    InvalidInput(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for KokorosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KokorosError::ModelLoad(e) => write!(f, "Failed to load model: {}", e),
+            KokorosError::VoiceLoad(e) => write!(f, "Failed to load voices: {}", e),
+            KokorosError::UnknownVoice(name) => write!(f, "Unknown voice: {}", name),
+            KokorosError::InvalidVoice(e) => write!(f, "Invalid voice: {}", e),
+            KokorosError::InputTooLong { tokens, max } => write!(
+                f,
+                "Input too long: {} phoneme tokens in one chunk, at most {} are supported",
+                tokens, max
+            ),
+            KokorosError::Phonemization(e) => write!(f, "Phonemization failed: {}", e),
+            KokorosError::Inference(e) => write!(f, "Inference failed: {}", e),
+            KokorosError::Encoding(e) => write!(f, "Audio encoding failed: {}", e),
+            KokorosError::Document(e) => write!(f, "Failed to read document: {}", e),
+            KokorosError::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            KokorosError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KokorosError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KokorosError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KokorosError {
+    fn from(e: io::Error) -> Self {
+        KokorosError::Io(e)
+    }
+}
+
+impl From<ort::Error> for KokorosError {
+    fn from(e: ort::Error) -> Self {
+        KokorosError::Inference(e.to_string())
+    }
+}
+
+impl From<hound::Error> for KokorosError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => KokorosError::Io(e),
+            e => KokorosError::Encoding(e.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, KokorosError>;
@@ -1,3 +1,4 @@
+pub mod error;
 pub mod onn;
 pub mod tts;
 pub mod utils;
@@ -1,2 +1,4 @@
 pub mod ort_base;
 pub mod ort_koko;
+pub mod providers;
+pub mod signature;
@@ -1,30 +1,54 @@
-use ort::ep;
 use ort::logging::LogLevel;
 use ort::session::Session;
 use ort::session::builder::SessionBuilder;
 
+use super::providers::{ExecutionProvider, fallback_chain};
+
+fn build_session(model_path: &str, provider: ExecutionProvider) -> Result<Session, String> {
+    SessionBuilder::new()
+        .map_err(|e| format!("Failed to create session builder: {}", e))?
+        .with_execution_providers([provider.dispatch()])
+        .map_err(|e| format!("Failed to register {}: {}", provider, e))?
+        .with_log_level(LogLevel::Warning)
+        .map_err(|e| format!("Failed to set log level: {}", e))?
+        .commit_from_file(model_path)
+        .map_err(|e| format!("Failed to commit from file: {}", e))
+}
+
 pub trait OrtBase {
-    fn load_model(&mut self, model_path: String) -> Result<(), String> {
-        #[cfg(feature = "cuda")]
-        let providers = [ep::CUDA::default().build()];
-
-        #[cfg(not(feature = "cuda"))]
-        let providers = [ep::CPU::default().build()];
-
-        match SessionBuilder::new() {
-            Ok(builder) => {
-                let session = builder
-                    .with_execution_providers(providers)
-                    .map_err(|e| format!("Failed to build session: {}", e))?
-                    .with_log_level(LogLevel::Warning)
-                    .map_err(|e| format!("Failed to set log level: {}", e))?
-                    .commit_from_file(model_path)
-                    .map_err(|e| format!("Failed to commit from file: {}", e))?;
-                self.set_sess(session);
-                Ok(())
+    /// Load the model, trying each provider in `providers` in order and
+    /// falling back to the CPU. Returns the provider that was activated.
+    fn load_model(
+        &mut self,
+        model_path: String,
+        providers: &[ExecutionProvider],
+    ) -> Result<ExecutionProvider, String> {
+        let mut last_error = None;
+
+        for provider in fallback_chain(providers) {
+            match build_session(&model_path, provider) {
+                Ok(session) => {
+                    tracing::info!(
+                        "ONNX Runtime session using '{}' execution provider",
+                        provider
+                    );
+                    // A signature mismatch is a property of the model, not the provider
+                    self.set_sess(session)?;
+                    self.set_provider(provider);
+                    return Ok(provider);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Execution provider '{}' unavailable, falling back: {}",
+                        provider,
+                        e
+                    );
+                    last_error = Some(e);
+                }
             }
-            Err(e) => Err(format!("Failed to create session builder: {}", e)),
         }
+
+        Err(last_error.unwrap_or_else(|| "No execution provider could be registered".to_string()))
     }
 
     fn print_info(&self) {
@@ -38,16 +62,17 @@ pub trait OrtBase {
                 eprintln!("  - {}", output.name());
             }
 
-            #[cfg(feature = "cuda")]
-            eprintln!("Configured with: CUDA execution provider");
-
-            #[cfg(not(feature = "cuda"))]
-            eprintln!("Configured with: CPU execution provider");
+            match self.provider() {
+                Some(provider) => eprintln!("Configured with: {} execution provider", provider),
+                None => eprintln!("Configured with: unknown execution provider"),
+            }
         } else {
             eprintln!("Session is not initialized.");
         }
     }
 
-    fn set_sess(&mut self, sess: Session);
+    fn set_sess(&mut self, sess: Session) -> Result<(), String>;
     fn sess(&self) -> Option<&Session>;
+    fn set_provider(&mut self, provider: ExecutionProvider);
+    fn provider(&self) -> Option<ExecutionProvider>;
 }
@@ -1,32 +1,18 @@
 use std::borrow::Cow;
 
 use super::ort_base;
+use super::providers::ExecutionProvider;
+use super::signature::{ElementType, ModelSignature, TensorNames, TensorSpec};
+use crate::error::KokorosError;
 use crate::utils::debug::format_debug_prefix;
-use model_schema::v1_0_timestamped::DURATIONS;
+use half::f16;
 use ndarray::{ArrayBase, IxDyn, OwnedRepr};
 use ort::{
-    session::{Session, SessionInputValue, SessionInputs},
-    value::{Tensor, Value},
+    session::{Session, SessionInputValue, SessionInputs, SessionOutputs},
+    value::{DynValue, Tensor, Value},
 };
 use ort_base::OrtBase;
 
-mod model_schema {
-    pub const STYLE: &str = "style";
-    pub const SPEED: &str = "speed";
-
-    pub mod v1_0 {
-        pub const TOKENS: &str = "tokens";
-        pub const AUDIO: &str = "audio";
-    }
-
-    pub mod v1_0_timestamped {
-        pub const TOKENS: &str = "input_ids";
-        pub const AUDIO: &str = "waveform";
-        // We define primary and fallback keys as a const array
-        pub const DURATIONS: &str = "durations";
-    }
-}
-
 pub enum ModelStrategy {
     Standard(Session),
     Timestamped(Session),
@@ -34,43 +20,37 @@ pub enum ModelStrategy {
 
 pub struct OrtKoko {
     inner: Option<ModelStrategy>,
-}
-
-impl ModelStrategy {
-    fn audio_key(&self) -> &'static str {
-        match self {
-            ModelStrategy::Standard(_) => model_schema::v1_0::AUDIO,
-            ModelStrategy::Timestamped(_) => model_schema::v1_0_timestamped::AUDIO,
-        }
-    }
-
-    fn tokens_key(&self) -> &'static str {
-        match self {
-            ModelStrategy::Standard(_) => model_schema::v1_0::TOKENS,
-            ModelStrategy::Timestamped(_) => model_schema::v1_0_timestamped::TOKENS,
-        }
-    }
+    signature: Option<ModelSignature>,
+    tensor_names: TensorNames,
+    provider: Option<ExecutionProvider>,
 }
 
 impl OrtBase for OrtKoko {
-    fn set_sess(&mut self, sess: Session) {
-        let output_count = sess.outputs().len();
+    fn set_sess(&mut self, sess: Session) -> Result<(), String> {
+        let signature = ModelSignature::from_session(&sess, &self.tensor_names)?;
 
-        let strategy = if output_count > 1 {
+        tracing::info!(
+            "OrtKoko: tokens {}, style {}, speed {}, audio {}",
+            signature.tokens,
+            signature.style,
+            signature.speed,
+            signature.audio
+        );
+
+        let strategy = if let Some(durations) = &signature.durations {
             tracing::info!(
-                "OrtKoko: Timestamped backend activated ({} outputs)",
-                output_count
+                "OrtKoko: Timestamped backend activated (durations {})",
+                durations
             );
             ModelStrategy::Timestamped(sess)
         } else {
-            tracing::info!(
-                "OrtKoko: Standard backend activated ({} output)",
-                output_count
-            );
+            tracing::info!("OrtKoko: Standard backend activated");
             ModelStrategy::Standard(sess)
         };
 
+        self.signature = Some(signature);
         self.inner = Some(strategy);
+        Ok(())
     }
 
     fn sess(&self) -> Option<&Session> {
@@ -79,11 +59,30 @@ impl OrtBase for OrtKoko {
             ModelStrategy::Timestamped(sess) => sess,
         })
     }
+
+    fn set_provider(&mut self, provider: ExecutionProvider) {
+        self.provider = Some(provider);
+    }
+
+    fn provider(&self) -> Option<ExecutionProvider> {
+        self.provider
+    }
 }
 impl OrtKoko {
-    pub fn new(model_path: String) -> Result<Self, String> {
-        let mut instance = OrtKoko { inner: None };
-        instance.load_model(model_path)?;
+    pub fn new(
+        model_path: String,
+        providers: &[ExecutionProvider],
+        tensor_names: &TensorNames,
+    ) -> Result<Self, KokorosError> {
+        let mut instance = OrtKoko {
+            inner: None,
+            signature: None,
+            tensor_names: tensor_names.clone(),
+            provider: None,
+        };
+        instance
+            .load_model(model_path, providers)
+            .map_err(KokorosError::ModelLoad)?;
         Ok(instance)
     }
 
@@ -91,41 +90,107 @@ impl OrtKoko {
         self.inner.as_ref()
     }
 
+    pub fn signature(&self) -> Option<&ModelSignature> {
+        self.signature.as_ref()
+    }
+
+    fn float_tensor(
+        shape: Vec<usize>,
+        data: Vec<f32>,
+        dtype: ElementType,
+    ) -> Result<SessionInputValue<'static>, KokorosError> {
+        let value = match dtype {
+            ElementType::F16 => Value::from(Tensor::from_array((
+                shape,
+                data.into_iter().map(f16::from_f32).collect::<Vec<f16>>(),
+            ))?),
+            _ => Value::from(Tensor::from_array((shape, data))?),
+        };
+        Ok(SessionInputValue::Owned(value))
+    }
+
     fn prepare_inputs(
-        tokens_key: &'static str,
+        signature: &ModelSignature,
         tokens: Vec<Vec<i64>>,
         styles: Vec<Vec<f32>>,
         speed: f32,
-    ) -> Result<Vec<(Cow<'static, str>, SessionInputValue<'static>)>, Box<dyn std::error::Error>>
-    {
-        let shape = [tokens.len(), tokens[0].len()];
-        let tokens_tensor =
-            Tensor::from_array((shape, tokens.into_iter().flatten().collect::<Vec<i64>>()))?;
-
-        let shape_style = [styles.len(), styles[0].len()];
-        let style_tensor = Tensor::from_array((
+    ) -> Result<Vec<(Cow<'static, str>, SessionInputValue<'static>)>, KokorosError> {
+        let shape = vec![tokens.len(), tokens[0].len()];
+        let tokens_flat = tokens.into_iter().flatten();
+        let tokens_value = match signature.tokens.dtype {
+            ElementType::I32 => Value::from(Tensor::from_array((
+                shape,
+                tokens_flat.map(|t| t as i32).collect::<Vec<i32>>(),
+            ))?),
+            _ => Value::from(Tensor::from_array((
+                shape,
+                tokens_flat.collect::<Vec<i64>>(),
+            ))?),
+        };
+
+        let shape_style = vec![styles.len(), styles[0].len()];
+        let style_value = Self::float_tensor(
             shape_style,
-            styles.into_iter().flatten().collect::<Vec<f32>>(),
-        ))?;
+            styles.into_iter().flatten().collect(),
+            signature.style.dtype,
+        )?;
 
-        let speed_tensor = Tensor::from_array(([1], vec![speed]))?;
+        // Some exports take the speed as a scalar rather than a 1-element vector
+        let speed_shape = if signature.speed.shape.is_empty() {
+            vec![]
+        } else {
+            vec![1]
+        };
+        let speed_value = Self::float_tensor(speed_shape, vec![speed], signature.speed.dtype)?;
 
         Ok(vec![
             (
-                Cow::Borrowed(tokens_key),
-                SessionInputValue::Owned(Value::from(tokens_tensor)),
-            ),
-            (
-                Cow::Borrowed(model_schema::STYLE),
-                SessionInputValue::Owned(Value::from(style_tensor)),
-            ),
-            (
-                Cow::Borrowed(model_schema::SPEED),
-                SessionInputValue::Owned(Value::from(speed_tensor)),
+                Cow::Owned(signature.tokens.name.clone()),
+                SessionInputValue::Owned(tokens_value),
             ),
+            (Cow::Owned(signature.style.name.clone()), style_value),
+            (Cow::Owned(signature.speed.name.clone()), speed_value),
         ])
     }
 
+    /// Read an output tensor as f32 regardless of the element type the model uses.
+    fn extract_f32(
+        outputs: &SessionOutputs,
+        tensor: &TensorSpec,
+    ) -> Result<(Vec<usize>, Vec<f32>), KokorosError> {
+        let value = outputs.get(tensor.name.as_str()).ok_or_else(|| {
+            KokorosError::Inference(format!("Model did not return output '{}'", tensor.name))
+        })?;
+        Self::value_f32(value, tensor.dtype)
+    }
+
+    /// Read a tensor of `dtype` as f32
+    fn value_f32(
+        value: &DynValue,
+        dtype: ElementType,
+    ) -> Result<(Vec<usize>, Vec<f32>), KokorosError> {
+        let (shape, data) = match dtype {
+            ElementType::F32 => {
+                let (shape, data) = value.try_extract_tensor::<f32>()?;
+                (shape, data.to_vec())
+            }
+            ElementType::F16 => {
+                let (shape, data) = value.try_extract_tensor::<f16>()?;
+                (shape, data.iter().map(|v| v.to_f32()).collect())
+            }
+            ElementType::I64 => {
+                let (shape, data) = value.try_extract_tensor::<i64>()?;
+                (shape, data.iter().map(|&v| v as f32).collect())
+            }
+            ElementType::I32 => {
+                let (shape, data) = value.try_extract_tensor::<i32>()?;
+                (shape, data.iter().map(|&v| v as f32).collect())
+            }
+        };
+
+        Ok((shape.iter().map(|&i| i as usize).collect(), data))
+    }
+
     pub fn infer(
         &mut self,
         tokens: Vec<Vec<i64>>,
@@ -134,8 +199,7 @@ impl OrtKoko {
         request_id: Option<&str>,
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
-    ) -> Result<(ArrayBase<OwnedRepr<f32>, IxDyn>, Option<Vec<f32>>), Box<dyn std::error::Error>>
-    {
+    ) -> Result<(ArrayBase<OwnedRepr<f32>, IxDyn>, Option<Vec<f32>>), KokorosError> {
         let debug_prefix = format_debug_prefix(request_id, instance_id);
         let chunk_info = chunk_number
             .map(|n| format!("Chunk: {}, ", n))
@@ -147,46 +211,113 @@ impl OrtKoko {
             tokens.len()
         );
 
-        let strategy = self.inner.as_mut().ok_or("Session is not initialized.")?;
-        let audio_key = strategy.audio_key();
-        let tokens_key = strategy.tokens_key();
-        let inputs = Self::prepare_inputs(tokens_key, tokens.clone(), styles, speed)?;
-        match strategy {
-            ModelStrategy::Standard(sess) => {
-                let outputs = sess.run(SessionInputs::from(inputs))?;
+        let not_initialized = || KokorosError::Inference("Session is not initialized.".into());
+        let signature = self.signature.as_ref().ok_or_else(not_initialized)?;
+        let inputs = Self::prepare_inputs(signature, tokens, styles, speed)?;
+        let strategy = self.inner.as_mut().ok_or_else(not_initialized)?;
+        let sess = match strategy {
+            ModelStrategy::Standard(sess) | ModelStrategy::Timestamped(sess) => sess,
+        };
 
-                let (shape, data) = outputs[audio_key]
-                    .try_extract_tensor::<f32>()
-                    .or_else(|_| outputs["waveforms"].try_extract_tensor::<f32>())
-                    .map_err(|_| "Standard Model: Could not find 'audio' output")?;
+        let outputs = sess.run(SessionInputs::from(inputs))?;
 
-                let shape_vec: Vec<usize> = shape.into_iter().map(|&i| i as usize).collect();
-                let audio_array = ArrayBase::from_shape_vec(shape_vec, data.to_vec())?;
+        let (shape_vec, data) = Self::extract_f32(&outputs, &signature.audio)?;
+        let audio_array = ArrayBase::from_shape_vec(shape_vec, data)
+            .map_err(|e| KokorosError::Inference(e.to_string()))?;
 
-                Ok((audio_array, None))
-            }
-            ModelStrategy::Timestamped(sess) => {
-                let outputs = sess.run(SessionInputs::from(inputs))?;
-
-                let (shape, data) = outputs[audio_key]
-                    .try_extract_tensor::<f32>()
-                    .or_else(|_| outputs["audio"].try_extract_tensor::<f32>())
-                    .map_err(|_| "Timestamped Model: Could not find 'waveforms' or 'audio'")?;
-
-                let shape_vec: Vec<usize> = shape.into_iter().map(|&i| i as usize).collect();
-                let audio_array = ArrayBase::from_shape_vec(shape_vec, data.to_vec())?;
-
-                let durations_vec = outputs[DURATIONS]
-                    .try_extract_tensor::<f32>()
-                    .map(|(_, d)| d.to_vec())
-                    .map_err(|_| format!(
-                        "Timestamped Model Error: Expected output tensor '{}' of type f32. \
-                        If your model uses 'duration' (singular) or i64, please update the schema constants.",
-                        DURATIONS
-                    ))?;
-
-                Ok((audio_array, Some(durations_vec)))
-            }
+        let durations = match &signature.durations {
+            Some(durations) => Some(Self::extract_f32(&outputs, durations)?.1),
+            None => None,
+        };
+
+        Ok((audio_array, durations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(tokens: ElementType, float: ElementType, speed_shape: Vec<i64>) -> ModelSignature {
+        let spec = |name: &str, dtype, shape| TensorSpec {
+            name: name.to_string(),
+            dtype,
+            shape,
+        };
+        ModelSignature {
+            tokens: spec("tokens", tokens, vec![1, -1]),
+            style: spec("style", float, vec![1, 256]),
+            speed: spec("speed", float, speed_shape),
+            audio: spec("audio", float, vec![-1]),
+            durations: None,
         }
     }
+
+    fn shape(value: &DynValue) -> Vec<i64> {
+        value.shape().iter().copied().collect()
+    }
+
+    #[test]
+    fn test_f16_i32_inputs() {
+        let signature = signature(ElementType::I32, ElementType::F16, vec![]);
+        let inputs =
+            OrtKoko::prepare_inputs(&signature, vec![vec![0, 50, 0]], vec![vec![0.5; 256]], 1.25)
+                .unwrap();
+        let names: Vec<&str> = inputs.iter().map(|(name, _)| name.as_ref()).collect();
+        assert_eq!(names, ["tokens", "style", "speed"]);
+
+        let (_, tokens) = inputs[0].1.try_extract_tensor::<i32>().unwrap();
+        assert_eq!((shape(&inputs[0].1), tokens), (vec![1, 3], &[0, 50, 0][..]));
+        let (_, style) = inputs[1].1.try_extract_tensor::<f16>().unwrap();
+        assert_eq!(shape(&inputs[1].1), [1, 256]);
+        assert!(style.iter().all(|v| v.to_f32() == 0.5));
+        // A scalar speed for exports that take one
+        let (_, speed) = inputs[2].1.try_extract_tensor::<f16>().unwrap();
+        assert!(shape(&inputs[2].1).is_empty());
+        assert_eq!(speed[0].to_f32(), 1.25);
+    }
+
+    #[test]
+    fn test_f32_i64_inputs() {
+        let signature = signature(ElementType::I64, ElementType::F32, vec![1]);
+        let inputs =
+            OrtKoko::prepare_inputs(&signature, vec![vec![0, 7, 0]], vec![vec![0.1; 256]], 0.8)
+                .unwrap();
+        let (_, tokens) = inputs[0].1.try_extract_tensor::<i64>().unwrap();
+        assert_eq!(tokens, [0, 7, 0]);
+        let (_, style) = inputs[1].1.try_extract_tensor::<f32>().unwrap();
+        assert_eq!(style[255], 0.1);
+        let (_, speed) = inputs[2].1.try_extract_tensor::<f32>().unwrap();
+        assert_eq!((shape(&inputs[2].1), speed), (vec![1], &[0.8][..]));
+    }
+
+    #[test]
+    fn test_outputs_as_f32() {
+        let half: DynValue =
+            Tensor::from_array((vec![1, 2], vec![f16::from_f32(0.25), f16::from_f32(-1.5)]))
+                .unwrap()
+                .into_dyn();
+        assert_eq!(
+            OrtKoko::value_f32(&half, ElementType::F16).unwrap(),
+            (vec![1, 2], vec![0.25, -1.5])
+        );
+
+        let frames: DynValue = Tensor::from_array((vec![3], vec![2i32, 0, 7]))
+            .unwrap()
+            .into_dyn();
+        assert_eq!(
+            OrtKoko::value_f32(&frames, ElementType::I32).unwrap().1,
+            [2.0, 0.0, 7.0]
+        );
+        let frames: DynValue = Tensor::from_array((vec![2], vec![4i64, 9]))
+            .unwrap()
+            .into_dyn();
+        assert_eq!(
+            OrtKoko::value_f32(&frames, ElementType::I64).unwrap().1,
+            [4.0, 9.0]
+        );
+
+        // The element type of the signature must match the tensor
+        assert!(OrtKoko::value_f32(&frames, ElementType::F16).is_err());
+    }
 }
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ort::ep::{self, ExecutionProviderDispatch};
+
+/// ONNX Runtime execution providers that Kokoros knows how to register.
+///
+/// Every variant can be named at runtime, but only the CPU provider is always
+/// compiled in. The others require the matching cargo feature (`xnnpack`,
+/// `cuda`, `tensorrt`, `openvino`, `coreml`, `directml`); if a requested
+/// provider is missing or fails to register, session creation falls through to
+/// the next provider in the list and finally to the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExecutionProvider {
+    Cpu,
+    Xnnpack,
+    Cuda,
+    TensorRt,
+    OpenVino,
+    CoreMl,
+    DirectMl,
+}
+
+impl ExecutionProvider {
+    pub const ALL: [ExecutionProvider; 7] = [
+        ExecutionProvider::Cpu,
+        ExecutionProvider::Xnnpack,
+        ExecutionProvider::Cuda,
+        ExecutionProvider::TensorRt,
+        ExecutionProvider::OpenVino,
+        ExecutionProvider::CoreMl,
+        ExecutionProvider::DirectMl,
+    ];
+
+    /// Short lowercase name, as accepted by `--provider`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecutionProvider::Cpu => "cpu",
+            ExecutionProvider::Xnnpack => "xnnpack",
+            ExecutionProvider::Cuda => "cuda",
+            ExecutionProvider::TensorRt => "tensorrt",
+            ExecutionProvider::OpenVino => "openvino",
+            ExecutionProvider::CoreMl => "coreml",
+            ExecutionProvider::DirectMl => "directml",
+        }
+    }
+
+    /// Whether support for this provider was compiled into this build.
+    pub fn is_compiled(&self) -> bool {
+        match self {
+            ExecutionProvider::Cpu => true,
+            ExecutionProvider::Xnnpack => cfg!(feature = "xnnpack"),
+            ExecutionProvider::Cuda => cfg!(feature = "cuda"),
+            ExecutionProvider::TensorRt => cfg!(feature = "tensorrt"),
+            ExecutionProvider::OpenVino => cfg!(feature = "openvino"),
+            ExecutionProvider::CoreMl => cfg!(feature = "coreml"),
+            ExecutionProvider::DirectMl => cfg!(feature = "directml"),
+        }
+    }
+
+    /// Providers compiled into this build, in the order they are listed in `ALL`.
+    pub fn compiled() -> Vec<ExecutionProvider> {
+        Self::ALL.into_iter().filter(|p| p.is_compiled()).collect()
+    }
+
+    /// Provider order used when none is requested explicitly.
+    ///
+    /// Builds with the `cuda` feature keep their historical behaviour of trying
+    /// CUDA first.
+    pub fn default_order() -> Vec<ExecutionProvider> {
+        if cfg!(feature = "cuda") {
+            vec![ExecutionProvider::Cuda, ExecutionProvider::Cpu]
+        } else {
+            vec![ExecutionProvider::Cpu]
+        }
+    }
+
+    /// Build the `ort` dispatch for this provider. Registration errors are not
+    /// swallowed so the caller can fall back to the next provider.
+    pub(crate) fn dispatch(&self) -> ExecutionProviderDispatch {
+        let dispatch = match self {
+            ExecutionProvider::Cpu => ep::CPU::default().build(),
+            ExecutionProvider::Xnnpack => ep::XNNPACK::default().build(),
+            ExecutionProvider::Cuda => ep::CUDA::default().build(),
+            ExecutionProvider::TensorRt => ep::TensorRT::default().build(),
+            ExecutionProvider::OpenVino => ep::OpenVINO::default().build(),
+            ExecutionProvider::CoreMl => ep::CoreML::default().build(),
+            ExecutionProvider::DirectMl => ep::DirectML::default().build(),
+        };
+        dispatch.error_on_failure()
+    }
+}
+
+impl fmt::Display for ExecutionProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExecutionProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(|p| p.as_str()).collect();
+                format!(
+                    "unknown execution provider '{}' (expected one of: {})",
+                    s.trim(),
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// Resolve the order in which providers are attempted for a session.
+///
+/// Duplicates are removed, providers that are not compiled into this build are
+/// dropped (with a warning), and the CPU provider is always appended as the
+/// final fallback.
+pub fn fallback_chain(requested: &[ExecutionProvider]) -> Vec<ExecutionProvider> {
+    let mut chain = Vec::with_capacity(requested.len() + 1);
+    for provider in requested {
+        if chain.contains(provider) {
+            continue;
+        }
+        if !provider.is_compiled() {
+            tracing::warn!(
+                "Execution provider '{}' is not compiled into this build (enable the `{}` feature); skipping",
+                provider,
+                provider
+            );
+            continue;
+        }
+        chain.push(*provider);
+    }
+    if !chain.contains(&ExecutionProvider::Cpu) {
+        chain.push(ExecutionProvider::Cpu);
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!(
+            " CPU".parse::<ExecutionProvider>().unwrap(),
+            ExecutionProvider::Cpu
+        );
+        assert_eq!(
+            "xnnpack".parse::<ExecutionProvider>().unwrap(),
+            ExecutionProvider::Xnnpack
+        );
+        assert!("quantum".parse::<ExecutionProvider>().is_err());
+    }
+
+    #[test]
+    fn test_fallback_chain_ends_with_cpu() {
+        let chain = fallback_chain(&[ExecutionProvider::Xnnpack, ExecutionProvider::Xnnpack]);
+        assert_eq!(chain.last(), Some(&ExecutionProvider::Cpu));
+        assert_eq!(
+            chain.contains(&ExecutionProvider::Xnnpack),
+            cfg!(feature = "xnnpack")
+        );
+        assert_eq!(
+            chain
+                .iter()
+                .filter(|p| **p == ExecutionProvider::Xnnpack)
+                .count(),
+            usize::from(cfg!(feature = "xnnpack"))
+        );
+
+        // CPU keeps its requested position instead of being appended twice
+        let chain = fallback_chain(&[ExecutionProvider::Cpu, ExecutionProvider::Xnnpack]);
+        assert_eq!(chain[0], ExecutionProvider::Cpu);
+        assert_eq!(
+            chain
+                .iter()
+                .filter(|p| **p == ExecutionProvider::Cpu)
+                .count(),
+            1
+        );
+    }
+}
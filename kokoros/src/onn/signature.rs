@@ -0,0 +1,357 @@
+use std::fmt;
+
+use ort::{session::Session, tensor::TensorElementType, value::Outlet};
+
+/// Width of one Kokoro style vector.
+pub const STYLE_DIM: i64 = 256;
+
+mod model_schema {
+    /// Accepted names for each tensor role, in order of preference.
+    /// v1.0 exports use `tokens`/`audio`, the timestamped exports use
+    /// `input_ids`/`waveform`, and some community conversions use the rest.
+    pub const TOKENS: &[&str] = &["tokens", "input_ids"];
+    pub const STYLE: &[&str] = &["style", "ref_s"];
+    pub const SPEED: &[&str] = &["speed"];
+    pub const AUDIO: &[&str] = &["audio", "waveform", "waveforms"];
+    pub const DURATIONS: &[&str] = &["durations", "duration", "pred_dur"];
+}
+
+/// Element types Kokoro exports use for their inputs and outputs.
+///
+/// Quantized (int8/uint8) exports keep float inputs and outputs, fp16 exports
+/// switch the style, speed and audio tensors to `f16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    F32,
+    F16,
+    I64,
+    I32,
+}
+
+impl ElementType {
+    fn from_ort(ty: TensorElementType) -> Option<Self> {
+        match ty {
+            TensorElementType::Float32 => Some(ElementType::F32),
+            TensorElementType::Float16 => Some(ElementType::F16),
+            TensorElementType::Int64 => Some(ElementType::I64),
+            TensorElementType::Int32 => Some(ElementType::I32),
+            _ => None,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, ElementType::F32 | ElementType::F16)
+    }
+
+    fn is_int(&self) -> bool {
+        matches!(self, ElementType::I64 | ElementType::I32)
+    }
+}
+
+impl fmt::Display for ElementType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ElementType::F32 => "f32",
+            ElementType::F16 => "f16",
+            ElementType::I64 => "i64",
+            ElementType::I32 => "i32",
+        })
+    }
+}
+
+/// Tensor names supplied by the user for custom exports, taking precedence
+/// over the built-in name lists.
+#[derive(Debug, Clone, Default)]
+pub struct TensorNames {
+    pub tokens: Option<String>,
+    pub style: Option<String>,
+    pub speed: Option<String>,
+    pub audio: Option<String>,
+    pub durations: Option<String>,
+}
+
+impl TensorNames {
+    pub const ROLES: [&'static str; 5] = ["tokens", "style", "speed", "audio", "durations"];
+
+    /// Set the tensor name for a role (`tokens`, `style`, `speed`, `audio` or `durations`).
+    pub fn set(&mut self, role: &str, name: &str) -> Result<(), String> {
+        let slot = match role.trim() {
+            "tokens" => &mut self.tokens,
+            "style" => &mut self.style,
+            "speed" => &mut self.speed,
+            "audio" => &mut self.audio,
+            "durations" => &mut self.durations,
+            other => {
+                return Err(format!(
+                    "unknown tensor role '{}' (expected one of: {})",
+                    other,
+                    Self::ROLES.join(", ")
+                ));
+            }
+        };
+        *slot = Some(name.trim().to_string());
+        Ok(())
+    }
+
+    /// Parse `role=name` pairs, as given to `--tensor-name`.
+    pub fn from_pairs<S: AsRef<str>>(pairs: &[S]) -> Result<Self, String> {
+        let mut names = TensorNames::default();
+        for pair in pairs {
+            let pair = pair.as_ref();
+            let (role, name) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected ROLE=NAME, got '{}'", pair))?;
+            names.set(role, name)?;
+        }
+        Ok(names)
+    }
+}
+
+/// A model input or output resolved to the role Kokoros uses it for.
+#[derive(Debug, Clone)]
+pub struct TensorSpec {
+    pub name: String,
+    pub dtype: ElementType,
+    /// Dimensions as reported by ONNX Runtime; `-1` marks a dynamic axis.
+    pub shape: Vec<i64>,
+}
+
+impl fmt::Display for TensorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dims: Vec<String> = self
+            .shape
+            .iter()
+            .map(|&d| {
+                if d < 0 {
+                    "dyn".to_string()
+                } else {
+                    d.to_string()
+                }
+            })
+            .collect();
+        write!(f, "'{}' {}[{}]", self.name, self.dtype, dims.join(", "))
+    }
+}
+
+/// Names, element types and shapes of the tensors Kokoros feeds and reads,
+/// resolved and validated once when the session is loaded.
+#[derive(Debug, Clone)]
+pub struct ModelSignature {
+    pub tokens: TensorSpec,
+    pub style: TensorSpec,
+    pub speed: TensorSpec,
+    pub audio: TensorSpec,
+    pub durations: Option<TensorSpec>,
+}
+
+impl ModelSignature {
+    pub fn from_session(sess: &Session, names: &TensorNames) -> Result<Self, String> {
+        let with_context = |e: String| {
+            format!(
+                "Unsupported Kokoro model signature: {}.\n{}",
+                e,
+                describe_session(sess)
+            )
+        };
+
+        let inputs = sess.inputs();
+        let outputs = sess.outputs();
+        let signature = ModelSignature {
+            tokens: resolve(inputs, "tokens", &names.tokens, model_schema::TOKENS)
+                .and_then(require("tokens", model_schema::TOKENS))
+                .map_err(with_context)?,
+            style: resolve(inputs, "style", &names.style, model_schema::STYLE)
+                .and_then(require("style", model_schema::STYLE))
+                .map_err(with_context)?,
+            speed: resolve(inputs, "speed", &names.speed, model_schema::SPEED)
+                .and_then(require("speed", model_schema::SPEED))
+                .map_err(with_context)?,
+            audio: resolve(outputs, "audio", &names.audio, model_schema::AUDIO)
+                .and_then(require("audio", model_schema::AUDIO))
+                .map_err(with_context)?,
+            durations: resolve(
+                outputs,
+                "durations",
+                &names.durations,
+                model_schema::DURATIONS,
+            )
+            .map_err(with_context)?,
+        };
+
+        signature.validate(sess).map_err(with_context)?;
+        Ok(signature)
+    }
+
+    /// Whether the model reports per-token durations, enabling word timestamps.
+    pub fn is_timestamped(&self) -> bool {
+        self.durations.is_some()
+    }
+
+    fn validate(&self, sess: &Session) -> Result<(), String> {
+        let check = |spec: &TensorSpec, role: &str, ok_type: bool, ranks: &[usize]| {
+            if !ok_type {
+                return Err(format!(
+                    "{} tensor '{}' has unsupported element type {}",
+                    role, spec.name, spec.dtype
+                ));
+            }
+            if !ranks.contains(&spec.shape.len()) {
+                return Err(format!(
+                    "{} tensor '{}' has rank {}, expected {:?}",
+                    role,
+                    spec.name,
+                    spec.shape.len(),
+                    ranks
+                ));
+            }
+            Ok(())
+        };
+
+        check(&self.tokens, "tokens", self.tokens.dtype.is_int(), &[2])?;
+        check(&self.style, "style", self.style.dtype.is_float(), &[2])?;
+        check(&self.speed, "speed", self.speed.dtype.is_float(), &[0, 1])?;
+        check(
+            &self.audio,
+            "audio",
+            self.audio.dtype.is_float(),
+            &[1, 2, 3],
+        )?;
+        if let Some(durations) = &self.durations {
+            check(durations, "durations", true, &[1, 2])?;
+        }
+
+        if let Some(&dim) = self.style.shape.last()
+            && dim >= 0
+            && dim != STYLE_DIM
+        {
+            return Err(format!(
+                "style tensor '{}' has width {}, expected {}",
+                self.style.name, dim, STYLE_DIM
+            ));
+        }
+
+        // Every input must be fed, so a model with extra inputs cannot be run
+        let known = [&self.tokens.name, &self.style.name, &self.speed.name];
+        if let Some(extra) = sess
+            .inputs()
+            .iter()
+            .find(|i| !known.iter().any(|name| name.as_str() == i.name()))
+        {
+            return Err(format!(
+                "model input '{}' is not one Kokoros knows how to feed; \
+                map it with --tensor-name if it is a renamed tokens/style/speed input",
+                extra.name()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ModelSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tokens:    {}", self.tokens)?;
+        writeln!(f, "style:     {}", self.style)?;
+        writeln!(f, "speed:     {}", self.speed)?;
+        writeln!(f, "audio:     {}", self.audio)?;
+        match &self.durations {
+            Some(durations) => write!(f, "durations: {}", durations),
+            None => write!(f, "durations: (none, timestamps unavailable)"),
+        }
+    }
+}
+
+/// Find the tensor for a role, preferring a user supplied name over the
+/// built-in candidates. An explicit name that does not exist is an error.
+fn resolve(
+    outlets: &[Outlet],
+    role: &str,
+    explicit: &Option<String>,
+    candidates: &[&str],
+) -> Result<Option<TensorSpec>, String> {
+    let outlet = match explicit {
+        Some(name) => Some(
+            outlets
+                .iter()
+                .find(|o| o.name() == name)
+                .ok_or_else(|| format!("configured {} tensor '{}' does not exist", role, name))?,
+        ),
+        None => candidates
+            .iter()
+            .find_map(|name| outlets.iter().find(|o| o.name() == *name)),
+    };
+    let Some(outlet) = outlet else {
+        return Ok(None);
+    };
+
+    let dtype = outlet
+        .dtype()
+        .tensor_type()
+        .and_then(ElementType::from_ort)
+        .ok_or_else(|| {
+            format!(
+                "{} tensor '{}' has unsupported type {}",
+                role,
+                outlet.name(),
+                outlet.dtype()
+            )
+        })?;
+    let shape = outlet
+        .dtype()
+        .tensor_shape()
+        .map(|s| s.to_vec())
+        .unwrap_or_default();
+
+    Ok(Some(TensorSpec {
+        name: outlet.name().to_string(),
+        dtype,
+        shape,
+    }))
+}
+
+fn require<'a>(
+    role: &'a str,
+    candidates: &'a [&'a str],
+) -> impl FnOnce(Option<TensorSpec>) -> Result<TensorSpec, String> + 'a {
+    move |spec| {
+        spec.ok_or_else(|| {
+            format!(
+                "no {} tensor found (expected one of: {})",
+                role,
+                candidates.join(", ")
+            )
+        })
+    }
+}
+
+/// One line per session input and output, used in load errors.
+fn describe_session(sess: &Session) -> String {
+    let list = |outlets: &[Outlet]| {
+        outlets
+            .iter()
+            .map(|o| format!("{}: {}", o.name(), o.dtype()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "Model inputs: [{}]; outputs: [{}]",
+        list(sess.inputs()),
+        list(sess.outputs())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_names_from_pairs() {
+        let names = TensorNames::from_pairs(&["tokens=ids", " audio = wav "]).unwrap();
+        assert_eq!(names.tokens.as_deref(), Some("ids"));
+        assert_eq!(names.audio.as_deref(), Some("wav"));
+        assert!(names.style.is_none());
+
+        assert!(TensorNames::from_pairs(&["pitch=p"]).is_err());
+        assert!(TensorNames::from_pairs(&["tokens"]).is_err());
+    }
+}
@@ -0,0 +1,553 @@
+//! Long-form input split into chapters for audiobook rendering.
+//!
+//! [`Book::open`] reads plain text, markdown or EPUB. Plain text is split at
+//! heading lines such as `Chapter 3`, `PROLOGUE` or a lone roman numeral
+//! that stand between blank lines, so hard-wrapped prose starting with
+//! "Part of" or "I" stays put; markdown at its top heading level; EPUB follows the reading order of its
+//! spine, with titles from the table of contents. Chapter text is spoken
+//! text, with markup already removed by [`SpokenDocument`].
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use quick_xml::Reader;
+use quick_xml::events::Event as XmlEvent;
+use regex::Regex;
+
+use crate::error::KokorosError;
+use crate::tts::document::{DocumentOptions, InputFormat, SpokenDocument};
+
+lazy_static! {
+    /// A capitalized heading word followed by a number, a roman numeral, a
+    /// `:` or `.` before a title, or nothing; or a number on its own
+    static ref HEADING_RE: Regex = Regex::new(
+        r"(?m)^[ \t]*(?:(?:Chapter|Part|Book|Prologue|Epilogue|Preface|Foreword|Introduction|Afterword|Interlude|Cap[ií]tulo|Chapitre|Capitolo|Kapitel|CHAPTER|PART|BOOK|PROLOGUE|EPILOGUE|PREFACE|FOREWORD|INTRODUCTION|AFTERWORD|INTERLUDE|CAP[IÍ]TULO|CHAPITRE|CAPITOLO|KAPITEL)(?:[ \t]+(?:\d{1,3}|[IVXLC]{1,7}))?(?:[ \t]*[:.](?:[ \t]+[^\n]{1,60})?)?|(?:[IVXLC]{1,7}|\d{1,3})\.?)[ \t]*$"
+    )
+    .unwrap();
+}
+
+/// Longest line read as the subtitle of a heading line
+const MAX_SUBTITLE: usize = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    /// Spoken text, paragraphs separated by blank lines
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Book {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl Book {
+    /// Read a book, choosing the format from the file extension: `.epub`,
+    /// `.md`/`.markdown`, or plain text for anything else. Books without a
+    /// title are named after the file.
+    pub fn open(path: impl AsRef<Path>, options: &DocumentOptions) -> Result<Self, KokorosError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut book = match extension.as_str() {
+            "epub" => Self::from_epub(path, options)?,
+            "md" | "markdown" => Self::from_markdown(&fs::read_to_string(path)?, options),
+            _ => Self::from_text(&fs::read_to_string(path)?),
+        };
+        if book.title.is_none() {
+            book.title = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(str::to_string);
+        }
+        if book.chapters.is_empty() {
+            return Err(KokorosError::Document(format!(
+                "{} has no text to read",
+                path.display()
+            )));
+        }
+        if let [chapter] = book.chapters.as_mut_slice()
+            && let Some(title) = &book.title
+        {
+            chapter.title = title.clone();
+        }
+        Ok(book)
+    }
+
+    /// Split plain text at its heading lines. A short line right after a
+    /// heading is taken as its subtitle, as in `Chapter 1` / `The Storm`.
+    pub fn from_text(text: &str) -> Self {
+        let text = text.replace("\r\n", "\n");
+        let mut book = Book::default();
+        let headings: Vec<_> = HEADING_RE
+            .find_iter(&text)
+            .filter(|m| stands_alone(&text, m.start(), m.end()))
+            .collect();
+
+        if let Some(first) = headings.first() {
+            push_chapter(&mut book, "Opening".to_string(), &text[..first.start()]);
+        }
+        for (i, heading) in headings.iter().enumerate() {
+            let end = headings.get(i + 1).map_or(text.len(), |next| next.start());
+            let body = &text[heading.end()..end];
+            let mut title = heading.as_str().trim().trim_end_matches('.').to_string();
+
+            let subtitle = body.trim_start_matches([' ', '\t', '\n', '\r']);
+            let line = subtitle.lines().next().unwrap_or_default().trim();
+            let ends_paragraph = subtitle[line.len().min(subtitle.len())..].starts_with("\n\n")
+                || subtitle.trim_end() == line;
+            if !line.is_empty()
+                && line.chars().count() <= MAX_SUBTITLE
+                && !line.ends_with(['.', ',', ';', '!', '?'])
+                && ends_paragraph
+            {
+                title = format!("{}: {}", title, line);
+            }
+            push_chapter(&mut book, title, &text[heading.start()..end]);
+        }
+        if headings.is_empty() {
+            push_chapter(&mut book, "Chapter 1".to_string(), &text);
+        }
+        book
+    }
+
+    /// Split markdown at its top heading level. A single `#` heading above
+    /// `##` chapters is the book title.
+    pub fn from_markdown(source: &str, options: &DocumentOptions) -> Self {
+        let mut headings: Vec<(HeadingLevel, std::ops::Range<usize>, String)> = Vec::new();
+        let mut current: Option<(HeadingLevel, std::ops::Range<usize>, String)> = None;
+        for (event, range) in Parser::new(source).into_offset_iter() {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    current = Some((level, range, String::new()));
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, _, title)) = &mut current {
+                        title.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+                _ => {}
+            }
+        }
+
+        let count = |level| headings.iter().filter(|h| h.0 == level).count();
+        let mut book = Book::default();
+        let mut start = 0;
+        let split = match (count(HeadingLevel::H1), count(HeadingLevel::H2)) {
+            (1, h2) if h2 > 0 => {
+                let (_, range, title) = headings.iter().find(|h| h.0 == HeadingLevel::H1).unwrap();
+                book.title = Some(title.trim().to_string());
+                start = range.end;
+                Some(HeadingLevel::H2)
+            }
+            (0, 0) => None,
+            (0, _) => Some(HeadingLevel::H2),
+            _ => Some(HeadingLevel::H1),
+        };
+
+        let chapters: Vec<_> = headings
+            .iter()
+            .filter(|h| Some(h.0) == split && h.1.start >= start)
+            .collect();
+        let spoken = |range: std::ops::Range<usize>| {
+            SpokenDocument::parse(&source[range], InputFormat::Markdown, options).text
+        };
+        match chapters.first() {
+            Some(first) => push_chapter(
+                &mut book,
+                "Opening".to_string(),
+                &spoken(start..first.1.start),
+            ),
+            None => push_chapter(
+                &mut book,
+                "Chapter 1".to_string(),
+                &spoken(start..source.len()),
+            ),
+        }
+        for (i, (_, range, title)) in chapters.iter().enumerate() {
+            let end = chapters
+                .get(i + 1)
+                .map_or(source.len(), |next| next.1.start);
+            push_chapter(
+                &mut book,
+                title.trim().to_string(),
+                &spoken(range.start..end),
+            );
+        }
+        book
+    }
+
+    /// Read an EPUB 2 or 3 file in spine order
+    pub fn from_epub(
+        path: impl AsRef<Path>,
+        options: &DocumentOptions,
+    ) -> Result<Self, KokorosError> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)
+            .map_err(|e| KokorosError::Document(e.to_string()))?;
+
+        let container = read_entry(&mut archive, "META-INF/container.xml")?;
+        let opf_path = xml_elements(&container)?
+            .into_iter()
+            .find(|e| e.name == "rootfile")
+            .and_then(|e| e.attr("full-path").map(str::to_string))
+            .ok_or_else(|| KokorosError::Document("container.xml names no package".into()))?;
+        let opf = read_entry(&mut archive, &opf_path)?;
+        let package = Package::parse(&opf)?;
+
+        let mut titles = HashMap::new();
+        if let Some(toc) = &package.toc {
+            let toc_path = resolve(&opf_path, toc);
+            let toc_xml = read_entry(&mut archive, &toc_path)?;
+            for (href, title) in toc_entries(&toc_xml)? {
+                titles.entry(resolve(&toc_path, &href)).or_insert(title);
+            }
+        }
+
+        let mut book = Book {
+            title: package.title,
+            author: package.author,
+            chapters: Vec::new(),
+        };
+        for href in &package.spine {
+            let chapter_path = resolve(&opf_path, href);
+            let html = read_entry(&mut archive, &chapter_path)?;
+            let text = SpokenDocument::parse(&html, InputFormat::Html, options).text;
+            let title = titles
+                .get(&chapter_path)
+                .cloned()
+                .unwrap_or_else(|| format!("Chapter {}", book.chapters.len() + 1));
+            push_chapter(&mut book, title, &text);
+        }
+        Ok(book)
+    }
+}
+
+/// Whether the line from `start` to `end` has a blank line, or the edge of
+/// the text, before it and after it or after its subtitle
+fn stands_alone(text: &str, start: usize, end: usize) -> bool {
+    let before = match text[..start].strip_suffix('\n') {
+        Some(rest) => rest.rsplit('\n').next().unwrap_or_default(),
+        None => &text[..start],
+    };
+    let mut after = text[end..].split('\n').skip(1).map(str::trim);
+    let blank_after = match after.next() {
+        None | Some("") => true,
+        Some(subtitle) => {
+            subtitle.chars().count() <= MAX_SUBTITLE && after.next().is_none_or(str::is_empty)
+        }
+    };
+    before.trim().is_empty() && blank_after
+}
+
+fn push_chapter(book: &mut Book, title: String, text: &str) {
+    let text = text.trim();
+    if text.chars().any(char::is_alphanumeric) {
+        book.chapters.push(Chapter {
+            title,
+            text: text.to_string(),
+        });
+    }
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<String, KokorosError> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| KokorosError::Document(format!("{}: {}", name, e)))?;
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// The parts of an OPF package file a reader needs
+#[derive(Default)]
+struct Package {
+    title: Option<String>,
+    author: Option<String>,
+    /// Hrefs in reading order, relative to the package file
+    spine: Vec<String>,
+    /// The EPUB 3 navigation document or EPUB 2 NCX
+    toc: Option<String>,
+}
+
+impl Package {
+    fn parse(opf: &str) -> Result<Self, KokorosError> {
+        let elements = xml_elements(opf)?;
+        let mut package = Package::default();
+        let mut manifest = HashMap::new();
+        let mut spine = Vec::new();
+        let mut ncx = None;
+        let mut nav = None;
+
+        for element in &elements {
+            match element.name.as_str() {
+                "title" if package.title.is_none() => {
+                    package.title = Some(element.text.trim().to_string())
+                }
+                "creator" if package.author.is_none() => {
+                    package.author = Some(element.text.trim().to_string())
+                }
+                "item" => {
+                    let (Some(id), Some(href)) = (element.attr("id"), element.attr("href")) else {
+                        continue;
+                    };
+                    if element
+                        .attr("properties")
+                        .is_some_and(|p| p.split_whitespace().any(|p| p == "nav"))
+                    {
+                        nav = Some(href.to_string());
+                    }
+                    manifest.insert(id.to_string(), href.to_string());
+                }
+                "spine" => ncx = element.attr("toc").map(str::to_string),
+                "itemref" if element.attr("linear") != Some("no") => {
+                    spine.extend(element.attr("idref").map(str::to_string));
+                }
+                _ => {}
+            }
+        }
+
+        package.spine = spine
+            .iter()
+            .filter_map(|id| manifest.get(id).cloned())
+            .collect();
+        package.toc = nav.or_else(|| ncx.and_then(|id| manifest.get(&id).cloned()));
+        Ok(package)
+    }
+}
+
+/// An XML element with its attributes and the text directly inside it
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Flatten a document into its elements in document order, names and
+/// attribute keys without their namespace prefix. The text of an element
+/// includes the text of its children.
+fn xml_elements(xml: &str) -> Result<Vec<Element>, KokorosError> {
+    let invalid = |e: &dyn std::fmt::Display| KokorosError::Document(e.to_string());
+    let mut reader = Reader::from_str(xml);
+    let mut elements = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    loop {
+        let (start, empty) = match reader.read_event().map_err(|e| invalid(&e))? {
+            XmlEvent::Start(e) => (e, false),
+            XmlEvent::Empty(e) => (e, true),
+            XmlEvent::Text(text) => {
+                let text = text.unescape().map_err(|e| invalid(&e))?;
+                for &i in &open {
+                    let element: &mut Element = &mut elements[i];
+                    element.text.push_str(&text);
+                }
+                continue;
+            }
+            XmlEvent::CData(text) => {
+                let text = String::from_utf8_lossy(&text).into_owned();
+                for &i in &open {
+                    let element: &mut Element = &mut elements[i];
+                    element.text.push_str(&text);
+                }
+                continue;
+            }
+            XmlEvent::End(_) => {
+                open.pop();
+                continue;
+            }
+            XmlEvent::Eof => break,
+            _ => continue,
+        };
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|e| invalid(&e))?;
+            let value = attribute.unescape_value().map_err(|e| invalid(&e))?;
+            attributes.push((
+                String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+                value.into_owned(),
+            ));
+        }
+        elements.push(Element {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attributes,
+            text: String::new(),
+        });
+        if !empty {
+            open.push(elements.len() - 1);
+        }
+    }
+    Ok(elements)
+}
+
+/// Chapter hrefs and titles from an EPUB 3 navigation document or an
+/// EPUB 2 NCX, in document order
+fn toc_entries(xml: &str) -> Result<Vec<(String, String)>, KokorosError> {
+    let mut entries = Vec::new();
+    let mut label = None;
+    for element in xml_elements(xml)? {
+        let text = element
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match element.name.as_str() {
+            "navLabel" => label = Some(text),
+            "content" => {
+                if let (Some(src), Some(label)) = (element.attr("src"), label.take()) {
+                    entries.push((src.to_string(), label));
+                }
+            }
+            "a" => {
+                if let Some(href) = element.attr("href")
+                    && !text.is_empty()
+                {
+                    entries.push((href.to_string(), text));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// The archive path of `href` as seen from the file at `base`, without its
+/// fragment
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = s.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(book: &Book) -> Vec<&str> {
+        book.chapters.iter().map(|c| c.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_text_chapters() {
+        let book = Book::from_text(
+            "A Tale\nby Someone\n\nCHAPTER I.\nThe Storm\n\nIt was dark.\n\n\
+             Chapter II\n\nIt rained. And rained.\n\nIII\n\nThe end.\n",
+        );
+        assert_eq!(
+            titles(&book),
+            ["Opening", "CHAPTER I: The Storm", "Chapter II", "III"]
+        );
+        assert!(book.chapters[1].text.ends_with("It was dark."));
+        assert_eq!(Book::from_text("Just one line.").chapters.len(), 1);
+    }
+
+    #[test]
+    fn test_wrapped_prose_is_not_a_heading() {
+        let book = Book::from_text(
+            "It was the best of times, and all that happened there was\n\
+             Part of the story that we tell. She opened the\n\
+             Book\n\
+             Introduction to the family went badly, as\n\
+             I\n\
+             said before.\n\n\
+             Part of the reason was money.\n\n\
+             chapter\n\n\
+             CHAPTER 2. The Return\n\n\
+             More.\n\n\
+             Part III\n\n\
+             The end.\n",
+        );
+        assert_eq!(
+            titles(&book),
+            ["Opening", "CHAPTER 2. The Return", "Part III"]
+        );
+        assert!(
+            book.chapters[0]
+                .text
+                .contains("Part of the reason was money.")
+        );
+    }
+
+    #[test]
+    fn test_markdown_chapters() {
+        let book = Book::from_markdown(
+            "# The Book\n\n## One\n\nFirst *chapter*.\n\n## Two\n\nSecond.\n",
+            &DocumentOptions::default(),
+        );
+        assert_eq!(book.title.as_deref(), Some("The Book"));
+        assert_eq!(titles(&book), ["One", "Two"]);
+        assert_eq!(book.chapters[0].text, "One.\n\nFirst chapter.");
+    }
+
+    #[test]
+    fn test_epub_paths_and_toc() {
+        assert_eq!(
+            resolve("OEBPS/content.opf", "text/ch%201.xhtml#top"),
+            "OEBPS/text/ch 1.xhtml"
+        );
+        assert_eq!(
+            resolve("OEBPS/nav/toc.xhtml", "../text/a.xhtml"),
+            "OEBPS/text/a.xhtml"
+        );
+        let ncx = r#"<ncx><navMap><navPoint><navLabel><text>Start</text></navLabel>
+            <content src="a.xhtml"/></navPoint></navMap></ncx>"#;
+        assert_eq!(
+            toc_entries(ncx).unwrap(),
+            [("a.xhtml".to_string(), "Start".to_string())]
+        );
+        let opf = r#"<package><metadata><dc:title>T</dc:title><dc:creator>A</dc:creator></metadata>
+            <manifest><item id="n" href="nav.xhtml" properties="nav"/><item id="c1" href="a.xhtml"/></manifest>
+            <spine><itemref idref="c1"/></spine></package>"#;
+        let package = Package::parse(opf).unwrap();
+        assert_eq!(package.title.as_deref(), Some("T"));
+        assert_eq!(package.spine, ["a.xhtml"]);
+        assert_eq!(package.toc.as_deref(), Some("nav.xhtml"));
+    }
+}
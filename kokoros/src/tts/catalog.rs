@@ -0,0 +1,425 @@
+//! Voice metadata: language, gender, accent, display name, aliases and
+//! quality grade of every loaded voice.
+//!
+//! The voices of the Kokoro releases are described by a bundled table, with
+//! the grades of the Kokoro-82M voice list. Other voices get what the usual
+//! `af_name` naming tells: the first letter is the language, the second the
+//! gender. `voices.json` in the user voice directory adds or overrides
+//! entries, keyed by voice:
+//!
+//! ```json
+//! {
+//!   "warm_narrator": {
+//!     "language": "en-gb",
+//!     "gender": "female",
+//!     "accent": "Scottish",
+//!     "name": "Morag",
+//!     "aliases": ["narrator"],
+//!     "quality": "B"
+//!   }
+//! }
+//! ```
+//!
+//! Everything that picks voices by language or gender, such as language
+//! detection and voice design, asks the catalogue.
+
+use crate::tts::voices::{Blend, Gender};
+use crate::utils::fileio::load_json_file;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// File in the user voice directory describing its voices
+pub const METADATA_FILE: &str = "voices.json";
+
+/// Kokoro language codes, the first letter of a voice name, with the eSpeak
+/// language, the accent of the voices and the ISO 639-3 code of the language
+pub(crate) const LANGUAGES: &[(char, &str, &str, &str)] = &[
+    ('a', "en-us", "American", "eng"),
+    ('b', "en-gb", "British", "eng"),
+    ('e', "es", "Spanish", "spa"),
+    ('f', "fr-fr", "French", "fra"),
+    ('h', "hi", "Hindi", "hin"),
+    ('i', "it", "Italian", "ita"),
+    ('j', "ja", "Japanese", "jpn"),
+    ('p', "pt-br", "Brazilian", "por"),
+    // Routed to the pinyin front end on v1.1-zh, eSpeak `cmn` on v1.0
+    ('z', "zh", "Mandarin", "cmn"),
+];
+
+/// Voices of the Kokoro releases and their overall grade, if rated
+const BUILTIN: &[(&str, Option<&str>)] = &[
+    ("af_heart", Some("A")),
+    ("af_alloy", Some("C")),
+    ("af_aoede", Some("C+")),
+    ("af_bella", Some("A-")),
+    ("af_jessica", Some("D")),
+    ("af_kore", Some("C+")),
+    ("af_nicole", Some("B-")),
+    ("af_nova", Some("C")),
+    ("af_river", Some("D")),
+    ("af_sarah", Some("C+")),
+    ("af_sky", Some("C-")),
+    ("am_adam", Some("F+")),
+    ("am_echo", Some("D")),
+    ("am_eric", Some("D")),
+    ("am_fenrir", Some("C+")),
+    ("am_liam", Some("D")),
+    ("am_michael", Some("C+")),
+    ("am_onyx", Some("D")),
+    ("am_puck", Some("C+")),
+    ("am_santa", Some("D-")),
+    ("bf_alice", Some("D")),
+    ("bf_emma", Some("B-")),
+    ("bf_isabella", Some("C")),
+    ("bf_lily", Some("D")),
+    ("bm_daniel", Some("D")),
+    ("bm_fable", Some("C")),
+    ("bm_george", Some("C")),
+    ("bm_lewis", Some("D+")),
+    ("ef_dora", None),
+    ("em_alex", None),
+    ("em_santa", None),
+    ("ff_siwis", Some("B-")),
+    ("hf_alpha", Some("C")),
+    ("hf_beta", Some("C")),
+    ("hm_omega", Some("C")),
+    ("hm_psi", Some("C")),
+    ("if_sara", Some("C")),
+    ("im_nicola", Some("C")),
+    ("jf_alpha", Some("C+")),
+    ("jf_gongitsune", Some("C")),
+    ("jf_nezumi", Some("C-")),
+    ("jf_tebukuro", Some("C")),
+    ("jm_kumo", Some("C-")),
+    ("pf_dora", None),
+    ("pm_alex", None),
+    ("pm_santa", None),
+    ("zf_xiaobei", Some("D")),
+    ("zf_xiaoni", Some("D")),
+    ("zf_xiaoxiao", Some("D")),
+    ("zf_xiaoyi", Some("D")),
+    ("zm_yunjian", Some("D")),
+    ("zm_yunxi", Some("D")),
+    ("zm_yunxia", Some("D")),
+    ("zm_yunyang", Some("D")),
+];
+
+/// What is known about a voice
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceMeta {
+    pub id: String,
+    /// Kokoro language code, e.g. `a` for American English
+    pub lang_code: Option<char>,
+    /// eSpeak language the voice was made for
+    pub language: Option<String>,
+    pub gender: Option<Gender>,
+    pub accent: Option<String>,
+    /// Name to show people, e.g. `Heart` for `af_heart`
+    pub name: String,
+    /// Other names the voice answers to, in lowercase
+    pub aliases: Vec<String>,
+    /// Grade such as `A-` or `C+`
+    pub quality: Option<String>,
+}
+
+impl VoiceMeta {
+    /// What the name of a voice tells about it
+    fn guess(id: &str) -> Self {
+        let language = LANGUAGES.iter().find(|(code, ..)| id.starts_with(*code));
+        let gender = match id.as_bytes() {
+            [_, b'f', b'_', ..] => Some(Gender::Female),
+            [_, b'm', b'_', ..] => Some(Gender::Male),
+            _ => None,
+        };
+        let (language, gender, name) = match (language, gender) {
+            (Some(language), Some(gender)) if id.len() > 3 => {
+                (Some(language), Some(gender), capitalize(&id[3..]))
+            }
+            _ => (None, None, id.to_string()),
+        };
+        let quality = BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == id)
+            .and_then(|(_, grade)| grade.map(str::to_string));
+        Self {
+            id: id.to_string(),
+            lang_code: language.map(|(code, ..)| *code),
+            language: language.map(|(_, language, ..)| language.to_string()),
+            gender,
+            accent: language.map(|(_, _, accent, _)| accent.to_string()),
+            name,
+            aliases: Vec::new(),
+            quality,
+        }
+    }
+
+    /// Override the fields given in a `voices.json` entry
+    fn update(&mut self, json: &Value) -> Result<(), String> {
+        let entry = json.as_object().ok_or("must be an object")?;
+        let text = |key: &str, value: &Value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("'{}' must be a string", key))
+        };
+        for (key, value) in entry {
+            match key.as_str() {
+                "language" => {
+                    let language = text(key, value)?;
+                    self.lang_code = LANGUAGES
+                        .iter()
+                        .find(|(_, espeak, ..)| *espeak == language)
+                        .map(|(code, ..)| *code);
+                    self.language = Some(language);
+                }
+                "lang_code" => {
+                    let code = text(key, value)?;
+                    let mut chars = code.chars();
+                    let (Some(c), None) = (chars.next(), chars.next()) else {
+                        return Err(format!("'lang_code' must be one letter, got '{}'", code));
+                    };
+                    self.lang_code = Some(c);
+                }
+                "gender" => self.gender = Some(text(key, value)?.parse::<Gender>()?),
+                "accent" => self.accent = Some(text(key, value)?),
+                "name" => self.name = text(key, value)?,
+                "quality" => self.quality = Some(text(key, value)?),
+                "aliases" => {
+                    self.aliases = value
+                        .as_array()
+                        .ok_or("'aliases' must be an array of strings")?
+                        .iter()
+                        .map(|alias| text(key, alias).map(|alias| alias.to_lowercase()))
+                        .collect::<Result<_, _>>()?;
+                }
+                other => return Err(format!("unknown field '{}'", other)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Metadata of the loaded voices
+#[derive(Debug, Clone, Default)]
+pub struct VoiceCatalog {
+    voices: BTreeMap<String, VoiceMeta>,
+}
+
+impl VoiceCatalog {
+    /// Describe `ids` from the bundled table and their names
+    pub fn new<'a>(ids: impl IntoIterator<Item = &'a str>) -> Self {
+        let voices = ids
+            .into_iter()
+            .map(|id| (id.to_string(), VoiceMeta::guess(id)))
+            .collect();
+        Self { voices }
+    }
+
+    /// Apply `voices.json` of a user voice directory, if there is one
+    pub fn load_user_metadata(&mut self, dir: &Path) -> Result<(), String> {
+        let path = dir.join(METADATA_FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+        let json = load_json_file(&path.to_string_lossy())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.update(&json)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Apply metadata keyed by voice. Entries for voices that are not loaded
+    /// are skipped.
+    pub fn update(&mut self, json: &Value) -> Result<(), String> {
+        let entries = json.as_object().ok_or("must be an object keyed by voice")?;
+        for (id, entry) in entries {
+            match self.voices.get_mut(id) {
+                Some(voice) => voice
+                    .update(entry)
+                    .map_err(|e| format!("'{}': {}", id, e))?,
+                None => tracing::debug!("Metadata for '{}' skipped, no such voice", id),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&VoiceMeta> {
+        self.voices.get(id)
+    }
+
+    /// Every voice, sorted by id
+    pub fn voices(&self) -> impl Iterator<Item = &VoiceMeta> {
+        self.voices.values()
+    }
+
+    /// Aliases and the voices they name, sorted by voice
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.voices.values().flat_map(|voice| {
+            voice
+                .aliases
+                .iter()
+                .map(move |alias| (alias.as_str(), voice.id.as_str()))
+        })
+    }
+
+    /// The voice `name` stands for: a voice id, or an alias in any case.
+    /// Other names are returned as they are.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.voices.contains_key(name) {
+            return name;
+        }
+        let lowercase = name.to_lowercase();
+        self.aliases()
+            .find(|(alias, _)| *alias == lowercase)
+            .map_or(name, |(_, id)| id)
+    }
+
+    /// The eSpeak language of a voice, or of the first voice of a blend.
+    /// Voices without metadata fall back to the first letter of their name.
+    pub fn language(&self, voice: &str) -> Option<&str> {
+        let first = if Blend::is_blend(voice) {
+            voice
+                .parse::<Blend>()
+                .ok()
+                .and_then(|blend| blend.components().first().map(|(name, _)| name.clone()))
+                .unwrap_or_default()
+        } else {
+            voice.to_string()
+        };
+        if let Some(meta) = self.voices.get(&first) {
+            return meta.language.as_deref();
+        }
+        let prefix = first.chars().next()?.to_ascii_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(code, ..)| *code == prefix)
+            .map(|(_, language, ..)| *language)
+    }
+
+    /// Gender of a voice, if the catalogue knows it
+    pub fn gender(&self, voice: &str) -> Option<Gender> {
+        self.voices.get(voice)?.gender
+    }
+
+    /// Whether a voice or blend already speaks `language`; British and
+    /// American voices both count as English
+    pub fn speaks(&self, voice: &str, language: &str) -> bool {
+        match (self.language(voice), language.starts_with("en")) {
+            (Some(own), true) => own.starts_with("en"),
+            (Some(own), false) => own == language,
+            (None, _) => false,
+        }
+    }
+
+    /// The first voice made for `language`, of `gender` if there is one
+    pub fn voice_for_language(&self, language: &str, gender: Option<Gender>) -> Option<&str> {
+        let voices: Vec<&VoiceMeta> = self
+            .voices
+            .values()
+            .filter(|voice| voice.language.as_deref() == Some(language))
+            .collect();
+        voices
+            .iter()
+            .find(|voice| gender.is_some() && voice.gender == gender)
+            .or(voices.first())
+            .map(|voice| voice.id.as_str())
+    }
+}
+
+/// A heading for voices named with `prefix`, e.g. `American Female(af)`
+pub fn describe_prefix(prefix: &str) -> String {
+    let mut chars = prefix.chars();
+    let accent = chars
+        .next()
+        .and_then(|code| LANGUAGES.iter().find(|(c, ..)| *c == code))
+        .map(|(_, _, accent, _)| *accent);
+    let gender = match chars.next() {
+        Some('f') => Some("Female"),
+        Some('m') => Some("Male"),
+        _ => None,
+    };
+    match (accent, gender) {
+        (Some(accent), Some(gender)) => format!("{} {}({})", accent, gender, prefix),
+        _ => prefix.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voices_by_language() {
+        let catalog = VoiceCatalog::new(["af_sky", "bf_emma", "ef_dora", "em_alex", "em_santa"]);
+        assert!(catalog.speaks("bf_emma", "en-us"));
+        assert!(catalog.speaks("em_alex:0.5,af_sky:0.5", "es"));
+        assert!(!catalog.speaks("af_sky", "es"));
+        assert_eq!(
+            catalog.voice_for_language("es", Some(Gender::Male)),
+            Some("em_alex")
+        );
+        assert_eq!(catalog.voice_for_language("es", None), Some("ef_dora"));
+        assert_eq!(catalog.voice_for_language("fr-fr", None), None);
+    }
+
+    #[test]
+    fn test_voice_catalog() {
+        let mut catalog = VoiceCatalog::new(["af_heart", "af_alloy", "zm_yunxi", "my_mix"]);
+        let heart = catalog.get("af_heart").unwrap();
+        assert_eq!(
+            (
+                heart.lang_code,
+                heart.language.as_deref(),
+                heart.gender,
+                heart.accent.as_deref(),
+                heart.name.as_str(),
+                heart.quality.as_deref()
+            ),
+            (
+                Some('a'),
+                Some("en-us"),
+                Some(Gender::Female),
+                Some("American"),
+                "Heart",
+                Some("A")
+            )
+        );
+        assert_eq!(catalog.resolve("af_alloy"), "af_alloy");
+        assert_eq!(catalog.resolve("alloy"), "alloy");
+        assert_eq!(catalog.language("zm_yunxi:0.5,af_heart:0.5"), Some("zh"));
+        assert_eq!(catalog.language("my_mix"), None);
+
+        catalog
+            .update(&serde_json::json!({
+                "my_mix": {"language": "en-gb", "gender": "male", "aliases": ["Narrator"]},
+                "missing": {"name": "Nobody"}
+            }))
+            .unwrap();
+        let mix = catalog.get("my_mix").unwrap();
+        assert_eq!(
+            (mix.lang_code, mix.gender, mix.name.as_str()),
+            (Some('b'), Some(Gender::Male), "my_mix")
+        );
+        assert_eq!(catalog.resolve("narrator"), "my_mix");
+        assert_eq!(catalog.language("my_mix"), Some("en-gb"));
+        assert!(
+            catalog
+                .update(&serde_json::json!({"my_mix": {"age": 3}}))
+                .is_err()
+        );
+
+        assert_eq!(catalog.gender("my_mix"), Some(Gender::Male));
+        assert_eq!(catalog.gender("zm_unknown"), None);
+
+        assert_eq!(describe_prefix("bm"), "British Male(bm)");
+        assert_eq!(describe_prefix("xx"), "xx");
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::tts::tokenize::tokenize_with;
+use crate::tts::vocab::get_vocab;
+use crate::utils::fileio::load_json_file;
+
+/// Model level constants, read from a Kokoro `config.json` placed next to the
+/// ONNX file. Every field falls back to the Kokoro v1.0 value, so a config
+/// only needs to list what differs.
+///
+/// Recognised keys:
+/// - `vocab`: map from phoneme symbol to token id
+/// - `sample_rate`: output sample rate in Hz (24000)
+/// - `hop_length`: audio samples per predicted duration frame (600); when
+///   absent it is derived from upstream's `istftnet` section as
+///   `2 * product(upsample_rates) * gen_istft_hop_size`
+/// - `max_tokens`: phoneme token budget for one inference chunk (500)
+/// - `plbert.max_position_embeddings`: context length; the voice style tables
+///   hold one entry per possible token count, i.e. this value minus one (512)
+#[derive(Debug, Clone)]
+pub struct ModelConfig {
+    pub vocab: HashMap<char, usize>,
+    pub sample_rate: u32,
+    pub hop_length: u32,
+    pub max_tokens: usize,
+    pub style_table_len: usize,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            vocab: get_vocab(),
+            sample_rate: 24000,
+            hop_length: 600,
+            max_tokens: 500,
+            style_table_len: 511,
+        }
+    }
+}
+
+impl ModelConfig {
+    /// Load the config for a model: an explicit path if given, otherwise
+    /// `config.json` in the model's directory if present, otherwise defaults.
+    pub fn load_for_model(model_path: &str, config_path: Option<&str>) -> Result<Self, String> {
+        if let Some(path) = config_path {
+            return Self::load(path);
+        }
+
+        let sibling = Path::new(model_path).with_file_name("config.json");
+        if sibling.exists() {
+            Self::load(&sibling.to_string_lossy())
+        } else {
+            tracing::info!(
+                "No config.json next to {}, using the Kokoro v1.0 model config",
+                model_path
+            );
+            Ok(Self::default())
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = load_json_file(path)?;
+        let config = Self::from_json(&json).map_err(|e| format!("{}: {}", path, e))?;
+        tracing::info!(
+            "Model config loaded from {} ({} symbols, {} Hz, {} frames/s)",
+            path,
+            config.vocab.len(),
+            config.sample_rate,
+            config.frames_per_sec()
+        );
+        Ok(config)
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        let uint = |key: &str, value: &Value| {
+            value
+                .as_u64()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| format!("'{}' must be a positive integer", key))
+        };
+
+        if let Some(vocab) = json.get("vocab") {
+            let entries = vocab
+                .as_object()
+                .ok_or("'vocab' must be an object of symbol to token id")?;
+            let mut map = HashMap::with_capacity(entries.len());
+            for (symbol, id) in entries {
+                let mut chars = symbol.chars();
+                let (Some(c), None) = (chars.next(), chars.next()) else {
+                    return Err(format!("vocab key '{}' is not a single character", symbol));
+                };
+                let id = id
+                    .as_u64()
+                    .ok_or_else(|| format!("vocab id for '{}' is not an integer", symbol))?;
+                map.insert(c, id as usize);
+            }
+            config.vocab = map;
+        }
+        if let Some(v) = json.get("sample_rate") {
+            config.sample_rate = uint("sample_rate", v)? as u32;
+        }
+        if let Some(v) = json.get("hop_length") {
+            config.hop_length = uint("hop_length", v)? as u32;
+        } else if let Some(istftnet) = json.get("istftnet") {
+            // The decoder upsamples the duration frames by 2 before the iSTFT
+            let rates = istftnet
+                .get("upsample_rates")
+                .and_then(Value::as_array)
+                .ok_or("'istftnet.upsample_rates' must be an array")?;
+            let mut hop = 2 * uint(
+                "istftnet.gen_istft_hop_size",
+                istftnet.get("gen_istft_hop_size").unwrap_or(&Value::Null),
+            )?;
+            for rate in rates {
+                hop *= uint("istftnet.upsample_rates", rate)?;
+            }
+            config.hop_length = hop as u32;
+        }
+        if let Some(v) = json.get("max_tokens") {
+            config.max_tokens = uint("max_tokens", v)? as usize;
+        }
+        if let Some(v) = json.pointer("/plbert/max_position_embeddings") {
+            config.style_table_len = uint("plbert.max_position_embeddings", v)? as usize - 1;
+        }
+
+        // Chunks are padded with a start and end token, and the style table is
+        // indexed by the unpadded token count
+        if config.max_tokens >= config.style_table_len {
+            return Err(format!(
+                "max_tokens ({}) must be below the style table length ({})",
+                config.max_tokens, config.style_table_len
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// Duration frames per second of audio, used to turn durations into timestamps.
+    pub fn frames_per_sec(&self) -> f32 {
+        self.sample_rate as f32 / self.hop_length as f32
+    }
+
+    /// Whether the vocabulary has the bopomofo symbols of Kokoro v1.1-zh,
+    /// which takes the pinyin front end instead of eSpeak for Mandarin.
+    pub fn supports_zhuyin(&self) -> bool {
+        self.vocab.contains_key(&'ㄅ')
+    }
+
+    /// Tokenize phonemes with this model's vocabulary.
+    pub fn tokenize(&self, phonemes: &str) -> Vec<i64> {
+        tokenize_with(&self.vocab, phonemes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_config_from_json() {
+        let json: Value = serde_json::from_str(
+            r#"{"vocab": {"a": 1, "ə": 2}, "hop_length": 300, "plbert": {"max_position_embeddings": 256}, "max_tokens": 200}"#,
+        )
+        .unwrap();
+        let config = ModelConfig::from_json(&json).unwrap();
+        assert_eq!(config.vocab.len(), 2);
+        assert_eq!(config.tokenize("aəx"), vec![1, 2]);
+        assert_eq!(config.sample_rate, 24000);
+        assert_eq!(config.frames_per_sec(), 80.0);
+        assert_eq!(config.style_table_len, 255);
+
+        let defaults = ModelConfig::from_json(&serde_json::json!({})).unwrap();
+        assert_eq!(defaults.frames_per_sec(), 40.0);
+
+        // Upstream config.json layout
+        let upstream = ModelConfig::from_json(&serde_json::json!({
+            "istftnet": {"upsample_rates": [10, 6], "gen_istft_hop_size": 5},
+            "plbert": {"max_position_embeddings": 512}
+        }))
+        .unwrap();
+        assert_eq!(upstream.hop_length, 600);
+        assert_eq!(upstream.style_table_len, 511);
+        assert_eq!(defaults.max_tokens, 500);
+
+        assert!(ModelConfig::from_json(&serde_json::json!({"vocab": {"ab": 1}})).is_err());
+        assert!(ModelConfig::from_json(&serde_json::json!({"max_tokens": 600})).is_err());
+    }
+}
@@ -0,0 +1,456 @@
+//! Voice design: new voices from the style space of the built-in voices.
+//!
+//! The style tables of the built-in voices are flattened and analysed with
+//! PCA; principal direction `k` is the axis `pcK`. The `gender` axis runs
+//! from the mean male voice to the mean female one, as the voice catalogue
+//! tells their gender, so positive values move towards female voices.
+//!
+//! A design starts from a base voice or blend, or from the mean voice, adds
+//! seeded random steps along the first principal axes and then moves along
+//! axes by slider values. Steps and sliders are in standard deviations of
+//! the built-in voices along the axis, so the same seed and sliders always
+//! give the same voice for the same voices file.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::tts::catalog::VoiceCatalog;
+use crate::tts::voices::{Gender, StyleTable};
+
+/// Principal axes kept by [`StyleSpace::analyze`]
+pub const MAX_COMPONENTS: usize = 16;
+
+/// Principal axes a seed takes random steps along
+pub const RANDOM_COMPONENTS: usize = 8;
+
+/// A direction in style space
+#[derive(Debug, Clone)]
+pub struct Axis {
+    /// `gender`, or `pc1`, `pc2`, ... for principal axes
+    pub name: String,
+    /// Standard deviation of the built-in voices along the axis
+    pub scale: f32,
+    /// Share of the variance of the voices along a principal axis
+    pub explained: Option<f32>,
+    /// Voices furthest along the axis in the negative and positive direction
+    pub extremes: (String, String),
+    direction: Vec<f32>,
+}
+
+impl Axis {
+    fn new(name: String, direction: Vec<f32>, explained: Option<f32>, voices: &Voices) -> Self {
+        let scores: Vec<f32> = voices
+            .rows
+            .iter()
+            .map(|row| dot(row, &direction) as f32)
+            .collect();
+        let mean = scores.iter().sum::<f32>() / scores.len() as f32;
+        let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / scores.len() as f32;
+        let (min, max) = scores
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(min, max), (i, score)| {
+                (
+                    if *score < scores[min] { i } else { min },
+                    if *score > scores[max] { i } else { max },
+                )
+            });
+        Self {
+            name,
+            scale: variance.sqrt(),
+            explained,
+            extremes: (voices.names[min].clone(), voices.names[max].clone()),
+            direction,
+        }
+    }
+
+    /// Where `table` lies along the axis relative to the mean voice, in
+    /// standard deviations
+    pub fn position(&self, space: &StyleSpace, table: &[[[f32; 256]; 1]]) -> f32 {
+        let centered: Vec<f32> = flatten(table, space.rows)
+            .iter()
+            .zip(&space.mean)
+            .map(|(value, mean)| value - mean)
+            .collect();
+        dot(&centered, &self.direction) as f32 / self.scale.max(f32::EPSILON)
+    }
+}
+
+/// The principal axes and the gender axis of a set of voices
+#[derive(Debug, Clone)]
+pub struct StyleSpace {
+    rows: usize,
+    mean: Vec<f32>,
+    axes: Vec<Axis>,
+}
+
+/// Flattened, centered style tables, sorted by voice name
+struct Voices {
+    names: Vec<String>,
+    rows: Vec<Vec<f32>>,
+}
+
+impl StyleSpace {
+    /// Analyse the style tables of `voices`, using the first `rows` rows,
+    /// with their genders from `catalog`
+    pub fn analyze(
+        voices: &[(&str, &StyleTable)],
+        rows: usize,
+        catalog: &VoiceCatalog,
+    ) -> Result<Self, String> {
+        if voices.len() < 3 {
+            return Err(format!(
+                "voice design needs at least 3 voices to analyse, got {}",
+                voices.len()
+            ));
+        }
+        let mut sorted: Vec<&(&str, &StyleTable)> = voices.iter().collect();
+        sorted.sort_by_key(|(name, _)| *name);
+        let mut data: Vec<Vec<f32>> = sorted
+            .iter()
+            .map(|(_, table)| flatten(table, rows))
+            .collect();
+        let dims = rows * 256;
+        let mut mean = vec![0.0f32; dims];
+        for row in &data {
+            for (m, v) in mean.iter_mut().zip(row) {
+                *m += v / data.len() as f32;
+            }
+        }
+        for row in &mut data {
+            for (v, m) in row.iter_mut().zip(&mean) {
+                *v -= m;
+            }
+        }
+        let voices = Voices {
+            names: sorted.iter().map(|(name, _)| name.to_string()).collect(),
+            rows: data,
+        };
+
+        // PCA through the small Gram matrix of the voices
+        let n = voices.rows.len();
+        let mut gram = vec![vec![0.0f64; n]; n];
+        for (i, a) in voices.rows.iter().enumerate() {
+            for (j, b) in voices.rows.iter().enumerate().skip(i) {
+                let value = dot(a, b);
+                gram[i][j] = value;
+                gram[j][i] = value;
+            }
+        }
+        let (values, vectors) = symmetric_eigen(gram);
+        let total: f64 = values.iter().filter(|v| **v > 0.0).sum();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|a, b| values[*b].total_cmp(&values[*a]));
+
+        let mut axes = Vec::new();
+        for (k, &i) in order
+            .iter()
+            .take(MAX_COMPONENTS)
+            .enumerate()
+            .filter(|(_, i)| values[**i] > total * 1e-6)
+        {
+            let norm = values[i].sqrt();
+            let mut direction = vec![0.0f32; dims];
+            for (voice, row) in voices.rows.iter().enumerate() {
+                let weight = (vectors[voice][i] / norm) as f32;
+                for (d, v) in direction.iter_mut().zip(row) {
+                    *d += weight * v;
+                }
+            }
+            // Point each axis at the voice furthest along it
+            let furthest = (0..n)
+                .max_by(|a, b| vectors[*a][i].abs().total_cmp(&vectors[*b][i].abs()))
+                .unwrap_or(0);
+            if vectors[furthest][i] < 0.0 {
+                direction.iter_mut().for_each(|d| *d = -*d);
+            }
+            let explained = (values[i] / total) as f32;
+            axes.push(Axis::new(
+                format!("pc{}", k + 1),
+                direction,
+                Some(explained),
+                &voices,
+            ));
+        }
+
+        if let Some(gender) = gender_direction(&voices, catalog) {
+            axes.insert(0, Axis::new("gender".to_string(), gender, None, &voices));
+        }
+        Ok(Self { rows, mean, axes })
+    }
+
+    pub fn axes(&self) -> &[Axis] {
+        &self.axes
+    }
+
+    pub fn axis(&self, name: &str) -> Option<&Axis> {
+        self.axes.iter().find(|axis| axis.name == name)
+    }
+
+    /// A new voice: `base`, or the mean voice, moved as `design` says
+    pub fn design(
+        &self,
+        design: &VoiceDesign,
+        base: Option<&[[[f32; 256]; 1]]>,
+    ) -> Result<StyleTable, String> {
+        let mut voice = match base {
+            Some(table) => flatten(table, self.rows),
+            None => self.mean.clone(),
+        };
+        let mut step = |axis: &Axis, amount: f32| {
+            for (v, d) in voice.iter_mut().zip(&axis.direction) {
+                *v += amount * axis.scale * d;
+            }
+        };
+        if let Some(seed) = design.seed {
+            let mut random = SplitMix64(seed);
+            for axis in self
+                .axes
+                .iter()
+                .filter(|axis| axis.explained.is_some())
+                .take(RANDOM_COMPONENTS)
+            {
+                step(axis, random.normal() * design.variation);
+            }
+        }
+        for Slider { axis, value } in &design.sliders {
+            let axis = self.axis(axis).ok_or_else(|| {
+                let names: Vec<&str> = self.axes.iter().map(|a| a.name.as_str()).collect();
+                format!("unknown axis '{}', use one of {}", axis, names.join(", "))
+            })?;
+            step(axis, *value);
+        }
+
+        let mut table = vec![[[0.0; 256]; 1]; self.rows];
+        for (row, values) in table.iter_mut().zip(voice.chunks(256)) {
+            row[0].copy_from_slice(values);
+        }
+        Ok(table)
+    }
+}
+
+/// How to make a voice from a [`StyleSpace`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceDesign {
+    /// Seed of the random steps; no random steps without one
+    pub seed: Option<u64>,
+    /// Size of the random steps, in standard deviations
+    pub variation: f32,
+    pub sliders: Vec<Slider>,
+}
+
+impl Default for VoiceDesign {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            variation: 1.0,
+            sliders: Vec::new(),
+        }
+    }
+}
+
+/// A move along an axis, written `AXIS=VALUE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slider {
+    pub axis: String,
+    pub value: f32,
+}
+
+impl FromStr for Slider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (axis, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' is not AXIS=VALUE", s))?;
+        let value: f32 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a number for axis '{}'", value, axis))?;
+        if !value.is_finite() {
+            return Err(format!("axis '{}' needs a finite value", axis));
+        }
+        Ok(Self {
+            axis: axis.trim().to_lowercase(),
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Slider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.axis, self.value)
+    }
+}
+
+fn flatten(table: &[[[f32; 256]; 1]], rows: usize) -> Vec<f32> {
+    let mut values = vec![0.0; rows * 256];
+    for (chunk, row) in values.chunks_mut(256).zip(table) {
+        chunk.copy_from_slice(&row[0]);
+    }
+    values
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum()
+}
+
+/// Unit direction from the mean male voice to the mean female voice
+fn gender_direction(voices: &Voices, catalog: &VoiceCatalog) -> Option<Vec<f32>> {
+    let dims = voices.rows.first()?.len();
+    let count = |gender| {
+        voices
+            .names
+            .iter()
+            .filter(|name| catalog.gender(name) == Some(gender))
+            .count() as f32
+    };
+    let (females, males) = (count(Gender::Female), count(Gender::Male));
+    if females == 0.0 || males == 0.0 {
+        return None;
+    }
+    let mut difference = vec![0.0f32; dims];
+    for (name, row) in voices.names.iter().zip(&voices.rows) {
+        let weight = match catalog.gender(name) {
+            Some(Gender::Female) => 1.0 / females,
+            Some(Gender::Male) => -1.0 / males,
+            None => continue,
+        };
+        for (d, v) in difference.iter_mut().zip(row) {
+            *d += weight * v;
+        }
+    }
+    let norm = dot(&difference, &difference).sqrt() as f32;
+    (norm > 0.0).then(|| difference.iter().map(|d| d / norm).collect())
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, by
+/// cyclic Jacobi rotations
+fn symmetric_eigen(mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = a.len();
+    let mut v = vec![vec![0.0; n]; n];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    let scale: f64 = a
+        .iter()
+        .flatten()
+        .map(|x| x * x)
+        .sum::<f64>()
+        .max(f64::MIN_POSITIVE);
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off <= scale * 1e-22 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q].abs() <= f64::MIN_POSITIVE {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (top, bottom) = a.split_at_mut(q);
+                for (apk, aqk) in top[p].iter_mut().zip(bottom[0].iter_mut()) {
+                    let (x, y) = (*apk, *aqk);
+                    *apk = c * x - s * y;
+                    *aqk = s * x + c * y;
+                }
+                for row in v.iter_mut() {
+                    let (vkp, vkq) = (row[p], row[q]);
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+/// Small seeded generator, so designs don't depend on a random crate's
+/// version
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, by the Box-Muller transform
+    fn normal(&mut self) -> f32 {
+        let (u, v) = (self.uniform(), self.uniform());
+        ((-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(values: [f32; 3]) -> StyleTable {
+        let mut row = [[0.0; 256]; 1];
+        row[0][..3].copy_from_slice(&values);
+        vec![row]
+    }
+
+    #[test]
+    fn test_style_space() {
+        // Female voices sit high on the first value, and the second value
+        // varies most
+        let tables = [
+            ("af_a", voice([2.0, 4.0, 0.0])),
+            ("af_b", voice([2.0, -4.0, 0.1])),
+            ("am_c", voice([-2.0, 3.0, 0.0])),
+            ("am_d", voice([-2.0, -3.0, -0.1])),
+        ];
+        let voices: Vec<(&str, &StyleTable)> =
+            tables.iter().map(|(name, table)| (*name, table)).collect();
+        let catalog = VoiceCatalog::new(tables.iter().map(|(name, _)| *name));
+        let space = StyleSpace::analyze(&voices, 1, &catalog).unwrap();
+
+        let gender = space.axis("gender").unwrap();
+        assert!(gender.position(&space, &tables[0].1) > 0.9);
+        assert!(gender.position(&space, &tables[2].1) < -0.9);
+        let pc1 = space.axis("pc1").unwrap();
+        assert!(pc1.explained.unwrap() > 0.6);
+        assert!(pc1.direction[1].abs() > 0.99);
+
+        let design = |seed| VoiceDesign {
+            seed,
+            sliders: vec!["gender=2".parse().unwrap()],
+            ..VoiceDesign::default()
+        };
+        let male = space.design(&design(None), Some(&tables[3].1)).unwrap();
+        assert!(gender.position(&space, &male) > 0.9);
+        let one = space.design(&design(Some(7)), None).unwrap();
+        assert_eq!(one, space.design(&design(Some(7)), None).unwrap());
+        assert_ne!(one, space.design(&design(Some(8)), None).unwrap());
+
+        let unknown = VoiceDesign {
+            sliders: vec!["pitch=1".parse().unwrap()],
+            ..VoiceDesign::default()
+        };
+        assert!(space.design(&unknown, None).is_err());
+        assert!("gender".parse::<Slider>().is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Language detection for the `auto` language mode.
+//!
+//! Text is split into sentences, each sentence is classified offline with
+//! whatlang's bundled trigram model, restricted to the languages of the
+//! [`catalog`](crate::tts::catalog), and consecutive sentences in the same
+//! language are merged into one span. Sentences too short to classify
+//! confidently inherit the language of the span before them.
+
+use lazy_static::lazy_static;
+use whatlang::{Detector, Lang};
+
+use crate::tts::{catalog, segment};
+
+/// Detect the language of every sentence
+pub const AUTO: &str = "auto";
+
+/// Like [`AUTO`], and also switch to a voice of the detected language
+pub const AUTO_VOICE: &str = "auto+voice";
+
+/// Below this confidence a sentence keeps the previous span's language
+const MIN_CONFIDENCE: f64 = 0.5;
+
+lazy_static! {
+    static ref DETECTOR: Detector = {
+        let mut allowlist: Vec<Lang> = catalog::LANGUAGES
+            .iter()
+            .filter_map(|(_, _, _, iso)| Lang::from_code(*iso))
+            .collect();
+        allowlist.dedup();
+        Detector::with_allowlist(allowlist)
+    };
+}
+
+/// A run of text in one language, with the voice it is spoken with
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageSpan {
+    pub text: String,
+    pub language: String,
+    pub voice: String,
+}
+
+pub fn is_auto(lan: &str) -> bool {
+    lan == AUTO || lan == AUTO_VOICE
+}
+
+/// The eSpeak language of a sentence, if it can be told with confidence
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = DETECTOR.detect(text)?;
+    if info.confidence() < MIN_CONFIDENCE {
+        return None;
+    }
+    // The first of the languages sharing a code, American for English
+    catalog::LANGUAGES
+        .iter()
+        .find(|(_, _, _, iso)| *iso == info.lang().code())
+        .map(|(_, language, ..)| *language)
+}
+
+/// Split text into runs of sentences in the same language. Text before the
+/// first confidently detected sentence gets `fallback`.
+pub fn split_by_language(text: &str, fallback: &str) -> Vec<(String, String)> {
+    let mut spans: Vec<(String, String)> = Vec::new();
+
+    for sentence in segment::split_sentences(text) {
+        let language = detect_language(sentence)
+            .map(str::to_string)
+            .or_else(|| spans.last().map(|(_, language)| language.clone()))
+            .unwrap_or_else(|| fallback.to_string());
+
+        match spans.last_mut() {
+            Some((span, last)) if *last == language => {
+                span.push(' ');
+                span.push_str(sentence);
+            }
+            _ => spans.push((sentence.to_string(), language)),
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by_language() {
+        let spans = split_by_language(
+            "Hola, ¿cómo estás? Estoy muy bien, gracias por preguntar. \
+             This paragraph is written in English and should be read with an English voice.",
+            "en-us",
+        );
+        let languages: Vec<&str> = spans.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(languages, vec!["es", "en-us"]);
+
+        assert_eq!(
+            detect_language("今天天气很好，我们去公园散步吧。"),
+            Some("zh")
+        );
+        assert_eq!(split_by_language("Ok.", "fr-fr")[0].1, "fr-fr");
+    }
+}
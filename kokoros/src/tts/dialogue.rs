@@ -0,0 +1,539 @@
+//! Multi-speaker scripts rendered into one recording.
+//!
+//! A script is a list of turns, each spoken by a named speaker with its own
+//! voice, speed, language and stereo position. The plain text form has one
+//! `SPEAKER: line` per turn, and `@SPEAKER = VOICE key=value ...` lines to
+//! declare voices:
+//!
+//! ```text
+//! @HOST = af_sarah pan=-0.4
+//! @GUEST = bm_george speed=1.1 lan=en-gb pan=0.4
+//! HOST: Welcome back to the show.
+//! GUEST: Thanks for having me.
+//! ```
+//!
+//! Speakers named after a voice, as in `af_sky: Hello`, need no
+//! declaration. Scripts can also be JSON, see [`Script::from_json`].
+//!
+//! A `:` doesn't always start a turn: `Note: bring these` and
+//! `https://...` are text. Only declared speakers, voices and, in scripts
+//! without declarations, names written in capitals start one.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::error::KokorosError;
+use crate::tts::koko::{TTSKoko, WordAlignment};
+
+/// Longest speaker label read before the `:` of a line
+const MAX_SPEAKER_NAME: usize = 32;
+
+/// Voice settings of one speaker. Unset speed and language fall back to
+/// [`DialogueOptions`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Speaker {
+    pub voice: String,
+    pub speed: Option<f32>,
+    pub lan: Option<String>,
+    /// Stereo position from -1.0 (left) to 1.0 (right)
+    pub pan: f32,
+}
+
+impl Speaker {
+    pub fn new(voice: impl Into<String>) -> Self {
+        Self {
+            voice: voice.into(),
+            speed: None,
+            lan: None,
+            pan: 0.0,
+        }
+    }
+
+    /// Parse `VOICE key=value ...` with keys `speed`, `lan` and `pan`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut words = spec.split_whitespace();
+        let voice = words
+            .next()
+            .filter(|voice| !voice.contains('='))
+            .ok_or_else(|| format!("'{}' does not start with a voice", spec.trim()))?;
+        let mut speaker = Speaker::new(voice);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", word))?;
+            let number = || {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("'{}' is not a number for {}", value, key))
+            };
+            match key {
+                "speed" => speaker.speed = Some(number()?),
+                "lan" | "lang" => speaker.lan = Some(value.to_string()),
+                "pan" => speaker.pan = number()?,
+                _ => {
+                    return Err(format!(
+                        "unknown speaker setting '{}', expected speed, lan or pan",
+                        key
+                    ));
+                }
+            }
+        }
+        speaker.validate()?;
+        Ok(speaker)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(speed) = self.speed
+            && !(0.25..=4.0).contains(&speed)
+        {
+            return Err(format!("speed must be between 0.25 and 4.0, got {}", speed));
+        }
+        if !(-1.0..=1.0).contains(&self.pan) {
+            return Err(format!(
+                "pan must be between -1.0 and 1.0, got {}",
+                self.pan
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub speaker: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    pub speakers: BTreeMap<String, Speaker>,
+    pub turns: Vec<Turn>,
+}
+
+impl Script {
+    /// Parse a script, as JSON when it starts with `{` or `[`, else as text
+    pub fn parse(source: &str) -> Result<Self, String> {
+        Self::parse_with(source, false, looks_like_voice)
+    }
+
+    /// [`Script::parse`] with `known` telling the names that start a turn
+    /// besides declared speakers, such as voices or speakers declared on
+    /// the command line. `declared` tells that speakers are declared
+    /// outside the script, which like `@` lines stops names in capitals
+    /// from starting a turn on their own.
+    pub fn parse_with(
+        source: &str,
+        declared: bool,
+        known: impl Fn(&str) -> bool,
+    ) -> Result<Self, String> {
+        let trimmed = source.trim_start();
+        if trimmed.starts_with(['{', '[']) {
+            let value: Value =
+                serde_json::from_str(trimmed).map_err(|e| format!("invalid JSON script: {}", e))?;
+            return Self::from_json(&value);
+        }
+        Self::from_text_with(source, declared, known)
+    }
+
+    /// Parse the `SPEAKER: line` form. Lines without a speaker continue the
+    /// previous turn; `#` starts a comment line. Declared speakers and voice
+    /// ids start a turn, and so do names in capitals, such as `HOST:`, when
+    /// nothing is declared.
+    pub fn from_text(source: &str) -> Result<Self, String> {
+        Self::from_text_with(source, false, looks_like_voice)
+    }
+
+    /// [`Script::from_text`] with `known` telling the names that start a
+    /// turn besides declared speakers, and `declared` that speakers are
+    /// declared outside the script
+    pub fn from_text_with(
+        source: &str,
+        declared: bool,
+        known: impl Fn(&str) -> bool,
+    ) -> Result<Self, String> {
+        let mut script = Script::default();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if let Some(declaration) = line.strip_prefix('@') {
+                script
+                    .declare(declaration)
+                    .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            }
+        }
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', '@']) {
+                continue;
+            }
+            let label = line.split_once(':').filter(|(name, text)| {
+                let name = name.trim();
+                if script.speakers.contains_key(name) || known(name) {
+                    return true;
+                }
+                // A name in capitals, not a word at the start of a sentence
+                !declared
+                    && script.speakers.is_empty()
+                    && name.chars().count() <= MAX_SPEAKER_NAME
+                    && name.starts_with(char::is_alphanumeric)
+                    && name.chars().any(char::is_uppercase)
+                    && !name.chars().any(char::is_lowercase)
+                    && !name.contains(['!', '?', ',', ';', '"'])
+                    && !text.starts_with("//")
+            });
+            match (label, script.turns.last_mut()) {
+                (Some((name, text)), _) => script.turns.push(Turn {
+                    speaker: name.trim().to_string(),
+                    text: text.trim().to_string(),
+                }),
+                (None, Some(turn)) => {
+                    if !turn.text.is_empty() {
+                        turn.text.push(' ');
+                    }
+                    turn.text.push_str(line);
+                }
+                (None, None) => {
+                    return Err(format!(
+                        "line {}: expected 'SPEAKER: text', got '{}'",
+                        number + 1,
+                        line
+                    ));
+                }
+            }
+        }
+        script.turns.retain(|turn| !turn.text.is_empty());
+        Ok(script)
+    }
+
+    /// Parse a JSON list of `{"speaker", "text"}` turns, or an object with
+    /// that list as `turns` and a `speakers` object mapping names to a
+    /// `VOICE key=value` string or `{"voice", "speed", "lan", "pan"}`
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let mut script = Script::default();
+        let turns = match value {
+            Value::Array(_) => value,
+            Value::Object(object) => {
+                if let Some(speakers) = object.get("speakers") {
+                    let speakers = speakers.as_object().ok_or("'speakers' must be an object")?;
+                    for (name, spec) in speakers {
+                        let speaker = match spec {
+                            Value::String(spec) => Speaker::parse(spec),
+                            Value::Object(_) => speaker_from_json(spec),
+                            _ => Err("expected a string or an object".to_string()),
+                        }
+                        .map_err(|e| format!("speaker '{}': {}", name, e))?;
+                        script.speakers.insert(name.clone(), speaker);
+                    }
+                }
+                object.get("turns").ok_or("missing 'turns'")?
+            }
+            _ => return Err("a JSON script must be a list or an object".to_string()),
+        };
+
+        for (i, turn) in turns
+            .as_array()
+            .ok_or("'turns' must be a list")?
+            .iter()
+            .enumerate()
+        {
+            let field = |key: &str| {
+                turn.get(key)
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("turn {}: missing '{}'", i + 1, key))
+            };
+            let text = field("text")?;
+            if !text.trim().is_empty() {
+                script.turns.push(Turn {
+                    speaker: field("speaker")?,
+                    text,
+                });
+            }
+        }
+        Ok(script)
+    }
+
+    /// Add or replace a speaker from `NAME = VOICE key=value ...`
+    pub fn declare(&mut self, declaration: &str) -> Result<(), String> {
+        let (name, spec) = declaration
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME = VOICE, got '{}'", declaration.trim()))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("speaker name is empty".to_string());
+        }
+        self.speakers
+            .insert(name.to_string(), Speaker::parse(spec)?);
+        Ok(())
+    }
+
+    /// The settings for every speaker of the script, in order of first
+    /// appearance. Speakers without a declaration must be named after a
+    /// voice.
+    pub fn cast(&self, tts: &TTSKoko) -> Result<Vec<(&str, Speaker)>, KokorosError> {
+        let mut cast: Vec<(&str, Speaker)> = Vec::new();
+        for turn in &self.turns {
+            if cast.iter().any(|(name, _)| *name == turn.speaker) {
+                continue;
+            }
+            let speaker = match self.speakers.get(&turn.speaker) {
+                Some(speaker) => speaker.clone(),
+                None if tts.check_voice(&turn.speaker).is_ok() => Speaker::new(&turn.speaker),
+                None => {
+                    return Err(KokorosError::Document(format!(
+                        "speaker '{}' has no voice, declare it with '@{} = VOICE'",
+                        turn.speaker, turn.speaker
+                    )));
+                }
+            };
+            tts.check_voice(&speaker.voice)?;
+            cast.push((&turn.speaker, speaker));
+        }
+        Ok(cast)
+    }
+}
+
+/// Kokoro voice ids: a language and gender letter, `_`, a name
+fn looks_like_voice(name: &str) -> bool {
+    let mut parts = name.splitn(2, '_');
+    matches!(
+        (parts.next(), parts.next()),
+        (Some(prefix), Some(rest)) if prefix.len() == 2
+            && prefix.chars().all(|c| c.is_ascii_lowercase())
+            && !rest.is_empty()
+            && rest.chars().all(|c| c.is_ascii_alphanumeric())
+    )
+}
+
+fn speaker_from_json(spec: &Value) -> Result<Speaker, String> {
+    let voice = spec
+        .get("voice")
+        .and_then(Value::as_str)
+        .ok_or("missing 'voice'")?;
+    let number = |key: &str| match spec.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .map(|n| Some(n as f32))
+            .ok_or_else(|| format!("'{}' must be a number", key)),
+    };
+    let speaker = Speaker {
+        voice: voice.to_string(),
+        speed: number("speed")?,
+        lan: spec
+            .get("lan")
+            .or_else(|| spec.get("lang_code"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        pan: number("pan")?.unwrap_or(0.0),
+    };
+    speaker.validate()?;
+    Ok(speaker)
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogueOptions {
+    /// Language of speakers without one
+    pub lan: String,
+    /// Speed of speakers without one
+    pub speed: f32,
+    /// Silence between turns, in seconds
+    pub gap: f32,
+    /// Silence before the first turn, in tokens
+    pub initial_silence: Option<usize>,
+    /// Align words, which needs a timestamped model
+    pub timestamps: bool,
+}
+
+impl Default for DialogueOptions {
+    fn default() -> Self {
+        Self {
+            lan: "en-us".to_string(),
+            speed: 1.0,
+            gap: 0.4,
+            initial_silence: None,
+            timestamps: false,
+        }
+    }
+}
+
+/// A word of the recording and who said it
+#[derive(Debug, Clone)]
+pub struct SpeakerWord {
+    pub speaker: String,
+    pub word: WordAlignment,
+}
+
+/// A rendered script. Samples are interleaved left and right when any
+/// speaker is panned, mono otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Dialogue {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub words: Vec<SpeakerWord>,
+}
+
+impl TTSKoko {
+    /// Render every turn of `script` with its speaker's voice, one after
+    /// another with `options.gap` seconds between turns
+    pub fn tts_dialogue(
+        &self,
+        script: &Script,
+        options: &DialogueOptions,
+    ) -> Result<Dialogue, KokorosError> {
+        let cast = script.cast(self)?;
+        let stereo = cast.iter().any(|(_, speaker)| speaker.pan != 0.0);
+        let channels: u16 = if stereo { 2 } else { 1 };
+        let sample_rate = self.sample_rate() as f32;
+        let gap = (options.gap.max(0.0) * sample_rate) as usize;
+
+        let mut dialogue = Dialogue {
+            channels,
+            ..Dialogue::default()
+        };
+        for (i, turn) in script.turns.iter().enumerate() {
+            let speaker = &cast
+                .iter()
+                .find(|(name, _)| *name == turn.speaker)
+                .expect("every speaker is cast")
+                .1;
+            let lan = speaker.lan.as_deref().unwrap_or(&options.lan);
+            let speed = speaker.speed.unwrap_or(options.speed);
+            let turn_number = Some(i);
+            // Silence leads the recording, not every turn
+            let initial_silence = if i == 0 {
+                options.initial_silence
+            } else {
+                None
+            };
+
+            let (audio, words) = if options.timestamps {
+                self.tts_timestamped_raw_audio(
+                    &turn.text,
+                    lan,
+                    &speaker.voice,
+                    speed,
+                    initial_silence,
+                    None,
+                    None,
+                    turn_number,
+                )?
+                .unwrap_or_default()
+            } else {
+                let audio = self.tts_raw_audio(
+                    &turn.text,
+                    lan,
+                    &speaker.voice,
+                    speed,
+                    initial_silence,
+                    None,
+                    None,
+                    turn_number,
+                )?;
+                (audio, Vec::new())
+            };
+
+            if i > 0 {
+                dialogue
+                    .samples
+                    .resize(dialogue.samples.len() + gap * channels as usize, 0.0);
+            }
+            let offset = (dialogue.samples.len() / channels as usize) as f32 / sample_rate;
+            dialogue.words.extend(words.into_iter().map(|mut word| {
+                word.start_sec += offset;
+                word.end_sec += offset;
+                SpeakerWord {
+                    speaker: turn.speaker.clone(),
+                    word,
+                }
+            }));
+
+            if stereo {
+                // Constant power: the voice keeps its loudness across the field
+                let angle = (speaker.pan + 1.0) * std::f32::consts::FRAC_PI_4;
+                let (left, right) = (angle.cos(), angle.sin());
+                dialogue.samples.reserve(audio.len() * 2);
+                for sample in audio {
+                    dialogue.samples.push(sample * left);
+                    dialogue.samples.push(sample * right);
+                }
+            } else {
+                dialogue.samples.extend(audio);
+            }
+        }
+        Ok(dialogue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_script() {
+        let script = Script::parse(
+            "# Episode 1\n@HOST = af_sarah pan=-0.4\n@Dr. Lee = bm_george speed=1.1 lan=en-gb\n\n\
+             HOST: Welcome back.\nToday: a guest.\nDr. Lee: Thanks, glad to be here.\naf_sky: Bye.\n",
+        )
+        .unwrap();
+        assert_eq!(script.speakers["HOST"].pan, -0.4);
+        assert_eq!(script.speakers["Dr. Lee"].lan.as_deref(), Some("en-gb"));
+        assert_eq!(
+            script.turns,
+            [
+                Turn {
+                    speaker: "HOST".into(),
+                    text: "Welcome back. Today: a guest.".into()
+                },
+                Turn {
+                    speaker: "Dr. Lee".into(),
+                    text: "Thanks, glad to be here.".into()
+                },
+                Turn {
+                    speaker: "af_sky".into(),
+                    text: "Bye.".into()
+                },
+            ]
+        );
+        assert!(Script::parse("no speaker here").is_err());
+
+        // Without declarations, only voices and names in capitals speak
+        let script = Script::parse(
+            "ALICE: Take the list.\nNote: bring these.\nhttps://example.com\nBOB: Ok.\n",
+        )
+        .unwrap();
+        let speakers: Vec<&str> = script.turns.iter().map(|t| t.speaker.as_str()).collect();
+        assert_eq!(speakers, ["ALICE", "BOB"]);
+        assert_eq!(
+            script.turns[0].text,
+            "Take the list. Note: bring these. https://example.com"
+        );
+        assert!(Script::parse("HTTP://example.com").is_err());
+        let script =
+            Script::from_text_with("Alice: Hi.\nNote: this.", true, |name| name == "Alice")
+                .unwrap();
+        assert_eq!(script.turns.len(), 1);
+        // Speakers declared apart turn off names in capitals too
+        let script =
+            Script::from_text_with("HOST: Hi.\nNOTE: this.", true, |name| name == "HOST").unwrap();
+        assert_eq!(script.turns.len(), 1);
+        assert_eq!(script.turns[0].text, "Hi. NOTE: this.");
+        assert!(Script::parse("@A = af_sky pan=3").is_err());
+    }
+
+    #[test]
+    fn test_json_script() {
+        let script = Script::parse(
+            r#"{"speakers": {"A": "af_sky speed=0.9", "B": {"voice": "am_adam", "pan": 0.5}},
+                "turns": [{"speaker": "A", "text": "Hi."}, {"speaker": "B", "text": "Hello."}]}"#,
+        )
+        .unwrap();
+        assert_eq!(script.speakers["A"].speed, Some(0.9));
+        assert_eq!(script.speakers["B"].pan, 0.5);
+        assert_eq!(script.turns.len(), 2);
+        let list = Script::parse(r#"[{"speaker": "af_sky", "text": "Hi."}]"#).unwrap();
+        assert!(list.speakers.is_empty());
+        assert_eq!(list.turns[0].speaker, "af_sky");
+    }
+}
@@ -0,0 +1,707 @@
+//! Markdown, HTML and subtitle input, read for its structure rather than its syntax.
+//!
+//! [`SpokenDocument::parse`] strips markup and keeps the words. Headings,
+//! paragraphs, list items and table rows end their sentence and leave a
+//! paragraph pause; code blocks and tables are read, summarized or skipped
+//! as [`DocumentOptions`] says. Every run of spoken text remembers the bytes
+//! of the source it came from, so word alignments can point back into the
+//! original document with [`SpokenDocument::locate`].
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::tts::koko::WordAlignment;
+use crate::tts::{segment, subtitles};
+
+/// Markup of the input text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    #[default]
+    Text,
+    Markdown,
+    Html,
+    /// SRT or WebVTT; the cue texts are read one after another, see
+    /// [`subtitles`]
+    Subtitles,
+}
+
+impl InputFormat {
+    pub const ALL: [InputFormat; 4] = [
+        InputFormat::Text,
+        InputFormat::Markdown,
+        InputFormat::Html,
+        InputFormat::Subtitles,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputFormat::Text => "text",
+            InputFormat::Markdown => "markdown",
+            InputFormat::Html => "html",
+            InputFormat::Subtitles => "subtitles",
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" | "txt" | "plain" => Ok(InputFormat::Text),
+            "markdown" | "md" => Ok(InputFormat::Markdown),
+            "html" | "htm" => Ok(InputFormat::Html),
+            "subtitles" | "srt" | "vtt" | "webvtt" => Ok(InputFormat::Subtitles),
+            _ => {
+                let known: Vec<&str> = Self::ALL.iter().map(|f| f.as_str()).collect();
+                Err(format!(
+                    "unknown input format '{}' (expected one of: {})",
+                    s.trim(),
+                    known.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// How code blocks and tables are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockPolicy {
+    /// Read the contents
+    Read,
+    /// Say what is there, e.g. `Table with 3 rows.`
+    Summarize,
+    /// Leave it out
+    Skip,
+}
+
+impl BlockPolicy {
+    pub const ALL: [BlockPolicy; 3] =
+        [BlockPolicy::Read, BlockPolicy::Summarize, BlockPolicy::Skip];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockPolicy::Read => "read",
+            BlockPolicy::Summarize => "summarize",
+            BlockPolicy::Skip => "skip",
+        }
+    }
+}
+
+impl fmt::Display for BlockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BlockPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(|p| p.as_str()).collect();
+                format!(
+                    "unknown block policy '{}' (expected one of: {})",
+                    s.trim(),
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentOptions {
+    pub code_blocks: BlockPolicy,
+    pub tables: BlockPolicy,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        Self {
+            code_blocks: BlockPolicy::Summarize,
+            tables: BlockPolicy::Read,
+        }
+    }
+}
+
+/// Spoken text and the source bytes it was taken from. Verbatim pieces have
+/// spoken and source ranges of the same length; generated ones, such as
+/// summaries and decoded entities, map to their whole source range.
+#[derive(Debug, Clone)]
+struct Piece {
+    spoken: Range<usize>,
+    source: Range<usize>,
+}
+
+impl Piece {
+    fn is_verbatim(&self) -> bool {
+        self.spoken.len() == self.source.len()
+    }
+}
+
+/// The text to speak for a document, with a map back to the source.
+#[derive(Debug, Clone, Default)]
+pub struct SpokenDocument {
+    pub text: String,
+    pieces: Vec<Piece>,
+}
+
+impl SpokenDocument {
+    pub fn parse(source: &str, format: InputFormat, options: &DocumentOptions) -> Self {
+        match format {
+            InputFormat::Text => Self {
+                text: source.to_string(),
+                pieces: vec![Piece {
+                    spoken: 0..source.len(),
+                    source: 0..source.len(),
+                }],
+            },
+            InputFormat::Markdown => markdown(source, options),
+            InputFormat::Html => {
+                let mut builder = Builder::default();
+                HtmlScanner::new(options).scan(&mut builder, source, 0);
+                builder.finish()
+            }
+            InputFormat::Subtitles => {
+                // A sentence often runs on over several cues
+                let mut builder = Builder::default();
+                for cue in subtitles::parse(source) {
+                    builder.push(&cue.text, cue.source);
+                    builder.space();
+                }
+                builder.finish()
+            }
+        }
+    }
+
+    /// The source bytes a range of the spoken text came from
+    pub fn source_range(&self, spoken: Range<usize>) -> Option<Range<usize>> {
+        let start = self.source_offset(spoken.start, false)?;
+        let end = self.source_offset(spoken.end.checked_sub(1)?, true)?;
+        (start < end).then_some(start..end)
+    }
+
+    fn source_offset(&self, pos: usize, end: bool) -> Option<usize> {
+        let i = self.pieces.partition_point(|p| p.spoken.end <= pos);
+        let piece = self.pieces.get(i).filter(|p| p.spoken.contains(&pos))?;
+        Some(match (piece.is_verbatim(), end) {
+            (true, _) => piece.source.start + (pos - piece.spoken.start) + end as usize,
+            (false, false) => piece.source.start,
+            (false, true) => piece.source.end,
+        })
+    }
+
+    /// Point each alignment at its word, without surrounding punctuation, in
+    /// the source. Words are matched in order as whole words, each at most
+    /// one word past the last match, plus one for every word since then that
+    /// wasn't found. Words the front end rewrote after parsing, such as
+    /// spelled out numbers, are left without a source range.
+    pub fn locate(&self, alignments: &mut [WordAlignment]) {
+        let mut cursor = 0;
+        let mut missed = 0;
+        for alignment in alignments {
+            let word = alignment.word.trim_matches(|c: char| !c.is_alphanumeric());
+            if word.is_empty() {
+                continue;
+            }
+            match self.find_word(word, cursor, missed + 1) {
+                Some(start) => {
+                    alignment.source = self.source_range(start..start + word.len());
+                    cursor = start + word.len();
+                    missed = 0;
+                }
+                None => missed += 1,
+            }
+        }
+    }
+
+    /// Where `word` stands on its own in the text after `cursor`, with at
+    /// most `skip` other words before it
+    fn find_word(&self, word: &str, cursor: usize, skip: usize) -> Option<usize> {
+        let rest = &self.text[cursor..];
+        for (at, _) in rest.match_indices(word) {
+            let before = &rest[..at];
+            let skipped = before
+                .split_whitespace()
+                .filter(|w| w.chars().any(char::is_alphanumeric))
+                .count();
+            if skipped > skip {
+                return None;
+            }
+            let bounded = !before
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+                && !rest[at + word.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(char::is_alphanumeric);
+            if bounded {
+                return Some(cursor + at);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    text: String,
+    pieces: Vec<Piece>,
+}
+
+impl Builder {
+    fn push(&mut self, s: &str, source: Range<usize>) {
+        if s.is_empty() {
+            return;
+        }
+        let spoken = self.text.len()..self.text.len() + s.len();
+        self.text.push_str(s);
+        if let Some(last) = self.pieces.last_mut()
+            && last.is_verbatim()
+            && s.len() == source.len()
+            && last.spoken.end == spoken.start
+            && last.source.end == source.start
+        {
+            last.spoken.end = spoken.end;
+            last.source.end = source.end;
+            return;
+        }
+        self.pieces.push(Piece { spoken, source });
+    }
+
+    /// Source text starting at byte `base`, with runs of whitespace read as
+    /// one space
+    fn push_text(&mut self, s: &str, base: usize) {
+        for (i, c) in s.char_indices() {
+            if c.is_whitespace() {
+                self.space();
+            } else {
+                let end = i + c.len_utf8();
+                self.push(&s[i..end], base + i..base + end);
+            }
+        }
+    }
+
+    fn space(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+    }
+
+    fn trim_end(&mut self) {
+        self.truncate(self.text.trim_end().len());
+    }
+
+    /// Cut the text back to `len` bytes, with the pieces that reach past it
+    fn truncate(&mut self, len: usize) {
+        self.text.truncate(len);
+        while let Some(last) = self.pieces.last_mut()
+            && last.spoken.end > len
+        {
+            if last.spoken.start >= len {
+                self.pieces.pop();
+                continue;
+            }
+            if last.is_verbatim() {
+                last.source.end -= last.spoken.end - len;
+            }
+            last.spoken.end = len;
+        }
+    }
+
+    /// Close the sentence and leave a paragraph pause
+    fn end_block(&mut self) {
+        self.trim_end();
+        if self.text.is_empty() {
+            return;
+        }
+        // The last table cell's comma gives way to the full stop, while a
+        // block that leads into a list or code keeps its colon
+        if self.text.ends_with(',') {
+            self.truncate(self.text.len() - 1);
+        }
+        if !segment::has_terminal(&self.text) && !self.text.ends_with([':', ';']) {
+            self.text.push('.');
+        }
+        self.text.push_str("\n\n");
+    }
+
+    /// Close a table cell with a short pause
+    fn end_clause(&mut self) {
+        self.trim_end();
+        if !self.text.is_empty() && !self.text.ends_with([',', '.', '!', '?', ';', ':']) {
+            self.text.push(',');
+        }
+        self.space();
+    }
+
+    fn finish(mut self) -> SpokenDocument {
+        self.trim_end();
+        SpokenDocument {
+            text: self.text,
+            pieces: self.pieces,
+        }
+    }
+}
+
+fn table_summary(rows: usize) -> String {
+    format!(
+        "Table with {} {}.",
+        rows,
+        if rows == 1 { "row" } else { "rows" }
+    )
+}
+
+fn markdown(source: &str, options: &DocumentOptions) -> SpokenDocument {
+    let mut builder = Builder::default();
+    let mut html = HtmlScanner::new(options);
+    let mut in_code = false;
+    let mut table: Option<(usize, usize)> = None;
+
+    let parser = Parser::new_ext(
+        source,
+        Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES,
+    );
+    for (event, range) in parser.into_offset_iter() {
+        let muted = (in_code && options.code_blocks != BlockPolicy::Read)
+            || (table.is_some() && options.tables != BlockPolicy::Read);
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                builder.end_block();
+                in_code = true;
+                if options.code_blocks == BlockPolicy::Summarize {
+                    let summary = match kind {
+                        CodeBlockKind::Fenced(info) if !info.trim().is_empty() => format!(
+                            "Code sample in {}.",
+                            info.split_whitespace().next().unwrap_or_default()
+                        ),
+                        _ => "Code sample.".to_string(),
+                    };
+                    builder.push(&summary, range);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code = false;
+                builder.end_block();
+            }
+            Event::Start(Tag::Table(_)) => {
+                builder.end_block();
+                table = Some((range.start, 0));
+            }
+            Event::End(TagEnd::Table) => {
+                if let Some((start, rows)) = table.take()
+                    && options.tables == BlockPolicy::Summarize
+                {
+                    builder.push(&table_summary(rows), start..range.end);
+                }
+                builder.end_block();
+            }
+            Event::End(TagEnd::TableRow) => {
+                if let Some((_, rows)) = &mut table {
+                    *rows += 1;
+                }
+                if !muted {
+                    builder.end_block();
+                }
+            }
+            Event::End(TagEnd::TableHead) if !muted => builder.end_block(),
+            Event::End(TagEnd::TableCell) if !muted => builder.end_clause(),
+            Event::Start(Tag::Heading { .. } | Tag::Item)
+            | Event::End(
+                TagEnd::Heading(_)
+                | TagEnd::Paragraph
+                | TagEnd::Item
+                | TagEnd::BlockQuote(_)
+                | TagEnd::List(_),
+            )
+            | Event::Rule => builder.end_block(),
+            Event::Text(text) | Event::Code(text) if !muted => {
+                // Escapes and entities make the text differ from its source
+                match source[range.clone()].find(text.as_ref()) {
+                    Some(at) => builder.push_text(&text, range.start + at),
+                    None => builder.push(text.trim(), range),
+                }
+            }
+            Event::Html(fragment) | Event::InlineHtml(fragment) if !muted => {
+                html.scan(&mut builder, &fragment, range.start);
+            }
+            Event::SoftBreak | Event::HardBreak => builder.space(),
+            _ => {}
+        }
+    }
+    builder.finish()
+}
+
+/// A forgiving HTML reader that keeps byte offsets, where a full parser
+/// would not.
+struct HtmlScanner {
+    options: DocumentOptions,
+    /// Inside `script`, `style` and the like
+    hidden: usize,
+    /// Inside `pre`
+    code: usize,
+    /// Start of the outermost table and the rows seen
+    table: Option<(usize, usize)>,
+}
+
+impl HtmlScanner {
+    fn new(options: &DocumentOptions) -> Self {
+        Self {
+            options: *options,
+            hidden: 0,
+            code: 0,
+            table: None,
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.hidden > 0
+            || (self.code > 0 && self.options.code_blocks != BlockPolicy::Read)
+            || (self.table.is_some() && self.options.tables != BlockPolicy::Read)
+    }
+
+    fn scan(&mut self, builder: &mut Builder, html: &str, base: usize) {
+        let mut i = 0;
+        while i < html.len() {
+            let rest = &html[i..];
+            if rest.starts_with("<!--") {
+                i += rest.find("-->").map_or(rest.len(), |end| end + 3);
+                continue;
+            }
+            let is_tag = rest.starts_with('<')
+                && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+            if is_tag && let Some(end) = rest.find('>') {
+                self.tag(builder, &rest[1..end], base + i..base + i + end + 1);
+                i += end + 1;
+                continue;
+            }
+            let end = rest[1..].find('<').map_or(rest.len(), |end| end + 1);
+            if !self.muted() {
+                self.text(builder, &rest[..end], base + i);
+            }
+            i += end;
+        }
+    }
+
+    fn text(&self, builder: &mut Builder, text: &str, base: usize) {
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let at = base + (text.len() - rest.len());
+            if c == '&'
+                && let Some(end) = rest.char_indices().take(12).find(|(_, c)| *c == ';')
+                && let entity = &rest[..end.0 + 1]
+                && let decoded = html_escape::decode_html_entities(entity)
+                && decoded != entity
+            {
+                if decoded.trim().is_empty() {
+                    builder.space();
+                } else {
+                    builder.push(&decoded, at..at + entity.len());
+                }
+                rest = &rest[entity.len()..];
+                continue;
+            }
+            let len = c.len_utf8();
+            builder.push_text(&rest[..len], at);
+            rest = &rest[len..];
+        }
+    }
+
+    fn tag(&mut self, builder: &mut Builder, tag: &str, range: Range<usize>) {
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let self_closing = tag.ends_with('/');
+
+        if matches!(
+            name.as_str(),
+            "script" | "style" | "noscript" | "template" | "head"
+        ) {
+            if closing {
+                self.hidden = self.hidden.saturating_sub(1);
+            } else if !self_closing {
+                self.hidden += 1;
+            }
+            return;
+        }
+        if self.hidden > 0 {
+            return;
+        }
+
+        match name.as_str() {
+            "pre" if closing => {
+                self.code = self.code.saturating_sub(1);
+                builder.end_block();
+            }
+            "pre" => {
+                builder.end_block();
+                if self.code == 0 && self.options.code_blocks == BlockPolicy::Summarize {
+                    builder.push("Code sample.", range);
+                }
+                self.code += 1;
+            }
+            "table" if closing => {
+                if let Some((start, rows)) = self.table.take()
+                    && self.options.tables == BlockPolicy::Summarize
+                {
+                    builder.push(&table_summary(rows), start..range.end);
+                }
+                builder.end_block();
+            }
+            "table" => {
+                builder.end_block();
+                self.table.get_or_insert((range.start, 0));
+            }
+            "tr" if closing => {
+                if let Some((_, rows)) = &mut self.table {
+                    *rows += 1;
+                }
+                if !self.muted() {
+                    builder.end_block();
+                }
+            }
+            "td" | "th" if closing && !self.muted() => builder.end_clause(),
+            "br" if !self.muted() => builder.space(),
+            "p" | "div" | "li" | "ul" | "ol" | "dl" | "dt" | "dd" | "h1" | "h2" | "h3" | "h4"
+            | "h5" | "h6" | "hr" | "blockquote" | "section" | "article" | "header" | "footer"
+            | "nav" | "aside" | "main" | "figure" | "figcaption" | "caption" | "title"
+                if !self.muted() =>
+            {
+                builder.end_block()
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alignment(word: &str) -> WordAlignment {
+        WordAlignment {
+            word: word.to_string(),
+            start_sec: 0.0,
+            end_sec: 0.0,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_markdown() {
+        let source = "# Setup\n\nInstall **Kokoros**:\n\n- run `cargo build`\n- wait\n\n```sh\nmake\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+        let document =
+            SpokenDocument::parse(source, InputFormat::Markdown, &DocumentOptions::default());
+        assert_eq!(
+            document.text,
+            "Setup.\n\nInstall Kokoros:\n\nrun cargo build.\n\nwait.\n\nCode sample in sh.\n\na, b.\n\n1, 2."
+        );
+
+        let mut words: Vec<WordAlignment> =
+            ["Setup.", "Install", "Kokoros:", "run", "cargo", "build."]
+                .into_iter()
+                .map(alignment)
+                .collect();
+        document.locate(&mut words);
+        assert_eq!(&source[words[2].source.clone().unwrap()], "Kokoros");
+        assert_eq!(&source[words[5].source.clone().unwrap()], "build");
+    }
+
+    #[test]
+    fn test_html() {
+        let source = "<html><head><title>x</title></head><body><h1>Caf&eacute; &amp; Bar</h1><script>alert(1)</script><p>Open<br>daily</p><table><tr><td>a</td></tr><tr><td>b</td></tr></table></body></html>";
+        let options = DocumentOptions {
+            tables: BlockPolicy::Summarize,
+            ..DocumentOptions::default()
+        };
+        let document = SpokenDocument::parse(source, InputFormat::Html, &options);
+        assert_eq!(
+            document.text,
+            "Café & Bar.\n\nOpen daily.\n\nTable with 2 rows."
+        );
+
+        let mut words = vec![alignment("Bar"), alignment("daily")];
+        document.locate(&mut words);
+        assert_eq!(&source[words[0].source.clone().unwrap()], "Bar");
+        assert_eq!(&source[words[1].source.clone().unwrap()], "daily");
+    }
+
+    #[test]
+    fn test_locate() {
+        let source = "Chapter 2 is two pages, often 10 apples.";
+        let document =
+            SpokenDocument::parse(source, InputFormat::Text, &DocumentOptions::default());
+        // As the front end speaks it
+        let mut words: Vec<WordAlignment> = [
+            "Chapter", "two", "is", "two", "pages,", "often", "ten", "apples.",
+        ]
+        .into_iter()
+        .map(alignment)
+        .collect();
+        document.locate(&mut words);
+        let located: Vec<Option<&str>> = words
+            .iter()
+            .map(|w| w.source.clone().map(|range| &source[range]))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                Some("Chapter"),
+                None,
+                Some("is"),
+                Some("two"),
+                Some("pages"),
+                Some("often"),
+                None,
+                Some("apples")
+            ]
+        );
+        assert_eq!(words[3].source, Some(13..16));
+    }
+
+    #[test]
+    fn test_source_comma() {
+        let source = "Hello, world,\n\nNext one";
+        let document =
+            SpokenDocument::parse(source, InputFormat::Markdown, &DocumentOptions::default());
+        assert_eq!(document.text, "Hello, world.\n\nNext one.");
+        assert!(
+            document
+                .pieces
+                .windows(2)
+                .all(|pair| pair[0].spoken.end <= pair[1].spoken.start)
+        );
+        assert!(
+            document
+                .pieces
+                .iter()
+                .all(|piece| piece.spoken.end <= document.text.len())
+        );
+
+        let mut words = vec![alignment("world."), alignment("Next")];
+        document.locate(&mut words);
+        assert_eq!(&source[words[0].source.clone().unwrap()], "world");
+        assert_eq!(&source[words[1].source.clone().unwrap()], "Next");
+    }
+}
@@ -0,0 +1,499 @@
+//! Speech fitted to a duration, for dubbing and fixed-length slots.
+//!
+//! Kokoro's durations scale with one over the speed, so one pass at the
+//! starting speed tells the speed that should fit, and a few more passes
+//! correct for what the model doesn't scale exactly. Speeds stay within
+//! [`MIN_FIT_SPEED`]..=[`MAX_FIT_SPEED`]; what is left over is padded with
+//! silence or time-stretched by at most [`MAX_STRETCH`], as [`Remainder`]
+//! says.
+//!
+//! A text is fitted as a whole first, so its sentences keep their relative
+//! pace. When that misses the target, each sentence is fitted on its own to
+//! its share of the target, and the closer of the two is kept. Subtitle cues
+//! are fitted one by one into their own slots, no shorter than
+//! [`MIN_TARGET_SEC`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::KokorosError;
+use crate::tts::koko::{TTSKoko, WordAlignment};
+use crate::tts::segment;
+use crate::tts::subtitles::Cue;
+use crate::utils::dsp;
+
+/// Slowest speed a fit uses
+pub const MIN_FIT_SPEED: f32 = 0.5;
+
+/// Fastest speed a fit uses
+pub const MAX_FIT_SPEED: f32 = 2.0;
+
+/// Most a fit time-stretches speech, either way, as a ratio of its length
+pub const MAX_STRETCH: f32 = 1.5;
+
+/// Shortest duration speech is fitted to, in seconds
+pub const MIN_TARGET_SEC: f32 = 0.2;
+
+/// Syntheses tried before settling for the closest
+const MAX_PASSES: usize = 4;
+
+/// What to do when speed alone doesn't reach the target duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Remainder {
+    /// Return the speech as it came out
+    Keep,
+    /// Append silence to speech that is too short
+    #[default]
+    Pad,
+    /// Time-stretch the speech towards the target, keeping its pitch, then
+    /// pad
+    Stretch,
+}
+
+impl Remainder {
+    pub const ALL: [Remainder; 3] = [Remainder::Keep, Remainder::Pad, Remainder::Stretch];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Remainder::Keep => "keep",
+            Remainder::Pad => "pad",
+            Remainder::Stretch => "stretch",
+        }
+    }
+}
+
+impl fmt::Display for Remainder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Remainder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|r| r.as_str() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(|r| r.as_str()).collect();
+                format!(
+                    "unknown remainder '{}' (expected one of: {})",
+                    s.trim(),
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FitOptions {
+    /// How far from the target, in seconds, counts as a fit
+    pub tolerance: f32,
+    pub remainder: Remainder,
+    pub initial_silence: Option<usize>,
+    /// Align words, which needs a timestamped model
+    pub timestamps: bool,
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.1,
+            remainder: Remainder::Pad,
+            initial_silence: None,
+            timestamps: false,
+        }
+    }
+}
+
+/// Speech fitted to a target duration
+#[derive(Debug, Clone, Default)]
+pub struct FittedAudio {
+    pub samples: Vec<f32>,
+    pub words: Vec<WordAlignment>,
+    /// Speed the speech was synthesized at, averaged over its length when
+    /// sentences were fitted one by one
+    pub speed: f32,
+    /// Length of the speech, after any stretching and before any padding
+    pub duration_sec: f32,
+}
+
+/// Where a subtitle cue ended up
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueFit {
+    /// Position of the cue in the subtitle file
+    pub index: usize,
+    /// Start of the speech; later than the cue when the cue before ran over
+    pub start_sec: f32,
+    pub slot_sec: f32,
+    pub duration_sec: f32,
+    pub speed: f32,
+}
+
+/// Subtitles rendered into one recording
+#[derive(Debug, Clone, Default)]
+pub struct SubtitleTrack {
+    pub samples: Vec<f32>,
+    pub words: Vec<WordAlignment>,
+    pub cues: Vec<CueFit>,
+}
+
+impl SubtitleTrack {
+    /// Add the speech of cue `index` at `start_sec`, or where the track ends
+    /// if the cue before ran over
+    fn place(&mut self, index: usize, cue: &Cue, fitted: FittedAudio, sample_rate: u32) {
+        let rate = sample_rate as f32;
+        let start = ((cue.start_sec * rate).round() as usize).max(self.samples.len());
+        self.samples.resize(start, 0.0);
+        let offset = start as f32 / rate;
+        self.words.extend(fitted.words.into_iter().map(|mut word| {
+            word.start_sec += offset;
+            word.end_sec += offset;
+            word
+        }));
+        self.samples.extend(fitted.samples);
+        self.cues.push(CueFit {
+            index,
+            start_sec: offset,
+            slot_sec: cue.slot_sec(),
+            duration_sec: fitted.duration_sec,
+            speed: fitted.speed,
+        });
+    }
+}
+
+impl TTSKoko {
+    /// Speak `txt` in about `target_sec` seconds, starting the search for a
+    /// speed at `speed`. Targets under [`MIN_TARGET_SEC`] are rejected.
+    pub fn tts_fitted(
+        &self,
+        txt: &str,
+        lan: &str,
+        style_name: &str,
+        speed: f32,
+        target_sec: f32,
+        options: &FitOptions,
+    ) -> Result<FittedAudio, KokorosError> {
+        if !target_sec.is_finite() || target_sec < MIN_TARGET_SEC {
+            return Err(KokorosError::InvalidInput(format!(
+                "target duration must be at least {} seconds, got {}",
+                MIN_TARGET_SEC, target_sec
+            )));
+        }
+        let rate = self.sample_rate() as f32;
+        let synthesize = |text: &str,
+                          speed: f32,
+                          initial_silence: Option<usize>|
+         -> Result<FittedAudio, KokorosError> {
+            let (samples, words) = if options.timestamps {
+                self.tts_timestamped_raw_audio(
+                    text,
+                    lan,
+                    style_name,
+                    speed,
+                    initial_silence,
+                    None,
+                    None,
+                    None,
+                )?
+                .unwrap_or_default()
+            } else {
+                let samples = self.tts_raw_audio(
+                    text,
+                    lan,
+                    style_name,
+                    speed,
+                    initial_silence,
+                    None,
+                    None,
+                    None,
+                )?;
+                (samples, Vec::new())
+            };
+            Ok(FittedAudio {
+                duration_sec: samples.len() as f32 / rate,
+                samples,
+                words,
+                speed,
+            })
+        };
+
+        let first = synthesize(
+            txt,
+            speed.clamp(MIN_FIT_SPEED, MAX_FIT_SPEED),
+            options.initial_silence,
+        )?;
+        let mut best = fit_search(first, target_sec, options.tolerance, |speed| {
+            synthesize(txt, speed, options.initial_silence)
+        })?;
+
+        let sentences = segment::split_sentences(txt);
+        if (best.duration_sec - target_sec).abs() > options.tolerance && sentences.len() > 1 {
+            // Sentences share the target as they shared the closest whole fit
+            let mut parts = Vec::with_capacity(sentences.len());
+            for (i, sentence) in sentences.iter().enumerate() {
+                let silence = if i == 0 {
+                    options.initial_silence
+                } else {
+                    None
+                };
+                parts.push((
+                    sentence,
+                    silence,
+                    synthesize(sentence, best.speed, silence)?,
+                ));
+            }
+            let total: f32 = parts.iter().map(|(_, _, part)| part.duration_sec).sum();
+            let tolerance = options.tolerance / parts.len() as f32;
+            let mut fitted = Vec::with_capacity(parts.len());
+            for (sentence, silence, first) in parts {
+                let share = if total > 0.0 {
+                    target_sec * first.duration_sec / total
+                } else {
+                    target_sec / sentences.len() as f32
+                };
+                fitted.push(fit_search(first, share, tolerance, |speed| {
+                    synthesize(sentence, speed, silence)
+                })?);
+            }
+            let by_sentence = concatenate(fitted, self.sample_rate());
+            if (by_sentence.duration_sec - target_sec).abs()
+                < (best.duration_sec - target_sec).abs()
+            {
+                best = by_sentence;
+            }
+        }
+
+        Ok(fill_remainder(
+            best,
+            target_sec,
+            self.sample_rate(),
+            options,
+        ))
+    }
+
+    /// Speak every cue within its slot, placed at its start time. A cue
+    /// that still runs over pushes the next one back rather than overlap,
+    /// and a cue shown for less than [`MIN_TARGET_SEC`] gets that long.
+    pub fn tts_subtitles(
+        &self,
+        cues: &[Cue],
+        lan: &str,
+        style_name: &str,
+        speed: f32,
+        options: &FitOptions,
+    ) -> Result<SubtitleTrack, KokorosError> {
+        let rate = self.sample_rate() as f32;
+        let mut track = SubtitleTrack::default();
+        for (index, cue) in cues.iter().enumerate() {
+            if cue.text.trim().is_empty() {
+                continue;
+            }
+            let slot = cue.slot_sec().max(MIN_TARGET_SEC);
+            let fitted = self.tts_fitted(&cue.text, lan, style_name, speed, slot, options)?;
+            track.place(index, cue, fitted, self.sample_rate());
+        }
+        if options.remainder != Remainder::Keep
+            && let Some(last) = cues.last()
+        {
+            let end = (last.end_sec * rate).round() as usize;
+            if track.samples.len() < end {
+                track.samples.resize(end, 0.0);
+            }
+        }
+        Ok(track)
+    }
+}
+
+/// Search for the speed whose speech lasts `target_sec`, from the `first`
+/// attempt, keeping the closest of at most [`MAX_PASSES`] syntheses
+fn fit_search(
+    first: FittedAudio,
+    target_sec: f32,
+    tolerance: f32,
+    mut synthesize: impl FnMut(f32) -> Result<FittedAudio, KokorosError>,
+) -> Result<FittedAudio, KokorosError> {
+    let mut last = (first.speed, first.duration_sec);
+    let mut best = first;
+    for _ in 1..MAX_PASSES {
+        let (speed, duration) = last;
+        if (duration - target_sec).abs() <= tolerance || duration == 0.0 {
+            break;
+        }
+        let next = if target_sec > 0.0 {
+            (speed * duration / target_sec).clamp(MIN_FIT_SPEED, MAX_FIT_SPEED)
+        } else {
+            MAX_FIT_SPEED
+        };
+        // Pinned at a speed limit
+        if (next - speed).abs() < 1e-3 {
+            break;
+        }
+        let attempt = synthesize(next)?;
+        last = (attempt.speed, attempt.duration_sec);
+        if (attempt.duration_sec - target_sec).abs() < (best.duration_sec - target_sec).abs() {
+            best = attempt;
+        }
+    }
+    Ok(best)
+}
+
+/// Sentences fitted one by one, as one recording
+fn concatenate(parts: Vec<FittedAudio>, sample_rate: u32) -> FittedAudio {
+    let rate = sample_rate as f32;
+    let mut joined = FittedAudio::default();
+    let mut weighted_speed = 0.0;
+    for part in parts {
+        let offset = joined.samples.len() as f32 / rate;
+        joined.words.extend(part.words.into_iter().map(|mut word| {
+            word.start_sec += offset;
+            word.end_sec += offset;
+            word
+        }));
+        weighted_speed += part.speed * part.duration_sec;
+        joined.duration_sec += part.duration_sec;
+        joined.samples.extend(part.samples);
+    }
+    joined.speed = if joined.duration_sec > 0.0 {
+        weighted_speed / joined.duration_sec
+    } else {
+        1.0
+    };
+    joined
+}
+
+/// Stretch and pad what speed left over, as `options.remainder` says
+fn fill_remainder(
+    mut best: FittedAudio,
+    target_sec: f32,
+    sample_rate: u32,
+    options: &FitOptions,
+) -> FittedAudio {
+    let rate = sample_rate as f32;
+    let target_len = (target_sec * rate).round() as usize;
+    if options.remainder == Remainder::Stretch
+        && !best.samples.is_empty()
+        && (best.duration_sec - target_sec).abs() > options.tolerance
+    {
+        let ratio =
+            (target_len as f32 / best.samples.len() as f32).clamp(1.0 / MAX_STRETCH, MAX_STRETCH);
+        best.samples = dsp::time_stretch(&best.samples, sample_rate, ratio);
+        for word in &mut best.words {
+            word.start_sec *= ratio;
+            word.end_sec *= ratio;
+        }
+        best.duration_sec = best.samples.len() as f32 / rate;
+    }
+    if options.remainder != Remainder::Keep && best.samples.len() < target_len {
+        best.samples.resize(target_len, 0.0);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    /// Speech of `natural_sec` seconds at speed 1, scaling a little less
+    /// than one over the speed as the model does
+    fn speech(natural_sec: f32, speed: f32) -> FittedAudio {
+        let duration_sec = natural_sec / speed.powf(0.9);
+        let len = (duration_sec * RATE as f32).round() as usize;
+        FittedAudio {
+            samples: (0..len).map(|i| (i as f32 * 0.3).sin() * 0.5).collect(),
+            words: Vec::new(),
+            speed,
+            duration_sec: len as f32 / RATE as f32,
+        }
+    }
+
+    #[test]
+    fn test_fit_search() {
+        let mut passes = 0;
+        let fitted = fit_search(speech(6.0, 1.0), 4.0, 0.05, |speed| {
+            passes += 1;
+            Ok(speech(6.0, speed))
+        })
+        .unwrap();
+        assert!(
+            (fitted.duration_sec - 4.0).abs() <= 0.05,
+            "{:?}",
+            fitted.duration_sec
+        );
+        assert!(fitted.speed > 1.5 && fitted.speed < MAX_FIT_SPEED);
+        assert!(passes < MAX_PASSES);
+
+        // Too short a target pins the speed at the limit and stops there
+        let mut passes = 0;
+        let fitted = fit_search(speech(6.0, 1.0), 1.0, 0.05, |speed| {
+            passes += 1;
+            Ok(speech(6.0, speed))
+        })
+        .unwrap();
+        assert_eq!(fitted.speed, MAX_FIT_SPEED);
+        assert_eq!(passes, 1);
+    }
+
+    #[test]
+    fn test_concatenate() {
+        let joined = concatenate(vec![speech(1.0, 1.0), speech(3.0, 2.0)], RATE);
+        let second = speech(3.0, 2.0).duration_sec;
+        assert!((joined.duration_sec - (1.0 + second)).abs() < 1e-3);
+        let speed = (1.0 + 2.0 * second) / (1.0 + second);
+        assert!((joined.speed - speed).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_fill_remainder() {
+        let options = |remainder| FitOptions {
+            remainder,
+            ..FitOptions::default()
+        };
+
+        let padded = fill_remainder(speech(2.0, 1.0), 3.0, RATE, &options(Remainder::Pad));
+        assert_eq!(padded.samples.len(), 3000);
+        assert_eq!(padded.duration_sec, 2.0);
+
+        let kept = fill_remainder(speech(2.0, 1.0), 3.0, RATE, &options(Remainder::Keep));
+        assert_eq!(kept.samples.len(), 2000);
+
+        // Stretching stops at MAX_STRETCH, the rest is silence
+        let stretched = fill_remainder(speech(2.0, 1.0), 4.0, RATE, &options(Remainder::Stretch));
+        assert!((stretched.duration_sec - 2.0 * MAX_STRETCH).abs() < 0.01);
+        assert_eq!(stretched.samples.len(), 4000);
+        let squeezed = fill_remainder(speech(2.0, 1.0), 0.2, RATE, &options(Remainder::Stretch));
+        assert!((squeezed.duration_sec - 2.0 / MAX_STRETCH).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_cue_placement() {
+        let cue = |start_sec: f32, end_sec: f32| Cue {
+            start_sec,
+            end_sec,
+            text: "Hello.".to_string(),
+            source: 0..6,
+        };
+        let mut first = speech(2.5, 1.0);
+        first.words.push(WordAlignment {
+            word: "Hello".to_string(),
+            start_sec: 0.1,
+            end_sec: 0.6,
+            source: None,
+        });
+        let mut track = SubtitleTrack::default();
+        track.place(0, &cue(1.0, 3.0), first, RATE);
+        track.place(2, &cue(3.0, 4.0), speech(0.5, 1.0), RATE);
+        track.place(3, &cue(6.0, 7.0), speech(0.5, 1.0), RATE);
+
+        let starts: Vec<(usize, f32)> = track.cues.iter().map(|c| (c.index, c.start_sec)).collect();
+        // The first cue runs over and pushes the second back
+        assert_eq!(starts, [(0, 1.0), (2, 3.5), (3, 6.0)]);
+        assert_eq!(track.words[0].start_sec, 1.1);
+        assert_eq!(track.samples.len(), 6500);
+        assert!(track.samples[..1000].iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,267 @@
+//! Configurable text front end, run on request text before synthesis.
+//!
+//! [`TextFrontend`] mirrors the normalization options of Kokoro-FastAPI so
+//! that a request reads the same on both servers: the read-aloud rules of
+//! [`semiotic`], phone numbers, units of measure, `word(s)` plurals and the
+//! remaining symbols. Units, plurals and symbols are English rules and only
+//! apply to English; other languages get their numbers and symbols from
+//! [`crate::tts::normalize`], run last when `language_rules` is on.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use crate::tts::semiotic::{self, ReadAloudOptions};
+use crate::tts::{detect, normalize};
+
+lazy_static! {
+    // A bracketed area code, or `-` or `.` between the groups, so a run of
+    // plain numbers such as "100 500 2000" is not taken for one
+    static ref PHONE_RE: Regex = Regex::new(
+        r"(?:\+(\d{1,3})[\s.-]?)?(?:\((\d{3})\)\s?(\d{3})[\s.-]|\b(\d{3})[.-](\d{3})[.-])(\d{4})\b"
+    )
+    .unwrap();
+    static ref UNIT_RE: Regex = {
+        let mut symbols: Vec<&str> = UNITS.iter().map(|unit| unit.0).collect();
+        symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.len()));
+        let symbols: Vec<String> = symbols.iter().map(|s| regex::escape(s)).collect();
+        Regex::new(&format!(r"(\d+(?:\.\d+)?)(\s?)({})\b", symbols.join("|"))).unwrap()
+    };
+    static ref PLURAL_RE: Regex = Regex::new(r"\b([A-Za-z]+)\(s\)").unwrap();
+    static ref SPACES_RE: Regex = Regex::new(r"[ \t]{2,}").unwrap();
+}
+
+/// Symbol, singular, plural, and whether the symbol must touch the number
+/// because it is also a word (`5 in the box`)
+const UNITS: &[(&str, &str, &str, bool)] = &[
+    ("km", "kilometer", "kilometers", false),
+    ("m", "meter", "meters", true),
+    ("cm", "centimeter", "centimeters", false),
+    ("mm", "millimeter", "millimeters", false),
+    ("mi", "mile", "miles", false),
+    ("ft", "foot", "feet", false),
+    ("in", "inch", "inches", true),
+    ("kg", "kilogram", "kilograms", false),
+    ("g", "gram", "grams", true),
+    ("mg", "milligram", "milligrams", false),
+    ("lb", "pound", "pounds", false),
+    ("lbs", "pound", "pounds", false),
+    ("oz", "ounce", "ounces", false),
+    ("l", "liter", "liters", true),
+    ("L", "liter", "liters", true),
+    ("ml", "milliliter", "milliliters", false),
+    ("mph", "mile per hour", "miles per hour", false),
+    ("km/h", "kilometer per hour", "kilometers per hour", false),
+    ("KB", "kilobyte", "kilobytes", false),
+    ("MB", "megabyte", "megabytes", false),
+    ("GB", "gigabyte", "gigabytes", false),
+    ("TB", "terabyte", "terabytes", false),
+    ("ms", "millisecond", "milliseconds", false),
+    ("s", "second", "seconds", true),
+    ("sec", "second", "seconds", false),
+    ("min", "minute", "minutes", true),
+    ("h", "hour", "hours", true),
+    ("hr", "hour", "hours", false),
+    ("hrs", "hour", "hours", false),
+    ("Hz", "hertz", "hertz", false),
+    ("kHz", "kilohertz", "kilohertz", false),
+    ("MHz", "megahertz", "megahertz", false),
+    ("GHz", "gigahertz", "gigahertz", false),
+    ("°C", "degree Celsius", "degrees Celsius", false),
+    ("°F", "degree Fahrenheit", "degrees Fahrenheit", false),
+];
+
+/// Symbols eSpeak would skip or spell out, and their English words
+const SYMBOLS: &[(char, &str)] = &[
+    ('&', " and "),
+    ('@', " at "),
+    ('+', " plus "),
+    ('=', " equals "),
+    ('<', " less than "),
+    ('>', " greater than "),
+    ('%', " percent "),
+    ('#', " number "),
+    ('~', " "),
+    ('^', " "),
+    ('|', " "),
+    ('*', " "),
+    ('_', " "),
+    ('\\', " "),
+];
+
+/// Which clean-up passes run on request text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFrontend {
+    pub read_aloud: ReadAloudOptions,
+    /// Read `10km` as `10 kilometers`
+    pub units: bool,
+    /// Read phone numbers digit by digit in groups
+    pub phone_numbers: bool,
+    /// Read `word(s)` as `words`
+    pub optional_plurals: bool,
+    /// Replace symbols such as `&` and `=` with words
+    pub replace_symbols: bool,
+    /// Spell out numbers, prices, dates and abbreviations by the rules of
+    /// the language, for the languages eSpeak reads poorly
+    pub language_rules: bool,
+}
+
+impl Default for TextFrontend {
+    fn default() -> Self {
+        Self {
+            read_aloud: ReadAloudOptions::default(),
+            units: false,
+            phone_numbers: true,
+            optional_plurals: true,
+            replace_symbols: true,
+            language_rules: true,
+        }
+    }
+}
+
+impl TextFrontend {
+    /// A front end that passes text through untouched
+    pub fn off() -> Self {
+        Self {
+            read_aloud: ReadAloudOptions::keep_all(),
+            units: false,
+            phone_numbers: false,
+            optional_plurals: false,
+            replace_symbols: false,
+            language_rules: false,
+        }
+    }
+
+    /// Run the enabled passes on `text` for language `lan`.
+    pub fn apply(&self, text: &str, lan: &str) -> String {
+        if *self == Self::off() {
+            return text.to_string();
+        }
+        let english = lan.to_ascii_lowercase().starts_with("en");
+        let mut text = semiotic::read_aloud(text, lan, &self.read_aloud);
+
+        if self.optional_plurals && english {
+            text = PLURAL_RE.replace_all(&text, "${1}s").into_owned();
+        }
+
+        if self.phone_numbers {
+            text = PHONE_RE
+                .replace_all(&text, |caps: &Captures| {
+                    let m = caps.get(0).unwrap();
+                    if !semiotic::starts_token(&text, m.start()) {
+                        return m.as_str().to_string();
+                    }
+                    // Spaced digits are read one by one, commas pause between groups
+                    (1..caps.len())
+                        .filter_map(|i| caps.get(i))
+                        .map(|group| {
+                            group
+                                .as_str()
+                                .chars()
+                                .map(String::from)
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .into_owned();
+        }
+
+        if self.units && english {
+            text = UNIT_RE
+                .replace_all(&text, |caps: &Captures| {
+                    let m = caps.get(0).unwrap();
+                    let symbol = &caps[3];
+                    let Some(&(_, singular, plural, attached)) =
+                        UNITS.iter().find(|unit| unit.0 == symbol)
+                    else {
+                        return m.as_str().to_string();
+                    };
+                    if !semiotic::starts_token(&text, m.start())
+                        || (attached && !caps[2].is_empty())
+                    {
+                        return m.as_str().to_string();
+                    }
+                    let noun = if &caps[1] == "1" { singular } else { plural };
+                    format!("{} {}", &caps[1], noun)
+                })
+                .into_owned();
+        }
+
+        if self.replace_symbols && english {
+            text = text
+                .chars()
+                .map(|c| match SYMBOLS.iter().find(|(symbol, _)| *symbol == c) {
+                    Some((_, word)) => word.to_string(),
+                    None => c.to_string(),
+                })
+                .collect();
+        }
+
+        if self.language_rules {
+            text = language_rules(&text, lan);
+        }
+
+        SPACES_RE.replace_all(text.trim(), " ").into_owned()
+    }
+}
+
+/// [`normalize::normalize_for_language`], sentence by sentence in the
+/// detected language when `lan` asks for detection
+fn language_rules(text: &str, lan: &str) -> String {
+    if !detect::is_auto(lan) {
+        return normalize::normalize_for_language(text, lan);
+    }
+    detect::split_by_language(text, "en-us")
+        .into_iter()
+        .map(|(span, language)| normalize::normalize_for_language(&span, &language))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontend_defaults() {
+        let frontend = TextFrontend::default();
+        assert_eq!(
+            frontend.apply("Call (555) 123-4567 about the file(s) & fees", "en-us"),
+            "Call 5 5 5, 1 2 3, 4 5 6 7 about the files and fees"
+        );
+        assert_eq!(
+            frontend.apply("Dial 555.123.4567, not 100 500 2000", "en-us"),
+            "Dial 5 5 5, 1 2 3, 4 5 6 7, not 100 500 2000"
+        );
+        assert_eq!(
+            frontend.apply("Run 10km in 5 min", "en-us"),
+            "Run 10km in 5 min"
+        );
+        assert_eq!(frontend.apply("Tú & yo", "es"), "Tú & yo");
+        assert_eq!(frontend.apply("Son 10 €", "es"), "Son diez euros");
+    }
+
+    #[test]
+    fn test_frontend_units() {
+        let frontend = TextFrontend {
+            units: true,
+            ..TextFrontend::default()
+        };
+        assert_eq!(
+            frontend.apply("Run 10km in 1h at 20°C, then 5 in the box", "en-us"),
+            "Run 10 kilometers in 1 hour at 20 degrees Celsius, then 5 in the box"
+        );
+        assert_eq!(TextFrontend::off().apply("a & b", "en-us"), "a & b");
+    }
+
+    #[test]
+    fn test_frontend_off_keeps_language_rules_off() {
+        assert_eq!(TextFrontend::off().apply("10 €", "es"), "10 €");
+        let frontend = TextFrontend {
+            language_rules: false,
+            ..TextFrontend::default()
+        };
+        assert_eq!(frontend.apply("Son 10 €", "es"), "Son 10 €");
+    }
+}
@@ -0,0 +1,220 @@
+//! Speech for text that arrives a little at a time, such as the tokens of a
+//! language model.
+//!
+//! [`IncrementalTTS`] buffers what it is given and speaks a sentence once
+//! the next one has begun, since until then more text may still change how
+//! it ends ("Dr." or "3." followed by "14"). What is ready goes through the
+//! same [`segment::speech_chunks`] as a streamed server response. The first
+//! chunk can go out at a clause instead of a sentence, trading some
+//! intonation for latency, and a run of text without any punctuation is
+//! spoken once it grows past a limit rather than held back indefinitely.
+
+use crate::error::KokorosError;
+use crate::tts::frontend::TextFrontend;
+use crate::tts::koko::{TTSKoko, WordAlignment};
+use crate::tts::segment;
+
+#[derive(Debug, Clone)]
+pub struct IncrementalOptions {
+    /// Words a chunk gathers before a comma ends it
+    pub words_per_chunk: usize,
+    /// Speak the first clause once it has this many words, without waiting
+    /// for its sentence to end; `None` always waits for a whole sentence
+    pub first_chunk_words: Option<usize>,
+    /// Words of an unfinished sentence held back before they are spoken at
+    /// the last clause, or the last complete word
+    pub max_pending_words: usize,
+    pub initial_silence: Option<usize>,
+    /// Applied to every chunk before it is spoken
+    pub frontend: Option<TextFrontend>,
+}
+
+impl Default for IncrementalOptions {
+    fn default() -> Self {
+        Self {
+            words_per_chunk: 10,
+            first_chunk_words: Some(4),
+            max_pending_words: 40,
+            initial_silence: None,
+            frontend: None,
+        }
+    }
+}
+
+/// Audio of one chunk of the text
+#[derive(Debug, Clone)]
+pub struct SpeechChunk {
+    pub text: String,
+    pub samples: Vec<f32>,
+    /// Words timed from the start of the whole stream; empty for models
+    /// without durations
+    pub words: Vec<WordAlignment>,
+    /// Where the chunk starts in the whole stream
+    pub start_sec: f32,
+}
+
+/// Synthesizer fed with text as it arrives
+pub struct IncrementalTTS<'a> {
+    tts: &'a TTSKoko,
+    lan: String,
+    style_name: String,
+    speed: f32,
+    options: IncrementalOptions,
+    pending: String,
+    spoken_samples: usize,
+    chunks: usize,
+}
+
+impl<'a> IncrementalTTS<'a> {
+    pub fn new(
+        tts: &'a TTSKoko,
+        lan: &str,
+        style_name: &str,
+        speed: f32,
+        options: IncrementalOptions,
+    ) -> Self {
+        Self {
+            tts,
+            lan: lan.to_string(),
+            style_name: style_name.to_string(),
+            speed,
+            options,
+            pending: String::new(),
+            spoken_samples: 0,
+            chunks: 0,
+        }
+    }
+
+    /// Add text and speak whatever is now safe to speak
+    pub fn push_text(&mut self, text: &str) -> Result<Vec<SpeechChunk>, KokorosError> {
+        self.pending.push_str(text);
+        let ready = ready_len(&self.pending, self.chunks == 0, &self.options);
+        let text: String = self.pending.drain(..ready).collect();
+        self.speak(&text)
+    }
+
+    /// Speak everything buffered, even an unfinished sentence, such as when
+    /// the source pauses
+    pub fn flush(&mut self) -> Result<Vec<SpeechChunk>, KokorosError> {
+        let text = std::mem::take(&mut self.pending);
+        self.speak(&text)
+    }
+
+    /// Speak what is left at the end of the text
+    pub fn finish(mut self) -> Result<Vec<SpeechChunk>, KokorosError> {
+        self.flush()
+    }
+
+    /// Text received but not spoken yet
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    fn speak(&mut self, text: &str) -> Result<Vec<SpeechChunk>, KokorosError> {
+        if text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let sample_rate = self.tts.sample_rate() as f32;
+        let mut spoken = Vec::new();
+        for chunk in segment::speech_chunks(text, self.options.words_per_chunk) {
+            let chunk = match &self.options.frontend {
+                Some(frontend) => frontend.apply(&chunk, &self.lan),
+                None => chunk,
+            };
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            let output = self.tts.tts_timestamped_raw_audio(
+                &chunk,
+                &self.lan,
+                &self.style_name,
+                self.speed,
+                if self.chunks == 0 {
+                    self.options.initial_silence
+                } else {
+                    None
+                },
+                None,
+                None,
+                Some(self.chunks),
+            )?;
+            self.chunks += 1;
+            let Some((samples, mut words)) = output else {
+                continue;
+            };
+            let start_sec = self.spoken_samples as f32 / sample_rate;
+            for word in &mut words {
+                word.start_sec += start_sec;
+                word.end_sec += start_sec;
+            }
+            self.spoken_samples += samples.len();
+            spoken.push(SpeechChunk {
+                text: chunk,
+                samples,
+                words,
+                start_sec,
+            });
+        }
+        Ok(spoken)
+    }
+}
+
+/// Bytes at the start of `pending` that are safe to speak
+fn ready_len(pending: &str, first: bool, options: &IncrementalOptions) -> usize {
+    let offset = |part: &str| part.as_ptr() as usize - pending.as_ptr() as usize;
+    let sentences = segment::split_sentences(pending);
+    // The last sentence may still grow
+    let Some((held, complete)) = sentences.split_last() else {
+        return 0;
+    };
+    let ready = complete
+        .last()
+        .map_or(0, |sentence| offset(sentence) + sentence.len());
+
+    let words = held.split_whitespace().count();
+    let early = first && ready == 0 && options.first_chunk_words.is_some_and(|n| words > n);
+    if !early && words <= options.max_pending_words {
+        return ready;
+    }
+    let clauses = segment::split_clauses(held);
+    let mut counted = 0;
+    let mut clause_end = None;
+    for clause in &clauses[..clauses.len() - 1] {
+        counted += clause.split_whitespace().count();
+        if !early || options.first_chunk_words.is_some_and(|n| counted >= n) {
+            clause_end = Some(offset(clause) + clause.len());
+        }
+    }
+    match clause_end {
+        Some(end) => end,
+        None if words > options.max_pending_words => {
+            // The last word may be cut off in the middle
+            let last_space = held.rfind(char::is_whitespace).unwrap_or(0);
+            offset(held) + last_space
+        }
+        None => ready,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_len() {
+        fn ready(pending: &str, first: bool) -> &str {
+            &pending[..ready_len(pending, first, &IncrementalOptions::default())]
+        }
+
+        assert_eq!(ready("Hello there. How are", false), "Hello there.");
+        assert_eq!(ready("Call Dr. ", false), "");
+        assert_eq!(ready("It costs 3.", false), "");
+        assert_eq!(
+            ready("Well, as I was saying, the weather to", true),
+            "Well, as I was saying,"
+        );
+        assert_eq!(ready("Well, as I was saying, the weather to", false), "");
+        let rambling = "word ".repeat(41) + "unfini";
+        assert_eq!(ready(&rambling, false), "word ".repeat(41).trim_end());
+    }
+}
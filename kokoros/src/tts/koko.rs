@@ -1,16 +1,22 @@
+use crate::error::KokorosError;
+use crate::onn::ort_base::OrtBase;
 use crate::onn::ort_koko::{self, ModelStrategy};
-use crate::tts::tokenize::tokenize;
+use crate::onn::providers::ExecutionProvider;
+use crate::onn::signature::{ModelSignature, TensorNames};
+use crate::tts::catalog::{self, VoiceCatalog};
+use crate::tts::config::ModelConfig;
+use crate::tts::detect::{self, LanguageSpan};
+use crate::tts::prosody::Segment;
+use crate::tts::voices::{self, Blend, Neighbour, StyleTable, VoiceFilter};
+use crate::tts::{segment, zh};
 use crate::utils;
 use crate::utils::debug::format_debug_prefix;
+use crate::utils::dsp::Effects;
 use lazy_static::lazy_static;
-use ndarray::Array3;
-use ndarray_npy::NpzReader;
 use std::collections::HashMap;
-use std::error::Error;
-use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use espeak_rs::text_to_phonemes;
 
@@ -20,6 +26,23 @@ lazy_static! {
     static ref ESPEAK_MUTEX: Mutex<()> = Mutex::new(());
 }
 
+fn espeak_phonemes(text: &str, lan: &str) -> Result<String, KokorosError> {
+    // The mutex guards no data, so a lock poisoned by a panicking caller is still usable
+    let _guard = ESPEAK_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+    text_to_phonemes(text, lan, None, true, false)
+        .map(|phonemes| phonemes.join(""))
+        .map_err(|e| KokorosError::Phonemization(format!("eSpeak ({}): {}", lan, e)))
+}
+
+/// Slowest speed the model is asked for
+pub const MIN_SPEED: f32 = 0.25;
+
+/// Fastest speed the model is asked for
+pub const MAX_SPEED: f32 = 4.0;
+
+/// Words (and punctuation marks) with their token span in a chunk
+type WordMap = Vec<(String, usize, usize)>;
+
 // Flag to ensure voice styles are only logged once
 static VOICES_LOGGED: AtomicBool = AtomicBool::new(false);
 
@@ -28,6 +51,11 @@ pub struct WordAlignment {
     pub word: String,
     pub start_sec: f32,
     pub end_sec: f32,
+    /// Bytes of the word in the source document, see
+    /// [`SpokenDocument::locate`](crate::tts::document::SpokenDocument::locate).
+    /// Only the CLI's timestamp sidecars fill it; the server's word timings
+    /// are for dialogue scripts, which have no source document.
+    pub source: Option<std::ops::Range<usize>>,
 }
 
 #[derive(Debug, Clone)]
@@ -51,7 +79,7 @@ enum ExecutionMode<'a> {
     /// Collects all data, adjusts timestamps to be global, returns it at the end.
     Batch,
     /// Yields chunks immediately with relative timestamps. Returns None at end.
-    Stream(&'a mut dyn FnMut(TtsOutput) -> Result<(), Box<dyn std::error::Error>>),
+    Stream(&'a mut dyn FnMut(TtsOutput) -> Result<(), KokorosError>),
 }
 
 #[derive(Debug, Clone)]
@@ -63,24 +91,26 @@ pub struct TTSOpts<'a> {
     pub mono: bool,
     pub speed: f32,
     pub initial_silence: Option<usize>,
+    /// Pitch and tempo applied before saving
+    pub effects: Effects,
 }
 
 #[derive(Clone)]
 pub struct TTSKoko {
-    #[allow(dead_code)]
-    model_path: String,
     model: Arc<Mutex<ort_koko::OrtKoko>>,
-    styles: HashMap<String, Vec<[[f32; 256]; 1]>>,
+    styles: HashMap<String, StyleTable>,
+    catalog: Arc<VoiceCatalog>,
+    model_config: Arc<ModelConfig>,
     init_config: InitConfig,
 }
 
 /// Parallel TTS with multiple ONNX instances for true concurrency
 #[derive(Clone)]
 pub struct TTSKokoParallel {
-    #[allow(dead_code)]
-    model_path: String,
     models: Vec<Arc<Mutex<ort_koko::OrtKoko>>>,
-    styles: HashMap<String, Vec<[[f32; 256]; 1]>>,
+    styles: HashMap<String, StyleTable>,
+    catalog: Arc<VoiceCatalog>,
+    model_config: Arc<ModelConfig>,
     init_config: InitConfig,
 }
 
@@ -88,52 +118,102 @@ pub struct TTSKokoParallel {
 pub struct InitConfig {
     pub model_url: String,
     pub voices_url: String,
+    /// Unused, the sample rate is read from the model's `config.json`
+    #[deprecated(
+        note = "the sample rate comes from the model's config.json, see `TTSKoko::sample_rate`"
+    )]
     pub sample_rate: u32,
+    /// Model `config.json`; defaults to the one next to the model file, if any
+    pub model_config_path: Option<String>,
+    /// Execution providers to try, in order; the CPU is always the last resort
+    pub providers: Vec<ExecutionProvider>,
+    /// Tensor names overriding the built-in ones, for custom exports
+    pub tensor_names: TensorNames,
+    /// Directory of saved voices, loaded next to the voices file; see
+    /// [`voices::default_user_dir`]
+    pub user_voices_path: Option<PathBuf>,
 }
 
 impl Default for InitConfig {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             model_url: "https://github.com/thewh1teagle/kokoro-onnx/releases/download/model-files-v1.0/kokoro-v1.0.onnx".into(),
             voices_url: "https://github.com/thewh1teagle/kokoro-onnx/releases/download/model-files-v1.0/voices-v1.0.bin".into(),
             sample_rate: 24000,
+            model_config_path: None,
+            providers: ExecutionProvider::default_order(),
+            tensor_names: TensorNames::default(),
+            user_voices_path: voices::default_user_dir(),
         }
     }
 }
 
-impl TTSKoko {
-    pub async fn new(model_path: &str, voices_path: &str) -> Self {
-        Self::from_config(model_path, voices_path, InitConfig::default()).await
+/// Download the model and voices files named in `cfg` if they are not on disk yet.
+async fn download_missing_files(
+    model_path: &str,
+    voices_path: &str,
+    cfg: &InitConfig,
+) -> Result<(), KokorosError> {
+    if !Path::new(model_path).exists() {
+        utils::fileio::download_file_from_url(cfg.model_url.as_str(), model_path)
+            .await
+            .map_err(|e| KokorosError::ModelLoad(format!("download model failed: {}", e)))?;
     }
 
-    pub async fn from_config(model_path: &str, voices_path: &str, cfg: InitConfig) -> Self {
-        if !Path::new(model_path).exists() {
-            utils::fileio::download_file_from_url(cfg.model_url.as_str(), model_path)
-                .await
-                .expect("download model failed.");
-        }
+    if !Path::new(voices_path).exists() {
+        utils::fileio::download_file_from_url(cfg.voices_url.as_str(), voices_path)
+            .await
+            .map_err(|e| {
+                KokorosError::VoiceLoad(format!("download voices data file failed: {}", e))
+            })?;
+    }
 
-        if !Path::new(voices_path).exists() {
-            utils::fileio::download_file_from_url(cfg.voices_url.as_str(), voices_path)
-                .await
-                .expect("download voices data file failed.");
-        }
+    Ok(())
+}
 
-        let model = Arc::new(Mutex::new(
-            ort_koko::OrtKoko::new(model_path.to_string())
-                .expect("Failed to create Kokoro TTS model"),
-        ));
+impl TTSKoko {
+    pub async fn new(model_path: &str, voices_path: &str) -> Result<Self, KokorosError> {
+        Self::from_config(model_path, voices_path, InitConfig::default()).await
+    }
+
+    pub async fn from_config(
+        model_path: &str,
+        voices_path: &str,
+        cfg: InitConfig,
+    ) -> Result<Self, KokorosError> {
+        download_missing_files(model_path, voices_path, &cfg).await?;
+
+        let model = Arc::new(Mutex::new(ort_koko::OrtKoko::new(
+            model_path.to_string(),
+            &cfg.providers,
+            &cfg.tensor_names,
+        )?));
         // TODO: if(not streaming) { model.print_info(); }
         // model.print_info();
 
-        let styles = Self::load_voices(voices_path);
+        let model_config =
+            ModelConfig::load_for_model(model_path, cfg.model_config_path.as_deref())
+                .map_err(KokorosError::ModelLoad)?;
+        let mut styles = Self::load_voices(voices_path, model_config.style_table_len)?;
+        if let Some(dir) = &cfg.user_voices_path {
+            Self::load_user_voices(&mut styles, dir, model_config.style_table_len)?;
+        }
+        let catalog = Self::load_catalog(&styles, cfg.user_voices_path.as_deref())?;
 
-        TTSKoko {
-            model_path: model_path.to_string(),
+        Ok(TTSKoko {
             model,
             styles,
+            catalog: Arc::new(catalog),
+            model_config: Arc::new(model_config),
             init_config: cfg,
-        }
+        })
+    }
+
+    fn lock_model(&self) -> Result<MutexGuard<'_, ort_koko::OrtKoko>, KokorosError> {
+        self.model
+            .lock()
+            .map_err(|_| KokorosError::Inference("model lock poisoned by an earlier panic".into()))
     }
 
     fn process_internal(
@@ -146,9 +226,62 @@ impl TTSKoko {
         request_id: Option<&str>,
         instance_id: Option<&str>,
         chunk_number_start: Option<usize>,
+        close: bool,
         mut mode: ExecutionMode,
-    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, Box<dyn std::error::Error>> {
-        let chunks = self.split_text_into_chunks(txt, 500, lan);
+    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, KokorosError> {
+        // Run each detected language span on its own, stitching the batch
+        // output together with alignments shifted onto a single timeline
+        if detect::is_auto(lan) {
+            let debug_prefix = format_debug_prefix(request_id, instance_id);
+            let sample_rate = self.sample_rate() as f32;
+            let mut batch_audio = Vec::new();
+            let mut batch_alignments = Vec::new();
+
+            let spans = self.detect_languages(txt, lan, style_name);
+            for (i, span) in spans.iter().enumerate() {
+                tracing::debug!(
+                    "{} Language span {}: {} with voice {}",
+                    debug_prefix,
+                    i,
+                    span.language,
+                    span.voice
+                );
+
+                let span_mode = match &mut mode {
+                    ExecutionMode::Stream(callback) => ExecutionMode::Stream(&mut **callback),
+                    ExecutionMode::Batch => ExecutionMode::Batch,
+                };
+                let output = self.process_internal(
+                    &span.text,
+                    &span.language,
+                    &span.voice,
+                    speed,
+                    if i == 0 { initial_silence } else { None },
+                    request_id,
+                    instance_id,
+                    chunk_number_start,
+                    close || i + 1 < spans.len(),
+                    span_mode,
+                )?;
+
+                if let Some((audio, alignments)) = output {
+                    let offset = batch_audio.len() as f32 / sample_rate;
+                    batch_alignments.extend(alignments.into_iter().map(|mut align| {
+                        align.start_sec += offset;
+                        align.end_sec += offset;
+                        align
+                    }));
+                    batch_audio.extend(audio);
+                }
+            }
+
+            return match mode {
+                ExecutionMode::Batch => Ok(Some((batch_audio, batch_alignments))),
+                ExecutionMode::Stream(_) => Ok(None),
+            };
+        }
+
+        let chunks = self.split_text_into_chunks(txt, self.model_config.max_tokens, lan, close)?;
 
         let start_chunk_num = chunk_number_start.unwrap_or(0);
 
@@ -156,22 +289,22 @@ impl TTSKoko {
 
         let process_one_chunk = |chunk: &str,
                                  chunk_num: usize|
-         -> Result<TtsOutput, Box<dyn std::error::Error>> {
+         -> Result<TtsOutput, KokorosError> {
             let chunk_info = format!("Chunk: {}, ", chunk_num);
             tracing::debug!("{} {}text: '{}'", debug_prefix, chunk_info, chunk);
 
             // A. Tokenize
             // Only build the expensive alignment map if the loaded model supports timestamps.
             let use_alignment = {
-                let model = self.model.lock().unwrap();
+                let model = self.lock_model()?;
                 matches!(model.strategy(), Some(ModelStrategy::Timestamped(_)))
             };
 
             let (mut tokens, word_map) = if use_alignment {
-                self.tokenize_with_alignment(chunk, lan)
+                self.tokenize_with_alignment(chunk, lan)?
             } else {
                 // Fast path for audio-only models: single eSpeak pass, no per-item calls
-                self.tokenize_full_no_alignment(chunk, lan)
+                self.tokenize_full_no_alignment(chunk, lan)?
             };
 
             // Log token count (helpful for debugging context limits)
@@ -200,7 +333,7 @@ impl TTSKoko {
             let tokens_batch = vec![padded_tokens];
 
             // E. Infer
-            let (chunk_audio_array, chunk_durations_opt) = self.model.lock().unwrap().infer(
+            let (chunk_audio_array, chunk_durations_opt) = self.lock_model()?.infer(
                 tokens_batch,
                 styles,
                 speed,
@@ -215,8 +348,8 @@ impl TTSKoko {
             if let Some(durations) = chunk_durations_opt {
                 let mut alignments = Vec::new();
 
-                // Model durations are in frames (hop=600 @ 24 kHz) ⇒ 40 frames/sec.
-                let frames_per_sec: f32 = 40.0;
+                // Model durations are in frames (hop=600 @ 24 kHz ⇒ 40 frames/sec for v1.0).
+                let frames_per_sec = self.model_config.frames_per_sec();
 
                 // Guard speed to avoid division by zero; timestamps should reflect the final render timeline.
                 let speed_safe = if speed > 1e-6 { speed } else { 1.0 };
@@ -260,6 +393,7 @@ impl TTSKoko {
                             word: word.clone(),
                             start_sec,
                             end_sec,
+                            source: None,
                         });
                         chunk_time_cursor_frames += pause_frames;
                         continue;
@@ -279,6 +413,7 @@ impl TTSKoko {
                             word,
                             start_sec,
                             end_sec,
+                            source: None,
                         });
                         chunk_time_cursor_frames += word_frames;
                     }
@@ -287,7 +422,7 @@ impl TTSKoko {
                 // Per‑chunk closure: linearly scale the local alignment times to match this chunk’s audio length.
                 // This eliminates cumulative drift across chunks and prevents middle events from sliding late.
                 let t_end_sec = chunk_time_cursor_frames / frames_per_sec; // alignment‑derived duration (sec)
-                let chunk_audio_sec = chunk_audio.len() as f32 / 24_000.0; // audio duration (sec)
+                let chunk_audio_sec = chunk_audio.len() as f32 / self.sample_rate() as f32; // audio duration (sec)
 
                 if t_end_sec > 0.0 {
                     let s = chunk_audio_sec / t_end_sec;
@@ -342,7 +477,7 @@ impl TTSKoko {
                 let mut batch_audio = Vec::new();
                 let mut batch_alignments = Vec::new();
                 let mut global_time_offset = 0.0;
-                let sample_rate = 24000.0;
+                let sample_rate = self.sample_rate() as f32;
 
                 for (i, chunk) in chunks.iter().enumerate() {
                     let output = process_one_chunk(chunk, start_chunk_num + i)?;
@@ -371,25 +506,33 @@ impl TTSKoko {
         }
     }
 
+    /// Text to phonemes for the loaded model. Mandarin goes through the
+    /// pinyin front end when the model's vocabulary is the v1.1-zh one, and
+    /// through eSpeak's `cmn` voice otherwise.
+    fn phonemize(&self, text: &str, lan: &str) -> Result<String, KokorosError> {
+        if zh::is_mandarin(lan) {
+            if self.model_config.supports_zhuyin() {
+                return zh::g2p(text, |latin| espeak_phonemes(latin, "en-us"));
+            }
+            return espeak_phonemes(text, "cmn");
+        }
+        espeak_phonemes(text, lan)
+    }
+
     /// Prosody-Aware Tokenization ---
     fn tokenize_with_alignment(
         &self,
         text: &str,
         lan: &str,
-    ) -> (Vec<i64>, Vec<(String, usize, usize)>) {
+    ) -> Result<(Vec<i64>, WordMap), KokorosError> {
         // We will produce tokens from the full, context-aware phonemes (best prosody)
         // and build an alignment map by estimating per-word token spans using
         // per-word phoneme tokenization. This keeps audio natural while providing
         // robust timestamps even when eSpeak merges words (e.g., "the model").
 
         // 1) Full-phrase phonemes and tokens (prosody source)
-        let full_phonemes = {
-            let _guard = ESPEAK_MUTEX.lock().unwrap();
-            text_to_phonemes(text, lan, None, true, false)
-                .unwrap_or_default()
-                .join("")
-        };
-        let all_tokens = tokenize(&full_phonemes);
+        let full_phonemes = self.phonemize(text, lan)?;
+        let all_tokens = self.model_config.tokenize(&full_phonemes);
 
         // 2) Build a tokenization plan per original "word or punctuation" unit.
         //    We want punctuation timestamps too, so we split words and punctuation as separate items.
@@ -443,13 +586,8 @@ impl TTSKoko {
                 per_item_token_counts.push(0);
                 per_item_is_punct.push(true);
             } else {
-                let ph = {
-                    let _guard = ESPEAK_MUTEX.lock().unwrap();
-                    text_to_phonemes(it, lan, None, true, false)
-                        .unwrap_or_default()
-                        .join("")
-                };
-                let cnt = tokenize(&ph).len();
+                let ph = self.phonemize(it, lan)?;
+                let cnt = self.model_config.tokenize(&ph).len();
                 per_item_token_counts.push(cnt);
                 per_item_is_punct.push(false);
             }
@@ -519,7 +657,50 @@ impl TTSKoko {
         }
 
         // If there are absolutely no tokens (empty text), return empty mapping
-        (all_tokens, word_map)
+        Ok((all_tokens, word_map))
+    }
+
+    /// Split text into the spans it is spoken in. With `lan` set to
+    /// [`detect::AUTO`] every run of sentences in one language becomes a span,
+    /// and [`detect::AUTO_VOICE`] also moves spans the voice does not speak to
+    /// a voice of their language. Any other `lan` gives a single span.
+    pub fn detect_languages(&self, txt: &str, lan: &str, style_name: &str) -> Vec<LanguageSpan> {
+        if !detect::is_auto(lan) {
+            return vec![LanguageSpan {
+                text: txt.to_string(),
+                language: lan.to_string(),
+                voice: style_name.to_string(),
+            }];
+        }
+
+        let fallback = self.catalog.language(style_name).unwrap_or("en-us");
+        detect::split_by_language(txt, fallback)
+            .into_iter()
+            .map(|(text, language)| {
+                let voice = if lan == detect::AUTO_VOICE {
+                    self.voice_for_language(style_name, &language)
+                } else {
+                    style_name.to_string()
+                };
+                LanguageSpan {
+                    text,
+                    language,
+                    voice,
+                }
+            })
+            .collect()
+    }
+
+    /// A loaded voice for `language`, preferring the gender of `style_name`.
+    /// Blends and voices that already speak the language are kept.
+    fn voice_for_language(&self, style_name: &str, language: &str) -> String {
+        if Blend::is_blend(style_name) || self.catalog.speaks(style_name, language) {
+            return style_name.to_string();
+        }
+        self.catalog
+            .voice_for_language(language, self.catalog.gender(style_name))
+            .unwrap_or(style_name)
+            .to_string()
     }
 
     /// Fast tokenization path for audio-only models (no timestamps)
@@ -528,40 +709,27 @@ impl TTSKoko {
         &self,
         text: &str,
         lan: &str,
-    ) -> (Vec<i64>, Vec<(String, usize, usize)>) {
-        let full_phonemes = {
-            let _guard = ESPEAK_MUTEX.lock().unwrap();
-            text_to_phonemes(text, lan, None, true, false)
-                .unwrap_or_default()
-                .join("")
-        };
-        let all_tokens = tokenize(&full_phonemes);
-        (all_tokens, Vec::new())
+    ) -> Result<(Vec<i64>, WordMap), KokorosError> {
+        let full_phonemes = self.phonemize(text, lan)?;
+        let all_tokens = self.model_config.tokenize(&full_phonemes);
+        Ok((all_tokens, Vec::new()))
     }
 
-    fn split_text_into_chunks(&self, text: &str, max_tokens: usize, lan: &str) -> Vec<String> {
+    fn split_text_into_chunks(
+        &self,
+        text: &str,
+        max_tokens: usize,
+        lan: &str,
+        close: bool,
+    ) -> Result<Vec<String>, KokorosError> {
         let mut chunks = Vec::new();
 
-        // First split by sentences - using common sentence ending punctuation
-        let sentences: Vec<&str> = text
-            .split(|c| c == '.' || c == '?' || c == '!' || c == ';')
-            .filter(|s| !s.trim().is_empty())
-            .collect();
-
         let mut current_chunk = String::new();
 
-        for sentence in sentences {
-            // Clean up the sentence and add back punctuation
-            let sentence = format!("{}.", sentence.trim());
-
+        for sentence in segment::spoken_sentences(text, close) {
             // Convert to phonemes to check token count
-            let sentence_phonemes = {
-                let _guard = ESPEAK_MUTEX.lock().unwrap();
-                text_to_phonemes(&sentence, lan, None, true, false)
-                    .unwrap_or_default()
-                    .join("")
-            };
-            let token_count = tokenize(&sentence_phonemes).len();
+            let sentence_phonemes = self.phonemize(&sentence, lan)?;
+            let token_count = self.model_config.tokenize(&sentence_phonemes).len();
 
             if token_count > max_tokens {
                 // If single sentence is too long, split by words
@@ -575,13 +743,8 @@ impl TTSKoko {
                         format!("{} {}", word_chunk, word)
                     };
 
-                    let test_phonemes = {
-                        let _guard = ESPEAK_MUTEX.lock().unwrap();
-                        text_to_phonemes(&test_chunk, lan, None, true, false)
-                            .unwrap_or_default()
-                            .join("")
-                    };
-                    let test_tokens = tokenize(&test_phonemes).len();
+                    let test_phonemes = self.phonemize(&test_chunk, lan)?;
+                    let test_tokens = self.model_config.tokenize(&test_phonemes).len();
 
                     if test_tokens > max_tokens {
                         if !word_chunk.is_empty() {
@@ -599,13 +762,8 @@ impl TTSKoko {
             } else if !current_chunk.is_empty() {
                 // Try to append to current chunk
                 let test_text = format!("{} {}", current_chunk, sentence);
-                let test_phonemes = {
-                    let _guard = ESPEAK_MUTEX.lock().unwrap();
-                    text_to_phonemes(&test_text, lan, None, true, false)
-                        .unwrap_or_default()
-                        .join("")
-                };
-                let test_tokens = tokenize(&test_phonemes).len();
+                let test_phonemes = self.phonemize(&test_text, lan)?;
+                let test_tokens = self.model_config.tokenize(&test_phonemes).len();
 
                 if test_tokens > max_tokens {
                     // If combining would exceed limit, start new chunk
@@ -624,7 +782,7 @@ impl TTSKoko {
             chunks.push(current_chunk);
         }
 
-        chunks
+        Ok(chunks)
     }
 
     /// Smart word-based chunking for async streaming
@@ -632,56 +790,20 @@ impl TTSKoko {
     pub fn split_text_into_speech_chunks(&self, text: &str, max_words: usize) -> Vec<String> {
         let mut chunks = Vec::new();
 
-        // Split by sentence-ending punctuation first
-        let sentences: Vec<&str> = text
-            .split(|c| c == '.' || c == '!' || c == '?')
-            .filter(|s| !s.trim().is_empty())
-            .collect();
-
-        for sentence in sentences {
-            let sentence = sentence.trim();
-            if sentence.is_empty() {
-                continue;
-            }
-
+        for sentence in segment::split_sentences(text) {
             // Count words in this sentence
             let words: Vec<&str> = sentence.split_whitespace().collect();
             let word_count = words.len();
 
             if word_count <= max_words {
                 // Small sentence - add as complete chunk (preserve original punctuation)
-                chunks.push(format!("{}.", sentence));
+                chunks.push(sentence.to_string());
             } else {
                 // Large sentence - split by punctuation marks while preserving them
-                let mut sub_clauses = Vec::new();
-                let mut current_pos = 0;
-
-                for (i, ch) in sentence.char_indices() {
-                    if ch == ',' || ch == ';' || ch == ':' {
-                        if i > current_pos {
-                            let clause_with_punct = format!("{}{}", &sentence[current_pos..i], ch);
-                            sub_clauses.push(clause_with_punct);
-                        }
-                        current_pos = i + 1;
-                    }
-                }
-
-                // Add remaining text
-                if current_pos < sentence.len() {
-                    sub_clauses.push(sentence[current_pos..].to_string());
-                }
-
-                let sub_clauses: Vec<&str> = sub_clauses
-                    .iter()
-                    .map(|s| s.trim())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-
                 let mut current_chunk = String::new();
                 let mut current_word_count = 0;
 
-                for clause in sub_clauses {
-                    let clause = clause.trim();
+                for clause in segment::split_clauses(sentence) {
                     let clause_words: Vec<&str> = clause.split_whitespace().collect();
                     let clause_word_count = clause_words.len();
 
@@ -751,7 +873,7 @@ impl TTSKoko {
         request_id: Option<&str>,
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
-    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, Box<dyn Error>> {
+    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, KokorosError> {
         self.process_internal(
             txt,
             lan,
@@ -761,6 +883,7 @@ impl TTSKoko {
             request_id,
             instance_id,
             chunk_number,
+            true,
             ExecutionMode::Batch,
         )
     }
@@ -775,7 +898,7 @@ impl TTSKoko {
         request_id: Option<&str>,
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
-    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<f32>, KokorosError> {
         let audio = self.process_internal(
             txt,
             lan,
@@ -785,10 +908,11 @@ impl TTSKoko {
             request_id,
             instance_id,
             chunk_number,
+            true,
             ExecutionMode::Batch,
         )?;
 
-        Ok(audio.unwrap().0)
+        Ok(audio.map(|(audio, _)| audio).unwrap_or_default())
     }
 
     /// Streaming version that yields audio chunks as they're generated
@@ -803,11 +927,11 @@ impl TTSKoko {
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
         mut chunk_callback: F,
-    ) -> Result<(), Box<dyn std::error::Error>>
+    ) -> Result<(), KokorosError>
     where
-        F: FnMut(Vec<f32>) -> Result<(), Box<dyn std::error::Error>>,
+        F: FnMut(Vec<f32>) -> Result<(), KokorosError>,
     {
-        let mut adapter = |output: TtsOutput| -> Result<(), Box<dyn std::error::Error>> {
+        let mut adapter = |output: TtsOutput| -> Result<(), KokorosError> {
             chunk_callback(output.raw_output().0)
         };
 
@@ -820,6 +944,7 @@ impl TTSKoko {
             request_id,
             instance_id,
             chunk_number,
+            true,
             // Pass the ADAPTER, not the original callback
             ExecutionMode::Stream(&mut adapter),
         )?;
@@ -840,14 +965,19 @@ impl TTSKoko {
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
         mut chunk_callback: F,
-    ) -> Result<(), Box<dyn std::error::Error>>
+    ) -> Result<(), KokorosError>
     where
         // CHANGE: Callback accepts TtsOutput instead of just Vec<f32>
-        F: FnMut((Vec<f32>, Vec<WordAlignment>)) -> Result<(), Box<dyn std::error::Error>>,
+        F: FnMut((Vec<f32>, Vec<WordAlignment>)) -> Result<(), KokorosError>,
     {
-        let mut adapter = |output: TtsOutput| -> Result<(), Box<dyn std::error::Error>> {
-            let audio = output.raw_output();
-            chunk_callback((audio.0, audio.1.unwrap()))
+        let mut adapter = |output: TtsOutput| -> Result<(), KokorosError> {
+            let (audio, alignments) = output.raw_output();
+            let alignments = alignments.ok_or_else(|| {
+                KokorosError::Inference(
+                    "the loaded model does not report durations, which word timestamps need".into(),
+                )
+            })?;
+            chunk_callback((audio, alignments))
         };
 
         self.process_internal(
@@ -859,12 +989,75 @@ impl TTSKoko {
             request_id,
             instance_id,
             chunk_number,
+            true,
             ExecutionMode::Stream(&mut adapter),
         )?;
 
         Ok(())
     }
 
+    /// Speak `segments` one after the other, each at `speed` times its own
+    /// speed, in its own voice or `style_name`, and followed by its pause.
+    /// Word alignments are on the timeline of the whole audio, and empty for
+    /// models without durations.
+    pub fn tts_segments(
+        &self,
+        segments: &[Segment],
+        lan: &str,
+        style_name: &str,
+        speed: f32,
+        initial_silence: Option<usize>,
+    ) -> Result<(Vec<f32>, Vec<WordAlignment>), KokorosError> {
+        let sample_rate = self.sample_rate() as f32;
+        let spoken = |segment: &&Segment| !segment.text.trim().is_empty();
+        for segment in segments.iter().filter(spoken) {
+            let segment_speed = speed * segment.speed;
+            if !(MIN_SPEED..=MAX_SPEED).contains(&segment_speed) {
+                return Err(KokorosError::InvalidInput(format!(
+                    "segment '{}' would be spoken at speed {}, outside {} to {}",
+                    segment.text, segment_speed, MIN_SPEED, MAX_SPEED
+                )));
+            }
+        }
+        // Only the last segment ends the text; the others run on into the
+        // next one without a full stop, which would drop the intonation
+        let last = segments.iter().rposition(|segment| spoken(&segment));
+
+        let mut audio = Vec::new();
+        let mut alignments = Vec::new();
+        // A pause before the first words does not replace the initial silence
+        let mut first_spoken = true;
+        for (i, segment) in segments.iter().enumerate() {
+            if spoken(&segment) {
+                let output = self.process_internal(
+                    &segment.text,
+                    lan,
+                    segment.voice.as_deref().unwrap_or(style_name),
+                    speed * segment.speed,
+                    if first_spoken { initial_silence } else { None },
+                    None,
+                    None,
+                    None,
+                    Some(i) == last,
+                    ExecutionMode::Batch,
+                )?;
+                first_spoken = false;
+                if let Some((segment_audio, segment_alignments)) = output {
+                    let offset = audio.len() as f32 / sample_rate;
+                    alignments.extend(segment_alignments.into_iter().map(|mut align| {
+                        align.start_sec += offset;
+                        align.end_sec += offset;
+                        align
+                    }));
+                    audio.extend(segment_audio);
+                }
+            }
+            let pause = (segment.pause_sec.max(0.0) * sample_rate).round() as usize;
+            audio.resize(audio.len() + pause, 0.0);
+        }
+        Ok((audio, alignments))
+    }
+
     pub fn tts(
         &self,
         TTSOpts {
@@ -875,8 +1068,9 @@ impl TTSKoko {
             mono,
             speed,
             initial_silence,
+            effects,
         }: TTSOpts,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<(), KokorosError> {
         let audio = self.tts_raw_audio(
             &txt,
             lan,
@@ -887,12 +1081,13 @@ impl TTSKoko {
             None,
             None,
         )?;
+        let audio = effects.apply(&audio, self.sample_rate());
 
         // Save to file
         if mono {
             let spec = hound::WavSpec {
                 channels: 1,
-                sample_rate: self.init_config.sample_rate,
+                sample_rate: self.sample_rate(),
                 bits_per_sample: 32,
                 sample_format: hound::SampleFormat::Float,
             };
@@ -905,7 +1100,7 @@ impl TTSKoko {
         } else {
             let spec = hound::WavSpec {
                 channels: 2,
-                sample_rate: self.init_config.sample_rate,
+                sample_rate: self.sample_rate(),
                 bits_per_sample: 32,
                 sample_format: hound::SampleFormat::Float,
             };
@@ -921,68 +1116,128 @@ impl TTSKoko {
         Ok(())
     }
 
+    /// The style vector for a chunk of `tokens_len` tokens of a voice or a
+    /// [`Blend`] of voices
     pub fn mix_styles(
         &self,
         style_name: &str,
         tokens_len: usize,
-    ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
-        if !style_name.contains("+") {
-            if let Some(style) = self.styles.get(style_name) {
-                let styles = vec![style[tokens_len][0].to_vec()];
-                Ok(styles)
-            } else {
-                Err(format!("can not found from styles_map: {}", style_name).into())
+    ) -> Result<Vec<Vec<f32>>, KokorosError> {
+        if !Blend::is_blend(style_name) {
+            return Ok(vec![self.style_at(style_name, tokens_len)?.to_vec()]);
+        }
+        let blend = Self::parse_blend(style_name)?;
+        let mut blended_style = vec![0.0; 256];
+        for (name, weight) in blend.components() {
+            let style = self.style_at(name, tokens_len)?;
+            for (blended, value) in blended_style.iter_mut().zip(style) {
+                *blended += value * weight;
             }
-        } else {
-            eprintln!("parsing style mix");
-            let styles: Vec<&str> = style_name.split('+').collect();
+        }
+        Ok(vec![blended_style])
+    }
 
-            let mut style_names = Vec::new();
-            let mut style_portions = Vec::new();
+    fn parse_blend(style_name: &str) -> Result<Blend, KokorosError> {
+        style_name
+            .parse()
+            .map_err(|e| KokorosError::InvalidVoice(format!("{}: {}", style_name, e)))
+    }
 
-            for style in styles {
-                if let Some((name, portion)) = style.split_once('.') {
-                    if let Ok(portion) = portion.parse::<f32>() {
-                        style_names.push(name);
-                        style_portions.push(portion * 0.1);
-                    }
-                }
-            }
-            eprintln!("styles: {:?}, portions: {:?}", style_names, style_portions);
+    /// Check that `lan` is a language the phonemizer reads, or one of the
+    /// [`detect`] modes
+    pub fn check_language(&self, lan: &str) -> Result<(), KokorosError> {
+        if detect::is_auto(lan) {
+            return Ok(());
+        }
+        self.phonemize("a", lan).map(|_| ())
+    }
 
-            let mut blended_style = vec![vec![0.0; 256]; 1];
+    /// Check that a voice, or every component of a blend, is loaded, without
+    /// running inference.
+    pub fn check_voice(&self, style_name: &str) -> Result<(), KokorosError> {
+        if !Blend::is_blend(style_name) {
+            return self.style_at(style_name, 0).map(|_| ());
+        }
+        Self::parse_blend(style_name)?
+            .components()
+            .iter()
+            .try_for_each(|(name, _)| self.style_at(name, 0).map(|_| ()))
+    }
 
-            for (name, portion) in style_names.iter().zip(style_portions.iter()) {
-                if let Some(style) = self.styles.get(*name) {
-                    let style_slice = &style[tokens_len][0]; // This is a [256] array
-                    // Blend into the blended_style
-                    for j in 0..256 {
-                        blended_style[0][j] += style_slice[j] * portion;
-                    }
-                }
-            }
-            eprintln!("blended_style: {:?}", blended_style);
-            Ok(blended_style)
+    /// Save a voice or blend under `name` in the user voice directory, with
+    /// the full style table of the mix. The voice is loaded by every
+    /// instance created afterwards.
+    pub fn save_blend(&self, name: &str, style_name: &str) -> Result<PathBuf, KokorosError> {
+        let path = voices::user_voice_path(self.init_config.user_voices_path.as_deref(), name)?;
+        if self.styles.contains_key(name) && !path.exists() {
+            return Err(KokorosError::InvalidVoice(format!(
+                "'{}' is a built-in voice",
+                name
+            )));
         }
+
+        let blend = Self::parse_blend(style_name)?;
+        let table = voices::mix(&blend, self.model_config.style_table_len, |name| {
+            self.styles.get(name)
+        })?;
+        voices::write_voice_file(&path, &table)?;
+        Ok(path)
     }
 
-    fn load_voices(voices_path: &str) -> HashMap<String, Vec<[[f32; 256]; 1]>> {
-        let mut npz = NpzReader::new(File::open(voices_path).unwrap()).unwrap();
-        let mut map = HashMap::new();
-
-        for voice in npz.names().unwrap() {
-            let voice_data: Result<Array3<f32>, _> = npz.by_name(&voice);
-            let voice_data = voice_data.unwrap();
-            let mut tensor = vec![[[0.0; 256]; 1]; 511];
-            for (i, inner_value) in voice_data.outer_iter().enumerate() {
-                for (j, inner_inner_value) in inner_value.outer_iter().enumerate() {
-                    for (k, number) in inner_inner_value.iter().enumerate() {
-                        tensor[i][j][k] = *number;
-                    }
-                }
-            }
-            map.insert(voice, tensor);
+    /// Use a style table as voice `name` in this instance only, e.g. to
+    /// preview a designed voice before saving it
+    pub fn add_voice(&mut self, name: &str, table: StyleTable) -> Result<(), KokorosError> {
+        if self.styles.contains_key(name) {
+            return Err(KokorosError::InvalidVoice(format!(
+                "'{}' is already loaded",
+                name
+            )));
         }
+        self.styles.insert(name.to_string(), table);
+        Ok(())
+    }
+
+    /// Loaded voices that sound like a voice or blend, nearest first. A
+    /// voice is not its own neighbour, however it is written.
+    pub fn similar_voices(
+        &self,
+        style_name: &str,
+        filter: &VoiceFilter,
+        limit: usize,
+    ) -> Result<Vec<Neighbour>, KokorosError> {
+        let blend = Self::parse_blend(style_name)?;
+        let target = voices::mix(&blend, self.model_config.style_table_len, |name| {
+            self.styles.get(name)
+        })?;
+        let candidates = self
+            .catalog
+            .voices()
+            .filter(|meta| blend.single() != Some(meta.id.as_str()))
+            .filter_map(|meta| Some((meta, &self.styles.get(&meta.id)?[..])));
+        Ok(voices::nearest_voices(&target, candidates, filter, limit))
+    }
+
+    /// The style vector of a voice for a chunk of `tokens_len` tokens. The
+    /// style table has one row per token count, so longer chunks are an error.
+    fn style_at(&self, name: &str, tokens_len: usize) -> Result<&[f32; 256], KokorosError> {
+        let style = self
+            .styles
+            .get(name)
+            .ok_or_else(|| KokorosError::UnknownVoice(name.to_string()))?;
+        style
+            .get(tokens_len)
+            .map(|row| &row[0])
+            .ok_or(KokorosError::InputTooLong {
+                tokens: tokens_len,
+                max: style.len().saturating_sub(1),
+            })
+    }
+
+    fn load_voices(
+        voices_path: &str,
+        style_table_len: usize,
+    ) -> Result<HashMap<String, StyleTable>, KokorosError> {
+        let map = voices::read_pack(Path::new(voices_path), style_table_len)?;
 
         let _sorted_voices = {
             let mut voices = map.keys().collect::<Vec<_>>();
@@ -1007,26 +1262,7 @@ impl TTSKoko {
                 }
 
                 for (prefix, voices_in_group) in grouped_voices {
-                    let category = match prefix {
-                        "af" => "American Female(af)",
-                        "am" => "American Male(am)",
-                        "bf" => "British Female(bf)",
-                        "bm" => "British Male(bm)",
-                        "ef" => "European Female(ef)",
-                        "em" => "European Male(em)",
-                        "ff" => "French Female(ff)",
-                        "hf" => "Hindi Female(hf)",
-                        "hm" => "Hindi Male(hm)",
-                        "if" => "Italian Female(if)",
-                        "im" => "Italian Male(im)",
-                        "jf" => "Japanese Female(jf)",
-                        "jm" => "Japanese Male(jm)",
-                        "pf" => "Portuguese Female(pf)",
-                        "pm" => "Portuguese Male(pm)",
-                        "zf" => "Chinese Female(zf)",
-                        "zm" => "Chinese Male(zm)",
-                        _ => prefix,
-                    };
+                    let category = catalog::describe_prefix(prefix);
 
                     let voices_str = voices_in_group.join(", ");
                     // Gray out the voice information
@@ -1039,7 +1275,47 @@ impl TTSKoko {
             voices
         };
 
-        map
+        Ok(map)
+    }
+
+    /// Add the saved voices of `dir`, if it exists. Built-in voices win over
+    /// saved ones of the same name.
+    fn load_user_voices(
+        styles: &mut HashMap<String, StyleTable>,
+        dir: &Path,
+        style_table_len: usize,
+    ) -> Result<(), KokorosError> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        let user_voices = Self::load_voices(&dir.to_string_lossy(), style_table_len)?;
+        for (name, table) in user_voices {
+            if styles.contains_key(&name) {
+                tracing::warn!(
+                    "Saved voice '{}' in {} has the name of a built-in voice and is ignored",
+                    name,
+                    dir.display()
+                );
+                continue;
+            }
+            styles.insert(name, table);
+        }
+        Ok(())
+    }
+
+    /// Describe the loaded voices, with the `voices.json` of the user voice
+    /// directory applied
+    fn load_catalog(
+        styles: &HashMap<String, StyleTable>,
+        user_dir: Option<&Path>,
+    ) -> Result<VoiceCatalog, KokorosError> {
+        let mut catalog = VoiceCatalog::new(styles.keys().map(String::as_str));
+        if let Some(dir) = user_dir {
+            catalog
+                .load_user_metadata(dir)
+                .map_err(KokorosError::VoiceLoad)?;
+        }
+        Ok(catalog)
     }
 
     // Returns a sorted list of available voice names
@@ -1048,6 +1324,31 @@ impl TTSKoko {
         voices.sort();
         voices
     }
+
+    /// Metadata of the loaded voices
+    pub fn voice_catalog(&self) -> &VoiceCatalog {
+        &self.catalog
+    }
+
+    /// Output sample rate of the loaded model, in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.model_config.sample_rate
+    }
+
+    /// Constants from the model's `config.json`, or the v1.0 defaults
+    pub fn model_config(&self) -> &ModelConfig {
+        &self.model_config
+    }
+
+    /// The execution provider the ONNX session ended up running on
+    pub fn execution_provider(&self) -> Option<ExecutionProvider> {
+        self.model.lock().ok()?.provider()
+    }
+
+    /// The tensor signature resolved when the model was loaded
+    pub fn model_signature(&self) -> Option<ModelSignature> {
+        self.model.lock().ok()?.signature().cloned()
+    }
 }
 
 impl TTSKokoParallel {
@@ -1055,7 +1356,7 @@ impl TTSKokoParallel {
         model_path: &str,
         voices_path: &str,
         num_instances: usize,
-    ) -> Self {
+    ) -> Result<Self, KokorosError> {
         Self::from_config_with_instances(
             model_path,
             voices_path,
@@ -1070,18 +1371,8 @@ impl TTSKokoParallel {
         voices_path: &str,
         cfg: InitConfig,
         num_instances: usize,
-    ) -> Self {
-        if !Path::new(model_path).exists() {
-            utils::fileio::download_file_from_url(cfg.model_url.as_str(), model_path)
-                .await
-                .expect("download model failed.");
-        }
-
-        if !Path::new(voices_path).exists() {
-            utils::fileio::download_file_from_url(cfg.voices_url.as_str(), voices_path)
-                .await
-                .expect("download voices data file failed.");
-        }
+    ) -> Result<Self, KokorosError> {
+        download_missing_files(model_path, voices_path, &cfg).await?;
 
         // Create multiple ONNX model instances
         let mut models = Vec::new();
@@ -1092,21 +1383,30 @@ impl TTSKokoParallel {
                 i + 1,
                 num_instances
             );
-            let model = Arc::new(Mutex::new(
-                ort_koko::OrtKoko::new(model_path.to_string())
-                    .expect("Failed to create Kokoro TTS model"),
-            ));
+            let model = Arc::new(Mutex::new(ort_koko::OrtKoko::new(
+                model_path.to_string(),
+                &cfg.providers,
+                &cfg.tensor_names,
+            )?));
             models.push(model);
         }
 
-        let styles = TTSKoko::load_voices(voices_path);
+        let model_config =
+            ModelConfig::load_for_model(model_path, cfg.model_config_path.as_deref())
+                .map_err(KokorosError::ModelLoad)?;
+        let mut styles = TTSKoko::load_voices(voices_path, model_config.style_table_len)?;
+        if let Some(dir) = &cfg.user_voices_path {
+            TTSKoko::load_user_voices(&mut styles, dir, model_config.style_table_len)?;
+        }
+        let catalog = TTSKoko::load_catalog(&styles, cfg.user_voices_path.as_deref())?;
 
-        TTSKokoParallel {
-            model_path: model_path.to_string(),
+        Ok(TTSKokoParallel {
             models,
             styles,
+            catalog: Arc::new(catalog),
+            model_config: Arc::new(model_config),
             init_config: cfg,
-        }
+        })
     }
 
     /// Get a specific model instance for a worker
@@ -1118,10 +1418,11 @@ impl TTSKokoParallel {
     /// HELPER: Create a lightweight wrapper for a specific model ---
     fn get_tts_wrapper(&self, model_instance: Arc<Mutex<ort_koko::OrtKoko>>) -> TTSKoko {
         TTSKoko {
-            model_path: self.model_path.clone(),
             model: model_instance,
             // TODO: This clones the HashMap. In a future PR, wrap styles in Arc<>!
             styles: self.styles.clone(),
+            catalog: Arc::clone(&self.catalog),
+            model_config: Arc::clone(&self.model_config),
             init_config: self.init_config.clone(),
         }
     }
@@ -1138,7 +1439,7 @@ impl TTSKokoParallel {
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
         model_instance: Arc<Mutex<ort_koko::OrtKoko>>,
-    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, Box<dyn Error>> {
+    ) -> Result<Option<(Vec<f32>, Vec<WordAlignment>)>, KokorosError> {
         let wrapper = self.get_tts_wrapper(model_instance);
         wrapper.tts_timestamped_raw_audio(
             text,
@@ -1164,7 +1465,7 @@ impl TTSKokoParallel {
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
         model_instance: Arc<Mutex<ort_koko::OrtKoko>>,
-    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<f32>, KokorosError> {
         let wrapper = self.get_tts_wrapper(model_instance);
 
         wrapper.tts_raw_audio(
@@ -1183,9 +1484,10 @@ impl TTSKokoParallel {
     pub fn split_text_into_speech_chunks(&self, text: &str, max_words: usize) -> Vec<String> {
         // Use TTSKoko's implementation for now - create temporary instance
         let temp_tts = TTSKoko {
-            model_path: self.model_path.clone(),
             model: Arc::clone(&self.models[0]), // Just for interface compatibility
             styles: self.styles.clone(),
+            catalog: Arc::clone(&self.catalog),
+            model_config: Arc::clone(&self.model_config),
             init_config: self.init_config.clone(),
         };
         temp_tts.split_text_into_speech_chunks(text, max_words)
@@ -1197,4 +1499,14 @@ impl TTSKokoParallel {
         voices.sort();
         voices
     }
+
+    /// Output sample rate of the loaded model, in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.model_config.sample_rate
+    }
+
+    /// The execution provider of the first model instance
+    pub fn execution_provider(&self) -> Option<ExecutionProvider> {
+        self.models[0].lock().ok()?.provider()
+    }
 }
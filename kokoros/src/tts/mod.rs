@@ -1,5 +1,21 @@
+pub mod book;
+pub mod catalog;
+pub mod config;
+pub mod design;
+pub mod detect;
+pub mod dialogue;
+pub mod document;
+pub mod fit;
+pub mod frontend;
+pub mod incremental;
 pub mod koko;
 pub mod normalize;
 pub mod phonemizer;
+pub mod prosody;
+pub mod segment;
+pub mod semiotic;
+pub mod subtitles;
 pub mod tokenize;
 pub mod vocab;
+pub mod voices;
+pub mod zh;
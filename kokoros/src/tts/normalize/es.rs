@@ -0,0 +1,161 @@
+use super::{Currency, LocaleRules};
+
+pub(super) static RULES: LocaleRules = LocaleRules {
+    cardinal,
+    cardinal_before_noun,
+    decimal_point: "coma",
+    percent: "por ciento",
+    and: "con",
+    currencies: &[
+        Currency::new("€", "euro", "euros", "céntimo", "céntimos"),
+        Currency::new("$", "dólar", "dólares", "centavo", "centavos"),
+        Currency::new("£", "libra", "libras", "penique", "peniques"),
+    ],
+    months: [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    date: "{day} de {month} de {year}",
+    first_day: "primero",
+    titles: &[
+        ("Sr.", "señor"),
+        ("Sra.", "señora"),
+        ("Srta.", "señorita"),
+        ("Dr.", "doctor"),
+        ("Dra.", "doctora"),
+        ("Lic.", "licenciado"),
+    ],
+    abbreviations: &[
+        ("p. ej.", "por ejemplo"),
+        ("etc.", "etcétera"),
+        ("aprox.", "aproximadamente"),
+        ("pág.", "página"),
+        ("núm.", "número"),
+        ("n.º", "número"),
+        ("EE. UU.", "Estados Unidos"),
+        ("Ud.", "usted"),
+        ("Uds.", "ustedes"),
+    ],
+};
+
+const UNITS: [&str; 30] = [
+    "cero",
+    "uno",
+    "dos",
+    "tres",
+    "cuatro",
+    "cinco",
+    "seis",
+    "siete",
+    "ocho",
+    "nueve",
+    "diez",
+    "once",
+    "doce",
+    "trece",
+    "catorce",
+    "quince",
+    "dieciséis",
+    "diecisiete",
+    "dieciocho",
+    "diecinueve",
+    "veinte",
+    "veintiuno",
+    "veintidós",
+    "veintitrés",
+    "veinticuatro",
+    "veinticinco",
+    "veintiséis",
+    "veintisiete",
+    "veintiocho",
+    "veintinueve",
+];
+
+const TENS: [&str; 10] = [
+    "",
+    "",
+    "",
+    "treinta",
+    "cuarenta",
+    "cincuenta",
+    "sesenta",
+    "setenta",
+    "ochenta",
+    "noventa",
+];
+
+const HUNDREDS: [&str; 10] = [
+    "",
+    "ciento",
+    "doscientos",
+    "trescientos",
+    "cuatrocientos",
+    "quinientos",
+    "seiscientos",
+    "setecientos",
+    "ochocientos",
+    "novecientos",
+];
+
+fn below_thousand(n: u64) -> String {
+    let (hundreds, rest) = ((n / 100) as usize, (n % 100) as usize);
+    let rest = match rest {
+        0 => None,
+        1..=29 => Some(UNITS[rest].to_string()),
+        _ if rest.is_multiple_of(10) => Some(TENS[rest / 10].to_string()),
+        _ => Some(format!("{} y {}", TENS[rest / 10], UNITS[rest % 10])),
+    };
+    match (hundreds, rest) {
+        (0, Some(rest)) => rest,
+        (1, None) => "cien".to_string(),
+        (h, None) => HUNDREDS[h].to_string(),
+        (h, Some(rest)) => format!("{} {}", HUNDREDS[h], rest),
+    }
+}
+
+/// `uno` shortens to `un` before a noun, including `mil` and `millones`
+fn apocope(words: String) -> String {
+    if let Some(head) = words.strip_suffix("veintiuno") {
+        format!("{}veintiún", head)
+    } else if let Some(head) = words.strip_suffix("uno") {
+        format!("{}un", head)
+    } else {
+        words
+    }
+}
+
+fn cardinal(n: u64) -> String {
+    if n == 0 {
+        return UNITS[0].to_string();
+    }
+
+    let mut parts = Vec::new();
+    match n / 1_000_000 {
+        0 => {}
+        1 => parts.push("un millón".to_string()),
+        millions => parts.push(format!("{} millones", apocope(cardinal(millions)))),
+    }
+    match n / 1000 % 1000 {
+        0 => {}
+        1 => parts.push("mil".to_string()),
+        thousands => parts.push(format!("{} mil", apocope(below_thousand(thousands)))),
+    }
+    if !n.is_multiple_of(1000) {
+        parts.push(below_thousand(n % 1000));
+    }
+    parts.join(" ")
+}
+
+fn cardinal_before_noun(n: u64) -> String {
+    apocope(cardinal(n))
+}
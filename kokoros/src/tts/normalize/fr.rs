@@ -0,0 +1,130 @@
+use super::{Currency, LocaleRules};
+
+pub(super) static RULES: LocaleRules = LocaleRules {
+    cardinal,
+    cardinal_before_noun: cardinal,
+    decimal_point: "virgule",
+    percent: "pour cent",
+    and: "et",
+    currencies: &[
+        Currency::new("€", "euro", "euros", "centime", "centimes"),
+        Currency::new("$", "dollar", "dollars", "cent", "cents"),
+        Currency::new("£", "livre", "livres", "penny", "pence"),
+    ],
+    months: [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    date: "{day} {month} {year}",
+    first_day: "premier",
+    titles: &[
+        ("M.", "monsieur"),
+        ("MM.", "messieurs"),
+        ("Mme", "madame"),
+        ("Mmes", "mesdames"),
+        ("Mlle", "mademoiselle"),
+        ("Dr", "docteur"),
+        ("Pr", "professeur"),
+        ("Me", "maître"),
+    ],
+    abbreviations: &[
+        ("p. ex.", "par exemple"),
+        ("etc.", "et cetera"),
+        ("env.", "environ"),
+        ("n°", "numéro"),
+        ("av. J.-C.", "avant Jésus-Christ"),
+        ("apr. J.-C.", "après Jésus-Christ"),
+    ],
+};
+
+const UNITS: [&str; 17] = [
+    "zéro", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf", "dix", "onze",
+    "douze", "treize", "quatorze", "quinze", "seize",
+];
+
+/// 0..=99. `last` is false when a multiplier follows, which drops the
+/// plural `s` of `quatre-vingts`.
+fn below_hundred(n: u64, last: bool) -> String {
+    if n < 17 {
+        return UNITS[n as usize].to_string();
+    }
+    if n < 20 {
+        return format!("dix-{}", UNITS[n as usize - 10]);
+    }
+
+    // Seventies and nineties count on from sixty and eighty
+    let (tens, units) = match n {
+        60..=79 => (60, n - 60),
+        80..=99 => (80, n - 80),
+        _ => (n / 10 * 10, n % 10),
+    };
+    let tens_word = match tens {
+        20 => "vingt",
+        30 => "trente",
+        40 => "quarante",
+        50 => "cinquante",
+        60 => "soixante",
+        _ => "quatre-vingt",
+    };
+
+    match units {
+        0 if tens == 80 && last => "quatre-vingts".to_string(),
+        0 => tens_word.to_string(),
+        1 | 11 if tens != 80 => format!("{} et {}", tens_word, below_hundred(units, true)),
+        _ => format!("{}-{}", tens_word, below_hundred(units, true)),
+    }
+}
+
+fn below_thousand(n: u64, last: bool) -> String {
+    let (hundreds, rest) = (n / 100, n % 100);
+    let hundreds = match hundreds {
+        0 => None,
+        1 => Some("cent".to_string()),
+        h if rest == 0 && last => Some(format!("{} cents", UNITS[h as usize])),
+        h => Some(format!("{} cent", UNITS[h as usize])),
+    };
+    let rest = (rest > 0).then(|| below_hundred(rest, last));
+    match (hundreds, rest) {
+        (Some(h), Some(r)) => format!("{} {}", h, r),
+        (Some(h), None) => h,
+        (None, Some(r)) => r,
+        (None, None) => String::new(),
+    }
+}
+
+fn cardinal(n: u64) -> String {
+    if n == 0 {
+        return UNITS[0].to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (base, singular, plural) in [
+        (1_000_000_000, "un milliard", "milliards"),
+        (1_000_000, "un million", "millions"),
+    ] {
+        match n / base % 1000 {
+            0 => {}
+            1 => parts.push(singular.to_string()),
+            count => parts.push(format!("{} {}", below_thousand(count, true), plural)),
+        }
+    }
+    match n / 1000 % 1000 {
+        0 => {}
+        1 => parts.push("mille".to_string()),
+        thousands => parts.push(format!("{} mille", below_thousand(thousands, false))),
+    }
+    if !n.is_multiple_of(1000) {
+        parts.push(below_thousand(n % 1000, true));
+    }
+    parts.join(" ")
+}
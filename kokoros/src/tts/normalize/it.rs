@@ -0,0 +1,163 @@
+use super::{Currency, LocaleRules};
+
+pub(super) static RULES: LocaleRules = LocaleRules {
+    cardinal,
+    cardinal_before_noun,
+    decimal_point: "virgola",
+    percent: "per cento",
+    and: "e",
+    currencies: &[
+        Currency::new("€", "euro", "euro", "centesimo", "centesimi"),
+        Currency::new("$", "dollaro", "dollari", "centesimo", "centesimi"),
+        Currency::new("£", "sterlina", "sterline", "penny", "pence"),
+    ],
+    months: [
+        "gennaio",
+        "febbraio",
+        "marzo",
+        "aprile",
+        "maggio",
+        "giugno",
+        "luglio",
+        "agosto",
+        "settembre",
+        "ottobre",
+        "novembre",
+        "dicembre",
+    ],
+    date: "{day} {month} {year}",
+    first_day: "primo",
+    titles: &[
+        ("Sig.", "signor"),
+        ("Sig.ra", "signora"),
+        ("Sigg.", "signori"),
+        ("Dott.", "dottor"),
+        ("Dott.ssa", "dottoressa"),
+        ("Prof.", "professor"),
+        ("Prof.ssa", "professoressa"),
+        ("Ing.", "ingegner"),
+        ("Avv.", "avvocato"),
+    ],
+    abbreviations: &[
+        ("ecc.", "eccetera"),
+        ("es.", "esempio"),
+        ("pag.", "pagina"),
+        ("n.", "numero"),
+        ("ca.", "circa"),
+    ],
+};
+
+const UNITS: [&str; 20] = [
+    "zero",
+    "uno",
+    "due",
+    "tre",
+    "quattro",
+    "cinque",
+    "sei",
+    "sette",
+    "otto",
+    "nove",
+    "dieci",
+    "undici",
+    "dodici",
+    "tredici",
+    "quattordici",
+    "quindici",
+    "sedici",
+    "diciassette",
+    "diciotto",
+    "diciannove",
+];
+
+const TENS: [&str; 10] = [
+    "",
+    "",
+    "venti",
+    "trenta",
+    "quaranta",
+    "cinquanta",
+    "sessanta",
+    "settanta",
+    "ottanta",
+    "novanta",
+];
+
+fn below_hundred(n: u64) -> String {
+    let n = n as usize;
+    if n < 20 {
+        return UNITS[n].to_string();
+    }
+    let tens = TENS[n / 10];
+    match n % 10 {
+        0 => tens.to_string(),
+        // The tens drop their vowel before `uno` and `otto`
+        units @ (1 | 8) => format!("{}{}", &tens[..tens.len() - 1], UNITS[units]),
+        3 => format!("{}tré", tens),
+        units => format!("{}{}", tens, UNITS[units]),
+    }
+}
+
+fn below_thousand(n: u64) -> String {
+    let (hundreds, rest) = (n / 100, n % 100);
+    let mut out = match hundreds {
+        0 => String::new(),
+        1 => "cento".to_string(),
+        h => format!("{}cento", UNITS[h as usize]),
+    };
+    match rest {
+        0 => {}
+        3 if hundreds > 0 => out.push_str("tré"),
+        _ => {
+            // `cento` loses its vowel before `otto` and `ottanta`
+            if hundreds > 0 && (rest == 8 || rest / 10 == 8) {
+                out.pop();
+            }
+            out.push_str(&below_hundred(rest));
+        }
+    }
+    out
+}
+
+fn cardinal(n: u64) -> String {
+    if n == 0 {
+        return UNITS[0].to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (base, singular, plural) in [
+        (1_000_000_000, "un miliardo", "miliardi"),
+        (1_000_000, "un milione", "milioni"),
+    ] {
+        match n / base % 1000 {
+            0 => {}
+            1 => parts.push(singular.to_string()),
+            count => parts.push(format!("{} {}", below_thousand(count), plural)),
+        }
+    }
+    match n / 1000 % 1000 {
+        0 => {}
+        1 => parts.push("mille".to_string()),
+        thousands => {
+            let words = below_thousand(thousands);
+            // The accent of `tré` only falls on the last syllable
+            let words = match words.strip_suffix("tré") {
+                Some(head) => format!("{}tre", head),
+                None => words,
+            };
+            parts.push(format!("{}mila", words));
+        }
+    }
+    if !n.is_multiple_of(1000) {
+        parts.push(below_thousand(n % 1000));
+    }
+    parts.join(" ")
+}
+
+fn cardinal_before_noun(n: u64) -> String {
+    if n == 1 {
+        "un".to_string()
+    } else {
+        cardinal(n)
+    }
+}
@@ -0,0 +1,407 @@
+//! Language aware text normalization, run before phonemization.
+//!
+//! [`normalizer_for`] picks the normalizer for a language code as used by
+//! `-l` and the server's `get_language_code`. Spanish, French, Italian and
+//! Brazilian Portuguese share one rule driven normalizer that spells out
+//! numbers, amounts of money, percentages and day-month-year dates, and
+//! expands titles and common abbreviations. Mandarin reads numbers, prices,
+//! dates and times the Chinese way. English and the other languages are left
+//! to eSpeak; [`normalize_text`] is the misaki port used by the `Phonemizer`.
+
+mod en;
+mod es;
+mod fr;
+mod it;
+mod pt;
+
+pub use en::normalize_text;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use crate::tts::zh;
+
+/// Rewrites text into the words it should be read as.
+pub trait Normalizer: Send + Sync {
+    fn normalize(&self, text: &str) -> String;
+}
+
+/// Digits with `.` or no-break space thousand groups and a `,` decimal part,
+/// or plain digits with an optional `.`/`,` decimal part
+const NUMBER: &str = r"\d{1,3}(?:[.\x{A0}\x{202F}]\d{3})+(?:,\d+)?|\d+(?:[.,]\d+)?";
+
+lazy_static! {
+    static ref DATE_RE: Regex = Regex::new(r"\b(\d{1,2})[/.-](\d{1,2})[/.-](\d{4})\b").unwrap();
+    static ref MONEY_RE: Regex = Regex::new(&format!(
+        r"(R\$|[€$£])\s?({NUMBER})|({NUMBER})\s?(R\$|[€$£])"
+    ))
+    .unwrap();
+    static ref PERCENT_RE: Regex = Regex::new(&format!(r"({NUMBER})\s?%")).unwrap();
+    static ref NUMBER_RE: Regex = Regex::new(&format!(r"\b(?:{NUMBER})\b")).unwrap();
+    static ref ES: LocaleNormalizer = LocaleNormalizer::new(&es::RULES);
+    static ref FR: LocaleNormalizer = LocaleNormalizer::new(&fr::RULES);
+    static ref IT: LocaleNormalizer = LocaleNormalizer::new(&it::RULES);
+    static ref PT: LocaleNormalizer = LocaleNormalizer::new(&pt::RULES);
+}
+
+/// The normalizer for a language code, if the language has one
+pub fn normalizer_for(lan: &str) -> Option<&'static dyn Normalizer> {
+    if zh::is_mandarin(lan) {
+        return Some(&ZhNormalizer);
+    }
+    let normalizer: &'static LocaleNormalizer =
+        match lan.split(['-', '_']).next()?.to_ascii_lowercase().as_str() {
+            "es" => &ES,
+            "fr" => &FR,
+            "it" => &IT,
+            "pt" => &PT,
+            _ => return None,
+        };
+    Some(normalizer)
+}
+
+/// Normalize text for a language, unchanged if the language has no normalizer
+pub fn normalize_for_language(text: &str, lan: &str) -> String {
+    match normalizer_for(lan) {
+        Some(normalizer) => normalizer.normalize(text),
+        None => text.to_string(),
+    }
+}
+
+struct ZhNormalizer;
+
+impl Normalizer for ZhNormalizer {
+    fn normalize(&self, text: &str) -> String {
+        zh::normalize(text)
+    }
+}
+
+struct Currency {
+    symbol: &'static str,
+    singular: &'static str,
+    plural: &'static str,
+    cent: &'static str,
+    cents: &'static str,
+}
+
+impl Currency {
+    const fn new(
+        symbol: &'static str,
+        singular: &'static str,
+        plural: &'static str,
+        cent: &'static str,
+        cents: &'static str,
+    ) -> Self {
+        Self {
+            symbol,
+            singular,
+            plural,
+            cent,
+            cents,
+        }
+    }
+}
+
+/// Words and conventions of a language that writes `1.234,5` and
+/// day-month-year dates.
+struct LocaleRules {
+    /// Cardinal number words, for numbers below 10^12
+    cardinal: fn(u64) -> String,
+    /// Cardinal in front of a noun, e.g. Spanish `veintiún euros`
+    cardinal_before_noun: fn(u64) -> String,
+    decimal_point: &'static str,
+    percent: &'static str,
+    /// Joins the units and the cents of an amount
+    and: &'static str,
+    currencies: &'static [Currency],
+    months: [&'static str; 12],
+    /// Date with `{day}`, `{month}` and `{year}` placeholders
+    date: &'static str,
+    /// How the first day of a month is read
+    first_day: &'static str,
+    /// Expanded only in front of a capitalised name
+    titles: &'static [(&'static str, &'static str)],
+    /// Expanded anywhere, keeping the full stop at the end of a sentence
+    abbreviations: &'static [(&'static str, &'static str)],
+}
+
+struct LocaleNormalizer {
+    rules: &'static LocaleRules,
+    titles: Regex,
+    abbreviations: Regex,
+}
+
+/// Match any of the literal words, longest first
+fn alternation(words: &[(&str, &str)]) -> Regex {
+    let mut words: Vec<&str> = words.iter().map(|(word, _)| *word).collect();
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    let pattern = words
+        .iter()
+        .map(|word| {
+            let start = if word.starts_with(char::is_alphanumeric) {
+                r"\b"
+            } else {
+                ""
+            };
+            let end = if word.ends_with(char::is_alphanumeric) {
+                r"\b"
+            } else {
+                ""
+            };
+            format!("{}{}{}", start, regex::escape(word), end)
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    Regex::new(&pattern).unwrap()
+}
+
+fn expansion(words: &[(&str, &'static str)], word: &str) -> &'static str {
+    words
+        .iter()
+        .find(|(w, _)| *w == word)
+        .map_or("", |(_, expansion)| *expansion)
+}
+
+/// Whether whitespace and then a capital letter follow
+fn followed_by_capital(rest: &str) -> bool {
+    let trimmed = rest.trim_start();
+    trimmed.len() < rest.len() && trimmed.starts_with(char::is_uppercase)
+}
+
+/// Split an amount into its integer digits and its decimal digits. A `.`
+/// before groups of exactly three digits separates thousands, any other `.`
+/// is a decimal point.
+fn split_amount(amount: &str) -> (String, Option<String>) {
+    let is_group = |c: char| matches!(c, '.' | '\u{A0}' | '\u{202F}');
+    if let Some((integer, fraction)) = amount.rsplit_once(',') {
+        return (integer.replace(is_group, ""), Some(fraction.to_string()));
+    }
+    let groups: Vec<&str> = amount.split(is_group).collect();
+    if groups.len() > 1 && groups[1..].iter().all(|group| group.len() == 3) {
+        return (groups.concat(), None);
+    }
+    match amount.split_once('.') {
+        Some((integer, fraction)) => (integer.to_string(), Some(fraction.to_string())),
+        None => (amount.to_string(), None),
+    }
+}
+
+impl LocaleNormalizer {
+    fn new(rules: &'static LocaleRules) -> Self {
+        Self {
+            rules,
+            titles: alternation(rules.titles),
+            abbreviations: alternation(rules.abbreviations),
+        }
+    }
+
+    /// Read digits as a number; leading zeros and huge numbers are read digit
+    /// by digit
+    fn read_integer(&self, digits: &str, before_noun: bool) -> String {
+        let cardinal = if before_noun {
+            self.rules.cardinal_before_noun
+        } else {
+            self.rules.cardinal
+        };
+        match digits.parse::<u64>() {
+            Ok(n) if n < 1_000_000_000_000 && !(digits.len() > 1 && digits.starts_with('0')) => {
+                cardinal(n)
+            }
+            _ => self.read_digits(digits),
+        }
+    }
+
+    fn read_digits(&self, digits: &str) -> String {
+        digits
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| (self.rules.cardinal)(d as u64))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn read_number(&self, amount: &str) -> String {
+        let (integer, fraction) = split_amount(amount);
+        let mut out = self.read_integer(&integer, false);
+        if let Some(fraction) = fraction {
+            let fraction = if fraction.len() > 3 {
+                self.read_digits(&fraction)
+            } else {
+                self.read_integer(&fraction, false)
+            };
+            out = format!("{} {} {}", out, self.rules.decimal_point, fraction);
+        }
+        out
+    }
+
+    fn read_money(&self, currency: &Currency, amount: &str) -> String {
+        let (integer, fraction) = split_amount(amount);
+        let cents = match fraction.as_deref() {
+            None => 0,
+            Some(f) if f.len() <= 2 => format!("{:0<2}", f).parse::<u64>().unwrap_or(0),
+            // Not a price with cents, read the number and name the currency
+            Some(_) => return format!("{} {}", self.read_number(amount), currency.plural),
+        };
+        let units = integer.parse::<u64>().unwrap_or(u64::MAX);
+
+        let mut parts = Vec::new();
+        if units > 0 || cents == 0 {
+            let noun = if units == 1 {
+                currency.singular
+            } else {
+                currency.plural
+            };
+            parts.push(format!("{} {}", self.read_integer(&integer, true), noun));
+        }
+        if cents > 0 {
+            let noun = if cents == 1 {
+                currency.cent
+            } else {
+                currency.cents
+            };
+            parts.push(format!(
+                "{} {}",
+                (self.rules.cardinal_before_noun)(cents),
+                noun
+            ));
+        }
+        parts.join(&format!(" {} ", self.rules.and))
+    }
+
+    fn read_date(&self, day: &str, month: &str, year: &str) -> Option<String> {
+        let day: u64 = day.parse().ok()?;
+        let month: usize = month.parse().ok()?;
+        if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+            return None;
+        }
+        let day = if day == 1 {
+            self.rules.first_day.to_string()
+        } else {
+            (self.rules.cardinal)(day)
+        };
+        Some(
+            self.rules
+                .date
+                .replace("{day}", &day)
+                .replace("{month}", self.rules.months[month - 1])
+                .replace("{year}", &self.read_integer(year, false)),
+        )
+    }
+}
+
+impl Normalizer for LocaleNormalizer {
+    fn normalize(&self, text: &str) -> String {
+        let rules = self.rules;
+
+        let text = self.titles.replace_all(text, |caps: &Captures| {
+            let m = caps.get(0).unwrap();
+            if followed_by_capital(&text[m.end()..]) {
+                expansion(rules.titles, m.as_str()).to_string()
+            } else {
+                m.as_str().to_string()
+            }
+        });
+
+        let text = self.abbreviations.replace_all(&text, |caps: &Captures| {
+            let m = caps.get(0).unwrap();
+            let rest = &text[m.end()..];
+            let word = expansion(rules.abbreviations, m.as_str());
+            if m.as_str().ends_with('.') && (rest.trim().is_empty() || followed_by_capital(rest)) {
+                format!("{}.", word)
+            } else {
+                word.to_string()
+            }
+        });
+
+        let text = DATE_RE.replace_all(&text, |caps: &Captures| {
+            self.read_date(&caps[1], &caps[2], &caps[3])
+                .unwrap_or_else(|| caps[0].to_string())
+        });
+
+        let text = MONEY_RE.replace_all(&text, |caps: &Captures| {
+            let (symbol, amount) = match (caps.get(1), caps.get(2)) {
+                (Some(symbol), Some(amount)) => (symbol.as_str(), amount.as_str()),
+                _ => (&caps[4], &caps[3]),
+            };
+            match rules.currencies.iter().find(|c| c.symbol == symbol) {
+                Some(currency) => self.read_money(currency, amount),
+                None => caps[0].to_string(),
+            }
+        });
+
+        let text = PERCENT_RE.replace_all(&text, |caps: &Captures| {
+            format!("{} {}", self.read_number(&caps[1]), rules.percent)
+        });
+
+        NUMBER_RE
+            .replace_all(&text, |caps: &Captures| {
+                let m = caps.get(0).unwrap();
+                // Leave version numbers, codes and the like to eSpeak
+                let before = text[..m.start()].chars().next_back();
+                let mut after = text[m.end()..].chars();
+                let chained = matches!(after.next(), Some('.' | ',' | ':'))
+                    && after.next().is_some_and(|c| c.is_ascii_digit());
+                if chained || before.is_some_and(|c| c.is_alphanumeric() || c == '.') {
+                    m.as_str().to_string()
+                } else {
+                    self.read_number(m.as_str())
+                }
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cardinals() {
+        let cases: [(&LocaleRules, u64, &str); 12] = [
+            (&es::RULES, 21, "veintiuno"),
+            (&es::RULES, 1_121_000, "un millón ciento veintiún mil"),
+            (&es::RULES, 500, "quinientos"),
+            (&fr::RULES, 71, "soixante et onze"),
+            (&fr::RULES, 80_080, "quatre-vingt mille quatre-vingts"),
+            (&fr::RULES, 200, "deux cents"),
+            (&it::RULES, 23_188, "ventitremila centottantotto"),
+            (&it::RULES, 1_000_001, "un milione uno"),
+            (&pt::RULES, 1_100, "mil e cem"),
+            (&pt::RULES, 1_234, "mil duzentos e trinta e quatro"),
+            (&pt::RULES, 2_000_020, "dois milhões e vinte"),
+            (&pt::RULES, 100, "cem"),
+        ];
+        for (rules, n, words) in cases {
+            assert_eq!((rules.cardinal)(n), words, "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_normalize_for_language() {
+        assert_eq!(
+            normalize_for_language("El Sr. García pagó 21,50 € el 1/5/2024.", "es"),
+            "El señor García pagó veintiún euros con cincuenta céntimos el primero de mayo de dos mil veinticuatro."
+        );
+        // Pronouns, not titles, so read before lower case words too
+        assert_eq!(
+            normalize_for_language("¿Ud. sabe dónde están Uds.?", "es"),
+            "¿usted sabe dónde están ustedes?"
+        );
+        assert_eq!(
+            normalize_for_language("Il reste 3,5 % de batterie, etc.", "fr-fr"),
+            "Il reste trois virgule cinq pour cent de batterie, et cetera."
+        );
+        assert_eq!(
+            normalize_for_language("Costa $1.250 per 2 persone.", "it"),
+            "Costa mille duecentocinquanta dollari per due persone."
+        );
+        assert_eq!(
+            normalize_for_language("Custa R$ 10,05 na versão 2.1.3.", "pt-br"),
+            "Custa dez reais e cinco centavos na versão 2.1.3."
+        );
+        assert_eq!(
+            normalize_for_language("It costs $5.", "en-us"),
+            "It costs $5."
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use super::{Currency, LocaleRules};
+
+pub(super) static RULES: LocaleRules = LocaleRules {
+    cardinal,
+    cardinal_before_noun: cardinal,
+    decimal_point: "vírgula",
+    percent: "por cento",
+    and: "e",
+    currencies: &[
+        Currency::new("R$", "real", "reais", "centavo", "centavos"),
+        Currency::new("€", "euro", "euros", "cêntimo", "cêntimos"),
+        Currency::new("$", "dólar", "dólares", "centavo", "centavos"),
+    ],
+    months: [
+        "janeiro",
+        "fevereiro",
+        "março",
+        "abril",
+        "maio",
+        "junho",
+        "julho",
+        "agosto",
+        "setembro",
+        "outubro",
+        "novembro",
+        "dezembro",
+    ],
+    date: "{day} de {month} de {year}",
+    first_day: "primeiro",
+    titles: &[
+        ("Sr.", "senhor"),
+        ("Sra.", "senhora"),
+        ("Srta.", "senhorita"),
+        ("Dr.", "doutor"),
+        ("Dra.", "doutora"),
+        ("Prof.", "professor"),
+        ("Profa.", "professora"),
+    ],
+    abbreviations: &[
+        ("p. ex.", "por exemplo"),
+        ("etc.", "et cetera"),
+        ("pág.", "página"),
+        ("nº", "número"),
+        ("aprox.", "aproximadamente"),
+    ],
+};
+
+const UNITS: [&str; 20] = [
+    "zero",
+    "um",
+    "dois",
+    "três",
+    "quatro",
+    "cinco",
+    "seis",
+    "sete",
+    "oito",
+    "nove",
+    "dez",
+    "onze",
+    "doze",
+    "treze",
+    "catorze",
+    "quinze",
+    "dezesseis",
+    "dezessete",
+    "dezoito",
+    "dezenove",
+];
+
+const TENS: [&str; 10] = [
+    "",
+    "",
+    "vinte",
+    "trinta",
+    "quarenta",
+    "cinquenta",
+    "sessenta",
+    "setenta",
+    "oitenta",
+    "noventa",
+];
+
+const HUNDREDS: [&str; 10] = [
+    "",
+    "cento",
+    "duzentos",
+    "trezentos",
+    "quatrocentos",
+    "quinhentos",
+    "seiscentos",
+    "setecentos",
+    "oitocentos",
+    "novecentos",
+];
+
+fn below_hundred(n: u64) -> String {
+    let n = n as usize;
+    match n {
+        0..=19 => UNITS[n].to_string(),
+        _ if n.is_multiple_of(10) => TENS[n / 10].to_string(),
+        _ => format!("{} e {}", TENS[n / 10], UNITS[n % 10]),
+    }
+}
+
+fn below_thousand(n: u64) -> String {
+    let (hundreds, rest) = ((n / 100) as usize, n % 100);
+    match (hundreds, rest) {
+        (0, _) => below_hundred(rest),
+        (1, 0) => "cem".to_string(),
+        (h, 0) => HUNDREDS[h].to_string(),
+        (h, _) => format!("{} e {}", HUNDREDS[h], below_hundred(rest)),
+    }
+}
+
+fn cardinal(n: u64) -> String {
+    if n == 0 {
+        return UNITS[0].to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (base, singular, plural) in [
+        (1_000_000_000, "um bilhão", "bilhões"),
+        (1_000_000, "um milhão", "milhões"),
+    ] {
+        match n / base % 1000 {
+            0 => {}
+            1 => parts.push(singular.to_string()),
+            count => parts.push(format!("{} {}", below_thousand(count), plural)),
+        }
+    }
+    match n / 1000 % 1000 {
+        0 => {}
+        1 => parts.push("mil".to_string()),
+        thousands => parts.push(format!("{} mil", below_thousand(thousands))),
+    }
+
+    let rest = n % 1000;
+    if rest == 0 {
+        return parts.join(" ");
+    }
+    let rest_words = below_thousand(rest);
+    if parts.is_empty() {
+        return rest_words;
+    }
+    // "mil e cem", "dois mil e vinte", but "mil duzentos e trinta"
+    let joiner = if rest < 100 || rest.is_multiple_of(100) {
+        " e "
+    } else {
+        " "
+    };
+    format!("{}{}{}", parts.join(" "), joiner, rest_words)
+}
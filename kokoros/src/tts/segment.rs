@@ -0,0 +1,550 @@
+//! Sentence and clause segmentation shared by every chunker.
+//!
+//! Sentences end at `.`, `!`, `?` and `…` followed by whitespace, at the CJK
+//! full stops `。！？` regardless of what follows, and at blank lines. A period
+//! does not end a sentence after a title or common abbreviation ("Dr.",
+//! "e.g."), a single capital initial, or a list number at the start of a
+//! sentence; no terminal ends one when the next word starts in lowercase
+//! ("Wait... what?"). Decimals, URLs and file names never split because their
+//! dots are not followed by whitespace. Segments are slices of the input, so
+//! terminal punctuation and closing quotes are kept for prosody.
+//!
+//! [`speech_chunks`] groups them into the short chunks a stream speaks one
+//! at a time, breaking at clauses and before list items as well.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// A list number: `1.`, `2)`, `3:`, `(4)`, `(5),`
+    static ref NUMBERED_RE: Regex = Regex::new(r"^\(?[0-9]+[.\):],?$").unwrap();
+}
+
+/// Lowercased words that a period never ends a sentence after
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "mx", "dr", "prof", "sr", "jr", "st", "mt", "rev", "hon", "gen", "col",
+    "capt", "lt", "sgt", "vs", "e.g", "i.e", "cf", "approx", "fig", "vol",
+];
+
+fn is_terminal(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '‽') || is_cjk_terminal(c)
+}
+
+fn is_cjk_terminal(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '｡')
+}
+
+/// Closing quotes and brackets that stay with the sentence they end
+fn is_closing(c: char) -> bool {
+    matches!(
+        c,
+        '"' | '\'' | '”' | '’' | '»' | ')' | ']' | '」' | '』' | '）' | '】' | '》'
+    )
+}
+
+fn is_opening(c: char) -> bool {
+    matches!(
+        c,
+        '"' | '\'' | '“' | '‘' | '«' | '(' | '[' | '「' | '『' | '（' | '【' | '《'
+    )
+}
+
+/// Split text into trimmed sentences, keeping their punctuation.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(text.len(), |(b, _)| *b);
+
+    let mut sentences = Vec::new();
+    let mut push = |start: usize, end: usize| {
+        let sentence = text[start..end].trim();
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+    };
+
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+
+        if c == '\n' {
+            let mut j = i + 1;
+            let mut newlines = 1;
+            while j < chars.len() && chars[j].1.is_whitespace() {
+                newlines += usize::from(chars[j].1 == '\n');
+                j += 1;
+            }
+            if newlines >= 2 {
+                push(start, byte_at(i));
+                start = byte_at(j);
+            }
+            i = j;
+            continue;
+        }
+
+        if !is_terminal(c) {
+            i += 1;
+            continue;
+        }
+
+        let mut run_end = i;
+        while run_end < chars.len() && is_terminal(chars[run_end].1) {
+            run_end += 1;
+        }
+        let mut end = run_end;
+        while end < chars.len() && is_closing(chars[end].1) {
+            end += 1;
+        }
+        let cjk = chars[i..run_end].iter().any(|(_, c)| is_cjk_terminal(*c));
+
+        let boundary = cjk || {
+            let followed_by_space = chars.get(end).is_none_or(|(_, c)| c.is_whitespace());
+            let single_period = c == '.' && run_end == i + 1;
+            followed_by_space
+                && !next_word_is_lowercase(&chars[end..])
+                && !(single_period
+                    && is_abbreviation(&text[start..byte_at(i)], &text[byte_at(end)..]))
+        };
+
+        if boundary {
+            push(start, byte_at(end));
+            start = byte_at(end);
+        }
+        i = end;
+    }
+    push(start, text.len());
+
+    sentences
+}
+
+/// Sentences of `text` as they are spoken. Their punctuation shapes the
+/// prosody, so unterminated ones (headings, the last line) get a full stop,
+/// except the last one when `close` is false, for text that goes on in
+/// whatever is spoken next.
+pub fn spoken_sentences(text: &str, close: bool) -> Vec<String> {
+    let sentences = split_sentences(text);
+    let count = sentences.len();
+    sentences
+        .into_iter()
+        .enumerate()
+        .map(|(i, sentence)| {
+            if has_terminal(sentence) || (!close && i + 1 == count) {
+                sentence.to_string()
+            } else {
+                format!("{}.", sentence)
+            }
+        })
+        .collect()
+}
+
+/// Split a sentence after `,`, `;`, `:` and their CJK forms, keeping the
+/// punctuation. ASCII marks only split when followed by whitespace, so
+/// "1,000" and "12:30" stay whole.
+pub fn split_clauses(sentence: &str) -> Vec<&str> {
+    let mut clauses = Vec::new();
+    let mut start = 0;
+    let mut chars = sentence.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let split = match c {
+            ',' | ';' | ':' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            '，' | '；' | '：' | '、' => true,
+            _ => false,
+        };
+        if split {
+            let end = i + c.len_utf8();
+            let clause = sentence[start..end].trim();
+            if !clause.is_empty() {
+                clauses.push(clause);
+            }
+            start = end;
+        }
+    }
+    let rest = sentence[start..].trim();
+    if !rest.is_empty() {
+        clauses.push(rest);
+    }
+
+    clauses
+}
+
+/// Whether a sentence already ends in terminal punctuation, ignoring closing
+/// quotes and brackets
+pub fn has_terminal(sentence: &str) -> bool {
+    sentence
+        .trim_end()
+        .chars()
+        .rev()
+        .find(|c| !is_closing(*c))
+        .is_some_and(is_terminal)
+}
+
+fn next_word_is_lowercase(rest: &[(usize, char)]) -> bool {
+    rest.iter()
+        .map(|(_, c)| *c)
+        .find(|c| !c.is_whitespace() && !is_opening(*c))
+        .is_some_and(char::is_lowercase)
+}
+
+/// Whether the last word of `sentence`, which a single period follows, is an
+/// abbreviation, an initial or a leading list number. A lone capital is only
+/// an initial within a name, next to another initial or after a capitalised
+/// word, so "It was I. Then" still ends a sentence.
+fn is_abbreviation(sentence: &str, rest: &str) -> bool {
+    let sentence = sentence.trim();
+    let word_start = sentence
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let word = sentence[word_start..].trim_start_matches(is_opening);
+
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => false,
+        (Some(c), None) if c.is_uppercase() => {
+            let previous = sentence[..word_start].split_whitespace().next_back();
+            rest.split_whitespace().next().is_some_and(is_initial)
+                || previous.is_some_and(|previous| {
+                    is_initial(previous)
+                        || previous
+                            .trim_start_matches(is_opening)
+                            .starts_with(char::is_uppercase)
+                })
+        }
+        _ if word.chars().all(|c| c.is_ascii_digit()) => word_start == 0,
+        _ => ABBREVIATIONS.contains(&word.to_lowercase().as_str()),
+    }
+}
+
+/// Whether `word` is a capital letter and a period, like the "R." of "J. R. Smith"
+fn is_initial(word: &str) -> bool {
+    let mut chars = word.trim_start_matches(is_opening).chars();
+    matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some(c), Some('.'), None) if c.is_uppercase()
+    )
+}
+
+/// Break words used for chunk splitting
+const BREAK_WORDS: &[&str] = &[
+    "and", "or", "but", "&", "because", "if", "since", "though", "although", "however", "which",
+];
+
+/// Split text into speech chunks for streaming
+///
+/// Prioritizes sentence boundaries over word count for natural speech breaks
+/// Then applies center-break word splitting for long chunks
+pub fn speech_chunks(text: &str, words_per_chunk: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current_chunk = String::new();
+    let mut word_count = 0;
+
+    // First pass: split by sentence, then by punctuation within a sentence
+    for sentence in split_sentences(text) {
+        for word in sentence.split_whitespace() {
+            if !current_chunk.is_empty() {
+                current_chunk.push(' ');
+            }
+            // A list number starts a chunk and stays with its item
+            let is_numbered_break = is_numbered_list_item(word);
+
+            if is_numbered_break && !current_chunk.is_empty() {
+                chunks.push(current_chunk.trim().to_string());
+                current_chunk.clear();
+                word_count = 0;
+            }
+            current_chunk.push_str(word);
+            word_count += 1;
+
+            // Check for unconditional breaks (always break regardless of word count)
+            let ends_with_unconditional =
+                !is_numbered_break && (word.ends_with(':') || word.ends_with(';'));
+
+            // Check for conditional breaks (commas - only break if enough words)
+            let ends_with_conditional = word.ends_with(',');
+
+            // Split conditions:
+            // 1. Unconditional punctuation - always break
+            // 2. Conditional punctuation + target word count reached
+            if ends_with_unconditional
+                || (ends_with_conditional && !is_numbered_break && word_count >= words_per_chunk)
+            {
+                chunks.push(current_chunk.trim().to_string());
+                current_chunk.clear();
+                word_count = 0;
+            }
+        }
+
+        // Sentence end - always break
+        if !current_chunk.trim().is_empty() {
+            chunks.push(current_chunk.trim().to_string());
+        }
+        current_chunk.clear();
+        word_count = 0;
+    }
+
+    // Second pass: apply center-break splitting for long chunks
+    // All chunks: ≥12 words
+    // First 2 chunks: punctuation priority, Others: break words only
+    let mut final_chunks = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let threshold = 12;
+        let use_punctuation = index < 2; // First 2 chunks can use punctuation
+        let split_chunks = split_long_chunk_with_depth(chunk, threshold, use_punctuation, 0);
+        final_chunks.extend(split_chunks);
+    }
+
+    // Final processing: Move break words from end of chunks to beginning of next chunk
+    for i in 0..final_chunks.len().saturating_sub(1) {
+        let current_chunk = &final_chunks[i];
+        let words: Vec<&str> = current_chunk.split_whitespace().collect();
+
+        if let Some(last_word) = words.last() {
+            // Check if last word is a break word (case insensitive)
+            if BREAK_WORDS.contains(&last_word.to_lowercase().as_str()) && words.len() > 1 {
+                // Only move if it won't create an empty chunk (need more than 1 word)
+                let new_current = words[..words.len() - 1].join(" ");
+
+                // Add break word to beginning of next chunk
+                let next_chunk = &final_chunks[i + 1];
+                let new_next = format!("{} {}", last_word, next_chunk);
+
+                // Update the chunks
+                final_chunks[i] = new_current;
+                final_chunks[i + 1] = new_next;
+            }
+        }
+    }
+
+    // After all processing, there is no explicit filter to remove empty chunks.
+    // If any empty string slipped through (e.g., from .trim().to_string() on
+    // whitespace-only current_chunk, or from split_long_chunk), it would remain.
+    // Dont consider filtering out empty chunks here, to enable catching potential bugs
+    // in the chunking logic.
+    final_chunks
+}
+
+/// Check if a word is a numbered list item: 1. 2) 3: (4), 5(\s)[.\)\:]
+fn is_numbered_list_item(word: &str) -> bool {
+    NUMBERED_RE.is_match(word)
+}
+
+fn split_long_chunk_with_depth(
+    chunk: &str,
+    threshold: usize,
+    use_punctuation: bool,
+    depth: usize,
+) -> Vec<String> {
+    // Prevent infinite recursion
+    if depth >= 3 {
+        return vec![chunk.to_string()];
+    }
+    let words: Vec<&str> = chunk.split_whitespace().collect();
+    let word_count = words.len();
+
+    // Only split if chunk meets the threshold
+    if word_count < threshold {
+        return vec![chunk.to_string()];
+    }
+
+    let center = word_count / 2;
+
+    if use_punctuation {
+        // Priority 1: Search for commas closest to center
+        if let Some(pos) = find_closest_punctuation(&words, center, &[","])
+            && pos >= 3
+            && pos < words.len()
+        {
+            let first_chunk = words[..pos].join(" ");
+            let second_chunk = words[pos..].join(" ");
+
+            // Recursively split both chunks if they're still too long
+            let mut result = Vec::new();
+            result.extend(split_long_chunk_with_depth(
+                &first_chunk,
+                threshold,
+                use_punctuation,
+                depth + 1,
+            ));
+            result.extend(split_long_chunk_with_depth(
+                &second_chunk,
+                threshold,
+                use_punctuation,
+                depth + 1,
+            ));
+            return result;
+        }
+    }
+
+    // Priority 2: Search for break words closest to center
+    if let Some(pos) = find_closest_break_word(&words, center, BREAK_WORDS)
+        && pos >= 3
+        && pos < words.len()
+    {
+        let first_chunk = words[..pos].join(" ");
+        let second_chunk = words[pos..].join(" ");
+
+        // Recursively split both chunks if they're still too long
+        let mut result = Vec::new();
+        result.extend(split_long_chunk_with_depth(
+            &first_chunk,
+            threshold,
+            use_punctuation,
+            depth + 1,
+        ));
+        result.extend(split_long_chunk_with_depth(
+            &second_chunk,
+            threshold,
+            use_punctuation,
+            depth + 1,
+        ));
+        return result;
+    }
+
+    // No suitable break point found, return original chunk
+    vec![chunk.to_string()]
+}
+
+/// Find closest punctuation to center
+fn find_closest_punctuation(words: &[&str], center: usize, punctuation: &[&str]) -> Option<usize> {
+    let mut closest_pos = None;
+    let mut min_distance = usize::MAX;
+
+    for (i, word) in words.iter().enumerate() {
+        if punctuation.iter().any(|p| word.ends_with(p)) {
+            let distance = center.abs_diff(i);
+            if distance < min_distance {
+                min_distance = distance;
+                closest_pos = Some(i + 1); // Split after the punctuation
+            }
+        }
+    }
+
+    closest_pos
+}
+
+/// Find closest break word to center
+fn find_closest_break_word(words: &[&str], center: usize, break_words: &[&str]) -> Option<usize> {
+    let mut closest_pos = None;
+    let mut min_distance = usize::MAX;
+
+    for (i, word) in words.iter().enumerate() {
+        if break_words.contains(&word.to_lowercase().as_str()) {
+            let distance = center.abs_diff(i);
+            if distance < min_distance {
+                min_distance = distance;
+                closest_pos = Some(i); // Break word becomes first word of second chunk
+            }
+        }
+    }
+
+    closest_pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            split_sentences("Dr. Smith paid $3.50 at example.com. Was it worth it? Yes!"),
+            vec![
+                "Dr. Smith paid $3.50 at example.com.",
+                "Was it worth it?",
+                "Yes!"
+            ]
+        );
+        assert_eq!(
+            split_sentences("Bring fruit, e.g. apples. \"Why?\" she asked. Fine..."),
+            vec![
+                "Bring fruit, e.g. apples.",
+                "\"Why?\" she asked.",
+                "Fine..."
+            ]
+        );
+        assert_eq!(
+            split_sentences("Wait... what? J. R. R. Tolkien wrote it."),
+            vec!["Wait... what?", "J. R. R. Tolkien wrote it."]
+        );
+        assert_eq!(
+            split_sentences("It was I. Then he left. Take vitamin C. It helps."),
+            vec!["It was I.", "Then he left.", "Take vitamin C.", "It helps."]
+        );
+        assert_eq!(
+            split_sentences("Ask John F. Kennedy. He knows."),
+            vec!["Ask John F. Kennedy.", "He knows."]
+        );
+        assert_eq!(
+            split_sentences("1. Buy milk. I have 3. You have 4."),
+            vec!["1. Buy milk.", "I have 3.", "You have 4."]
+        );
+        assert_eq!(
+            split_sentences("你好。今天天气很好！「真的吗？」是的"),
+            vec!["你好。", "今天天气很好！", "「真的吗？」", "是的"]
+        );
+        assert_eq!(
+            split_sentences("Chapter One\n\nIt begins"),
+            vec!["Chapter One", "It begins"]
+        );
+        assert!(split_sentences("  ").is_empty());
+    }
+
+    #[test]
+    fn test_split_clauses() {
+        assert_eq!(
+            split_clauses("At 12:30, we paid 1,000 dollars; then left."),
+            vec!["At 12:30,", "we paid 1,000 dollars;", "then left."]
+        );
+        assert_eq!(split_clauses("我们，他们"), vec!["我们，", "他们"]);
+    }
+
+    #[test]
+    fn test_spoken_sentences() {
+        assert_eq!(
+            spoken_sentences("A heading\n\nThe end", true),
+            ["A heading.", "The end."]
+        );
+        // What emphasis splits off mid-sentence stays open
+        for (i, segment) in ["I said", "never", "again"].into_iter().enumerate() {
+            let close = i == 2;
+            let expected = if close { "again." } else { segment };
+            assert_eq!(spoken_sentences(segment, close), [expected]);
+        }
+        assert_eq!(
+            spoken_sentences("Stop. Then I said", false),
+            ["Stop.", "Then I said"]
+        );
+    }
+
+    #[test]
+    fn test_speech_chunks() {
+        assert_eq!(
+            speech_chunks("Note: bring these. 1. apples 2. pears", 10),
+            ["Note:", "bring these.", "1. apples", "2. pears"]
+        );
+        assert_eq!(
+            speech_chunks("Steps: 1: mix, 2) bake (3) serve", 10),
+            ["Steps:", "1: mix,", "2) bake", "(3) serve"]
+        );
+        assert_eq!(
+            speech_chunks(
+                "We walked along the river for hours and the sun went down slowly behind us.",
+                10
+            ),
+            [
+                "We walked along the river for hours",
+                "and the sun went down slowly behind us."
+            ]
+        );
+        assert!(speech_chunks("", 10).is_empty());
+    }
+
+    #[test]
+    fn test_has_terminal() {
+        assert!(has_terminal("Really?\""));
+        assert!(has_terminal("好。"));
+        assert!(!has_terminal("Chapter One"));
+    }
+}
@@ -0,0 +1,530 @@
+//! Read-aloud rules for text that is not plain prose.
+//!
+//! LLM output is full of URLs, e-mail addresses, file paths, hashtags,
+//! emoji, inline code and `snake_case` identifiers, which eSpeak reads as
+//! symbol recitals. [`read_aloud`] finds these semiotic classes and rewrites
+//! each one according to its [`ReadPolicy`]:
+//!
+//! | class      | `spell`                        | `short`               |
+//! |------------|--------------------------------|-----------------------|
+//! | `urls`     | host and path, `dot`, `slash`  | the domain only       |
+//! | `emails`   | `name at example dot com`      | the domain only       |
+//! | `paths`    | every directory and the file   | the file name only    |
+//! | `hashtags` | `hashtag` and the words        | the words only        |
+//! | `emoji`    | the emoji's name               | the emoji's name      |
+//! | `code`     | identifiers split into words   | as `spell`, without fenced blocks |
+//!
+//! `keep` leaves the class to eSpeak and `skip` drops it. Symbol words are
+//! translated for es, fr, it and pt; emoji names are English.
+
+use std::fmt;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    static ref FENCE_RE: Regex = Regex::new(r"(?s)```[^\n]*\n?(.*?)```").unwrap();
+    static ref INLINE_CODE_RE: Regex = Regex::new(r"`([^`\n]+)`").unwrap();
+    static ref URL_RE: Regex = Regex::new(r"\b(?:https?://|www\.)[^\s<>()\[\]{}]+").unwrap();
+    static ref EMAIL_RE: Regex =
+        Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)+\b").unwrap();
+    static ref PATH_RE: Regex = Regex::new(
+        r"(?:~|\.{1,2})?/[\w.-]+(?:/[\w.-]+)*/?|\b[A-Za-z]:\\[\w.\\-]+|\b[\w-]+(?:/[\w.-]+)+\.[A-Za-z0-9]{1,5}\b"
+    )
+    .unwrap();
+    static ref HASHTAG_RE: Regex = Regex::new(r"#([A-Za-z][A-Za-z0-9_]*)").unwrap();
+    static ref IDENTIFIER_RE: Regex =
+        Regex::new(r"\b(?:[A-Za-z][A-Za-z0-9]*(?:_+[A-Za-z0-9]+)+|[a-z]+[0-9]*(?:[A-Z][a-z0-9]+)+)\b")
+            .unwrap();
+    static ref SPACES_RE: Regex = Regex::new(r"[ \t]{2,}").unwrap();
+}
+
+/// How a semiotic class is read aloud.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadPolicy {
+    /// Leave the text as it is
+    Keep,
+    /// Read out every part
+    Spell,
+    /// Read a shortened form, such as the domain of a URL
+    Short,
+    /// Drop it
+    Skip,
+}
+
+impl ReadPolicy {
+    pub const ALL: [ReadPolicy; 4] = [
+        ReadPolicy::Keep,
+        ReadPolicy::Spell,
+        ReadPolicy::Short,
+        ReadPolicy::Skip,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadPolicy::Keep => "keep",
+            ReadPolicy::Spell => "spell",
+            ReadPolicy::Short => "short",
+            ReadPolicy::Skip => "skip",
+        }
+    }
+}
+
+impl fmt::Display for ReadPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReadPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(|p| p.as_str()).collect();
+                format!(
+                    "unknown read-aloud policy '{}' (expected one of: {})",
+                    s.trim(),
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// The policy for every semiotic class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadAloudOptions {
+    pub urls: ReadPolicy,
+    pub emails: ReadPolicy,
+    pub paths: ReadPolicy,
+    pub hashtags: ReadPolicy,
+    pub emoji: ReadPolicy,
+    pub code: ReadPolicy,
+}
+
+impl Default for ReadAloudOptions {
+    fn default() -> Self {
+        Self {
+            urls: ReadPolicy::Short,
+            emails: ReadPolicy::Spell,
+            paths: ReadPolicy::Spell,
+            hashtags: ReadPolicy::Spell,
+            emoji: ReadPolicy::Skip,
+            code: ReadPolicy::Spell,
+        }
+    }
+}
+
+impl ReadAloudOptions {
+    pub const CLASSES: [&'static str; 6] = ["urls", "emails", "paths", "hashtags", "emoji", "code"];
+
+    /// Options that leave all text to eSpeak
+    pub fn keep_all() -> Self {
+        Self {
+            urls: ReadPolicy::Keep,
+            emails: ReadPolicy::Keep,
+            paths: ReadPolicy::Keep,
+            hashtags: ReadPolicy::Keep,
+            emoji: ReadPolicy::Keep,
+            code: ReadPolicy::Keep,
+        }
+    }
+
+    /// Set the policy of a class by name; `all` sets every class.
+    pub fn set(&mut self, class: &str, policy: ReadPolicy) -> Result<(), String> {
+        match class.trim().to_lowercase().as_str() {
+            "urls" | "url" => self.urls = policy,
+            "emails" | "email" => self.emails = policy,
+            "paths" | "path" => self.paths = policy,
+            "hashtags" | "hashtag" => self.hashtags = policy,
+            "emoji" | "emojis" => self.emoji = policy,
+            "code" => self.code = policy,
+            "all" => {
+                *self = Self {
+                    urls: policy,
+                    emails: policy,
+                    paths: policy,
+                    hashtags: policy,
+                    emoji: policy,
+                    code: policy,
+                }
+            }
+            other => {
+                return Err(format!(
+                    "unknown read-aloud class '{}' (expected one of: all, {})",
+                    other,
+                    Self::CLASSES.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse `CLASS=POLICY` pairs such as `urls=spell`, on top of the defaults.
+    pub fn from_pairs<S: AsRef<str>>(pairs: &[S]) -> Result<Self, String> {
+        let mut options = Self::default();
+        for pair in pairs {
+            let pair = pair.as_ref();
+            let (class, policy) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected CLASS=POLICY, got '{}'", pair))?;
+            options.set(class, policy.parse()?)?;
+        }
+        Ok(options)
+    }
+}
+
+/// Words for the symbols that are read out.
+struct SymbolWords {
+    dot: &'static str,
+    at: &'static str,
+    slash: &'static str,
+    backslash: &'static str,
+    underscore: &'static str,
+    dash: &'static str,
+    colon: &'static str,
+    plus: &'static str,
+    hashtag: &'static str,
+}
+
+const EN: SymbolWords = SymbolWords {
+    dot: "dot",
+    at: "at",
+    slash: "slash",
+    backslash: "backslash",
+    underscore: "underscore",
+    dash: "dash",
+    colon: "colon",
+    plus: "plus",
+    hashtag: "hashtag",
+};
+
+const ES: SymbolWords = SymbolWords {
+    dot: "punto",
+    at: "arroba",
+    slash: "barra",
+    backslash: "barra invertida",
+    underscore: "guion bajo",
+    dash: "guion",
+    colon: "dos puntos",
+    plus: "más",
+    hashtag: "hashtag",
+};
+
+const FR: SymbolWords = SymbolWords {
+    dot: "point",
+    at: "arobase",
+    slash: "slash",
+    backslash: "antislash",
+    underscore: "tiret bas",
+    dash: "tiret",
+    colon: "deux-points",
+    plus: "plus",
+    hashtag: "hashtag",
+};
+
+const IT: SymbolWords = SymbolWords {
+    dot: "punto",
+    at: "chiocciola",
+    slash: "slash",
+    backslash: "backslash",
+    underscore: "trattino basso",
+    dash: "trattino",
+    colon: "due punti",
+    plus: "più",
+    hashtag: "hashtag",
+};
+
+const PT: SymbolWords = SymbolWords {
+    dot: "ponto",
+    at: "arroba",
+    slash: "barra",
+    backslash: "barra invertida",
+    underscore: "underline",
+    dash: "hífen",
+    colon: "dois pontos",
+    plus: "mais",
+    hashtag: "hashtag",
+};
+
+fn symbol_words(lan: &str) -> &'static SymbolWords {
+    match lan
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "es" => &ES,
+        "fr" => &FR,
+        "it" => &IT,
+        "pt" => &PT,
+        _ => &EN,
+    }
+}
+
+/// Read every symbol of a token as a word, e.g. `a_b.rs` as `a underscore b dot rs`
+fn spell(token: &str, words: &SymbolWords) -> String {
+    let mut out = String::new();
+    for c in token.chars() {
+        let word = match c {
+            '.' => words.dot,
+            '@' => words.at,
+            '/' => words.slash,
+            '\\' => words.backslash,
+            '_' => words.underscore,
+            '-' => words.dash,
+            ':' => words.colon,
+            '+' => words.plus,
+            c if c.is_alphanumeric() => {
+                out.push(c);
+                continue;
+            }
+            _ => " ",
+        };
+        out.push(' ');
+        out.push_str(word);
+        out.push(' ');
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Split `snake_case`, `kebab-case` and `camelCase` names into words
+fn split_identifier(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            out.push(' ');
+        } else {
+            if c.is_uppercase() && prev.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+                out.push(' ');
+            }
+            out.push(c);
+        }
+        prev = Some(c);
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether the match starts a token rather than continuing a word or number
+pub(crate) fn starts_token(text: &str, start: usize) -> bool {
+    !text[..start]
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Sentence punctuation that ends a URL or path, such as the full stop in
+/// `see https://example.com.`, handed back to the text
+fn trailing_punctuation(token: &str) -> (&str, &str) {
+    let end = token
+        .trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"'])
+        .len();
+    token.split_at(end)
+}
+
+fn read_url(url: &str, policy: ReadPolicy, words: &SymbolWords) -> String {
+    let rest = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/');
+    match policy {
+        ReadPolicy::Short => {
+            let host = rest.split(['/', '?', '#', ':']).next().unwrap_or(rest);
+            spell(host.strip_prefix("www.").unwrap_or(host), words)
+        }
+        _ => spell(rest, words),
+    }
+}
+
+fn read_path(path: &str, policy: ReadPolicy, words: &SymbolWords) -> String {
+    let path = path.trim_end_matches(['/', '\\']);
+    match policy {
+        ReadPolicy::Short => spell(path.rsplit(['/', '\\']).next().unwrap_or(path), words),
+        _ => spell(path, words),
+    }
+}
+
+/// Replace every match of `re` that starts a token, keeping trailing
+/// sentence punctuation out of the token
+fn replace_tokens(text: &str, re: &Regex, read: impl Fn(&str) -> String) -> String {
+    re.replace_all(text, |caps: &Captures| {
+        let m = caps.get(0).unwrap();
+        if !starts_token(text, m.start()) {
+            return m.as_str().to_string();
+        }
+        let (token, punctuation) = trailing_punctuation(m.as_str());
+        format!("{}{}", read(token), punctuation)
+    })
+    .into_owned()
+}
+
+/// Replace emoji with their names, or drop them
+fn replace_emoji(text: &str, policy: ReadPolicy) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if !c.is_ascii() {
+            // Longest sequence first, for skin tones, flags and ZWJ sequences
+            let ends: Vec<usize> = rest
+                .char_indices()
+                .take(10)
+                .map(|(i, c)| i + c.len_utf8())
+                .collect();
+            if let Some((end, emoji)) = ends
+                .iter()
+                .rev()
+                .find_map(|&end| emojis::get(&rest[..end]).map(|emoji| (end, emoji)))
+            {
+                if policy != ReadPolicy::Skip {
+                    out.push(' ');
+                    out.push_str(&emoji.name().replace(':', ""));
+                    out.push(' ');
+                }
+                rest = &rest[end..];
+                continue;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Rewrite URLs, e-mail addresses, paths, hashtags, emoji and code in `text`
+/// so that they can be read aloud in language `lan`.
+pub fn read_aloud(text: &str, lan: &str, options: &ReadAloudOptions) -> String {
+    if *options == ReadAloudOptions::keep_all() {
+        return text.to_string();
+    }
+    let words = symbol_words(lan);
+    let mut text = text.to_string();
+
+    if options.code != ReadPolicy::Keep {
+        text = FENCE_RE
+            .replace_all(&text, |caps: &Captures| match options.code {
+                ReadPolicy::Spell => caps[1].to_string(),
+                _ => String::new(),
+            })
+            .into_owned();
+        text = INLINE_CODE_RE
+            .replace_all(&text, |caps: &Captures| match options.code {
+                ReadPolicy::Skip => String::new(),
+                _ => caps[1].to_string(),
+            })
+            .into_owned();
+    }
+
+    if options.urls != ReadPolicy::Keep {
+        text = replace_tokens(&text, &URL_RE, |url| match options.urls {
+            ReadPolicy::Skip => String::new(),
+            policy => read_url(url, policy, words),
+        });
+    }
+
+    if options.emails != ReadPolicy::Keep {
+        text = replace_tokens(&text, &EMAIL_RE, |email| match options.emails {
+            ReadPolicy::Skip => String::new(),
+            ReadPolicy::Short => spell(email.rsplit('@').next().unwrap_or(email), words),
+            _ => spell(email, words),
+        });
+    }
+
+    if options.paths != ReadPolicy::Keep {
+        text = replace_tokens(&text, &PATH_RE, |path| match options.paths {
+            ReadPolicy::Skip => String::new(),
+            policy => read_path(path, policy, words),
+        });
+    }
+
+    if options.hashtags != ReadPolicy::Keep {
+        text = HASHTAG_RE
+            .replace_all(&text, |caps: &Captures| {
+                let m = caps.get(0).unwrap();
+                if !starts_token(&text, m.start()) {
+                    return m.as_str().to_string();
+                }
+                match options.hashtags {
+                    ReadPolicy::Skip => String::new(),
+                    ReadPolicy::Short => split_identifier(&caps[1]),
+                    _ => format!("{} {}", words.hashtag, split_identifier(&caps[1])),
+                }
+            })
+            .into_owned();
+    }
+
+    if options.code != ReadPolicy::Keep {
+        text = IDENTIFIER_RE
+            .replace_all(&text, |caps: &Captures| match options.code {
+                ReadPolicy::Skip => String::new(),
+                _ => split_identifier(&caps[0]),
+            })
+            .into_owned();
+    }
+
+    if options.emoji != ReadPolicy::Keep {
+        text = replace_emoji(&text, options.emoji);
+    }
+
+    SPACES_RE.replace_all(text.trim(), " ").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_aloud_defaults() {
+        let options = ReadAloudOptions::default();
+        assert_eq!(
+            read_aloud(
+                "See https://www.example.com/docs?page=2. Mail dev@kokoro.ai 🚀",
+                "en-us",
+                &options
+            ),
+            "See example dot com. Mail dev at kokoro dot ai"
+        );
+        assert_eq!(
+            read_aloud(
+                "Call `parse_voice` in src/tts/koko.rs #RustLang",
+                "en-us",
+                &options
+            ),
+            "Call parse voice in src slash tts slash koko dot rs hashtag Rust Lang"
+        );
+        assert_eq!(
+            read_aloud("1/2 of 3 and/or 4", "en-us", &options),
+            "1/2 of 3 and/or 4"
+        );
+    }
+
+    #[test]
+    fn test_read_aloud_policies() {
+        let options =
+            ReadAloudOptions::from_pairs(&["all=skip", "emoji=spell", "paths=short"]).unwrap();
+        assert_eq!(
+            read_aloud(
+                "Open /etc/kokoro/config.json 👍 at https://example.com",
+                "en-us",
+                &options
+            ),
+            "Open config dot json thumbs up at"
+        );
+        assert_eq!(
+            read_aloud(
+                "Escribe a hola@ejemplo.es",
+                "es",
+                &ReadAloudOptions::default()
+            ),
+            "Escribe a hola arroba ejemplo punto es"
+        );
+        assert!(ReadAloudOptions::from_pairs(&["links=skip"]).is_err());
+        assert!(ReadAloudOptions::from_pairs(&["urls=loud"]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! SRT and WebVTT subtitles, read as timed cues.
+//!
+//! A cue is a block of lines: an optional identifier, a `start --> end`
+//! timing line and the text. SRT writes times as `00:00:01,500`, WebVTT as
+//! `00:00:01.500` or `00:01.500` with cue settings after the end time.
+//! Blocks without a timing line, such as the `WEBVTT` header and `NOTE` or
+//! `STYLE` blocks, are skipped the way players skip them. Tags such as
+//! `<i>`, `<v Anna>` and `{\an8}` are removed from the text.
+
+use std::ops::Range;
+
+/// Text shown from `start_sec` to `end_sec`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_sec: f32,
+    pub end_sec: f32,
+    /// Lines of the cue joined by spaces, without tags
+    pub text: String,
+    /// Bytes of the cue text in the source
+    pub source: Range<usize>,
+}
+
+impl Cue {
+    /// Time the cue is shown for, the slot its speech should fit
+    pub fn slot_sec(&self) -> f32 {
+        (self.end_sec - self.start_sec).max(0.0)
+    }
+}
+
+/// Cues of an SRT or WebVTT file, in file order
+pub fn parse(source: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut block: Vec<(usize, &str)> = Vec::new();
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if content.trim().is_empty() {
+            cues.extend(cue(&block));
+            block.clear();
+        } else {
+            block.push((offset, content));
+        }
+        offset += line.len();
+    }
+    cues.extend(cue(&block));
+    cues
+}
+
+fn cue(block: &[(usize, &str)]) -> Option<Cue> {
+    let timing = block.iter().position(|(_, line)| line.contains("-->"))?;
+    let (start, rest) = block[timing].1.split_once("-->")?;
+    let start_sec = timestamp(start)?;
+    let end_sec = timestamp(rest.split_whitespace().next()?)?;
+
+    let lines = &block[timing + 1..];
+    let (first, last) = (lines.first()?, lines.last()?);
+    let joined: Vec<&str> = lines.iter().map(|(_, line)| *line).collect();
+    let text = strip_tags(&joined.join(" "));
+    let text = html_escape::decode_html_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(Cue {
+        start_sec,
+        end_sec,
+        text,
+        source: first.0..last.0 + last.1.len(),
+    })
+}
+
+/// Seconds of `hh:mm:ss,mmm`, `hh:mm:ss.mmm` or `mm:ss.mmm`
+fn timestamp(s: &str) -> Option<f32> {
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes, seconds] => (hours.parse::<u32>().ok()?, *minutes, *seconds),
+        [minutes, seconds] => (0, *minutes, *seconds),
+        _ => return None,
+    };
+    let minutes: u32 = minutes.parse().ok()?;
+    let seconds: f32 = seconds.replace(',', ".").parse().ok()?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    Some(hours as f32 * 3600.0 + minutes as f32 * 60.0 + seconds)
+}
+
+/// Text without `<...>` tags and `{\...}` override blocks
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(['<', '{']) {
+        plain.push_str(&rest[..i]);
+        let close = if rest[i..].starts_with('<') { '>' } else { '}' };
+        let tag = rest[i..].starts_with('<') || rest[i + 1..].starts_with('\\');
+        match rest[i..].find(close) {
+            Some(end) if tag => rest = &rest[i + end + 1..],
+            _ => {
+                plain.push_str(&rest[i..i + 1]);
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subtitles() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:03,500\r\n<i>Hello</i> there,\r\nfriend.\r\n\r\n\
+                   2\r\n00:00:04,000 --> 00:00:05,000\r\n{\\an8}Bye &amp; thanks!\r\n";
+        let cues = parse(srt);
+        assert_eq!(cues.len(), 2);
+        assert_eq!(
+            (cues[0].start_sec, cues[0].slot_sec(), cues[0].text.as_str()),
+            (1.0, 2.5, "Hello there, friend.")
+        );
+        assert_eq!(
+            &srt[cues[0].source.clone()],
+            "<i>Hello</i> there,\r\nfriend."
+        );
+        assert_eq!(cues[1].text, "Bye & thanks!");
+
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.250 --> 00:02.000 align:start\n\
+                   <v Anna>A {curly} line\n\nbroken --> cue\nskipped\n\n01:00:00.000 --> 01:00:01.000\nLate\n";
+        let cues = parse(vtt);
+        let found: Vec<(f32, &str)> = cues
+            .iter()
+            .map(|cue| (cue.start_sec, cue.text.as_str()))
+            .collect();
+        assert_eq!(found, [(1.25, "A {curly} line"), (3600.0, "Late")]);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::tts::vocab::VOCAB;
+use std::collections::HashMap;
 
 /// Tokenizes the given phonemes string into a vector of token indices.
 ///
@@ -12,9 +13,15 @@ use crate::tts::vocab::VOCAB;
 /// # Returns
 /// A vector of `i64` token indices representing the input text.
 pub fn tokenize(phonemes: &str) -> Vec<i64> {
+    tokenize_with(&VOCAB, phonemes)
+}
+
+/// Tokenizes phonemes with an explicit vocabulary, such as one loaded from a
+/// model's `config.json`. Characters missing from the vocabulary are dropped.
+pub fn tokenize_with(vocab: &HashMap<char, usize>, phonemes: &str) -> Vec<i64> {
     phonemes
         .chars()
-        .filter_map(|c| VOCAB.get(&c))
+        .filter_map(|c| vocab.get(&c))
         .map(|&idx| idx as i64)
         .collect()
 }
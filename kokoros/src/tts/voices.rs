@@ -0,0 +1,808 @@
+//! Voice blends and the user voice directory.
+//!
+//! A [`Blend`] mixes voice styles by weight. It is written
+//! `af_sarah:0.4,af_nicole:0.6`; the older `af_sarah.4+af_nicole.6` form
+//! reads the digits after the dot as a decimal fraction. Weights are
+//! normalized to sum to one, so `af_sarah:2,af_nicole:1` works too.
+//!
+//! Blends can be saved under a new name in the user voice directory, one
+//! raw little-endian f32 file per voice shaped `[rows, 1, 256]`, the layout
+//! of the v1.1-zh voice release. Saved voices load next to the voices file
+//! and are used like any built-in voice.
+//!
+//! A [`VoiceLibrary`] reads the voices file and the user directory without
+//! the model, to list, inspect, import and export voices. Single voices are
+//! exchanged as `.npy` arrays (`np.save` of a PyTorch `.pt` voice converted
+//! with `.numpy()`) or raw `.bin` files; several as an NPZ pack.
+
+use crate::error::KokorosError;
+use crate::tts::catalog::{VoiceCatalog, VoiceMeta};
+use crate::tts::design::StyleSpace;
+use ndarray::{Array3, ArrayD};
+use ndarray_npy::{NpzReader, NpzWriter};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One style vector per possible token count of a chunk
+pub type StyleTable = Vec<[[f32; 256]; 1]>;
+
+/// Environment variable naming the user voice directory
+pub const USER_VOICES_ENV: &str = "KOKOROS_VOICES_DIR";
+
+/// File extension of a voice in a voice directory
+pub const VOICE_EXTENSION: &str = "bin";
+
+/// Voices and their weights, normalized to sum to one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blend {
+    components: Vec<(String, f32)>,
+}
+
+impl Blend {
+    /// Whether `spec` mixes several voices rather than naming one
+    pub fn is_blend(spec: &str) -> bool {
+        spec.contains([':', ',', '+'])
+    }
+
+    pub fn components(&self) -> &[(String, f32)] {
+        &self.components
+    }
+
+    /// The voice, when the blend is all one voice, e.g. `af_sky:1`
+    pub fn single(&self) -> Option<&str> {
+        match &self.components[..] {
+            [(name, _)] => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The blend with each voice renamed, e.g. from an alias
+    pub fn map_names(&self, rename: impl Fn(&str) -> String) -> Self {
+        Self {
+            components: self
+                .components
+                .iter()
+                .map(|(name, weight)| (rename(name), *weight))
+                .collect(),
+        }
+    }
+
+    fn from_weights(components: Vec<(String, f32)>) -> Result<Self, String> {
+        if components.is_empty() {
+            return Err("no voices in the blend".to_string());
+        }
+        let mut merged: Vec<(String, f32)> = Vec::new();
+        for (name, weight) in components {
+            if name.is_empty() {
+                return Err("a voice name in the blend is empty".to_string());
+            }
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(format!(
+                    "weight of '{}' must be a positive number, got {}",
+                    name, weight
+                ));
+            }
+            match merged.iter_mut().find(|(other, _)| *other == name) {
+                Some((_, total)) => *total += weight,
+                None => merged.push((name, weight)),
+            }
+        }
+        let total: f32 = merged.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return Err("the weights of the blend add up to zero".to_string());
+        }
+        for (_, weight) in &mut merged {
+            *weight /= total;
+        }
+        Ok(Self { components: merged })
+    }
+}
+
+impl FromStr for Blend {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        if spec.contains('+') {
+            // Legacy `name.4+name.6`: the digits after the dot are a fraction
+            let components = spec
+                .split('+')
+                .map(|part| {
+                    let part = part.trim();
+                    match part.split_once('.') {
+                        Some((name, digits)) => {
+                            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                                return Err(format!("'{}' is not a voice and a portion", part));
+                            }
+                            let weight = format!("0.{}", digits).parse::<f32>().unwrap_or(0.0);
+                            Ok((name.to_string(), weight))
+                        }
+                        None => Ok((part.to_string(), 1.0)),
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            return Self::from_weights(components);
+        }
+
+        let components = spec
+            .split(',')
+            .map(|part| {
+                let part = part.trim();
+                match part.split_once(':') {
+                    Some((name, weight)) => weight
+                        .trim()
+                        .parse::<f32>()
+                        .map(|weight| (name.trim().to_string(), weight))
+                        .map_err(|_| format!("'{}' is not a weight for '{}'", weight, name)),
+                    None => Ok((part.to_string(), 1.0)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Self::from_weights(components)
+    }
+}
+
+impl fmt::Display for Blend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let [(name, _)] = self.components.as_slice() {
+            return write!(f, "{}", name);
+        }
+        let parts: Vec<String> = self
+            .components
+            .iter()
+            .map(|(name, weight)| format!("{}:{}", name, (weight * 1000.0).round() / 1000.0))
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+/// Whether `name` can name a saved voice: lowercase letters, digits and `_`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The user voice directory: `$KOKOROS_VOICES_DIR`, else
+/// `$XDG_DATA_HOME/kokoros/voices`, else `~/.local/share/kokoros/voices`
+pub fn default_user_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(USER_VOICES_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(data.join("kokoros").join("voices"))
+}
+
+/// Read a voice file into `rows` style vectors; missing rows stay zero. A
+/// file that isn't whole rows of 256 f32 values is truncated or not a voice.
+pub fn read_voice_file(path: &Path, rows: usize) -> std::io::Result<Vec<[[f32; 256]; 1]>> {
+    let bytes = fs::read(path)?;
+    if bytes.is_empty() || bytes.len() % (256 * 4) != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} bytes is not a whole number of 256-value f32 rows",
+                bytes.len()
+            ),
+        ));
+    }
+    let mut table = vec![[[0.0; 256]; 1]; rows];
+    for (i, row) in bytes.chunks_exact(256 * 4).take(rows).enumerate() {
+        for (k, number) in row.chunks_exact(4).enumerate() {
+            table[i][0][k] = f32::from_le_bytes([number[0], number[1], number[2], number[3]]);
+        }
+    }
+    Ok(table)
+}
+
+/// Write style vectors as a voice file
+pub fn write_voice_file(path: &Path, table: &[[[f32; 256]; 1]]) -> std::io::Result<()> {
+    let mut bytes = Vec::with_capacity(table.len() * 256 * 4);
+    for row in table {
+        for number in row[0] {
+            bytes.extend(number.to_le_bytes());
+        }
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, bytes)
+}
+
+/// Read a voices file, an NPZ pack, or a directory of voice files
+pub fn read_pack(path: &Path, rows: usize) -> Result<HashMap<String, StyleTable>, KokorosError> {
+    let voice_err =
+        |e: &dyn fmt::Display| KokorosError::VoiceLoad(format!("{}: {}", path.display(), e));
+    let mut map = HashMap::new();
+
+    if path.is_dir() {
+        // Per-voice layout of the v1.1-zh ONNX release: one raw little-endian
+        // f32 file per voice, shaped [rows, 1, 256]
+        for entry in fs::read_dir(path).map_err(|e| voice_err(&e))? {
+            let path = entry.map_err(|e| voice_err(&e))?.path();
+            if path.extension().is_none_or(|ext| ext != VOICE_EXTENSION) {
+                continue;
+            }
+            let table = read_voice_file(&path, rows)
+                .map_err(|e| KokorosError::VoiceLoad(format!("{}: {}", path.display(), e)))?;
+            let Some(voice) = path.file_stem() else {
+                continue;
+            };
+            map.insert(voice.to_string_lossy().into_owned(), table);
+        }
+        return Ok(map);
+    }
+
+    let file = File::open(path).map_err(|e| voice_err(&e))?;
+    let mut npz = NpzReader::new(file).map_err(|e| voice_err(&e))?;
+    for voice in npz.names().map_err(|e| voice_err(&e))? {
+        let data: Array3<f32> = npz.by_name(&voice).map_err(|e| voice_err(&e))?;
+        if data.shape()[1..] != [1, 256] {
+            return Err(voice_err(&format!(
+                "voice '{}' has shape {:?}, expected [rows, 1, 256]",
+                voice,
+                data.shape()
+            )));
+        }
+        let mut table = vec![[[0.0; 256]; 1]; rows];
+        for (row, values) in table.iter_mut().zip(data.outer_iter()) {
+            for (value, number) in row[0].iter_mut().zip(values.iter()) {
+                *value = *number;
+            }
+        }
+        map.insert(voice, table);
+    }
+    Ok(map)
+}
+
+/// Read one voice from a `.npy` array shaped `[rows, 1, 256]` or
+/// `[rows, 256]`, or from a raw `.bin` voice file
+pub fn read_tensor(path: &Path, rows: usize) -> Result<StyleTable, KokorosError> {
+    let voice_err =
+        |e: &dyn fmt::Display| KokorosError::VoiceLoad(format!("{}: {}", path.display(), e));
+    if path.extension().is_some_and(|ext| ext == "npy") {
+        let data: ArrayD<f32> = ndarray_npy::read_npy(path).map_err(|e| voice_err(&e))?;
+        let shape = data.shape().to_vec();
+        if !matches!(shape.as_slice(), [_, 1, 256] | [_, 256]) {
+            return Err(voice_err(&format!(
+                "shape {:?}, expected [rows, 1, 256] or [rows, 256]",
+                shape
+            )));
+        }
+        // Iteration is in logical order, so both shapes are rows of 256 values
+        let values: Vec<f32> = data.iter().copied().collect();
+        let mut table = vec![[[0.0; 256]; 1]; rows];
+        for (row, chunk) in table.iter_mut().zip(values.chunks(256)) {
+            row[0].copy_from_slice(chunk);
+        }
+        return Ok(table);
+    }
+
+    read_voice_file(path, rows).map_err(|e| voice_err(&e))
+}
+
+/// Write one voice as a `.npy` array shaped `[rows, 1, 256]`, or as a raw
+/// voice file for any other extension
+pub fn write_tensor(path: &Path, table: &[[[f32; 256]; 1]]) -> Result<(), KokorosError> {
+    if path.extension().is_some_and(|ext| ext == "npy") {
+        ndarray_npy::write_npy(path, &to_array(table))
+            .map_err(|e| KokorosError::VoiceLoad(format!("{}: {}", path.display(), e)))
+    } else {
+        write_voice_file(path, table).map_err(KokorosError::Io)
+    }
+}
+
+/// Write voices as an NPZ pack, the format of `voices-v1.0.bin`
+pub fn write_pack(path: &Path, voices: &[(&str, &StyleTable)]) -> Result<(), KokorosError> {
+    let pack_err =
+        |e: &dyn fmt::Display| KokorosError::VoiceLoad(format!("{}: {}", path.display(), e));
+    let mut npz = NpzWriter::new(File::create(path)?);
+    for (name, table) in voices {
+        npz.add_array(*name, &to_array(table))
+            .map_err(|e| pack_err(&e))?;
+    }
+    npz.finish().map_err(|e| pack_err(&e))?;
+    Ok(())
+}
+
+fn to_array(table: &[[[f32; 256]; 1]]) -> Array3<f32> {
+    Array3::from_shape_fn((table.len(), 1, 256), |(i, j, k)| table[i][j][k])
+}
+
+/// The style table of a blend, with `lookup` finding each voice
+pub fn mix<'a>(
+    blend: &Blend,
+    rows: usize,
+    lookup: impl Fn(&str) -> Option<&'a StyleTable>,
+) -> Result<StyleTable, KokorosError> {
+    let mut table: StyleTable = vec![[[0.0; 256]; 1]; rows];
+    for (name, weight) in blend.components() {
+        let style = lookup(name).ok_or_else(|| KokorosError::UnknownVoice(name.clone()))?;
+        for (row, source) in table.iter_mut().zip(style) {
+            for (value, component) in row[0].iter_mut().zip(&source[0]) {
+                *value += component * weight;
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// The file of a saved voice named `name` in the user directory
+pub fn user_voice_path(dir: Option<&Path>, name: &str) -> Result<PathBuf, KokorosError> {
+    if !is_valid_name(name) {
+        return Err(KokorosError::InvalidVoice(format!(
+            "'{}' is not a valid voice name, use lowercase letters, digits and _",
+            name
+        )));
+    }
+    let dir = dir.ok_or_else(|| {
+        KokorosError::InvalidVoice(format!("no user voice directory, set {}", USER_VOICES_ENV))
+    })?;
+    Ok(dir.join(format!("{}.{}", name, VOICE_EXTENSION)))
+}
+
+/// Gender of a voice, as the [`VoiceCatalog`] describes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gender {
+    Female,
+    Male,
+}
+
+impl FromStr for Gender {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "female" | "f" => Ok(Gender::Female),
+            "male" | "m" => Ok(Gender::Male),
+            other => Err(format!("unknown gender '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for Gender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Gender::Female => "female",
+            Gender::Male => "male",
+        })
+    }
+}
+
+/// Where a voice was loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceSource {
+    BuiltIn,
+    User,
+}
+
+impl fmt::Display for VoiceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VoiceSource::BuiltIn => "built-in",
+            VoiceSource::User => "user",
+        })
+    }
+}
+
+/// A voice as listed by a [`VoiceLibrary`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceInfo {
+    pub meta: VoiceMeta,
+    pub source: VoiceSource,
+}
+
+/// Summary statistics of the style vectors of a voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceStats {
+    /// Rows holding a style vector; trailing all-zero rows are not counted
+    pub rows: usize,
+    pub mean: f32,
+    pub std: f32,
+    pub min: f32,
+    pub max: f32,
+    /// Mean Euclidean norm of a row
+    pub norm: f32,
+}
+
+impl VoiceStats {
+    pub fn of(table: &[[[f32; 256]; 1]]) -> Self {
+        let rows = table
+            .iter()
+            .rposition(|row| row[0].iter().any(|v| *v != 0.0))
+            .map_or(0, |last| last + 1);
+        if rows == 0 {
+            return Self {
+                rows,
+                mean: 0.0,
+                std: 0.0,
+                min: 0.0,
+                max: 0.0,
+                norm: 0.0,
+            };
+        }
+        let values = || table[..rows].iter().flat_map(|row| row[0].iter().copied());
+        let count = (rows * 256) as f32;
+        let mean = values().sum::<f32>() / count;
+        let variance = values().map(|v| (v - mean).powi(2)).sum::<f32>() / count;
+        let (min, max) = values().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+        let norm = table[..rows]
+            .iter()
+            .map(|row| row[0].iter().map(|v| v * v).sum::<f32>().sqrt())
+            .sum::<f32>()
+            / rows as f32;
+        Self {
+            rows,
+            mean,
+            std: variance.sqrt(),
+            min,
+            max,
+            norm,
+        }
+    }
+}
+
+/// How far apart two voices are: the mean Euclidean distance between their
+/// style vectors, over the rows either of them defines
+pub fn style_distance(a: &[[[f32; 256]; 1]], b: &[[[f32; 256]; 1]]) -> f32 {
+    let distances: Vec<f32> = a
+        .iter()
+        .zip(b)
+        .filter(|(x, y)| x[0].iter().chain(&y[0]).any(|v| *v != 0.0))
+        .map(|(x, y)| {
+            x[0].iter()
+                .zip(&y[0])
+                .map(|(p, q)| (p - q).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        })
+        .collect();
+    if distances.is_empty() {
+        return 0.0;
+    }
+    distances.iter().sum::<f32>() / distances.len() as f32
+}
+
+/// Which voices a similarity search may return
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoiceFilter {
+    /// A language such as `en-gb`, a language family such as `en`, or an
+    /// accent such as `British`
+    pub language: Option<String>,
+    pub gender: Option<Gender>,
+}
+
+impl VoiceFilter {
+    pub fn matches(&self, meta: &VoiceMeta) -> bool {
+        let language = self.language.as_deref().is_none_or(|wanted| {
+            meta.language.as_deref().is_some_and(|language| {
+                language == wanted || language.starts_with(&format!("{}-", wanted))
+            }) || meta
+                .accent
+                .as_deref()
+                .is_some_and(|accent| accent.eq_ignore_ascii_case(wanted))
+        });
+        language && self.gender.is_none_or(|gender| meta.gender == Some(gender))
+    }
+}
+
+/// A voice close to the one searched for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbour {
+    pub meta: VoiceMeta,
+    /// [`style_distance`] to the voice searched for
+    pub distance: f32,
+}
+
+/// The `limit` voices of `candidates` nearest to `target` that pass
+/// `filter`, nearest first
+pub fn nearest_voices<'a>(
+    target: &[[[f32; 256]; 1]],
+    candidates: impl IntoIterator<Item = (&'a VoiceMeta, &'a [[[f32; 256]; 1]])>,
+    filter: &VoiceFilter,
+    limit: usize,
+) -> Vec<Neighbour> {
+    let mut neighbours: Vec<Neighbour> = candidates
+        .into_iter()
+        .filter(|(meta, _)| filter.matches(meta))
+        .map(|(meta, table)| Neighbour {
+            meta: meta.clone(),
+            distance: style_distance(target, table),
+        })
+        .collect();
+    neighbours.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then_with(|| a.meta.id.cmp(&b.meta.id))
+    });
+    neighbours.truncate(limit);
+    neighbours
+}
+
+/// The voices of a voices file and a user voice directory, read without
+/// loading the model
+pub struct VoiceLibrary {
+    rows: usize,
+    builtin: HashMap<String, StyleTable>,
+    user: HashMap<String, StyleTable>,
+    user_dir: Option<PathBuf>,
+}
+
+impl VoiceLibrary {
+    /// Read `voices_path` and the saved voices of `user_dir`, with `rows`
+    /// style vectors per voice. Built-in voices win over saved ones of the
+    /// same name, as in [`crate::tts::koko::TTSKoko`].
+    pub fn open(
+        voices_path: &Path,
+        user_dir: Option<PathBuf>,
+        rows: usize,
+    ) -> Result<Self, KokorosError> {
+        let builtin = read_pack(voices_path, rows)?;
+        let mut user = match &user_dir {
+            Some(dir) if dir.is_dir() => read_pack(dir, rows)?,
+            _ => HashMap::new(),
+        };
+        user.retain(|name, _| !builtin.contains_key(name));
+        Ok(Self {
+            rows,
+            builtin,
+            user,
+            user_dir,
+        })
+    }
+
+    /// Style vectors per voice
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Every voice, sorted by name, described by the catalogue with the
+    /// `voices.json` of the user directory applied
+    pub fn list(&self) -> Result<Vec<VoiceInfo>, KokorosError> {
+        Ok(self
+            .catalog()?
+            .voices()
+            .map(|meta| VoiceInfo {
+                meta: meta.clone(),
+                source: if self.builtin.contains_key(&meta.id) {
+                    VoiceSource::BuiltIn
+                } else {
+                    VoiceSource::User
+                },
+            })
+            .collect())
+    }
+
+    /// The catalogue of every voice, with the `voices.json` of the user
+    /// directory applied
+    fn catalog(&self) -> Result<VoiceCatalog, KokorosError> {
+        let mut catalog = VoiceCatalog::new(
+            self.builtin
+                .keys()
+                .chain(self.user.keys())
+                .map(String::as_str),
+        );
+        if let Some(dir) = &self.user_dir {
+            catalog
+                .load_user_metadata(dir)
+                .map_err(KokorosError::VoiceLoad)?;
+        }
+        Ok(catalog)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StyleTable> {
+        self.builtin.get(name).or_else(|| self.user.get(name))
+    }
+
+    pub fn info(&self, name: &str) -> Result<(VoiceInfo, VoiceStats), KokorosError> {
+        let info = self
+            .list()?
+            .into_iter()
+            .find(|voice| voice.meta.id == name)
+            .ok_or_else(|| KokorosError::UnknownVoice(name.to_string()))?;
+        let stats = VoiceStats::of(&self.get(name).expect("listed voices are loaded")[..]);
+        Ok((info, stats))
+    }
+
+    /// The style table of a voice or a [`Blend`] of voices
+    pub fn mix(&self, spec: &str) -> Result<StyleTable, KokorosError> {
+        let blend: Blend = spec
+            .parse()
+            .map_err(|e| KokorosError::InvalidVoice(format!("{}: {}", spec, e)))?;
+        mix(&blend, self.rows, |name| self.get(name))
+    }
+
+    /// Voices that sound like a voice or blend, nearest first. A voice is
+    /// not its own neighbour, however it is written.
+    pub fn similar(
+        &self,
+        spec: &str,
+        filter: &VoiceFilter,
+        limit: usize,
+    ) -> Result<Vec<Neighbour>, KokorosError> {
+        let blend: Blend = spec
+            .parse()
+            .map_err(|e| KokorosError::InvalidVoice(format!("{}: {}", spec, e)))?;
+        let target = mix(&blend, self.rows, |name| self.get(name))?;
+        let voices = self.list()?;
+        let candidates = voices
+            .iter()
+            .filter(|voice| blend.single() != Some(voice.meta.id.as_str()))
+            .filter_map(|voice| Some((&voice.meta, &self.get(&voice.meta.id)?[..])));
+        Ok(nearest_voices(&target, candidates, filter, limit))
+    }
+
+    /// The style space of the built-in voices, for designing new voices.
+    /// Saved voices are left out so designs stay reproducible.
+    pub fn style_space(&self) -> Result<StyleSpace, KokorosError> {
+        let voices: Vec<(&str, &StyleTable)> = self
+            .builtin
+            .iter()
+            .map(|(name, table)| (name.as_str(), table))
+            .collect();
+        StyleSpace::analyze(&voices, self.rows, &self.catalog()?)
+            .map_err(KokorosError::InvalidVoice)
+    }
+
+    /// Save a voice in the user directory, replacing a saved voice of the
+    /// same name. Built-in names are refused.
+    pub fn save(&mut self, name: &str, table: StyleTable) -> Result<PathBuf, KokorosError> {
+        let path = user_voice_path(self.user_dir.as_deref(), name)?;
+        if self.builtin.contains_key(name) {
+            return Err(KokorosError::InvalidVoice(format!(
+                "'{}' is a built-in voice",
+                name
+            )));
+        }
+        write_voice_file(&path, &table)?;
+        self.user.insert(name.to_string(), table);
+        Ok(path)
+    }
+
+    /// Delete a saved voice; built-in voices can't be removed
+    pub fn remove(&mut self, name: &str) -> Result<PathBuf, KokorosError> {
+        if self.builtin.contains_key(name) {
+            return Err(KokorosError::InvalidVoice(format!(
+                "'{}' is a built-in voice and can't be removed",
+                name
+            )));
+        }
+        if self.user.remove(name).is_none() {
+            return Err(KokorosError::UnknownVoice(name.to_string()));
+        }
+        let path = user_voice_path(self.user_dir.as_deref(), name)?;
+        fs::remove_file(&path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights(spec: &str) -> Vec<(String, f32)> {
+        spec.parse::<Blend>().unwrap().components().to_vec()
+    }
+
+    #[test]
+    fn test_blend_syntax() {
+        assert_eq!(
+            weights("af_sarah:0.4, af_nicole:0.6"),
+            [("af_sarah".into(), 0.4), ("af_nicole".into(), 0.6)]
+        );
+        assert_eq!(
+            weights("af_sarah:2,af_nicole:2"),
+            [("af_sarah".into(), 0.5), ("af_nicole".into(), 0.5)]
+        );
+        assert_eq!(
+            weights("af_sarah.4+af_nicole.6"),
+            [("af_sarah".into(), 0.4), ("af_nicole".into(), 0.6)]
+        );
+        // `.25` and `.15` are fractions, not tenths
+        assert_eq!(
+            weights("af_bella.25+af_sky.75"),
+            [("af_bella".into(), 0.25), ("af_sky".into(), 0.75)]
+        );
+        assert_eq!(weights("af_sky"), [("af_sky".into(), 1.0)]);
+        assert!("af_sky:-1,af_bella:1".parse::<Blend>().is_err());
+        assert!("af_sky:x".parse::<Blend>().is_err());
+        assert!("af_sky.4x+af_bella.6".parse::<Blend>().is_err());
+        assert_eq!(
+            "af_sarah.4+af_nicole.6"
+                .parse::<Blend>()
+                .unwrap()
+                .to_string(),
+            "af_sarah:0.4,af_nicole:0.6"
+        );
+        assert!(is_valid_name("my_voice2") && !is_valid_name("My Voice"));
+    }
+
+    #[test]
+    fn test_truncated_voice_file() {
+        let dir = std::env::temp_dir().join(format!("kokoros-truncated-{}", std::process::id()));
+        write_voice_file(&dir.join("af_whole.bin"), &[[[0.5; 256]; 1]; 2]).unwrap();
+        assert_eq!(read_pack(&dir, 2).unwrap()["af_whole"][1][0][255], 0.5);
+
+        // Half a row short, as when a download is cut off
+        let bytes = fs::read(dir.join("af_whole.bin")).unwrap();
+        fs::write(dir.join("af_cut.bin"), &bytes[..bytes.len() - 512]).unwrap();
+        assert!(read_voice_file(&dir.join("af_cut.bin"), 2).is_err());
+        assert!(read_tensor(&dir.join("af_cut.bin"), 2).is_err());
+        let err = read_pack(&dir, 2).unwrap_err().to_string();
+        assert!(err.contains("af_cut.bin"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_voice_library() {
+        let root = std::env::temp_dir().join(format!("kokoros-voices-{}", std::process::id()));
+        let (builtin, user) = (root.join("builtin"), root.join("user"));
+        let row = |value: f32| [[value; 256]; 1];
+        write_voice_file(&builtin.join("af_one.bin"), &[row(1.0), row(3.0)]).unwrap();
+        write_voice_file(&builtin.join("bm_two.bin"), &[row(-1.0), row(-1.0)]).unwrap();
+
+        let mut library = VoiceLibrary::open(&builtin, Some(user.clone()), 3).unwrap();
+        let mixed = library.mix("af_one:0.5,bm_two:0.5").unwrap();
+        assert_eq!(
+            (mixed[0][0][0], mixed[1][0][0], mixed[2][0][0]),
+            (0.0, 1.0, 0.0)
+        );
+        library.save("my_mix", mixed).unwrap();
+        assert!(library.save("af_one", vec![row(0.0)]).is_err());
+
+        let listed = library.list().unwrap();
+        assert_eq!(listed.len(), 3);
+        let first = &listed[0].meta;
+        assert_eq!(
+            (first.language.as_deref(), first.gender, listed[0].source),
+            (Some("en-us"), Some(Gender::Female), VoiceSource::BuiltIn)
+        );
+        assert_eq!(listed[1].meta.gender, Some(Gender::Male));
+        assert_eq!(
+            (listed[2].meta.gender, listed[2].source),
+            (None, VoiceSource::User)
+        );
+
+        let (_, stats) = library.info("af_one").unwrap();
+        assert_eq!((stats.rows, stats.mean, stats.std), (2, 2.0, 1.0));
+        assert_eq!((stats.min, stats.max, stats.norm), (1.0, 3.0, 32.0));
+
+        let nearest = library
+            .similar("af_one", &VoiceFilter::default(), 5)
+            .unwrap();
+        let found: Vec<(&str, f32)> = nearest
+            .iter()
+            .map(|n| (n.meta.id.as_str(), n.distance))
+            .collect();
+        assert_eq!(found, [("my_mix", 24.0), ("bm_two", 48.0)]);
+        let nearest = library
+            .similar("af_one:1", &VoiceFilter::default(), 5)
+            .unwrap();
+        assert!(nearest.iter().all(|n| n.meta.id != "af_one"));
+        assert_eq!(nearest.len(), 2);
+        let british = VoiceFilter {
+            language: Some("british".to_string()),
+            gender: Some("m".parse().unwrap()),
+        };
+        let nearest = library.similar("af_one", &british, 5).unwrap();
+        assert_eq!(nearest.len(), 1);
+        assert_eq!(nearest[0].meta.id, "bm_two");
+
+        // Saved voices are found again on the next start
+        let reopened = VoiceLibrary::open(&builtin, Some(user.clone()), 3).unwrap();
+        assert!(reopened.get("my_mix").is_some());
+        library.remove("my_mix").unwrap();
+        assert!(library.remove("bm_two").is_err());
+        assert!(!user.join("my_mix.bin").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+//! Mandarin front end for Kokoro v1.1-zh.
+//!
+//! The v1.1-zh model was trained on the output of misaki's `ZHFrontend`
+//! rather than eSpeak IPA. This follows the same pipeline: text is segmented
+//! into words with jieba, converted to numbered pinyin, tone sandhi is
+//! applied, and every syllable is spelled with the bopomofo based symbols of
+//! the v1.1-zh vocabulary followed by its tone digit. Words are separated by
+//! `/`, and runs of Latin text are handed to a fallback phonemizer.
+
+use jieba_rs::Jieba;
+use lazy_static::lazy_static;
+use pinyin::ToPinyin;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    static ref JIEBA: Jieba = Jieba::new();
+    static ref MONEY_RE: Regex = Regex::new(r"([¥￥$€£])\s?(\d+(?:\.\d+)?)").unwrap();
+    static ref DATE_RE: Regex = Regex::new(r"(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})").unwrap();
+    static ref TIME_RE: Regex = Regex::new(r"(\d{1,2}):(\d{2})").unwrap();
+}
+
+/// Pinyin initials, longest first so `zh`/`ch`/`sh` win over `z`/`c`/`s`.
+const INITIALS: [(&str, &str); 21] = [
+    ("zh", "ㄓ"),
+    ("ch", "ㄔ"),
+    ("sh", "ㄕ"),
+    ("b", "ㄅ"),
+    ("p", "ㄆ"),
+    ("m", "ㄇ"),
+    ("f", "ㄈ"),
+    ("d", "ㄉ"),
+    ("t", "ㄊ"),
+    ("n", "ㄋ"),
+    ("l", "ㄌ"),
+    ("g", "ㄍ"),
+    ("k", "ㄎ"),
+    ("h", "ㄏ"),
+    ("j", "ㄐ"),
+    ("q", "ㄑ"),
+    ("x", "ㄒ"),
+    ("r", "ㄖ"),
+    ("z", "ㄗ"),
+    ("c", "ㄘ"),
+    ("s", "ㄙ"),
+];
+
+/// Finals in their full (un-abbreviated) form. Compound finals use the single
+/// symbols the v1.1-zh vocabulary assigns to them.
+const FINALS: [(&str, &str); 39] = [
+    ("a", "ㄚ"),
+    ("o", "ㄛ"),
+    ("e", "ㄜ"),
+    ("ie", "ㄝ"),
+    ("ai", "ㄞ"),
+    ("ei", "ㄟ"),
+    ("ao", "ㄠ"),
+    ("ou", "ㄡ"),
+    ("an", "ㄢ"),
+    ("en", "ㄣ"),
+    ("ang", "ㄤ"),
+    ("eng", "ㄥ"),
+    ("er", "ㄦ"),
+    ("i", "ㄧ"),
+    ("u", "ㄨ"),
+    ("v", "ㄩ"),
+    ("ii", "ㄭ"),
+    ("iii", "十"),
+    ("ve", "月"),
+    ("ia", "压"),
+    ("ian", "言"),
+    ("iang", "阳"),
+    ("iao", "要"),
+    ("in", "阴"),
+    ("ing", "应"),
+    ("iong", "用"),
+    ("iou", "又"),
+    ("ong", "中"),
+    ("ua", "穵"),
+    ("uai", "外"),
+    ("uan", "万"),
+    ("uang", "王"),
+    ("uei", "为"),
+    ("uen", "文"),
+    ("ueng", "瓮"),
+    ("uo", "我"),
+    ("van", "元"),
+    ("vn", "云"),
+    ("io", "ㄧㄛ"),
+];
+
+const DIGITS: [&str; 10] = ["零", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+
+/// Whether a language code asks for Mandarin.
+pub fn is_mandarin(lan: &str) -> bool {
+    matches!(
+        lan.to_lowercase().as_str(),
+        "zh" | "cmn" | "zh-cn" | "zh-hans" | "cmn-latn-pinyin"
+    )
+}
+
+pub fn is_han(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+struct Syllable {
+    hanzi: char,
+    pinyin: String,
+    tone: u8,
+}
+
+enum Segment {
+    Han(Vec<Syllable>),
+    Latin(String),
+    Punct(String),
+}
+
+/// Phonemize Mandarin text for the v1.1-zh vocabulary. Runs of Latin letters
+/// are phonemized with `fallback`, whose errors are passed through.
+pub fn g2p<E>(
+    text: &str,
+    mut fallback: impl FnMut(&str) -> Result<String, E>,
+) -> Result<String, E> {
+    let text = normalize_numbers(text);
+    let mut segments: Vec<Segment> = JIEBA
+        .cut(&text, true)
+        .into_iter()
+        .filter_map(|word| {
+            if word.chars().any(is_han) {
+                Some(Segment::Han(word_to_syllables(word)))
+            } else if word.chars().any(|c| c.is_alphabetic()) {
+                Some(Segment::Latin(word.to_string()))
+            } else {
+                let punct: String = word.chars().filter_map(map_punctuation).collect();
+                (!punct.is_empty()).then_some(Segment::Punct(punct))
+            }
+        })
+        .collect();
+
+    apply_yi_bu_sandhi(&mut segments);
+
+    let mut out = String::new();
+    let mut prev_han = false;
+    for segment in &segments {
+        match segment {
+            Segment::Han(syllables) => {
+                if prev_han {
+                    out.push('/');
+                }
+                for syllable in syllables {
+                    out.push_str(&syllable_to_zhuyin(&syllable.pinyin, syllable.tone));
+                }
+                prev_han = true;
+            }
+            Segment::Latin(word) => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+                out.push_str(fallback(word)?.trim());
+                out.push(' ');
+                prev_han = false;
+            }
+            Segment::Punct(punct) => {
+                out.push_str(punct);
+                prev_han = false;
+            }
+        }
+    }
+    Ok(out.trim().to_string())
+}
+
+/// Pinyin for each character of a word, with third tone sandhi applied
+/// inside the word.
+fn word_to_syllables(word: &str) -> Vec<Syllable> {
+    let mut syllables: Vec<Syllable> = word
+        .chars()
+        .filter_map(|c| {
+            let numbered = c.to_pinyin()?.with_tone_num_end();
+            let (pinyin, tone) = match numbered.char_indices().last() {
+                Some((i, t @ '1'..='5')) => (&numbered[..i], t as u8 - b'0'),
+                _ => (numbered, 5),
+            };
+            Some(Syllable {
+                hanzi: c,
+                pinyin: pinyin.replace('ü', "v"),
+                tone,
+            })
+        })
+        .collect();
+
+    // 3-3 becomes 2-3; with longer runs every syllable but the last is raised
+    let tones: Vec<u8> = syllables.iter().map(|s| s.tone).collect();
+    for (i, syllable) in syllables.iter_mut().enumerate() {
+        if tones[i] == 3 && tones.get(i + 1) == Some(&3) {
+            syllable.tone = 2;
+        }
+    }
+    syllables
+}
+
+/// 不 and 一 change tone depending on the following syllable, which may sit
+/// in the next word.
+fn apply_yi_bu_sandhi(segments: &mut [Segment]) {
+    let is_numeral = |c: char| "零一二两三四五六七八九十百千万亿第".contains(c);
+    let flat: Vec<(char, u8)> = segments
+        .iter()
+        .flat_map(|s| match s {
+            Segment::Han(syllables) => syllables.iter().map(|s| (s.hanzi, s.tone)).collect(),
+            _ => Vec::new(),
+        })
+        .collect();
+
+    let mut index = 0usize;
+    for segment in segments.iter_mut() {
+        let Segment::Han(syllables) = segment else {
+            continue;
+        };
+        for syllable in syllables.iter_mut() {
+            let prev = index.checked_sub(1).map(|i| flat[i].0);
+            let next = flat.get(index + 1);
+            match (syllable.hanzi, next) {
+                ('不', Some(&(_, 4))) => syllable.tone = 2,
+                ('一', Some(&(c, tone))) if !is_numeral(c) && !prev.is_some_and(is_numeral) => {
+                    syllable.tone = if tone == 4 { 2 } else { 4 };
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+    }
+}
+
+fn syllable_to_zhuyin(pinyin: &str, tone: u8) -> String {
+    let (initial, final_) = split_syllable(pinyin);
+    let mut out = String::new();
+    if let Some((_, symbol)) = INITIALS.iter().find(|(i, _)| *i == initial) {
+        out.push_str(symbol);
+    }
+    match FINALS.iter().find(|(f, _)| *f == final_) {
+        Some((_, symbol)) => out.push_str(symbol),
+        None => {
+            // Interjections such as "ng" or "m": spell what can be spelled
+            for c in final_.chars() {
+                let c = c.to_string();
+                if let Some((_, symbol)) = FINALS.iter().find(|(f, _)| *f == c) {
+                    out.push_str(symbol);
+                }
+            }
+        }
+    }
+    out.push(char::from(b'0' + tone));
+    out
+}
+
+/// Split a toneless pinyin syllable into its initial and its full final,
+/// undoing the spelling rules for `y`/`w`, `ü` after j/q/x, the contracted
+/// finals `iu`/`ui`/`un`, and the apical vowels of `zhi` and `zi`.
+fn split_syllable(pinyin: &str) -> (&'static str, String) {
+    if let Some(rest) = pinyin.strip_prefix('y') {
+        let final_ = if let Some(rest) = rest.strip_prefix('u') {
+            format!("v{}", rest)
+        } else if rest.starts_with('i') {
+            rest.to_string()
+        } else {
+            format!("i{}", rest)
+        };
+        return ("", final_);
+    }
+    if let Some(rest) = pinyin.strip_prefix('w') {
+        let final_ = if rest.starts_with('u') {
+            rest.to_string()
+        } else {
+            format!("u{}", rest)
+        };
+        return ("", final_);
+    }
+
+    let Some(&(initial, _)) = INITIALS.iter().find(|(i, _)| pinyin.starts_with(i)) else {
+        return ("", pinyin.to_string());
+    };
+    let rest = &pinyin[initial.len()..];
+    let final_ = match (initial, rest) {
+        ("j" | "q" | "x", rest) if rest.starts_with('u') => format!("v{}", &rest[1..]),
+        ("zh" | "ch" | "sh" | "r", "i") => "iii".to_string(),
+        ("z" | "c" | "s", "i") => "ii".to_string(),
+        (_, "iu") => "iou".to_string(),
+        (_, "ui") => "uei".to_string(),
+        (_, "un") => "uen".to_string(),
+        (_, rest) => rest.to_string(),
+    };
+    (initial, final_)
+}
+
+fn map_punctuation(c: char) -> Option<char> {
+    match c {
+        '，' | '、' | ',' | '（' | '）' => Some(','),
+        '。' | '.' => Some('.'),
+        '！' | '!' => Some('!'),
+        '？' | '?' => Some('?'),
+        '；' | ';' => Some(';'),
+        '：' | ':' => Some(':'),
+        '“' | '「' | '『' | '《' => Some('“'),
+        '”' | '」' | '』' | '》' => Some('”'),
+        '…' => Some('…'),
+        '—' | '－' => Some('—'),
+        _ => None,
+    }
+}
+
+/// Rewrite prices, dates, times and numbers the way they are read in
+/// Mandarin, e.g. `¥12` as 十二元 and `2024-05-01` as 二零二四年五月一日.
+pub fn normalize(text: &str) -> String {
+    let text = MONEY_RE.replace_all(text, |caps: &Captures| {
+        let unit = match &caps[1] {
+            "$" => "美元",
+            "€" => "欧元",
+            "£" => "英镑",
+            _ => "元",
+        };
+        format!("{}{}", &caps[2], unit)
+    });
+    let text = DATE_RE.replace_all(&text, |caps: &Captures| {
+        let (month, day) = (&caps[2], &caps[3]);
+        match (month.parse::<u32>(), day.parse::<u32>()) {
+            (Ok(1..=12), Ok(1..=31)) => format!(
+                "{}年{}月{}日",
+                &caps[1],
+                month.trim_start_matches('0'),
+                day.trim_start_matches('0')
+            ),
+            _ => caps[0].to_string(),
+        }
+    });
+    let text = TIME_RE.replace_all(&text, |caps: &Captures| {
+        let hour = caps[1].trim_start_matches('0');
+        let hour = if hour.is_empty() { "0" } else { hour };
+        match (hour.parse::<u32>(), &caps[2]) {
+            (Ok(0..=23), "00") => format!("{}点整", hour),
+            (Ok(0..=23), minutes) if minutes < "60" => format!("{}点{}分", hour, minutes),
+            _ => caps[0].to_string(),
+        }
+    });
+    normalize_numbers(&text)
+}
+
+/// Replace Arabic numerals with their Chinese reading, e.g. `3.14` with
+/// 三点一四 and `50%` with 百分之五十.
+fn normalize_numbers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let integer: String = chars[start..i].iter().collect();
+        // Years are read digit by digit
+        let mut reading = if chars.get(i) == Some(&'年') {
+            read_digits(&integer)
+        } else {
+            read_integer(&integer)
+        };
+        if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+            i += 1;
+            reading.push('点');
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                reading.push_str(DIGITS[chars[i] as usize - '0' as usize]);
+                i += 1;
+            }
+        }
+        if chars.get(i) == Some(&'%') {
+            out.push_str("百分之");
+            i += 1;
+        }
+        out.push_str(&reading);
+    }
+    out
+}
+
+/// Read an integer the way it is spoken. Numbers with a leading zero or more
+/// than twelve digits (phone numbers, IDs) are read digit by digit.
+fn read_integer(digits: &str) -> String {
+    let n = match digits.parse::<u64>() {
+        Ok(n) if digits.len() <= 12 && !(digits.len() > 1 && digits.starts_with('0')) => n,
+        _ => return read_digits(digits),
+    };
+    if n == 0 {
+        return DIGITS[0].to_string();
+    }
+
+    let mut out = String::new();
+    let mut pending_zero = false;
+    for (base, unit) in [(100_000_000, "亿"), (10_000, "万"), (1, "")] {
+        let section = n / base % 10_000;
+        if section == 0 {
+            pending_zero |= !out.is_empty();
+            continue;
+        }
+        if pending_zero || (!out.is_empty() && section < 1000) {
+            out.push('零');
+        }
+        pending_zero = false;
+        out.push_str(&read_section(section));
+        out.push_str(unit);
+    }
+
+    // 一十五 is read 十五
+    match out.strip_prefix("一十") {
+        Some(rest) => format!("十{}", rest),
+        None => out,
+    }
+}
+
+fn read_digits(digits: &str) -> String {
+    digits
+        .chars()
+        .map(|c| DIGITS[c as usize - '0' as usize])
+        .collect()
+}
+
+/// Read 1..=9999.
+fn read_section(n: u64) -> String {
+    let mut out = String::new();
+    let mut pending_zero = false;
+    for (base, unit) in [(1000, "千"), (100, "百"), (10, "十"), (1, "")] {
+        let digit = (n / base % 10) as usize;
+        if digit == 0 {
+            pending_zero |= !out.is_empty();
+            continue;
+        }
+        if pending_zero {
+            out.push('零');
+            pending_zero = false;
+        }
+        out.push_str(DIGITS[digit]);
+        out.push_str(unit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syllable_to_zhuyin() {
+        assert_eq!(syllable_to_zhuyin("ni", 2), "ㄋㄧ2");
+        assert_eq!(syllable_to_zhuyin("shi", 4), "ㄕ十4");
+        assert_eq!(syllable_to_zhuyin("zi", 4), "ㄗㄭ4");
+        assert_eq!(syllable_to_zhuyin("jue", 2), "ㄐ月2");
+        assert_eq!(syllable_to_zhuyin("liu", 2), "ㄌ又2");
+        assert_eq!(syllable_to_zhuyin("yuan", 2), "元2");
+        assert_eq!(syllable_to_zhuyin("wo", 3), "我3");
+        assert_eq!(syllable_to_zhuyin("yi", 1), "ㄧ1");
+    }
+
+    #[test]
+    fn test_normalize_numbers() {
+        assert_eq!(normalize_numbers("15"), "十五");
+        assert_eq!(normalize_numbers("10010"), "一万零一十");
+        assert_eq!(normalize_numbers("2024年"), "二零二四年");
+        assert_eq!(normalize_numbers("2024"), "二千零二十四");
+        assert_eq!(normalize_numbers("3.14"), "三点一四");
+        assert_eq!(normalize_numbers("50%"), "百分之五十");
+        assert_eq!(normalize_numbers("007"), "零零七");
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("¥12.5"), "十二点五元");
+        assert_eq!(normalize("2024-05-01"), "二零二四年五月一日");
+        assert_eq!(normalize("下午3:05"), "下午三点零五分");
+        assert_eq!(normalize("8:00"), "八点整");
+    }
+}
@@ -0,0 +1,166 @@
+//! Chapter markers and tags for long recordings.
+//!
+//! MP3 files carry chapters as ID3v2.3 `CHAP` frames listed by a `CTOC`
+//! frame; M4B files are tagged by ffmpeg from an ffmetadata file.
+
+/// One chapter of a recording, in milliseconds from its start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterMark {
+    pub title: String,
+    pub start_ms: u32,
+    pub end_ms: u32,
+}
+
+/// Tags written to a finished recording
+#[derive(Debug, Clone, Default)]
+pub struct AudioMetadata<'a> {
+    pub title: Option<&'a str>,
+    pub artist: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub chapters: &'a [ChapterMark],
+}
+
+impl AudioMetadata<'_> {
+    /// An ID3v2.3 tag to put in front of MP3 frames
+    pub fn id3v2(&self) -> Vec<u8> {
+        let mut frames = Vec::new();
+        for (id, value) in [
+            (b"TIT2", self.title),
+            (b"TPE1", self.artist),
+            (b"TALB", self.album),
+        ] {
+            if let Some(value) = value {
+                frames.extend(frame(id, &text_body(value)));
+            }
+        }
+
+        if !self.chapters.is_empty() {
+            // The table of contents holds at most 255 entries
+            let mut toc = b"toc\0".to_vec();
+            toc.push(0x03); // top level, ordered
+            let listed = self.chapters.len().min(u8::MAX as usize);
+            toc.push(listed as u8);
+            for i in 0..listed {
+                toc.extend(format!("chp{}\0", i).as_bytes());
+            }
+            frames.extend(frame(b"CTOC", &toc));
+        }
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            let mut body = format!("chp{}\0", i).into_bytes();
+            body.extend(chapter.start_ms.to_be_bytes());
+            body.extend(chapter.end_ms.to_be_bytes());
+            // Byte offsets are unknown until the frames are encoded
+            body.extend(u32::MAX.to_be_bytes());
+            body.extend(u32::MAX.to_be_bytes());
+            body.extend(frame(b"TIT2", &text_body(&chapter.title)));
+            frames.extend(frame(b"CHAP", &body));
+        }
+
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend(syncsafe(frames.len() as u32));
+        tag.extend(frames);
+        tag
+    }
+
+    /// An ffmetadata file, as read by `ffmpeg -i FILE -map_metadata N`
+    pub fn ffmetadata(&self) -> String {
+        let mut out = String::from(";FFMETADATA1\n");
+        for (key, value) in [
+            ("title", self.title),
+            ("artist", self.artist),
+            ("album", self.album),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!("{}={}\n", key, escape_ffmetadata(value)));
+            }
+        }
+        for chapter in self.chapters {
+            out.push_str(&format!(
+                "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+                chapter.start_ms,
+                chapter.end_ms,
+                escape_ffmetadata(&chapter.title)
+            ));
+        }
+        out
+    }
+}
+
+fn frame(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut frame = id.to_vec();
+    frame.extend((body.len() as u32).to_be_bytes());
+    frame.extend([0, 0]);
+    frame.extend(body);
+    frame
+}
+
+/// UTF-16 with a byte order mark, the only Unicode encoding ID3v2.3 knows
+fn text_body(text: &str) -> Vec<u8> {
+    let mut body = vec![0x01, 0xFF, 0xFE];
+    for unit in text.encode_utf16() {
+        body.extend(unit.to_le_bytes());
+    }
+    body.extend([0, 0]);
+    body
+}
+
+fn syncsafe(size: u32) -> [u8; 4] {
+    [
+        (size >> 21) as u8 & 0x7F,
+        (size >> 14) as u8 & 0x7F,
+        (size >> 7) as u8 & 0x7F,
+        size as u8 & 0x7F,
+    ]
+}
+
+fn escape_ffmetadata(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_tags() {
+        let chapters = [
+            ChapterMark {
+                title: "One".into(),
+                start_ms: 0,
+                end_ms: 1500,
+            },
+            ChapterMark {
+                title: "Two; the end".into(),
+                start_ms: 1500,
+                end_ms: 3000,
+            },
+        ];
+        let metadata = AudioMetadata {
+            title: Some("Book"),
+            chapters: &chapters,
+            ..AudioMetadata::default()
+        };
+
+        let tag = metadata.id3v2();
+        assert_eq!(&tag[..6], b"ID3\x03\x00\x00");
+        let size = tag[6..10]
+            .iter()
+            .fold(0usize, |size, b| (size << 7) | *b as usize);
+        assert_eq!(size, tag.len() - 10);
+        assert_eq!(tag.windows(4).filter(|w| w == b"CHAP").count(), 2);
+        assert!(tag.windows(4).any(|w| w == b"CTOC"));
+
+        assert_eq!(
+            metadata.ffmetadata(),
+            ";FFMETADATA1\ntitle=Book\n\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\ntitle=One\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1500\nEND=3000\ntitle=Two\\; the end\n"
+        );
+    }
+}
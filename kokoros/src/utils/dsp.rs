@@ -0,0 +1,579 @@
+//! Signal processing on synthesized audio.
+//!
+//! [`time_stretch`] changes the length of speech without changing its pitch,
+//! by WSOLA (waveform similarity overlap-add): windowed frames are taken from
+//! the input at the rate of the stretch and overlap-added at a fixed hop,
+//! each frame shifted by up to half a hop to where it best continues the
+//! waveform of the previous one, so pitch periods line up.
+//!
+//! [`Effects`] builds pitch and tempo on it. A pitch shift stretches the
+//! speech by the pitch ratio and resamples it back to length, which moves
+//! the formants with the pitch: a few semitones up sounds younger or
+//! smaller rather than like the same speaker singing higher. Every frame
+//! only looks a few milliseconds around itself, so an [`EffectsStream`]
+//! processes the chunks of a stream one by one as they are synthesized,
+//! carrying those milliseconds over from each chunk to the next.
+
+use crate::tts::koko::WordAlignment;
+
+/// Largest pitch shift, up or down, in semitones
+pub const MAX_PITCH: f32 = 12.0;
+
+/// Slowest tempo, as a coefficient of the synthesized one
+pub const MIN_TEMPO: f32 = 0.5;
+
+/// Fastest tempo, as a coefficient of the synthesized one
+pub const MAX_TEMPO: f32 = 2.0;
+
+/// Taps of the low-pass filter run before resampling down
+const LOW_PASS_TAPS: usize = 31;
+
+/// Length of a WSOLA frame, in seconds
+const FRAME_SECONDS: f32 = 0.025;
+
+/// Stretch `samples` to `ratio` times their length, keeping their pitch.
+/// `ratio` above one slows speech down, below one speeds it up; audio
+/// shorter than two frames is padded with silence to stretch it. Empty if
+/// `ratio` isn't positive.
+pub fn time_stretch(samples: &[f32], sample_rate: u32, ratio: f32) -> Vec<f32> {
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Vec::new();
+    }
+    let mut stretcher = TimeStretcher::new(sample_rate, ratio);
+    let mut out = stretcher.process(samples);
+    out.extend(stretcher.finish());
+    out
+}
+
+/// [`time_stretch`] over a stream of chunks. The input the next frame
+/// search may reach back to and the output later frames still overlap are
+/// kept between chunks, so the stream comes out as if stretched at once.
+pub struct TimeStretcher {
+    ratio: f32,
+    frame: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Input from `input_start` on, counted from the start of the stream
+    input: Vec<f32>,
+    input_start: usize,
+    /// Overlap-added output and window weights from `output_start` on
+    output: Vec<f32>,
+    weight: Vec<f32>,
+    output_start: usize,
+    /// Output position of the next frame
+    next: usize,
+    /// Input position of the previous frame
+    previous: Option<usize>,
+}
+
+impl TimeStretcher {
+    /// A stretcher to `ratio` times the length, which must be positive
+    pub fn new(sample_rate: u32, ratio: f32) -> Self {
+        let frame = ((sample_rate as f32 * FRAME_SECONDS) as usize).max(4) & !1;
+        let hop = frame / 2;
+        Self {
+            ratio,
+            frame,
+            hop,
+            tolerance: hop / 2,
+            window: (0..frame)
+                .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / frame as f32).cos())
+                .collect(),
+            input: Vec::new(),
+            input_start: 0,
+            output: Vec::new(),
+            weight: Vec::new(),
+            output_start: 0,
+            next: 0,
+            previous: None,
+        }
+    }
+
+    /// Stretch the next chunk, returning the output no later frame changes
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let total = self.input_start + self.input.len();
+        // Place frames while their whole search range has arrived
+        while self.nominal() + self.tolerance + self.frame <= total
+            && self
+                .previous
+                .is_none_or(|previous| previous + self.frame <= total)
+        {
+            self.place(usize::MAX);
+        }
+        if let Some(previous) = self.previous {
+            let keep = (previous + self.hop)
+                .min(self.nominal().saturating_sub(self.tolerance))
+                .min(total.saturating_sub(self.frame));
+            self.input.drain(..keep.saturating_sub(self.input_start));
+            self.input_start = self.input_start.max(keep);
+        }
+        let ready = (total as f32 * self.ratio) as usize;
+        self.emit(self.next.min(ready))
+    }
+
+    /// Stretch the rest of the stream, which ends here
+    pub fn finish(&mut self) -> Vec<f32> {
+        let total = self.input_start + self.input.len();
+        let out_len = (total as f32 * self.ratio).round() as usize;
+        if total == 0 {
+            return Vec::new();
+        }
+        let padded = total.max(self.frame * 2);
+        self.input.resize(padded - self.input_start, 0.0);
+        let last_start = padded - self.frame;
+        while self.next < out_len {
+            self.place(last_start);
+        }
+        self.emit(out_len)
+    }
+
+    /// Input position the next frame is searched around
+    fn nominal(&self) -> usize {
+        (self.next as f32 / self.ratio).round() as usize
+    }
+
+    /// Overlap-add the next frame, from no later than `last_start`
+    fn place(&mut self, last_start: usize) {
+        let nominal = self.nominal().min(last_start);
+        let start = match self.previous {
+            None => 0,
+            Some(previous) => {
+                // Where the previous frame would naturally continue
+                let natural = (previous + self.hop).min(last_start) - self.input_start;
+                let low = nominal.saturating_sub(self.tolerance).max(self.input_start);
+                let high = (nominal + self.tolerance).min(last_start);
+                (low..=high)
+                    .max_by(|a, b| {
+                        let a = similarity(&self.input, a - self.input_start, natural, self.hop);
+                        let b = similarity(&self.input, b - self.input_start, natural, self.hop);
+                        a.total_cmp(&b)
+                    })
+                    .unwrap_or(nominal)
+            }
+        };
+        let end = self.next + self.frame - self.output_start;
+        if self.output.len() < end {
+            self.output.resize(end, 0.0);
+            self.weight.resize(end, 0.0);
+        }
+        let offset = self.next - self.output_start;
+        let input = &self.input[start - self.input_start..][..self.frame];
+        for (i, (sample, window)) in input.iter().zip(&self.window).enumerate() {
+            self.output[offset + i] += sample * window;
+            self.weight[offset + i] += window;
+        }
+        self.previous = Some(start);
+        self.next += self.hop;
+    }
+
+    /// Normalized output up to `end`
+    fn emit(&mut self, end: usize) -> Vec<f32> {
+        let len = end.saturating_sub(self.output_start).min(self.output.len());
+        self.output_start += len;
+        self.output
+            .drain(..len)
+            .zip(self.weight.drain(..len))
+            .map(|(sample, weight)| {
+                if weight > 1e-3 {
+                    sample / weight
+                } else {
+                    sample
+                }
+            })
+            .collect()
+    }
+}
+
+/// Pitch and tempo applied to speech after synthesis. Unlike the speed of
+/// the model, neither changes the prosody.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effects {
+    /// Shift in semitones, positive for higher
+    pub pitch: f32,
+    /// Rate of the speech, above one faster, without changing its pitch
+    pub tempo: f32,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            tempo: 1.0,
+        }
+    }
+}
+
+impl Effects {
+    /// Effects within [`MAX_PITCH`] and [`MIN_TEMPO`]..=[`MAX_TEMPO`]
+    pub fn new(pitch: f32, tempo: f32) -> Result<Self, String> {
+        if !(-MAX_PITCH..=MAX_PITCH).contains(&pitch) {
+            return Err(format!(
+                "pitch must be between -{} and {} semitones, got {}",
+                MAX_PITCH, MAX_PITCH, pitch
+            ));
+        }
+        if !(MIN_TEMPO..=MAX_TEMPO).contains(&tempo) {
+            return Err(format!(
+                "tempo must be between {} and {}, got {}",
+                MIN_TEMPO, MAX_TEMPO, tempo
+            ));
+        }
+        Ok(Self { pitch, tempo })
+    }
+
+    /// Whether the effects leave audio untouched
+    pub fn is_neutral(&self) -> bool {
+        self.pitch == 0.0 && self.tempo == 1.0
+    }
+
+    /// `samples` at the pitch and tempo, `1 / tempo` times as long
+    pub fn apply(&self, samples: &[f32], sample_rate: u32) -> Vec<f32> {
+        if self.is_neutral() {
+            return samples.to_vec();
+        }
+        let out_len = (samples.len() as f32 / self.tempo).round() as usize;
+        let shift = 2f32.powf(self.pitch / 12.0);
+        if self.pitch == 0.0 {
+            return time_stretch(samples, sample_rate, out_len as f32 / samples.len() as f32);
+        }
+        let stretched_len = (samples.len() as f32 * shift / self.tempo).round() as usize;
+        let stretched = time_stretch(
+            samples,
+            sample_rate,
+            stretched_len as f32 / samples.len().max(1) as f32,
+        );
+        if shift > 1.0 {
+            // Resampling down folds what is above the new Nyquist back in
+            resample_linear(&low_pass(&stretched, 0.5 / shift), out_len)
+        } else {
+            resample_linear(&stretched, out_len)
+        }
+    }
+
+    /// The effects over a stream of chunks, in order
+    pub fn stream(&self, sample_rate: u32) -> EffectsStream {
+        let shift = 2f32.powf(self.pitch / 12.0);
+        EffectsStream {
+            stretcher: (!self.is_neutral())
+                .then(|| TimeStretcher::new(sample_rate, shift / self.tempo)),
+            low_pass: (shift > 1.0).then(|| LowPass::new(0.5 / shift)),
+            resampler: (self.pitch != 0.0).then(|| Resampler::new(shift)),
+        }
+    }
+
+    /// Seconds of the original speech in the processed speech
+    pub fn time(&self, seconds: f32) -> f32 {
+        seconds / self.tempo
+    }
+
+    /// Move word timings to where [`Effects::apply`] puts the words
+    pub fn retime(&self, words: &mut [WordAlignment]) {
+        for word in words {
+            word.start_sec = self.time(word.start_sec);
+            word.end_sec = self.time(word.end_sec);
+        }
+    }
+}
+
+/// Shift the pitch of `samples` by `semitones`, keeping their length
+pub fn pitch_shift(samples: &[f32], sample_rate: u32, semitones: f32) -> Vec<f32> {
+    Effects {
+        pitch: semitones,
+        tempo: 1.0,
+    }
+    .apply(samples, sample_rate)
+}
+
+/// [`Effects`] over a stream of chunks. The stretch, filter and resampler
+/// keep their state from one chunk to the next, so chunk edges neither
+/// click nor shift.
+pub struct EffectsStream {
+    stretcher: Option<TimeStretcher>,
+    low_pass: Option<LowPass>,
+    resampler: Option<Resampler>,
+}
+
+impl EffectsStream {
+    /// The next chunk, as far as it can be processed yet
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.run(samples, false)
+    }
+
+    /// The rest of the stream, which ends here
+    pub fn finish(&mut self) -> Vec<f32> {
+        self.run(&[], true)
+    }
+
+    fn run(&mut self, samples: &[f32], finish: bool) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        if let Some(stretcher) = &mut self.stretcher {
+            samples = stretcher.process(&samples);
+            if finish {
+                samples.extend(stretcher.finish());
+            }
+        }
+        if let Some(low_pass) = &mut self.low_pass {
+            samples = low_pass.process(&samples);
+            if finish {
+                samples.extend(low_pass.finish());
+            }
+        }
+        if let Some(resampler) = &mut self.resampler {
+            samples = resampler.process(&samples);
+            if finish {
+                samples.extend(resampler.finish());
+            }
+        }
+        samples
+    }
+}
+
+/// Windowed-sinc low-pass with its cutoff at `cutoff` times the sample rate
+fn low_pass(samples: &[f32], cutoff: f32) -> Vec<f32> {
+    let mut filter = LowPass::new(cutoff);
+    let mut out = filter.process(samples);
+    out.extend(filter.finish());
+    out
+}
+
+/// [`low_pass`] over a stream, delayed by half its taps
+struct LowPass {
+    taps: Vec<f32>,
+    gain: f32,
+    /// Input from half the taps before the next output sample on
+    input: Vec<f32>,
+}
+
+impl LowPass {
+    fn new(cutoff: f32) -> Self {
+        let half = (LOW_PASS_TAPS / 2) as isize;
+        let taps: Vec<f32> = (-half..=half)
+            .map(|n| {
+                let x = n as f32;
+                let sinc = if n == 0 {
+                    2.0 * cutoff
+                } else {
+                    (std::f32::consts::TAU * cutoff * x).sin() / (std::f32::consts::PI * x)
+                };
+                let window = 0.54 + 0.46 * (std::f32::consts::PI * x / half as f32).cos();
+                sinc * window
+            })
+            .collect();
+        Self {
+            gain: taps.iter().sum(),
+            taps,
+            input: vec![0.0; LOW_PASS_TAPS / 2],
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let out: Vec<f32> = self
+            .input
+            .windows(self.taps.len())
+            .map(|window| {
+                window
+                    .iter()
+                    .zip(&self.taps)
+                    .map(|(sample, tap)| sample * tap)
+                    .sum::<f32>()
+                    / self.gain
+            })
+            .collect();
+        self.input.drain(..out.len());
+        out
+    }
+
+    fn finish(&mut self) -> Vec<f32> {
+        self.process(&[0.0; LOW_PASS_TAPS / 2])
+    }
+}
+
+/// Linear interpolation over a stream, `step` input samples per output one
+struct Resampler {
+    step: f64,
+    /// Input from `input_start` on, counted from the start of the stream
+    input: Vec<f32>,
+    input_start: usize,
+    emitted: usize,
+}
+
+impl Resampler {
+    fn new(step: f32) -> Self {
+        Self {
+            step: step as f64,
+            input: Vec::new(),
+            input_start: 0,
+            emitted: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let total = self.input_start + self.input.len();
+        let mut out = Vec::new();
+        loop {
+            let position = self.emitted as f64 * self.step;
+            let index = position as usize;
+            if index + 1 >= total {
+                break;
+            }
+            let fraction = (position - index as f64) as f32;
+            let local = index - self.input_start;
+            out.push(self.input[local] * (1.0 - fraction) + self.input[local + 1] * fraction);
+            self.emitted += 1;
+        }
+        let keep = ((self.emitted as f64 * self.step) as usize).min(total.saturating_sub(1));
+        self.input.drain(..keep.saturating_sub(self.input_start));
+        self.input_start = self.input_start.max(keep);
+        out
+    }
+
+    fn finish(&mut self) -> Vec<f32> {
+        let total = self.input_start + self.input.len();
+        let len = (total as f64 / self.step).round() as usize;
+        let last = self.input.last().copied().unwrap_or(0.0);
+        let out = vec![last; len.saturating_sub(self.emitted)];
+        self.emitted = self.emitted.max(len);
+        out
+    }
+}
+
+/// Cross-correlation of `len` samples starting at `a` and at `b`
+fn similarity(samples: &[f32], a: usize, b: usize, len: usize) -> f32 {
+    samples[a..a + len]
+        .iter()
+        .zip(&samples[b..b + len])
+        .map(|(x, y)| x * y)
+        .sum()
+}
+
+/// Resample to `len` samples by linear interpolation, which shifts pitch
+/// along with length
+pub fn resample_linear(samples: &[f32], len: usize) -> Vec<f32> {
+    match samples {
+        [] => vec![0.0; len],
+        [only] => vec![*only; len],
+        _ => {
+            let step = (samples.len() - 1) as f32 / (len.max(2) - 1) as f32;
+            (0..len)
+                .map(|i| {
+                    let position = i as f32 * step;
+                    let index = (position as usize).min(samples.len() - 2);
+                    let fraction = position - index as f32;
+                    samples[index] * (1.0 - fraction) + samples[index + 1] * fraction
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frequency of a sine from its zero crossings
+    fn frequency(samples: &[f32], sample_rate: u32) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * sample_rate as f32 / samples.len() as f32
+    }
+
+    fn tone(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_time_stretch() {
+        let rate = 24000;
+        let tone = tone(220.0, rate, rate as usize);
+        for ratio in [0.7, 1.5] {
+            let stretched = time_stretch(&tone, rate, ratio);
+            assert_eq!(stretched.len(), (rate as f32 * ratio).round() as usize);
+            let pitch = frequency(&stretched, rate);
+            assert!((pitch - 220.0).abs() < 5.0, "{} Hz at {}", pitch, ratio);
+        }
+        assert!(time_stretch(&tone, rate, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_pitch_shift() {
+        let rate = 24000;
+        let tone = tone(220.0, rate, rate as usize);
+        let up = pitch_shift(&tone, rate, 12.0);
+        assert_eq!(up.len(), tone.len());
+        let pitch = frequency(&up, rate);
+        assert!((pitch - 440.0).abs() < 10.0, "{} Hz an octave up", pitch);
+
+        let effects = Effects::new(-5.0, 1.25).unwrap();
+        let down = effects.apply(&tone, rate);
+        assert_eq!(down.len(), (rate as f32 / 1.25).round() as usize);
+        let expected = 220.0 * 2f32.powf(-5.0 / 12.0);
+        let pitch = frequency(&down, rate);
+        assert!(
+            (pitch - expected).abs() < 5.0,
+            "{} Hz, not {}",
+            pitch,
+            expected
+        );
+        assert!(Effects::new(13.0, 1.0).is_err() && Effects::new(0.0, 3.0).is_err());
+        assert_eq!(resample_linear(&[0.0, 1.0], 3), [0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_short_input() {
+        let rate = 24000;
+        // Under two frames, padded instead of resampled an octave down
+        let short = tone(1000.0, rate, 900);
+        let stretched = time_stretch(&short, rate, 2.0);
+        assert_eq!(stretched.len(), 1800);
+        let pitch = frequency(&stretched, rate);
+        assert!((pitch - 1000.0).abs() < 50.0, "{} Hz", pitch);
+        assert_eq!(time_stretch(&[0.5; 10], rate, 2.0).len(), 20);
+        assert!(time_stretch(&[], rate, 2.0).is_empty());
+    }
+
+    #[test]
+    fn test_stream() {
+        let rate = 24000;
+        let tone = tone(220.0, rate, rate as usize);
+        let whole = time_stretch(&tone, rate, 1.5);
+        let mut stretcher = TimeStretcher::new(rate, 1.5);
+        let mut streamed: Vec<f32> = tone
+            .chunks(1000)
+            .flat_map(|chunk| stretcher.process(chunk))
+            .collect();
+        streamed.extend(stretcher.finish());
+        assert_eq!(streamed, whole);
+
+        let effects = Effects::new(7.0, 0.8).unwrap();
+        let mut stream = effects.stream(rate);
+        let mut streamed: Vec<f32> = tone
+            .chunks(777)
+            .flat_map(|chunk| stream.process(chunk))
+            .collect();
+        streamed.extend(stream.finish());
+        let whole = effects.apply(&tone, rate);
+        assert!(streamed.len().abs_diff(whole.len()) <= 1);
+        let expected = 220.0 * 2f32.powf(7.0 / 12.0);
+        let pitch = frequency(&streamed, rate);
+        assert!(
+            (pitch - expected).abs() < 5.0,
+            "{} Hz, not {}",
+            pitch,
+            expected
+        );
+        // No click where one chunk meets the next
+        let jump = streamed[100..streamed.len() - 100]
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(jump < 0.2, "jump of {}", jump);
+    }
+}
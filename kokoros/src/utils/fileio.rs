@@ -3,15 +3,12 @@ use serde_json::Value;
 use std::{io::Read, path::Path};
 use tokio::{fs::File, io::AsyncWriteExt};
 
-pub async fn download_file_from_url(
-    url: &str,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn download_file_from_url(url: &str, path: &str) -> Result<(), String> {
     if let Some(parent) = Path::new(path).parent() {
-        std::fs::create_dir_all(parent)?;
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
 
-    let mut resp = reqwest::get(url).await?;
+    let mut resp = reqwest::get(url).await.map_err(|e| e.to_string())?;
 
     if resp.status().is_success() {
         let total_size = resp.content_length().unwrap_or(0);
@@ -24,19 +21,19 @@ pub async fn download_file_from_url(
             .unwrap()
             .progress_chars("#>-"));
 
-        let mut file = File::create(path).await?;
+        let mut file = File::create(path).await.map_err(|e| e.to_string())?;
         let mut downloaded = 0;
 
-        while let Some(chunk) = resp.chunk().await? {
-            file.write_all(&chunk).await?;
-            downloaded += chunk.len();
-            pb.set_position(downloaded.try_into()?);
+        while let Some(chunk) = resp.chunk().await.map_err(|e| e.to_string())? {
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            downloaded += chunk.len() as u64;
+            pb.set_position(downloaded);
         }
 
         pb.finish_with_message("Download completed");
         Ok(())
     } else {
-        Err(format!("Failed to download file: {}", resp.status()).into())
+        Err(format!("Failed to download file: {}", resp.status()))
     }
 }
 
@@ -1,4 +1,6 @@
+pub mod chapters;
 pub mod debug;
+pub mod dsp;
 pub mod fileio;
 pub mod mp3;
 pub mod opus;
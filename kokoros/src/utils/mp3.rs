@@ -84,3 +84,60 @@ pub fn pcm_to_mp3(pcm_data: &[f32], sample_rate: u32) -> Result<Vec<u8>, std::io
 
     Ok(mp3_out_buffer)
 }
+
+/// An MP3 encoder fed in blocks, for recordings too long to hold in memory.
+/// Speech is encoded at 64 kbps without LAME's own tag, so the caller can
+/// write a chapter tag in front.
+pub struct Mp3Stream {
+    encoder: mp3lame_encoder::Encoder,
+}
+
+impl Mp3Stream {
+    pub fn new(sample_rate: u32) -> Result<Self, std::io::Error> {
+        let failed = |step: &str, e: &dyn std::fmt::Debug| {
+            std::io::Error::other(format!("{} failed: {:?}", step, e))
+        };
+        let mut builder =
+            Builder::new().ok_or_else(|| std::io::Error::other("Encoder init failed"))?;
+        builder
+            .set_num_channels(1)
+            .map_err(|e| failed("Set channels", &e))?;
+        builder
+            .set_sample_rate(sample_rate)
+            .map_err(|e| failed("Set sample rate", &e))?;
+        builder
+            .set_brate(mp3lame_encoder::Bitrate::Kbps64)
+            .map_err(|e| failed("Set bitrate", &e))?;
+        builder
+            .set_quality(mp3lame_encoder::Quality::Good)
+            .map_err(|e| failed("Set quality", &e))?;
+        let encoder = builder.build().map_err(|e| failed("Build encoder", &e))?;
+        Ok(Self { encoder })
+    }
+
+    /// Encode a block of samples, returning the MP3 frames completed so far
+    pub fn encode(&mut self, samples: &[i16]) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(samples.len()));
+        let size = self
+            .encoder
+            .encode(MonoPcm(samples), out.spare_capacity_mut())
+            .map_err(|e| std::io::Error::other(format!("Encoding failed: {:?}", e)))?;
+        unsafe {
+            out.set_len(size);
+        }
+        Ok(out)
+    }
+
+    /// Flush the last frames
+    pub fn finish(mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = Vec::with_capacity(7200);
+        let size = self
+            .encoder
+            .flush::<FlushNoGap>(out.spare_capacity_mut())
+            .map_err(|e| std::io::Error::other(format!("Flush failed: {:?}", e)))?;
+        unsafe {
+            out.set_len(size);
+        }
+        Ok(out)
+    }
+}